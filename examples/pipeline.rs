@@ -0,0 +1,159 @@
+//! The whole lifecycle of a storage location, through its public API: it
+//! inserts random batches into a spine, compacting as it goes, checks point
+//! lookups and range scans against a `BTreeMap` of the same data, takes a
+//! checkpoint, reopens the storage location from it, and checks everything
+//! again.  It exits nonzero if anything differs.
+//!
+//! Run it with `cargo run --release --example pipeline`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use storage_design::layer::Row;
+use storage_design::rng;
+use storage_design::storage::{Storage, StorageOptions};
+
+/// The spine that the example uses.
+const SPINE: &str = "pipeline";
+
+/// Number of batches, and of rows in each.
+const BATCHES: u64 = 200;
+const BATCH_ROWS: u64 = 10_000;
+
+/// Keys are below this, so that batches overlap.
+const KEYS: u64 = 1_000_000;
+
+/// The same data as the spine, as the weight of each key and value.
+type Model = BTreeMap<(u64, u64), i64>;
+
+/// Returns a batch of random rows, in no particular order and not
+/// consolidated, which [Storage::insert_batch] accepts as is.  Some of them
+/// retract rows already in `model`.
+fn random_batch(random: &mut impl FnMut() -> u64, model: &Model) -> Vec<Row> {
+    (0..BATCH_ROWS)
+        .map(|_| {
+            let key = random() % KEYS;
+            match model.range((key, 0)..).next() {
+                Some((&(key, value), &weight)) if random().is_multiple_of(4) => {
+                    (key, value, -weight)
+                }
+                _ => (key, random() % 8, 1 + (random() % 3) as i64),
+            }
+        })
+        .collect()
+}
+
+/// Adds `rows` to `model`, dropping rows whose weights cancel out.
+fn apply(model: &mut Model, rows: &[Row]) {
+    for &(key, value, weight) in rows {
+        let sum = model.entry((key, value)).or_default();
+        *sum += weight;
+        if *sum == 0 {
+            model.remove(&(key, value));
+        }
+    }
+}
+
+/// Inserts the batches, one per step, compacting after each one, as a
+/// circuit would between steps.
+fn ingest(storage: &mut Storage, model: &mut Model, seed: u64) -> Result<(), String> {
+    let mut random = rng::random(seed);
+    for step in 1..=BATCHES {
+        let rows = random_batch(&mut random, model);
+        apply(model, &rows);
+        storage.insert_batch(SPINE, step, rows)?;
+        storage.compact(SPINE)?;
+    }
+    Ok(())
+}
+
+/// Returns the rows of `model` with keys from `from` to `to`, inclusive.
+fn model_rows(model: &Model, from: u64, to: u64) -> Vec<Row> {
+    model
+        .range((from, 0)..=(to, u64::MAX))
+        .map(|(&(key, value), &weight)| (key, value, weight))
+        .collect()
+}
+
+/// Checks `n` random point lookups and `n` random range scans against
+/// `model`.
+fn check_reads(storage: &Storage, model: &Model, n: usize, seed: u64) -> Result<(), String> {
+    let mut random = rng::random(seed);
+    for _ in 0..n {
+        let key = random() % KEYS;
+        let expected: Vec<(u64, i64)> = model_rows(model, key, key)
+            .into_iter()
+            .map(|(_, value, weight)| (value, weight))
+            .collect();
+        if storage.get(SPINE, key)? != expected {
+            return Err(format!("lookup of {key} differs from the model"));
+        }
+
+        let (from, to) = (key, key + random() % 1000);
+        let rows = storage
+            .scan(SPINE, from, to)?
+            .collect::<Result<Vec<_>, _>>()?;
+        if rows != model_rows(model, from, to) {
+            return Err(format!("scan from {from} to {to} differs from the model"));
+        }
+    }
+    Ok(())
+}
+
+/// Checks a full scan against `model`.
+fn check_scan(storage: &Storage, model: &Model) -> Result<(), String> {
+    let rows = storage
+        .scan(SPINE, 0, u64::MAX)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let expected = model_rows(model, 0, u64::MAX);
+    if rows != expected {
+        return Err(format!(
+            "full scan returned {} rows instead of {}",
+            rows.len(),
+            expected.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Takes a checkpoint, drops the storage location as a crash would, reopens
+/// it from the checkpoint, and checks that it holds just the files that the
+/// checkpoint refers to.
+fn restart(mut storage: Storage, root: &Path, options: &StorageOptions) -> Result<Storage, String> {
+    storage.checkpoint(BATCHES)?;
+    drop(storage);
+    let storage = Storage::open(root, options)?;
+    storage.check()?;
+    Ok(storage)
+}
+
+fn run(root: &Path) -> Result<(), String> {
+    let options = StorageOptions::default();
+    let mut storage = Storage::open(root, &options)?;
+    let mut model = Model::new();
+    ingest(&mut storage, &mut model, 1)?;
+    let batches = storage.spines()[SPINE].batches.len();
+    println!(
+        "inserted {} rows in {BATCHES} batches, which compacted into {batches}",
+        BATCHES * BATCH_ROWS
+    );
+    check_reads(&storage, &model, 1000, 2)?;
+    check_scan(&storage, &model)?;
+
+    let storage = restart(storage, root, &options)?;
+    check_reads(&storage, &model, 1000, 2)?;
+    check_scan(&storage, &model)?;
+    println!("{} rows read back after restarting", model.len());
+    Ok(())
+}
+
+fn main() {
+    let root: PathBuf =
+        std::env::temp_dir().join(format!("storage-design-pipeline-{}", std::process::id()));
+    let result = run(&root);
+    let _ = std::fs::remove_dir_all(&root);
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
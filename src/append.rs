@@ -0,0 +1,322 @@
+//! Appending a sorted batch to an existing layer file.
+//!
+//! When a small batch arrives whose keys all sort after the last key of an
+//! existing file, the writer doesn't have to merge the two into a new file.
+//! It can instead write the batch's data blocks after the file's current
+//! trailer, then a copy of the right edge of each index, the last block at
+//! each level with the new entries added, then any new index blocks above
+//! them, and finally a trailer of a new generation that points to the new
+//! roots.  Every other index block is shared with the previous generation,
+//! which stays readable, since nothing it refers to is overwritten.  The last
+//! data block of the old generation stays as it was, however full it is, so
+//! each append leaves one partly full data block and a copy of the index's
+//! right edge that only older generations use.
+//!
+//! This generates a file of sorted integer keys, appends batches of a given
+//! size to it, checks that every generation still finds its own keys and none
+//! of the later ones and that a batch that doesn't sort after the file is
+//! refused, and compares the bytes written against rewriting the file for
+//! each batch, along with how much a rewrite would save afterward.
+
+use clap::Args as ClapArgs;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct AppendArgs {
+    /// Number of rows in each appended batch.
+    #[clap(long = "batch-rows", default_values_t = vec![100, 1000, 10_000, 100_000])]
+    batch_rows: Vec<u64>,
+
+    /// Number of batches to append to the file.
+    #[clap(long, default_value_t = 100)]
+    appends: u64,
+
+    /// Number of rows in the file before the first append.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Size of each data and index block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Fraction of the file that a rewrite would have to save before the
+    /// file is merged instead of appended to.
+    #[clap(long, default_value_t = 0.25)]
+    max_waste: f64,
+}
+
+/// Size of the file header and of each trailer.
+const TRAILER_SIZE: u64 = 4096;
+
+/// Size of an index entry: a first key and a child pointer.
+const ENTRY_SIZE: u64 = 16;
+
+/// A block of the file, as its data block's keys or its index block's
+/// entries, each the first key under a child and the child's block number.
+enum Block {
+    Data(Vec<u64>),
+    Index(Vec<(u64, usize)>),
+}
+
+/// A generation of the file, as a reader that opened it at `length` sees it.
+struct Generation {
+    root: usize,
+    rows: usize,
+    length: u64,
+}
+
+/// A file of sorted keys that can be appended to.
+struct File {
+    blocks: Vec<Block>,
+    generations: Vec<Generation>,
+    keys_per_block: usize,
+    entries_per_block: usize,
+    block_size: u64,
+
+    /// The latest generation's index, level by level from the entries for
+    /// the data blocks up to the root's, which the next append extends.
+    levels: Vec<Vec<(u64, usize)>>,
+    last: Option<u64>,
+    length: u64,
+}
+
+impl File {
+    fn new(block_size: u64) -> Self {
+        Self {
+            blocks: Vec::new(),
+            generations: Vec::new(),
+            keys_per_block: (block_size / 8) as usize,
+            entries_per_block: (block_size / ENTRY_SIZE) as usize,
+            block_size,
+            levels: vec![Vec::new()],
+            last: None,
+            length: TRAILER_SIZE,
+        }
+    }
+
+    /// Returns the size of a file of `rows` keys written in one go.
+    fn fresh_size(&self, rows: usize) -> u64 {
+        let mut entries = rows.div_ceil(self.keys_per_block);
+        let mut blocks = entries;
+        while entries > 1 {
+            entries = entries.div_ceil(self.entries_per_block);
+            blocks += entries;
+        }
+        2 * TRAILER_SIZE + blocks as u64 * self.block_size
+    }
+
+    fn push(&mut self, block: Block) -> usize {
+        self.blocks.push(block);
+        self.length += self.block_size;
+        self.blocks.len() - 1
+    }
+
+    /// Appends `batch`, which must be sorted, as a new generation, and
+    /// returns the number of bytes written.
+    fn append(&mut self, batch: &[u64]) -> Result<u64, String> {
+        match (self.last, batch.first()) {
+            (_, None) => return Err("empty batch".into()),
+            (Some(last), Some(&first)) if first <= last => {
+                return Err(format!(
+                    "batch starts at {first}, not after the file's last key {last}"
+                ))
+            }
+            _ => (),
+        }
+        let start = self.length;
+        for chunk in batch.chunks(self.keys_per_block) {
+            let block = self.push(Block::Data(chunk.to_vec()));
+            self.levels[0].push((chunk[0], block));
+        }
+
+        // Rebuild each level above from the one below, keeping every index
+        // block whose entries didn't change.
+        let mut level = 0;
+        while self.levels[level].len() > 1 {
+            let entries = self.entries_per_block;
+            let old = self.levels.get(level + 1).cloned().unwrap_or_default();
+            let mut above = Vec::new();
+            for (i, chunk) in self.levels[level].clone().chunks(entries).enumerate() {
+                let unchanged = old.get(i).filter(|&&(_, block)| match &self.blocks[block] {
+                    Block::Index(children) => children.as_slice() == chunk,
+                    Block::Data(_) => false,
+                });
+                let block = match unchanged {
+                    Some(&(_, block)) => block,
+                    None => self.push(Block::Index(chunk.to_vec())),
+                };
+                above.push((chunk[0].0, block));
+            }
+            self.levels.truncate(level + 1);
+            self.levels.push(above);
+            level += 1;
+        }
+
+        self.length += TRAILER_SIZE;
+        self.last = batch.last().copied();
+        self.generations.push(Generation {
+            root: self.levels[level][0].1,
+            rows: self.generations.last().map_or(0, |g| g.rows) + batch.len(),
+            length: self.length,
+        });
+        Ok(self.length - start)
+    }
+
+    /// Returns whether `generation` finds `key`.
+    fn contains(&self, generation: &Generation, key: u64) -> bool {
+        let mut block = generation.root;
+        loop {
+            match &self.blocks[block] {
+                Block::Index(entries) => {
+                    let i = entries.partition_point(|&(first, _)| first <= key);
+                    match i {
+                        0 => return false,
+                        _ => block = entries[i - 1].1,
+                    }
+                }
+                Block::Data(keys) => return keys.binary_search(&key).is_ok(),
+            }
+        }
+    }
+
+    /// Returns every key in `generation`, in order, and the number of data
+    /// blocks that they are in.
+    fn scan(&self, generation: &Generation) -> (Vec<u64>, usize) {
+        let (mut keys, mut data_blocks) = (Vec::new(), 0);
+        let mut stack = vec![generation.root];
+        while let Some(block) = stack.pop() {
+            match &self.blocks[block] {
+                Block::Index(entries) => {
+                    stack.extend(entries.iter().rev().map(|&(_, child)| child))
+                }
+                Block::Data(data) => {
+                    keys.extend_from_slice(data);
+                    data_blocks += 1;
+                }
+            }
+        }
+        (keys, data_blocks)
+    }
+}
+
+/// Prints the bytes written by appending batches to a file against rewriting
+/// it, for each batch size in `args`.  Returns the number of failed checks.
+pub fn append(args: &AppendArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = args.block_size.max(2 * ENTRY_SIZE);
+    let rows = args.rows.max(1) as usize;
+    let appends = args.appends.max(1) as usize;
+    println!(
+        "Appending {appends} batches to a file of {} rows of 8-byte keys in {} blocks:",
+        HumanCount(rows as u64),
+        HumanBytes(block_size),
+    );
+    print!(
+        r#"
+   Batch  --Bytes Written--   Write   Data  Rewrite   Merge
+    Rows    Append  Rewrite   Ampl.   Fill    Saves   After  Failures
+--------  --------  -------  ------  -----  -------  ------  --------
+"#
+    );
+    for &batch_rows in &args.batch_rows {
+        let batch_rows = batch_rows.max(1) as usize;
+        let mut random = rng::random(batch_rows as u64);
+        let mut key = 0u64;
+        let keys: Vec<u64> = (0..rows + batch_rows * appends)
+            .map(|_| {
+                key += 1 + random() % 16;
+                key
+            })
+            .collect();
+
+        let mut file = File::new(block_size);
+        let mut failures = 0;
+        file.append(&keys[..rows]).unwrap();
+        failures += (file.length != file.fresh_size(rows)) as u64;
+
+        let (mut appended, mut rewritten) = (0, 0);
+        let mut merge_after = None;
+        for (i, batch) in keys[rows..].chunks(batch_rows).enumerate() {
+            appended += file.append(batch).unwrap();
+            let live = file.generations.last().unwrap().rows;
+            rewritten += file.fresh_size(live);
+            let saves = 1.0 - file.fresh_size(live) as f64 / file.length as f64;
+            if saves > args.max_waste && merge_after.is_none() {
+                merge_after = Some(i + 1);
+            }
+        }
+
+        // A batch that overlaps the file, or that repeats its last key, has
+        // to be merged instead.
+        let last = *keys.last().unwrap();
+        failures += file.append(&[last]).is_ok() as u64;
+        failures += file.append(&[last - 1, last + 1]).is_ok() as u64;
+
+        // Every generation finds its own first, last, and middle keys and
+        // not the first key of the next batch.  The first, a middle, and the
+        // last generation also scan back exactly their own keys.
+        let n = file.generations.len();
+        for (i, generation) in file.generations.iter().enumerate() {
+            let own = &keys[..generation.rows];
+            for key in [own[0], own[own.len() / 2], own[own.len() - 1]] {
+                failures += !file.contains(generation, key) as u64;
+            }
+            if let Some(&next) = keys.get(generation.rows) {
+                failures += file.contains(generation, next) as u64;
+            }
+            if i == 0 || i == n / 2 || i == n - 1 {
+                failures += (file.scan(generation).0 != own) as u64;
+            }
+        }
+        let latest = file.generations.last().unwrap();
+        failures += (latest.length != file.length) as u64;
+
+        let (live, data_blocks) = file.scan(latest);
+        let written = (batch_rows * appends * 8) as u64;
+        println!(
+            "{:>8}  {:>8}  {:>7}  {:6.1}  {:4.0}%  {:6.0}%  {:>6}  {failures:8}",
+            HumanCount(batch_rows as u64),
+            HumanBytes(appended),
+            HumanBytes(rewritten),
+            appended as f64 / written as f64,
+            (live.len() * 8) as f64 / (data_blocks as u64 * block_size) as f64 * 100.0,
+            (1.0 - file.fresh_size(live.len()) as f64 / file.length as f64) * 100.0,
+            match merge_after {
+                Some(i) => i.to_string(),
+                None => "never".into(),
+            },
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: AppendArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "append",
+            "--rows",
+            "20000",
+            "--appends",
+            "10",
+            "--batch-rows",
+            "100",
+            "--batch-rows",
+            "1000",
+        ]);
+        assert_eq!(append(&args), 0);
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-file overhead of ingesting a stream of small batches.
+//!
+//! The rest of the model assumes files big enough that fixed costs don't
+//! matter.  A streaming pipeline instead writes a small batch every step, and
+//! each batch becomes its own layer file until the spine merges it.  Every one
+//! of those files pays for a header and a trailer block, pads its last data
+//! block and the last block at each index level out to a full block, and has
+//! at least one index block per index even if it only holds a few values.
+//! This reports those costs as a fraction of the data in the batches.
+//!
+//! A batch whose data fits within the small-file threshold instead gets the
+//! small-file layout, which is a single data block followed by a trailer that
+//! holds the root index entries inline, with no header or index blocks.
+
+use clap::{Args as ClapArgs, ValueEnum};
+
+use crate::{HumanBytes, HumanCount, Index, IndexType, LayerFile, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct SmallBatchesArgs {
+    /// Average number of rows in each batch.
+    #[clap(long = "batch-rows", default_values_t = vec![1, 10, 100, 1000, 10_000, 100_000, 1_000_000])]
+    batch_rows: Vec<u64>,
+
+    /// Size of each row, in bytes.
+    #[clap(long, default_value_t = 64)]
+    value_size: u64,
+
+    /// Distribution of the number of rows in a batch around the average.
+    #[clap(long, value_enum, default_value_t = Distribution::Fixed)]
+    distribution: Distribution,
+
+    /// Largest batch, in bytes of data, that the writer writes in the
+    /// small-file layout, or 0 to always use the full layout.
+    #[clap(long, default_value_t = SMALL_FILE_THRESHOLD)]
+    small_file_threshold: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Distribution {
+    /// Every batch has the average number of rows.
+    Fixed,
+
+    /// Uniform between 1 row and twice the average.
+    Uniform,
+
+    /// Exponential with the given average, as when a batch holds whatever
+    /// arrived during a fixed interval of a Poisson stream.
+    Exponential,
+}
+
+impl Distribution {
+    /// Number of batch sizes to average over for distributions other than
+    /// [Distribution::Fixed].
+    const SAMPLES: u64 = 256;
+
+    /// Returns batch sizes that are evenly spaced quantiles of this
+    /// distribution with average `mean`.
+    pub fn samples(&self, mean: u64) -> Vec<u64> {
+        let quantiles = (0..Self::SAMPLES).map(|i| (i as f64 + 0.5) / Self::SAMPLES as f64);
+        let mean = mean.max(1) as f64;
+        match self {
+            Distribution::Fixed => vec![mean as u64],
+            Distribution::Uniform => quantiles
+                .map(|q| (1.0 + q * (2.0 * mean - 2.0)).round() as u64)
+                .collect(),
+            Distribution::Exponential => quantiles
+                .map(|q| ((-mean * (1.0 - q).ln()).round() as u64).max(1))
+                .collect(),
+        }
+    }
+}
+
+/// Size of the file header block and of the file trailer block.
+const HEADER_SIZE: u64 = 4096;
+const TRAILER_SIZE: u64 = 4096;
+
+/// Default for the largest file that the writer writes in the small-file
+/// layout, in bytes of data.  The writer buffers this much before it
+/// writes anything, so it has to be modest.
+const SMALL_FILE_THRESHOLD: u64 = 64 << 10;
+
+/// Returns the size of a block that holds `bytes` bytes, which is a
+/// power-of-2 multiple of 4 kB, or 0 if `bytes` is 0.
+pub fn padded(bytes: u64) -> u64 {
+    match bytes {
+        0 => 0,
+        _ => bytes.div_ceil(4096).next_power_of_two() * 4096,
+    }
+}
+
+/// Space taken by one small file.
+struct FileCost {
+    /// Bytes of values.
+    data: u64,
+
+    /// Bytes of header block, which is 0 in the small-file layout.
+    header: u64,
+
+    /// Bytes of data blocks beyond `data`, which is the padding in the last
+    /// data block.
+    data_padding: u64,
+
+    /// Bytes of index blocks, including padding.
+    index: u64,
+}
+
+impl FileCost {
+    fn new(params: &Params, indexes: &[IndexType], rows: u64, small_file_threshold: u64) -> Self {
+        let rows = rows.max(1);
+        let data = rows * params.value_size;
+        if data <= small_file_threshold {
+            return Self {
+                data,
+                header: 0,
+                data_padding: padded(data) - data,
+                index: 0,
+            };
+        }
+        let layer_file = LayerFile::new(&Params {
+            total_data_size: rows * params.value_size,
+            ..params.clone()
+        });
+        let full_blocks = rows / layer_file.values_per_data_block;
+        let last_block = (rows % layer_file.values_per_data_block) * params.value_size;
+        let data_blocks = full_blocks * layer_file.data_block_size + padded(last_block);
+        let index = layer_file
+            .indexes
+            .iter()
+            .filter(|index| indexes.contains(&index.index_type))
+            .map(|index| index_size(index, rows))
+            .sum();
+        Self {
+            data,
+            header: HEADER_SIZE,
+            data_padding: data_blocks - data,
+            index,
+        }
+    }
+
+    fn overhead(&self) -> u64 {
+        self.header + TRAILER_SIZE + self.data_padding + self.index
+    }
+
+    fn is_small(&self) -> bool {
+        self.header == 0
+    }
+}
+
+/// Returns the size of `index` over `rows` rows, with the last block at each
+/// level padded to the next block size instead of filled.  Even a file small
+/// enough not to need an index has one index block, because the trailer
+/// points to the top-level index block.
+fn index_size(index: &Index, rows: u64) -> u64 {
+    if index.index_type == IndexType::Hash {
+        return rows.div_ceil(index.coverage[0]) * index.block_size;
+    }
+    let mut entries = rows.div_ceil(index.values_per_entry);
+    let mut size = 0;
+    for level in 0..index.height.max(1) {
+        let block_size = index
+            .level_block_sizes
+            .get(level)
+            .copied()
+            .unwrap_or(index.block_size);
+        let entries_per_block = block_size / index.index_entry_size;
+        let blocks = entries.div_ceil(entries_per_block);
+        let last_entries = entries - (blocks - 1) * entries_per_block;
+        size += (blocks - 1) * block_size + padded(last_entries * index.index_entry_size);
+        entries = blocks;
+    }
+    size
+}
+
+/// Prints the per-file overhead of writing each batch as its own layer file,
+/// for each average batch size in `args`.  The overhead includes `indexes`.
+pub fn small_batches(params: &Params, indexes: &[IndexType], args: &SmallBatchesArgs) {
+    let value_size = args.value_size.max(1);
+    let params = Params {
+        value_size,
+        ..params.clone()
+    };
+    println!(
+        "Per-file overhead for {} batches of {value_size}-byte rows, min_branch={}, min_data_block={}, min_index_block={}{}, small files up to {}:",
+        format!("{:?}", args.distribution).to_lowercase(),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults(),
+        HumanBytes(args.small_file_threshold),
+    );
+    print!(
+        r#"
+ Batch    Batch     File   Header,     Data             Overhead   Small
+  Rows     Data     Size   Trailer  Padding    Index     / Data   Files
+------  -------  -------  --------  -------  -------  ---------  ------
+"#
+    );
+    for &batch_rows in &args.batch_rows {
+        let samples = args.distribution.samples(batch_rows);
+        let costs: Vec<_> = samples
+            .iter()
+            .map(|&rows| FileCost::new(&params, indexes, rows, args.small_file_threshold))
+            .collect();
+        let n = costs.len() as u64;
+        let sum = |f: fn(&FileCost) -> u64| costs.iter().map(f).sum::<u64>();
+        let (data, overhead) = (sum(|cost| cost.data), sum(FileCost::overhead));
+        let small = costs.iter().filter(|cost| cost.is_small()).count();
+        println!(
+            "{:>6}  {:>7}  {:>7}  {:>8}  {:>7}  {:>7}  {:8.1}%  {:5.0}%",
+            HumanCount(batch_rows),
+            HumanBytes(data / n),
+            HumanBytes((data + overhead) / n),
+            HumanBytes(sum(|cost| cost.header) / n + TRAILER_SIZE),
+            HumanBytes(sum(|cost| cost.data_padding) / n),
+            HumanBytes(sum(|cost| cost.index) / n),
+            overhead as f64 / data as f64 * 100.0,
+            small as f64 / n as f64 * 100.0
+        );
+    }
+}
+
+/// Prints a table comparing `params.total_data_size` in a single layer file
+/// against the same data split evenly across `file_count` files, for each
+/// value size.  Each file has its own top index levels, header, trailer, and
+/// partly filled last blocks.  The index size includes `indexes`.
+pub fn print_file_count(params: &Params, indexes: &[IndexType], file_count: u64) {
+    let file_count = file_count.max(1);
+    println!(
+        "Overhead of {} data in 1 file versus {file_count} files, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        ------1 File------   -----------{:-^9}-----------
+ Value    Index   Overhead    Index  Headers  Padding  Overhead   Extra
+------  -------  ---------  -------  -------  -------  --------  ------
+"#,
+        format!("{file_count} Files")
+    );
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let rows = params.total_values();
+        let one = FileCost::new(&params, indexes, rows, SMALL_FILE_THRESHOLD);
+        let many = FileCost::new(
+            &params,
+            indexes,
+            rows.div_ceil(file_count),
+            SMALL_FILE_THRESHOLD,
+        );
+        let data = one.data as f64;
+        let many_overhead = many.overhead() * file_count;
+        println!(
+            "{:5}  {:>7}  {:8.2}%  {:>7}  {:>7}  {:>7}  {:7.2}%  {:>6}",
+            HumanBytes(value_size),
+            HumanBytes(one.index),
+            one.overhead() as f64 / data * 100.0,
+            HumanBytes(many.index * file_count),
+            HumanBytes((many.header + TRAILER_SIZE) * file_count),
+            HumanBytes(many.data_padding * file_count),
+            many_overhead as f64 / data * 100.0,
+            HumanBytes(many_overhead.saturating_sub(one.overhead())),
+        );
+    }
+}
@@ -0,0 +1,759 @@
+//! Benchmark of block checksum algorithms.
+//!
+//! Every block in a layer file begins with a checksum, which a reader verifies
+//! each time it reads the block from storage.  The file header records which
+//! algorithm the file uses.  This measures the CPU cost of each algorithm at
+//! typical block sizes, relative to the time it takes to read the block.
+//!
+//! BLAKE3 isn't included because it needs an external crate, so it will have
+//! to be measured in the real implementation.
+//!
+//! With `--corruption`, this instead flips bits in each field of a block's
+//! header and in its payload, one at a time and in adjacent pairs, and
+//! reports how each way of verifying a block handles the damage.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::HumanBytes;
+
+#[derive(ClapArgs, Debug)]
+pub struct ChecksumArgs {
+    /// Block size to benchmark, in bytes.
+    #[clap(long = "block-size", default_values_t = vec![4096, 8192, 65536, 1 << 20])]
+    block_sizes: Vec<u64>,
+
+    /// Algorithm to benchmark.
+    #[clap(long = "algorithm", default_values_t = vec![Algorithm::Crc32c, Algorithm::XxHash64, Algorithm::Xxh3])]
+    algorithms: Vec<Algorithm>,
+
+    /// Number of bytes to checksum for each measurement.
+    #[clap(long, default_value_t = 1 << 28)]
+    bytes: u64,
+
+    /// Device read bandwidth, in MB/s, for reporting the checksum time as a
+    /// fraction of the time to read a block.
+    #[clap(long, default_value_t = 2000)]
+    device_bandwidth: u64,
+
+    /// Instead of measuring throughput, corrupts a block of the first block
+    /// size with the first algorithm and reports which corruptions each
+    /// verification scheme catches.
+    #[clap(long)]
+    corruption: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Algorithm {
+    /// CRC-32C (Castagnoli), using the SSE 4.2 instruction if available.
+    Crc32c,
+
+    /// xxHash64.
+    #[clap(name = "xxhash64")]
+    XxHash64,
+
+    /// XXH3-64, with the default secret.
+    Xxh3,
+}
+
+impl Algorithm {
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        match self {
+            Algorithm::Crc32c => crc32c(data) as u64,
+            Algorithm::XxHash64 => xxhash64(data, 0),
+            Algorithm::Xxh3 => xxh3_64(data),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Algorithm::Crc32c => "crc32c",
+            Algorithm::XxHash64 => "xxhash64",
+            Algorithm::Xxh3 => "xxh3",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// Prints the throughput of each algorithm in `args` at each block size.
+pub fn checksum(args: &ChecksumArgs) {
+    if args.corruption {
+        let block_size = args.block_sizes.first().copied().unwrap_or(8192);
+        let algorithm = args
+            .algorithms
+            .first()
+            .copied()
+            .unwrap_or(Algorithm::Crc32c);
+        corruption(block_size.max(HEADER_SIZE as u64) as usize, algorithm);
+        return;
+    }
+    println!(
+        "Checksum throughput over {} per measurement, relative to reading at {} MB/s:",
+        HumanBytes(args.bytes),
+        args.device_bandwidth
+    );
+    print!(
+        r#"
+ Block                                   Read
+  Size  Algorithm    GB/s  us/Block   Overhead
+------  ---------  ------  --------   --------
+"#
+    );
+    for &block_size in &args.block_sizes {
+        let block_size = block_size.max(1);
+        let data: Vec<u8> = (0..block_size)
+            .map(|i| (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8)
+            .collect();
+        let n = (args.bytes / block_size).max(1);
+        for (i, algorithm) in args.algorithms.iter().enumerate() {
+            let start = Instant::now();
+            for _ in 0..n {
+                black_box(algorithm.checksum(black_box(&data)));
+            }
+            let seconds = start.elapsed().as_secs_f64();
+            let per_block = seconds / n as f64;
+            let read_time = block_size as f64 / (args.device_bandwidth as f64 * 1e6);
+            if i == 0 {
+                print!("{:>6}", HumanBytes(block_size));
+            } else {
+                print!("{:6}", "");
+            }
+            println!(
+                "  {algorithm:>9}  {:6.2}  {:8.2}   {:7.1}%",
+                (n * block_size) as f64 / seconds / 1e9,
+                per_block * 1e6,
+                per_block / read_time * 100.0
+            );
+        }
+    }
+}
+
+/// Returns the CRC-32C of `data`.
+fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("sse4.2") {
+        // SAFETY: We just checked that the CPU supports SSE 4.2.
+        return unsafe { crc32c_sse42(data) };
+    }
+    crc32c_table(data)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc = !0u64;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for &byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    !crc
+}
+
+fn crc32c_table(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0x82f6_3b78
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Returns the xxHash64 of `data` with the given `seed`.
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    const P1: u64 = 0x9e37_79b1_85eb_ca87;
+    const P2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+    const P3: u64 = 0x1656_67b1_9e37_79f9;
+    const P4: u64 = 0x85eb_ca77_c2b2_ae63;
+    const P5: u64 = 0x27d4_eb2f_1656_67c5;
+
+    fn round(acc: u64, input: u64) -> u64 {
+        acc.wrapping_add(input.wrapping_mul(P2))
+            .rotate_left(31)
+            .wrapping_mul(P1)
+    }
+    fn merge(acc: u64, value: u64) -> u64 {
+        (acc ^ round(0, value)).wrapping_mul(P1).wrapping_add(P4)
+    }
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+
+    let mut rest = data;
+    let mut hash = if data.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(P1).wrapping_add(P2),
+            seed.wrapping_add(P2),
+            seed,
+            seed.wrapping_sub(P1),
+        ];
+        let mut stripes = data.chunks_exact(32);
+        for stripe in &mut stripes {
+            for (i, v) in v.iter_mut().enumerate() {
+                *v = round(*v, read_u64(&stripe[i * 8..]));
+            }
+        }
+        rest = stripes.remainder();
+        let hash = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(hash, |hash, &v| merge(hash, v))
+    } else {
+        seed.wrapping_add(P5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let mut words = rest.chunks_exact(8);
+    for word in &mut words {
+        hash = (hash ^ round(0, read_u64(word)))
+            .rotate_left(27)
+            .wrapping_mul(P1)
+            .wrapping_add(P4);
+    }
+    let mut rest = words.remainder();
+    if rest.len() >= 4 {
+        let word = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+        hash = (hash ^ word.wrapping_mul(P1))
+            .rotate_left(23)
+            .wrapping_mul(P2)
+            .wrapping_add(P3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = (hash ^ (byte as u64).wrapping_mul(P5))
+            .rotate_left(11)
+            .wrapping_mul(P1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(P2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(P3);
+    hash ^ (hash >> 32)
+}
+
+/// Returns the XXH3-64 of `data`, with seed 0 and the default secret.
+fn xxh3_64(data: &[u8]) -> u64 {
+    const SECRET: [u8; 192] = [
+        0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad,
+        0x1c, 0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3,
+        0x67, 0x1f, 0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc,
+        0xff, 0x72, 0x21, 0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6,
+        0x81, 0x3a, 0x26, 0x4c, 0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65,
+        0x8b, 0x1b, 0x53, 0x2e, 0xa3, 0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19,
+        0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8, 0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9,
+        0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d, 0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31,
+        0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64, 0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb,
+        0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb, 0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0,
+        0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e, 0x2b, 0x16, 0xbe, 0x58, 0x7d,
+        0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce, 0x45, 0xcb, 0x3a, 0x8f,
+        0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+    ];
+    const P32_1: u64 = 0x9e37_79b1;
+    const P32_2: u64 = 0x85eb_ca77;
+    const P32_3: u64 = 0xc2b2_ae3d;
+    const P64_1: u64 = 0x9e37_79b1_85eb_ca87;
+    const P64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+    const P64_3: u64 = 0x1656_67b1_9e37_79f9;
+    const P64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+    const P64_5: u64 = 0x27d4_eb2f_1656_67c5;
+    const MX1: u64 = 0x1656_6791_9e37_79f9;
+    const MX2: u64 = 0x9fb2_1c65_1e98_df25;
+
+    let r64 = |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let r32 =
+        |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as u64;
+    let fold = |a: u64, b: u64| {
+        let product = a as u128 * b as u128;
+        product as u64 ^ (product >> 64) as u64
+    };
+    let avalanche = |mut h: u64| {
+        h ^= h >> 37;
+        h = h.wrapping_mul(MX1);
+        h ^ (h >> 32)
+    };
+    let mix16 = |at: usize, secret: usize| {
+        fold(
+            r64(data, at) ^ r64(&SECRET, secret),
+            r64(data, at + 8) ^ r64(&SECRET, secret + 8),
+        )
+    };
+
+    let len = data.len();
+    match len {
+        0 => {
+            let mut h = r64(&SECRET, 56) ^ r64(&SECRET, 64);
+            h ^= h >> 33;
+            h = h.wrapping_mul(P64_2);
+            h ^= h >> 29;
+            h = h.wrapping_mul(P64_3);
+            h ^ (h >> 32)
+        }
+        1..=3 => {
+            let combined = ((data[0] as u64) << 16)
+                | ((data[len >> 1] as u64) << 24)
+                | data[len - 1] as u64
+                | ((len as u64) << 8);
+            let mut h = combined ^ (r32(&SECRET, 0) ^ r32(&SECRET, 4));
+            h ^= h >> 33;
+            h = h.wrapping_mul(P64_2);
+            h ^= h >> 29;
+            h = h.wrapping_mul(P64_3);
+            h ^ (h >> 32)
+        }
+        4..=8 => {
+            let input = r32(data, len - 4).wrapping_add(r32(data, 0) << 32);
+            let mut h = input ^ (r64(&SECRET, 8) ^ r64(&SECRET, 16));
+            h ^= h.rotate_left(49) ^ h.rotate_left(24);
+            h = h.wrapping_mul(MX2);
+            h ^= (h >> 35).wrapping_add(len as u64);
+            h = h.wrapping_mul(MX2);
+            h ^ (h >> 28)
+        }
+        9..=16 => {
+            let lo = r64(data, 0) ^ (r64(&SECRET, 24) ^ r64(&SECRET, 32));
+            let hi = r64(data, len - 8) ^ (r64(&SECRET, 40) ^ r64(&SECRET, 48));
+            avalanche(
+                (len as u64)
+                    .wrapping_add(lo.swap_bytes())
+                    .wrapping_add(hi)
+                    .wrapping_add(fold(lo, hi)),
+            )
+        }
+        17..=128 => {
+            let mut acc = (len as u64).wrapping_mul(P64_1);
+            let rounds = (len - 1) / 32;
+            for i in (0..=rounds).rev() {
+                acc = acc.wrapping_add(mix16(16 * i, 32 * i));
+                acc = acc.wrapping_add(mix16(len - 16 * (i + 1), 32 * i + 16));
+            }
+            avalanche(acc)
+        }
+        129..=240 => {
+            let mut acc = (len as u64).wrapping_mul(P64_1);
+            for i in 0..8 {
+                acc = acc.wrapping_add(mix16(16 * i, 16 * i));
+            }
+            acc = avalanche(acc);
+            for i in 8..len / 16 {
+                acc = acc.wrapping_add(mix16(16 * i, 16 * (i - 8) + 3));
+            }
+            avalanche(acc.wrapping_add(mix16(len - 16, 136 - 17)))
+        }
+        _ => {
+            let mut acc = [P32_3, P64_1, P64_2, P64_3, P64_4, P32_2, P64_5, P32_1];
+            let accumulate = |acc: &mut [u64; 8], stripe: &[u8], secret: usize| {
+                let stripe: &[u8; 64] = stripe[..64].try_into().unwrap();
+                let secret: &[u8; 64] = SECRET[secret..secret + 64].try_into().unwrap();
+                for i in 0..8 {
+                    let value = r64(stripe, 8 * i);
+                    let key = value ^ r64(secret, 8 * i);
+                    acc[i ^ 1] = acc[i ^ 1].wrapping_add(value);
+                    acc[i] = acc[i].wrapping_add((key & 0xffff_ffff).wrapping_mul(key >> 32));
+                }
+            };
+            const STRIPES_PER_BLOCK: usize = (192 - 64) / 8;
+            const BLOCK_LEN: usize = 64 * STRIPES_PER_BLOCK;
+            let blocks = (len - 1) / BLOCK_LEN;
+            for block in 0..blocks {
+                for stripe in 0..STRIPES_PER_BLOCK {
+                    accumulate(
+                        &mut acc,
+                        &data[block * BLOCK_LEN + stripe * 64..],
+                        stripe * 8,
+                    );
+                }
+                for (i, acc) in acc.iter_mut().enumerate() {
+                    *acc =
+                        (*acc ^ (*acc >> 47) ^ r64(&SECRET, 192 - 64 + 8 * i)).wrapping_mul(P32_1);
+                }
+            }
+            let stripes = ((len - 1) - BLOCK_LEN * blocks) / 64;
+            for stripe in 0..stripes {
+                accumulate(
+                    &mut acc,
+                    &data[blocks * BLOCK_LEN + stripe * 64..],
+                    stripe * 8,
+                );
+            }
+            accumulate(&mut acc, &data[len - 64..], 192 - 64 - 7);
+            let mut result = (len as u64).wrapping_mul(P64_1);
+            for i in 0..4 {
+                result = result.wrapping_add(fold(
+                    acc[2 * i] ^ r64(&SECRET, 11 + 16 * i),
+                    acc[2 * i + 1] ^ r64(&SECRET, 11 + 16 * i + 8),
+                ));
+            }
+            avalanche(result)
+        }
+    }
+}
+
+/// Layout of a block header, as `(name, offset, length)` for each field, in
+/// bytes.  `count` is the number of values or entries in the block.
+const HEADER_FIELDS: [(&str, usize, usize); 6] = [
+    ("magic", 0, 4),
+    ("size", 4, 4),
+    ("checksum", 8, 8),
+    ("epoch", 16, 8),
+    ("count", 24, 4),
+    ("reserved", 28, 4),
+];
+const HEADER_SIZE: usize = 32;
+const CHECKSUM_FIELD: std::ops::Range<usize> = 8..16;
+
+/// Magic number and epoch of the test block.
+const MAGIC: u32 = 0x6b6c_6244;
+const EPOCH: u64 = 0x0123_4567_89ab_cdef;
+
+/// Ways for a reader to verify a block that it's read.  Every scheme checks
+/// that the magic number and the epoch are the expected ones.
+#[derive(Clone, Copy, Debug)]
+enum Scheme {
+    /// The checksum covers only the payload, and the reader trusts the size
+    /// in the header.
+    Payload,
+
+    /// The checksum covers the whole block except the checksum field, and
+    /// the reader checks that the size in the header is a power-of-2
+    /// multiple of 4 kB before reading that much.
+    Block,
+
+    /// Like [Scheme::Block], but the reader also checks the size in the
+    /// header against the size in the pointer that led to the block.
+    BlockPointer,
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Scheme::Payload => "payload",
+            Scheme::Block => "block",
+            Scheme::BlockPointer => "block+ptr",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// What a reader does with a corrupted block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Outcome {
+    /// Rejected the block without reading past its end.
+    Rejected,
+
+    /// Rejected the block, but only after reading past its end, because it
+    /// trusted a corrupted size.
+    Overread,
+
+    /// Accepted the block.
+    Missed,
+}
+
+impl Scheme {
+    fn covered(&self, block: &[u8]) -> Vec<u8> {
+        match self {
+            Scheme::Payload => block[HEADER_SIZE..].to_vec(),
+            Scheme::Block | Scheme::BlockPointer => {
+                let mut covered = block.to_vec();
+                covered[CHECKSUM_FIELD].fill(0);
+                covered
+            }
+        }
+    }
+
+    /// Returns a block of `block_size` bytes with its checksum filled in for
+    /// this scheme.
+    fn block(&self, block_size: usize, algorithm: Algorithm) -> Vec<u8> {
+        let mut block: Vec<u8> = (0..block_size as u64)
+            .map(|i| (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8)
+            .collect();
+        block[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        block[4..8].copy_from_slice(&(block_size as u32).to_le_bytes());
+        block[16..24].copy_from_slice(&EPOCH.to_le_bytes());
+        block[24..28].copy_from_slice(&((block_size - HEADER_SIZE) as u32 / 16).to_le_bytes());
+        block[28..32].fill(0);
+        let checksum = algorithm.checksum(&self.covered(&block));
+        block[CHECKSUM_FIELD].copy_from_slice(&checksum.to_le_bytes());
+        block
+    }
+
+    /// Verifies the block at the start of `file`, which a pointer says is
+    /// `expected_size` bytes long.
+    fn verify(&self, file: &[u8], expected_size: usize, algorithm: Algorithm) -> Outcome {
+        let field = |offset: usize, len: usize| {
+            let mut bytes = [0; 8];
+            bytes[..len].copy_from_slice(&file[offset..offset + len]);
+            u64::from_le_bytes(bytes)
+        };
+        let size = field(4, 4) as usize;
+        if field(0, 4) != MAGIC as u64 || field(16, 8) != EPOCH || size < HEADER_SIZE {
+            return Outcome::Rejected;
+        }
+        match self {
+            Scheme::Payload => (),
+            Scheme::Block => {
+                if !size.is_multiple_of(4096) || !(size / 4096).is_power_of_two() {
+                    return Outcome::Rejected;
+                }
+            }
+            Scheme::BlockPointer => {
+                if size != expected_size {
+                    return Outcome::Rejected;
+                }
+            }
+        }
+        let rejected = match size > expected_size {
+            true => Outcome::Overread,
+            false => Outcome::Rejected,
+        };
+        let Some(block) = file.get(..size) else {
+            return rejected;
+        };
+        if algorithm.checksum(&self.covered(block)) != field(8, 8) {
+            return rejected;
+        }
+        Outcome::Missed
+    }
+}
+
+/// Returns the sets of bits to flip within `bytes` bytes starting at byte
+/// `offset`: every `stride`th bit on its own and together with the next
+/// bit.  Flipping a pair of adjacent bits can move the single bit set in a
+/// power-of-2 size to its neighbor's position, which a single flip can't.
+fn flips(offset: usize, bytes: usize, stride: usize) -> Vec<Vec<usize>> {
+    let first = offset * 8;
+    let last = (offset + bytes) * 8;
+    let mut flips = Vec::new();
+    for bit in (first..last).step_by(stride) {
+        flips.push(vec![bit]);
+        if bit + 1 < last {
+            flips.push(vec![bit, bit + 1]);
+        }
+    }
+    flips
+}
+
+/// Prints how each verification scheme handles corruptions of each header
+/// field and of the payload, in a block of `block_size` bytes followed by
+/// another block of the same size.
+fn corruption(block_size: usize, algorithm: Algorithm) {
+    println!(
+        "Corruption of {} blocks with {algorithm}, flipping single bits and adjacent pairs:",
+        HumanBytes(block_size as u64)
+    );
+    print!(
+        r#"
+   Field     Scheme  Flips  Rejected  Overread  Missed
+--------  ---------  -----  --------  --------  ------
+"#
+    );
+    let payload = ("payload", HEADER_SIZE, block_size - HEADER_SIZE);
+    for (name, offset, len) in HEADER_FIELDS.into_iter().chain([payload]) {
+        let stride = if name == "payload" { 61 } else { 1 };
+        let flips = flips(offset, len, stride);
+        for (i, scheme) in [Scheme::Payload, Scheme::Block, Scheme::BlockPointer]
+            .into_iter()
+            .enumerate()
+        {
+            let mut file = scheme.block(block_size, algorithm);
+            file.extend(scheme.block(block_size, algorithm));
+            let mut counts = [0; 3];
+            for bits in &flips {
+                let mut file = file.clone();
+                for &bit in bits {
+                    file[bit / 8] ^= 1 << (bit % 8);
+                }
+                let outcome = scheme.verify(&file, block_size, algorithm);
+                counts[outcome as usize] += 1;
+            }
+            if i == 0 {
+                print!("{name:>8}");
+            } else {
+                print!("{:8}", "");
+            }
+            println!(
+                "  {scheme:>9}  {:5}  {:8}  {:8}  {:6}",
+                flips.len(),
+                counts[Outcome::Rejected as usize],
+                counts[Outcome::Overread as usize],
+                counts[Outcome::Missed as usize]
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Algorithm; 3] = [Algorithm::Crc32c, Algorithm::XxHash64, Algorithm::Xxh3];
+
+    /// Returns the outcome of each flip of a bit or a pair of adjacent bits in
+    /// field `(offset, len)` of a block of `block_size` bytes, followed by
+    /// another block, under `scheme`.
+    fn outcomes(
+        scheme: Scheme,
+        algorithm: Algorithm,
+        block_size: usize,
+        (offset, len): (usize, usize),
+    ) -> Vec<Outcome> {
+        let mut file = scheme.block(block_size, algorithm);
+        file.extend(scheme.block(block_size, algorithm));
+        flips(offset, len, 1)
+            .into_iter()
+            .map(|bits| {
+                let mut file = file.clone();
+                for bit in bits {
+                    file[bit / 8] ^= 1 << (bit % 8);
+                }
+                scheme.verify(&file, block_size, algorithm)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn intact_blocks_verify() {
+        for algorithm in ALGORITHMS {
+            for scheme in [Scheme::Payload, Scheme::Block, Scheme::BlockPointer] {
+                let block = scheme.block(8192, algorithm);
+                assert_eq!(scheme.verify(&block, 8192, algorithm), Outcome::Missed);
+            }
+        }
+    }
+
+    #[test]
+    fn block_pointer_rejects_every_header_flip() {
+        for algorithm in ALGORITHMS {
+            for (name, offset, len) in HEADER_FIELDS {
+                let outcomes = outcomes(Scheme::BlockPointer, algorithm, 8192, (offset, len));
+                assert!(
+                    outcomes.iter().all(|&outcome| outcome == Outcome::Rejected),
+                    "{algorithm} {name}: {outcomes:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn block_never_misses_but_overreads_on_size() {
+        for algorithm in ALGORITHMS {
+            for (name, offset, len) in HEADER_FIELDS {
+                let outcomes = outcomes(Scheme::Block, algorithm, 8192, (offset, len));
+                assert!(!outcomes.contains(&Outcome::Missed), "{algorithm} {name}");
+                let overread = outcomes.contains(&Outcome::Overread);
+                assert_eq!(overread, name == "size", "{algorithm} {name}");
+            }
+        }
+    }
+
+    #[test]
+    fn payload_misses_uncovered_header_fields() {
+        for (name, offset, len) in HEADER_FIELDS {
+            let outcomes = outcomes(Scheme::Payload, Algorithm::Crc32c, 8192, (offset, len));
+            let missed = outcomes.contains(&Outcome::Missed);
+            assert_eq!(missed, ["count", "reserved"].contains(&name), "{name}");
+        }
+    }
+
+    #[test]
+    fn payload_flips_are_rejected() {
+        for algorithm in ALGORITHMS {
+            for scheme in [Scheme::Payload, Scheme::Block, Scheme::BlockPointer] {
+                let outcomes = outcomes(scheme, algorithm, 4096, (HEADER_SIZE, 256));
+                assert!(
+                    outcomes.iter().all(|&outcome| outcome == Outcome::Rejected),
+                    "{algorithm} {scheme}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c_table(b"123456789"), 0xe306_9283);
+        assert_eq!(xxhash64(b"abc", 0), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(xxh3_64(b"abc"), 0x78af_5f94_892f_3950);
+    }
+
+    /// Hashes of the first `len` bytes of `0x03, 0x0a, 0x11, ...` (byte `i`
+    /// is `i * 7 + 3`), from the reference implementations: XXH3-64, xxHash64
+    /// with seeds 0 and `0x9e3779b97f4a7c15`, and CRC-32C.  The lengths cross
+    /// each of XXH3's code paths (0, 1 to 3, 4 to 8, 9 to 16, 17 to 128, 129
+    /// to 240, and longer inputs with partial stripes and blocks), xxHash64's
+    /// 32-byte stripes and 8-, 4-, and 1-byte tails, and the 8-byte words of
+    /// the SSE 4.2 CRC-32C.
+    #[rustfmt::skip]
+    const VECTORS: [(usize, u64, u64, u64, u32); 26] = [
+        (0, 0x2d06800538d394c2, 0xef46db3751d8e999, 0xc4349fc93c010000, 0x00000000),
+        (1, 0x13e608bc156defed, 0x1f25c8d0bc1f4bb6, 0x79826bcd749d267a, 0x412da0a5),
+        (2, 0x1c9074b93943b86c, 0xf5bedec232706303, 0x416ec13888be56bd, 0xae64f773),
+        (3, 0xa9088dda485b481c, 0x31d2363f52e564c9, 0x78efd77575e26575, 0xd22ed433),
+        (4, 0x6d9253b16c8b1ed3, 0x9bb64b7d66ee9fda, 0x6f0a6c97d68bf353, 0xeb9b5860),
+        (8, 0x60539db630471163, 0xdab99d95c6f90092, 0xa2f1e28437a78a1b, 0xd225c0e8),
+        (9, 0xfeff668361d723a8, 0x170bb6bf975b4c02, 0x9c9a3cd83532d84f, 0x922c64ce),
+        (16, 0xb8c859b0f030b585, 0x434850232b787be2, 0x93351859a7286376, 0x6b24bde1),
+        (17, 0x714a04408e79b80f, 0x1efa7025f1b97a7a, 0x81d900d244223adc, 0x2185fb0c),
+        (31, 0xed41ac283d0674b3, 0xa2aa5f33cc4a6119, 0x755437271d1d0a84, 0x5e441712),
+        (32, 0x19ff4ee1d6ba1a55, 0x23c3c17ef790fd97, 0xbf624b932c090428, 0x3dd68ea5),
+        (64, 0x287eb1fa9e4be2c1, 0x0eb64b3ef6eeb01f, 0x4af341f14e3a6fc9, 0x2884f9f3),
+        (100, 0xb5937857f0d78c9f, 0xa61f8d4c170fe531, 0xf6d8f65c625abb4f, 0x594b1b65),
+        (128, 0x67425a03650261bf, 0x46fbcfbf0150793f, 0x60d4184dd722fddc, 0x2cd1d07e),
+        (129, 0xc664bf3311c6abc4, 0x3eb5d118151c8303, 0xb28e96b042575d9d, 0x1e17a127),
+        (200, 0x746cd0025327bf5b, 0xa6cb3c09bc829b24, 0x17e5f0aa6728f859, 0x0afeac95),
+        (240, 0x64556dc6b462a6cf, 0x42562f61ef11b5ae, 0x1bc0b11916e898f8, 0xbf0d176b),
+        (241, 0x8beadd3a8874fe17, 0x07cf94f8eba111b5, 0xe5211a936c86ded3, 0x2b7569fc),
+        (255, 0xb67b6637a76e6c39, 0x39ae55a29989206f, 0x8310ff6a20cadfad, 0x92bb6a3d),
+        (256, 0x3c38817f6d79c0da, 0x00cfc5207dd8e201, 0x05da853cbd232c06, 0x63094dfc),
+        (1024, 0x9b81661c641c72b1, 0xe6816a6e134b7a33, 0x864260e1cbe4d4b4, 0x29022ef0),
+        (1025, 0x806c2072ed713576, 0x6385e21250a735ca, 0xc19a065dc06169ed, 0xb26acdfe),
+        (2000, 0x71f8268c6d856c4a, 0x5e2ca5035bf96d5d, 0x5ede538668023cd6, 0xa9322617),
+        (3000, 0xc89178bb873c6b3d, 0x5f58b024b3628a2c, 0x0d13baa3b7d747d0, 0x01359494),
+        (4096, 0xd7428746842be37e, 0x796398cd432797cc, 0xc12f98913fe0f03d, 0xed96b643),
+        (8192, 0x4bdd498cfc5719ba, 0x0e3c9ae918375a86, 0x7b73d32ad78c1eb9, 0x70949443),
+    ];
+
+    #[test]
+    fn known_answers_by_length() {
+        let data: Vec<u8> = (0..8192u32).map(|i| (i * 7 + 3) as u8).collect();
+        for (len, xxh3, xxh64, xxh64_seeded, crc) in VECTORS {
+            let data = &data[..len];
+            assert_eq!(xxh3_64(data), xxh3, "XXH3-64 of {len} bytes");
+            assert_eq!(xxhash64(data, 0), xxh64, "xxHash64 of {len} bytes");
+            assert_eq!(
+                xxhash64(data, 0x9e37_79b9_7f4a_7c15),
+                xxh64_seeded,
+                "seeded xxHash64 of {len} bytes"
+            );
+            assert_eq!(crc32c(data), crc, "CRC-32C of {len} bytes");
+            assert_eq!(crc32c_table(data), crc, "table CRC-32C of {len} bytes");
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! Multi-column layout with a column-chunk directory.
+//!
+//! Each column of an `n`-column file has its own data blocks and indexes,
+//! written as the rows arrive, so if every column appended its blocks to the
+//! file as it filled them, a column's blocks would be scattered among the
+//! other columns', and a scan of one column would read it a block at a time.
+//! Instead, each column's writer collects its blocks in a chunk buffer of a
+//! fixed size and appends the whole chunk when the buffer is full, and at the
+//! end of the column, so each column's region is a list of contiguous chunks.
+//! The columns' writers are independent, so they can run on a thread each,
+//! with a single appender that gives each finished chunk the next offset in
+//! the file in the order they arrive.  The writer needs one chunk buffer per
+//! column, but no more, so memory stays `O(1)` in the size of the file.
+//!
+//! The directory, a block with magic `LFcd` that the trailer points to, maps
+//! each column to its region and its index roots.  For each column, it holds
+//! the value root's offset (8 bytes) and size (4 bytes), the row root's
+//! offset (8 bytes) and size (4 bytes), the number of rows (8 bytes), and the
+//! number of chunks (4 bytes), then each chunk's offset (8 bytes), size (4
+//! bytes), and first row (8 bytes), in row order.
+//!
+//! Every row of column `i` holds the end of its row group in column `i + 1`,
+//! which is the start of the next row's, so a reader that has found row `j`
+//! of column `i` reads the rows between the end of row `j - 1`'s group and
+//! the end of row `j`'s through column `i + 1`'s row index.  A scan of one
+//! column reads its chunks from the directory instead of going through its
+//! row index, one read per chunk.
+//!
+//! This generates a 3-column file with row groups of random sizes, writes it
+//! with each chunk size, both from a single thread in row order and from a
+//! thread per column, encodes the directory and reads it back through its
+//! checksum, and checks that the chunks tile the file without overlapping,
+//! that a scan of each column through the directory returns all of its rows
+//! in order, and that navigating from random column 1 rows through the row
+//! indexes finds exactly their row groups in columns 2 and 3.  It compares
+//! the reads that a scan of column 2 takes, and the blocks that a navigation
+//! reads, against the writer's memory and the directory's size.  It models
+//! only the row indexes, so the value roots are empty.
+
+use clap::Args as ClapArgs;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::batches::padded;
+use crate::checksum::Algorithm;
+use crate::rng;
+use crate::superblock::{check_block, new_block, put, seal};
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct ColumnsArgs {
+    /// Size of each column's chunk buffer, in bytes.  A chunk of a single
+    /// block interleaves the columns block by block.
+    #[clap(long = "chunk-size", default_values_t = vec![8192, 65536, 1 << 20, 8 << 20])]
+    chunk_sizes: Vec<u64>,
+
+    /// Size of each data and index block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Size of a row in each column, in bytes, including its row group end.
+    #[clap(long = "row-size", default_values_t = vec![48, 64, 32])]
+    row_sizes: Vec<u64>,
+
+    /// Largest row group in columns 2 and later, in rows.  Row groups have
+    /// from 1 to this many rows.
+    #[clap(long, default_value_t = 8)]
+    max_group: u64,
+
+    /// Number of rows in column 1.
+    #[clap(long, default_value_t = 200_000)]
+    rows: u64,
+
+    /// Number of column 1 rows to navigate from.
+    #[clap(long, default_value_t = 10_000)]
+    lookups: u64,
+}
+
+const DIRECTORY_MAGIC: u32 = u32::from_le_bytes(*b"LFcd");
+
+/// Size of a row index entry: a row number and a child pointer.
+const ENTRY_SIZE: u64 = 12;
+
+/// Sizes of the common block header, a column's directory entry, and a chunk.
+const HEADER_SIZE: usize = 32;
+const COLUMN_SIZE: usize = 36;
+const CHUNK_SIZE: usize = 20;
+
+/// A block of a column, as what the file holds at its offset: a data block's
+/// first row, or a row index block's level and position in it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Block {
+    Data(u64),
+    Index(usize, usize),
+}
+
+/// A chunk that a column's writer has finished, before it has an offset.
+struct Chunk {
+    column: usize,
+    first_row: u64,
+    blocks: Vec<Block>,
+}
+
+/// Location of one block, as its offset and size.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Root {
+    offset: u64,
+    size: u32,
+}
+
+/// One column's entry in the directory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Region {
+    value_root: Root,
+    row_root: Root,
+    rows: u64,
+
+    /// Offset, size, and first row of each chunk, in row order.
+    chunks: Vec<(u64, u32, u64)>,
+}
+
+/// Writes one column's blocks into chunks.
+struct ColumnWriter {
+    column: usize,
+    rows_per_block: u64,
+    entries_per_block: usize,
+    blocks_per_chunk: usize,
+    rows: u64,
+    data_blocks: usize,
+    chunk: Chunk,
+}
+
+impl ColumnWriter {
+    fn new(column: usize, row_size: u64, block_size: u64, chunk_size: u64) -> Self {
+        Self {
+            column,
+            rows_per_block: (block_size / row_size).max(1),
+            entries_per_block: (block_size / ENTRY_SIZE) as usize,
+            blocks_per_chunk: (chunk_size / block_size).max(1) as usize,
+            rows: 0,
+            data_blocks: 0,
+            chunk: Chunk {
+                column,
+                first_row: 0,
+                blocks: Vec::new(),
+            },
+        }
+    }
+
+    /// Adds `block` to the chunk and returns the chunk if that fills it.
+    fn push(&mut self, block: Block) -> Option<Chunk> {
+        self.chunk.blocks.push(block);
+        (self.chunk.blocks.len() == self.blocks_per_chunk).then(|| self.take())
+    }
+
+    fn take(&mut self) -> Chunk {
+        let next = Chunk {
+            column: self.column,
+            first_row: self.rows,
+            blocks: Vec::new(),
+        };
+        std::mem::replace(&mut self.chunk, next)
+    }
+
+    /// Adds a row and returns a chunk if it filled one.
+    fn row(&mut self) -> Option<Chunk> {
+        self.rows += 1;
+        if !self.rows.is_multiple_of(self.rows_per_block) {
+            return None;
+        }
+        self.data_blocks += 1;
+        self.push(Block::Data(self.rows - self.rows_per_block))
+    }
+
+    /// Writes the last data block and the row index, and returns the chunks
+    /// that they fill, ending with the last one.
+    fn finish(mut self) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        if !self.rows.is_multiple_of(self.rows_per_block) {
+            let first = self.rows - self.rows % self.rows_per_block;
+            self.data_blocks += 1;
+            chunks.extend(self.push(Block::Data(first)));
+        }
+        let mut entries = self.data_blocks;
+        let mut level = 0;
+        loop {
+            entries = entries.div_ceil(self.entries_per_block);
+            for i in 0..entries {
+                chunks.extend(self.push(Block::Index(level, i)));
+            }
+            if entries <= 1 {
+                break;
+            }
+            level += 1;
+        }
+        if !self.chunk.blocks.is_empty() {
+            chunks.push(self.take());
+        }
+        chunks
+    }
+}
+
+/// A written file, as what each of its blocks holds, the directory, and each
+/// column's row index, as the offset of each data block.
+struct File {
+    blocks: Vec<(usize, Block)>,
+    directory: Vec<Region>,
+    data_offsets: Vec<Vec<u64>>,
+}
+
+impl File {
+    /// Appends chunks to an empty file as `chunks` produces them, then
+    /// returns the file.
+    fn assemble(n_columns: usize, block_size: u64, chunks: impl Iterator<Item = Chunk>) -> Self {
+        let mut file = Self {
+            blocks: Vec::new(),
+            directory: vec![Region::default(); n_columns],
+            data_offsets: vec![Vec::new(); n_columns],
+        };
+        for chunk in chunks {
+            let offset = block_size * (1 + file.blocks.len() as u64);
+            let region = &mut file.directory[chunk.column];
+            region.chunks.push((
+                offset,
+                (chunk.blocks.len() as u64 * block_size) as u32,
+                chunk.first_row,
+            ));
+            for (i, &block) in chunk.blocks.iter().enumerate() {
+                let block_offset = offset + i as u64 * block_size;
+                match block {
+                    Block::Data(_) => file.data_offsets[chunk.column].push(block_offset),
+                    Block::Index(..) => {
+                        region.row_root = Root {
+                            offset: block_offset,
+                            size: block_size as u32,
+                        }
+                    }
+                }
+                file.blocks.push((chunk.column, block));
+            }
+        }
+        file
+    }
+
+    /// Returns what the file holds at `offset`.
+    fn read(&self, offset: u64, block_size: u64) -> Option<(usize, Block)> {
+        self.blocks
+            .get((offset / block_size).checked_sub(1)? as usize)
+            .copied()
+    }
+}
+
+/// Encodes `directory` as a directory block.
+fn encode(directory: &[Region]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for region in directory {
+        for root in [region.value_root, region.row_root] {
+            body.extend(root.offset.to_le_bytes());
+            body.extend(root.size.to_le_bytes());
+        }
+        body.extend(region.rows.to_le_bytes());
+        body.extend((region.chunks.len() as u32).to_le_bytes());
+    }
+    for region in directory {
+        for &(offset, size, first_row) in &region.chunks {
+            body.extend(offset.to_le_bytes());
+            body.extend(size.to_le_bytes());
+            body.extend(first_row.to_le_bytes());
+        }
+    }
+    let size = padded((HEADER_SIZE + body.len()) as u64) as usize;
+    let mut block = new_block(DIRECTORY_MAGIC, 0, directory.len() as u32);
+    block.resize(size, 0);
+    put(&mut block, 4, &(size as u32).to_le_bytes());
+    put(&mut block, HEADER_SIZE, &body);
+    seal(&mut block, Algorithm::XxHash64);
+    block
+}
+
+/// Checks and decodes a directory block.
+fn decode(block: &[u8]) -> Result<Vec<Region>, String> {
+    let get = |offset: usize, len: usize| {
+        let mut bytes = [0; 8];
+        bytes[..len].copy_from_slice(
+            block
+                .get(offset..offset + len)
+                .ok_or("truncated directory")?,
+        );
+        Ok::<u64, String>(u64::from_le_bytes(bytes))
+    };
+    if block.len() < HEADER_SIZE {
+        return Err("truncated directory".into());
+    }
+    check_block(block, DIRECTORY_MAGIC, Algorithm::XxHash64)
+        .map_err(|e| format!("directory block: {e}"))?;
+    let n_columns = get(24, 4)? as usize;
+    let mut chunk = HEADER_SIZE + n_columns * COLUMN_SIZE;
+    let mut directory = Vec::with_capacity(n_columns);
+    for i in 0..n_columns {
+        let at = HEADER_SIZE + i * COLUMN_SIZE;
+        let root = |at| {
+            Ok::<Root, String>(Root {
+                offset: get(at, 8)?,
+                size: get(at + 8, 4)? as u32,
+            })
+        };
+        let mut region = Region {
+            value_root: root(at)?,
+            row_root: root(at + 12)?,
+            rows: get(at + 24, 8)?,
+            chunks: Vec::new(),
+        };
+        for _ in 0..get(at + 32, 4)? {
+            region.chunks.push((
+                get(chunk, 8)?,
+                get(chunk + 8, 4)? as u32,
+                get(chunk + 12, 8)?,
+            ));
+            chunk += CHUNK_SIZE;
+        }
+        directory.push(region);
+    }
+    Ok(directory)
+}
+
+/// Prints the chunks, directory size, writer memory, and reads of a file
+/// written with each chunk size in `args`, by one thread and by a thread per
+/// column.  Returns the number of failed checks.
+pub fn columns(args: &ColumnsArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = args.block_size.max(4096);
+    let row_sizes: Vec<u64> = match args.row_sizes.is_empty() {
+        true => vec![64, 64],
+        false => args
+            .row_sizes
+            .iter()
+            .map(|&size| size.clamp(1, block_size))
+            .collect(),
+    };
+    let n_columns = row_sizes.len();
+    let rows = args.rows.max(1);
+    let max_group = args.max_group.max(1);
+    println!(
+        "{n_columns}-column file with {} rows in column 1, row groups of 1 to {max_group} rows, {} blocks:",
+        HumanCount(rows),
+        HumanBytes(block_size),
+    );
+    print!(
+        r#"
+                                       Writer  --Column 2 Scan---  Blocks/
+   Chunk    Writer  Chunks  Directory  Memory   Reads  Bytes/Read      Nav  Failures
+--------  --------  ------  ---------  ------  ------  ----------  -------  --------
+"#
+    );
+
+    // The end of each row's group in the next column.
+    let mut random = rng::random(rows);
+    let mut ends: Vec<Vec<u64>> = Vec::new();
+    let mut n_rows = vec![rows];
+    for column in 0..n_columns - 1 {
+        let mut end = 0;
+        let column_ends: Vec<u64> = (0..n_rows[column])
+            .map(|_| {
+                end += 1 + random() % max_group;
+                end
+            })
+            .collect();
+        n_rows.push(end);
+        ends.push(column_ends);
+    }
+    let starts = |column: usize, row: u64| match row {
+        0 => 0,
+        _ => ends[column][row as usize - 1],
+    };
+    let lookups: Vec<u64> = (0..args.lookups).map(|_| random() % rows).collect();
+
+    for &chunk_size in &args.chunk_sizes {
+        let chunk_size = chunk_size.max(block_size);
+        let writers = || -> Vec<ColumnWriter> {
+            (0..n_columns)
+                .map(|column| ColumnWriter::new(column, row_sizes[column], block_size, chunk_size))
+                .collect()
+        };
+        for parallel in [false, true] {
+            let file = match parallel {
+                // Rows arrive in order, each one followed by its row group in
+                // the next column, recursively.
+                false => {
+                    let mut writers = writers();
+                    let mut chunks = Vec::new();
+                    let mut stack = vec![(0, 0, rows)];
+                    while let Some((column, row, end)) = stack.pop() {
+                        if row == end {
+                            continue;
+                        }
+                        stack.push((column, row + 1, end));
+                        chunks.extend(writers[column].row());
+                        if column + 1 < n_columns {
+                            stack.push((
+                                column + 1,
+                                starts(column, row),
+                                ends[column][row as usize],
+                            ));
+                        }
+                    }
+                    for writer in writers {
+                        chunks.extend(writer.finish());
+                    }
+                    File::assemble(n_columns, block_size, chunks.into_iter())
+                }
+
+                // Each column's writer gets its rows on its own thread.
+                true => {
+                    let (sender, receiver) = mpsc::channel();
+                    thread::scope(|scope| {
+                        for (column, mut writer) in writers().into_iter().enumerate() {
+                            let sender = sender.clone();
+                            let n = n_rows[column];
+                            scope.spawn(move || {
+                                for _ in 0..n {
+                                    if let Some(chunk) = writer.row() {
+                                        sender.send(chunk).unwrap();
+                                    }
+                                }
+                                for chunk in writer.finish() {
+                                    sender.send(chunk).unwrap();
+                                }
+                            });
+                        }
+                        drop(sender);
+                        File::assemble(n_columns, block_size, receiver.into_iter())
+                    })
+                }
+            };
+            let mut directory = file.directory.clone();
+            for (column, region) in directory.iter_mut().enumerate() {
+                region.rows = n_rows[column];
+            }
+
+            // The directory reads back, and its chunks tile the file between
+            // the header and the directory.
+            let block = encode(&directory);
+            let mut failures = (decode(&block).as_ref() != Ok(&directory)) as u64;
+            let mut tiles: Vec<(u64, u32)> = directory
+                .iter()
+                .flat_map(|region| {
+                    region
+                        .chunks
+                        .iter()
+                        .map(|&(offset, size, _)| (offset, size))
+                })
+                .collect();
+            tiles.sort_unstable();
+            let mut next = block_size;
+            for (offset, size) in tiles {
+                failures += (offset != next) as u64;
+                next = offset + size as u64;
+            }
+            failures += (next != block_size * (1 + file.blocks.len() as u64)) as u64;
+
+            // A scan of each column through the directory reads its chunks in
+            // row order and finds all of its data blocks, and nothing else.
+            for (column, region) in directory.iter().enumerate() {
+                let mut first_rows = Vec::new();
+                let mut chunk_rows = 0;
+                for &(offset, size, first_row) in &region.chunks {
+                    failures += (first_row != chunk_rows) as u64;
+                    for block in (offset..offset + size as u64).step_by(block_size as usize) {
+                        match file.read(block, block_size) {
+                            Some((c, Block::Data(first))) if c == column => {
+                                first_rows.push(first);
+                                let per_block = (block_size / row_sizes[column]).max(1);
+                                chunk_rows = (first + per_block).min(n_rows[column]);
+                            }
+                            Some((c, Block::Index(..))) if c == column => (),
+                            _ => failures += 1,
+                        }
+                    }
+                }
+                let per_block = (block_size / row_sizes[column]).max(1);
+                let expected: Vec<u64> = (0..n_rows[column].div_ceil(per_block))
+                    .map(|i| i * per_block)
+                    .collect();
+                failures += (first_rows != expected) as u64;
+                failures += (file
+                    .read(region.row_root.offset, block_size)
+                    .map(|(c, _)| c)
+                    != Some(column)) as u64;
+            }
+
+            // Navigating from a column 1 row reads its data block and the
+            // row index and data blocks of its row group in each later
+            // column, and finds exactly the group's rows there.
+            let mut nav_blocks = 0;
+            for &row in &lookups {
+                let (mut start, mut end) = (row, row + 1);
+                for column in 0..n_columns {
+                    let per_block = (block_size / row_sizes[column]).max(1);
+                    let entries = block_size / ENTRY_SIZE;
+                    let (first, last) = (start / per_block, (end - 1) / per_block);
+                    nav_blocks += last - first + 1 + last / entries - first / entries + 1;
+                    let mut found = Vec::new();
+                    for i in first..=last {
+                        match file.read(file.data_offsets[column][i as usize], block_size) {
+                            Some((c, Block::Data(first_row))) if c == column => {
+                                let block_end = (first_row + per_block).min(n_rows[column]);
+                                found.extend(
+                                    (first_row..block_end).filter(|r| (start..end).contains(r)),
+                                );
+                            }
+                            _ => failures += 1,
+                        }
+                    }
+                    failures += (found != (start..end).collect::<Vec<_>>()) as u64;
+                    if column + 1 < n_columns {
+                        (start, end) = (starts(column, start), ends[column][end as usize - 1]);
+                    }
+                }
+            }
+
+            let scan = &directory[1.min(n_columns - 1)];
+            let scan_bytes: u64 = scan.chunks.iter().map(|&(_, size, _)| size as u64).sum();
+            println!(
+                "{:>8}  {:>8}  {:>6}  {:>9}  {:>6}  {:>6}  {:>10}  {:7.2}  {failures:8}",
+                HumanBytes(chunk_size),
+                match parallel {
+                    true => "parallel",
+                    false => "serial",
+                },
+                HumanCount(
+                    directory
+                        .iter()
+                        .map(|region| region.chunks.len() as u64)
+                        .sum()
+                ),
+                HumanBytes(block.len() as u64),
+                HumanBytes(chunk_size * n_columns as u64),
+                HumanCount(scan.chunks.len() as u64),
+                HumanBytes(scan_bytes / scan.chunks.len().max(1) as u64),
+                nav_blocks as f64 / lookups.len().max(1) as f64,
+            );
+            total_failures += failures;
+        }
+    }
+    total_failures
+}
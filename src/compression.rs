@@ -0,0 +1,281 @@
+//! Compression of data blocks, and a benchmark of it.
+//!
+//! [compress] and [decompress] are the codecs for the compressed data blocks
+//! of layer files (see `layer.rs`), which the schema block records for each
+//! column along with the level.  zstd produces a frame and LZ4 a raw block,
+//! which doesn't record its sizes, so a data block records both sizes for
+//! either codec.  The `compression` subcommand measures the ratio and the
+//! speed of each codec on a synthetic table of IDs, names, regions, amounts,
+//! and timestamps, cut into blocks of whole records, which is where the
+//! figures in format.md come from.  Build with `--release` to reproduce them.
+
+use clap::Args as ClapArgs;
+use lz4::block::CompressionMode;
+use std::cell::RefCell;
+use std::hint::black_box;
+use std::time::Instant;
+use zstd::bulk::{Compressor, Decompressor};
+
+use crate::rng;
+use crate::superblock::Compression;
+use crate::HumanBytes;
+
+#[derive(ClapArgs, Debug)]
+pub struct CompressionArgs {
+    /// Block size to benchmark, in bytes.
+    #[clap(long = "block-size", default_values_t = vec![8192, 65536])]
+    block_sizes: Vec<u64>,
+
+    /// Codec to benchmark: `none`, or `zstd` or `lz4` with an optional level,
+    /// e.g. `zstd:3`.
+    #[clap(long = "codec", value_parser = parse_codec, default_values = ["zstd:1", "zstd:3", "lz4"])]
+    codecs: Vec<Compression>,
+
+    /// Number of bytes of table for each measurement.
+    #[clap(long, default_value_t = 1 << 26)]
+    bytes: u64,
+}
+
+/// Parses a codec and its level, as in `zstd:3`.
+fn parse_codec(s: &str) -> Result<Compression, String> {
+    let (name, level) = match s.split_once(':') {
+        Some((name, level)) => {
+            let level = level
+                .parse()
+                .map_err(|_| format!("{level}: bad compression level"))?;
+            (name, Some(level))
+        }
+        None => (s, None),
+    };
+    match (name, level) {
+        ("none", None) => Ok(Compression::None),
+        ("zstd", None) => Ok(Compression::Zstd(3)),
+        ("zstd", Some(level @ -7..=22)) => Ok(Compression::Zstd(level)),
+        ("zstd", Some(level)) => Err(format!("zstd level {level} not in -7 through 22")),
+        ("lz4", None) => Ok(Compression::Lz4(0)),
+        ("lz4", Some(level @ 0..=12)) => Ok(Compression::Lz4(level)),
+        ("lz4", Some(level)) => Err(format!("LZ4 level {level} not in 0 through 12")),
+        _ => Err(format!("{s}: unknown codec")),
+    }
+}
+
+/// Returns the name of `compression`, in the syntax of [parse_codec].
+fn codec_name(compression: Compression) -> String {
+    match compression {
+        Compression::None => "none".into(),
+        Compression::Zstd(level) => format!("zstd:{level}"),
+        Compression::Lz4(level) => format!("lz4:{level}"),
+    }
+}
+
+thread_local! {
+    /// zstd contexts, kept from one block to the next because creating one
+    /// costs more than compressing or decompressing a small block.
+    static ZSTD: RefCell<(Compressor<'static>, Decompressor<'static>)> = RefCell::new((
+        Compressor::new(0).expect("zstd compression context"),
+        Decompressor::new().expect("zstd decompression context"),
+    ));
+}
+
+/// Compresses `data` with `compression`.
+pub fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>, String> {
+    match compression {
+        Compression::None => Ok(data.into()),
+        Compression::Zstd(level) => ZSTD.with(|zstd| {
+            let compressor = &mut zstd.borrow_mut().0;
+            compressor
+                .set_compression_level(level as i32)
+                .and_then(|_| compressor.compress(data))
+                .map_err(|e| format!("zstd: {e}"))
+        }),
+        Compression::Lz4(level) => {
+            let mode = match level {
+                0 => CompressionMode::DEFAULT,
+                level => CompressionMode::HIGHCOMPRESSION(level as i32),
+            };
+            lz4::block::compress(data, Some(mode), false).map_err(|e| format!("LZ4: {e}"))
+        }
+    }
+}
+
+/// Decompresses `data`, which `compression` compressed from exactly `size`
+/// bytes.
+pub fn decompress(compression: Compression, data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let output = match compression {
+        Compression::None => data.into(),
+        Compression::Zstd(_) => ZSTD.with(|zstd| {
+            zstd.borrow_mut()
+                .1
+                .decompress(data, size)
+                .map_err(|e| format!("zstd: {e}"))
+        })?,
+        // An LZ4 block doesn't record its size, so the framing does.
+        Compression::Lz4(_) => {
+            lz4::block::decompress(data, Some(size as i32)).map_err(|e| format!("LZ4: {e}"))?
+        }
+    };
+    if output.len() != size {
+        return Err(format!(
+            "decompressed to {} bytes instead of {size}",
+            output.len()
+        ));
+    }
+    Ok(output)
+}
+
+/// Returns about `bytes` of a synthetic table, in blocks of up to
+/// `block_size` bytes of whole records.  Each record is an ID, a name, a
+/// region, an amount in cents, and a timestamp in microseconds, with
+/// integers little-endian and strings preceded by their length in a byte.
+fn table_blocks(bytes: u64, block_size: usize) -> Vec<Vec<u8>> {
+    const FIRST: [&str; 12] = [
+        "Alice", "Bob", "Carol", "Dave", "Erin", "Frank", "Grace", "Heidi", "Ivan", "Judy",
+        "Mallory", "Oscar",
+    ];
+    const LAST: [&str; 10] = [
+        "Smith", "Johnson", "Williams", "Brown", "Jones", "Garcia", "Miller", "Davis", "Lopez",
+        "Wilson",
+    ];
+    const REGIONS: [&str; 6] = [
+        "us-east-1",
+        "us-west-2",
+        "eu-west-1",
+        "eu-central-1",
+        "ap-southeast-1",
+        "ap-northeast-1",
+    ];
+
+    let mut random = rng::random(1);
+    let mut blocks = Vec::new();
+    let mut block = Vec::with_capacity(block_size);
+    let (mut id, mut timestamp, mut total) = (1_000_000u64, 1_700_000_000_000_000u64, 0);
+    while total < bytes {
+        id += 1 + random() % 4;
+        timestamp += random() % 5_000_000;
+        let name = format!(
+            "{} {}",
+            FIRST[random() as usize % FIRST.len()],
+            LAST[random() as usize % LAST.len()]
+        );
+        let region = REGIONS[random() as usize % REGIONS.len()];
+        let amount = (random() % 1_000_000) as i64 - 100_000;
+
+        let mut record = Vec::new();
+        record.extend(id.to_le_bytes());
+        for s in [name.as_str(), region] {
+            record.push(s.len() as u8);
+            record.extend(s.as_bytes());
+        }
+        record.extend(amount.to_le_bytes());
+        record.extend(timestamp.to_le_bytes());
+        if block.len() + record.len() > block_size && !block.is_empty() {
+            blocks.push(std::mem::replace(
+                &mut block,
+                Vec::with_capacity(block_size),
+            ));
+        }
+        total += record.len() as u64;
+        block.extend(record);
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Prints the ratio and speed of each codec in `args` at each block size.
+pub fn compression(args: &CompressionArgs) -> Result<(), String> {
+    println!(
+        "Compression of a synthetic table, {} per measurement:",
+        HumanBytes(args.bytes)
+    );
+    print!(
+        r#"
+ Block                    Compress  Decompress
+  Size  Codec     Ratio       MB/s        GB/s  us/Block
+------  -------  ------  ---------  ----------  --------
+"#
+    );
+    for &block_size in &args.block_sizes {
+        let blocks = table_blocks(args.bytes, block_size.max(64) as usize);
+        let total: usize = blocks.iter().map(Vec::len).sum();
+        for (i, &codec) in args.codecs.iter().enumerate() {
+            let start = Instant::now();
+            let compressed = blocks
+                .iter()
+                .map(|block| compress(codec, block))
+                .collect::<Result<Vec<_>, _>>()?;
+            let compress_seconds = start.elapsed().as_secs_f64();
+            let compressed_bytes: usize = compressed.iter().map(Vec::len).sum();
+
+            let start = Instant::now();
+            for (block, data) in blocks.iter().zip(&compressed) {
+                black_box(decompress(codec, black_box(data), block.len())?);
+            }
+            let seconds = start.elapsed().as_secs_f64();
+
+            if i == 0 {
+                print!("{:>6}", HumanBytes(block_size));
+            } else {
+                print!("{:6}", "");
+            }
+            println!(
+                "  {:7}  {:>6}  {:9.0}  {:10.2}  {:8.2}",
+                codec_name(codec),
+                format!("{:.1}:1", total as f64 / compressed_bytes as f64),
+                total as f64 / compress_seconds / 1e6,
+                total as f64 / seconds / 1e9,
+                seconds / blocks.len() as f64 * 1e6
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codecs_round_trip() {
+        let blocks = table_blocks(1 << 16, 8192);
+        assert!(blocks.len() >= 8);
+        assert!(blocks.iter().all(|block| block.len() <= 8192));
+        for codec in ["none", "zstd:-7", "zstd:1", "zstd:19", "lz4", "lz4:9"] {
+            let codec = parse_codec(codec).unwrap();
+            let mut compressed_bytes = 0;
+            for block in &blocks {
+                let compressed = compress(codec, block).unwrap();
+                compressed_bytes += compressed.len();
+                assert_eq!(&decompress(codec, &compressed, block.len()).unwrap(), block);
+                assert!(decompress(codec, &compressed, block.len() - 1).is_err());
+            }
+            if let Compression::Zstd(1..) | Compression::Lz4(_) = codec {
+                assert!(
+                    compressed_bytes * 3 < 2 << 16,
+                    "{codec:?}: {compressed_bytes}"
+                );
+            }
+        }
+
+        for codec in [Compression::Zstd(3), Compression::Lz4(0)] {
+            let compressed = compress(codec, &blocks[0]).unwrap();
+            let truncated = &compressed[..compressed.len() - 4];
+            assert!(decompress(codec, truncated, blocks[0].len()).is_err());
+        }
+    }
+
+    #[test]
+    fn codec_names() {
+        for s in ["none", "zstd:-7", "zstd:3", "zstd:22", "lz4:0", "lz4:12"] {
+            assert_eq!(codec_name(parse_codec(s).unwrap()), s);
+        }
+        assert_eq!(parse_codec("zstd"), Ok(Compression::Zstd(3)));
+        assert_eq!(parse_codec("lz4"), Ok(Compression::Lz4(0)));
+        assert!(parse_codec("zstd:23").is_err());
+        assert!(parse_codec("lz4:13").is_err());
+        assert!(parse_codec("zstd:x").is_err());
+        assert!(parse_codec("none:1").is_err());
+        assert!(parse_codec("brotli").is_err());
+    }
+}
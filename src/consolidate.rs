@@ -0,0 +1,355 @@
+//! Retractions and consolidation of weights across files.
+//!
+//! A layer file holds a Z-set: every row has a signed weight, and a deletion
+//! is just a row with a negative weight, called a retraction, with no separate
+//! tombstone type or flag.  Its weight is stored like any other, sign-extended
+//! in the weight column.  Each file is consolidated, so a (key, value) pair
+//! appears in it at most once and never with weight 0, and every key in
+//! column 1 has at least one row in column 2.  A merge keeps both properties:
+//!
+//! - Rows with equal key and value sum their weights across the inputs, and a
+//!   row whose sum is 0 is dropped.  Sums that overflow a 64-bit weight are an
+//!   error rather than wrapping around.
+//!
+//! - A key is dropped when every one of its rows is, and kept otherwise, even
+//!   if all of its remaining weights are negative.
+//!
+//! Addition is associative and commutative, so merging any subset of a
+//! spine's files, in any grouping, gives the same result as merging them all
+//! at once, restricted to those files.  A retraction whose insertion is in a
+//! file outside the merge stays in the output until a later merge brings the
+//! two together.
+//!
+//! This generates files of random rows in which a given fraction of each
+//! file's rows retract some or all of the weight of rows in earlier files,
+//! merges them all at once, one at a time from either end, and in a
+//! random grouping, and checks that each merge is consolidated and equals the
+//! sum of the inputs' weights.
+
+use clap::Args as ClapArgs;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::rng;
+use crate::HumanCount;
+
+#[derive(ClapArgs, Debug)]
+pub struct ConsolidateArgs {
+    /// Fraction of each file's rows, after the first file's, that retract
+    /// weight from rows in earlier files.
+    #[clap(long = "retractions", default_values_t = vec![0.1, 0.3, 0.5, 0.9])]
+    retraction_fractions: Vec<f64>,
+
+    /// Number of files to merge.
+    #[clap(long, default_value_t = 8)]
+    files: u64,
+
+    /// Number of rows in each file.
+    #[clap(long, default_value_t = 100_000)]
+    rows: u64,
+
+    /// Number of distinct keys, each of which has up to 4 values.
+    #[clap(long, default_value_t = 200_000)]
+    keys: u64,
+}
+
+/// A consolidated batch laid out like a layer file's two columns: each key
+/// with the end of its rows in `rows`, and each row's value and weight.
+#[derive(Debug, Default, PartialEq)]
+struct Batch {
+    keys: Vec<(u64, usize)>,
+    rows: Vec<(u64, i64)>,
+}
+
+impl Batch {
+    /// Returns the batch that holds the nonzero weights in `rows`.
+    fn from_map(rows: &BTreeMap<(u64, u64), i64>) -> Self {
+        let mut batch = Self::default();
+        for (&(key, value), &weight) in rows.iter().filter(|(_, &weight)| weight != 0) {
+            batch.push(key, value, weight);
+        }
+        batch
+    }
+
+    fn push(&mut self, key: u64, value: u64, weight: i64) {
+        if self.keys.last().map(|&(last, _)| last) != Some(key) {
+            self.keys.push((key, self.rows.len()));
+        }
+        self.rows.push((value, weight));
+        self.keys.last_mut().unwrap().1 = self.rows.len();
+    }
+
+    /// Returns the rows of the `i`th key.
+    fn rows(&self, i: usize) -> &[(u64, i64)] {
+        let start = match i {
+            0 => 0,
+            _ => self.keys[i - 1].1,
+        };
+        &self.rows[start..self.keys[i].1]
+    }
+
+    /// Returns whether the batch is consolidated: its keys and each key's
+    /// values strictly increase, no weight is 0, and no key is empty.
+    fn is_consolidated(&self) -> bool {
+        self.keys.windows(2).all(|w| w[0].0 < w[1].0)
+            && (0..self.keys.len()).all(|i| {
+                let rows = self.rows(i);
+                !rows.is_empty()
+                    && rows.windows(2).all(|w| w[0].0 < w[1].0)
+                    && rows.iter().all(|&(_, weight)| weight != 0)
+            })
+            && self.keys.last().map_or(0, |&(_, end)| end) == self.rows.len()
+    }
+}
+
+/// Merges `inputs` into one consolidated batch.
+fn merge(inputs: &[&Batch]) -> Result<Batch, String> {
+    let mut output = Batch::default();
+    let mut cursors = vec![0; inputs.len()];
+    loop {
+        // Find the smallest key among the inputs, and merge the rows of every
+        // input that has it.
+        let Some(key) = (0..inputs.len())
+            .filter_map(|i| inputs[i].keys.get(cursors[i]).map(|&(key, _)| key))
+            .min()
+        else {
+            return Ok(output);
+        };
+        let mut rows: Vec<&[(u64, i64)]> = Vec::new();
+        for (i, input) in inputs.iter().enumerate() {
+            if input.keys.get(cursors[i]).map(|&(k, _)| k) == Some(key) {
+                rows.push(input.rows(cursors[i]));
+                cursors[i] += 1;
+            }
+        }
+        let mut positions = vec![0; rows.len()];
+        while let Some(value) = (0..rows.len())
+            .filter_map(|j| rows[j].get(positions[j]).map(|&(value, _)| value))
+            .min()
+        {
+            let mut weight = 0i64;
+            for (j, rows) in rows.iter().enumerate() {
+                if let Some(&(v, w)) = rows.get(positions[j]).filter(|&&(v, _)| v == value) {
+                    weight = weight
+                        .checked_add(w)
+                        .ok_or_else(|| format!("weight of ({key}, {v}) overflows"))?;
+                    positions[j] += 1;
+                }
+            }
+            if weight != 0 {
+                output.push(key, value, weight);
+            }
+        }
+    }
+}
+
+/// Prints the rows and keys that merges drop, for each fraction of
+/// retractions in `args`.  Returns the number of failed checks.
+pub fn consolidate(args: &ConsolidateArgs) -> u64 {
+    let mut total_failures = 0;
+    let n_files = args.files.max(2) as usize;
+    let rows = args.rows.max(1);
+    let n_keys = args.keys.max(1);
+    println!(
+        "Consolidating {n_files} files of {} rows over {} keys:",
+        HumanCount(rows),
+        HumanCount(n_keys),
+    );
+    print!(
+        r#"
+Retracted    Input   Output   --Dropped--   Partial
+     Rows     Rows     Rows     Rows  Keys  Negative  Failures
+---------  -------  -------  -------  ----  --------  --------
+"#
+    );
+    for &fraction in &args.retraction_fractions {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let mut random = rng::random(rows ^ (fraction * 1000.0) as u64);
+
+        // Each file inserts rows with weights of +1 to +3, and retracts all of
+        // a live row's weight, part of it, or more than all of it.
+        let mut total: BTreeMap<(u64, u64), i64> = BTreeMap::new();
+        let mut inserted = Vec::new();
+        let mut files = Vec::new();
+        for file in 0..n_files {
+            let mut rows_in_file: BTreeMap<(u64, u64), i64> = BTreeMap::new();
+            for _ in 0..rows {
+                let retract = file > 0 && (random() % 1000) as f64 / 1000.0 < fraction;
+                let live = match retract && !inserted.is_empty() {
+                    true => {
+                        let row = inserted[random() as usize % inserted.len()];
+                        Some((row, total[&row])).filter(|&(_, weight)| weight > 0)
+                    }
+                    false => None,
+                };
+                let (row, weight) = match live {
+                    Some((row, weight)) => match random() % 4 {
+                        0 | 1 => (row, -weight),
+                        2 => (row, -((random() % weight as u64) as i64).max(1)),
+                        _ => (row, -(weight + 1)),
+                    },
+                    None => ((random() % n_keys, random() % 4), 1 + (random() % 3) as i64),
+                };
+                if weight > 0 {
+                    inserted.push(row);
+                }
+                *rows_in_file.entry(row).or_default() += weight;
+                *total.entry(row).or_default() += weight;
+            }
+            files.push(Batch::from_map(&rows_in_file));
+        }
+        let expected = Batch::from_map(&total);
+        let inputs: Vec<&Batch> = files.iter().collect();
+
+        let mut failures = 0;
+        let mut check = |batch: &Result<Batch, String>| {
+            failures += match batch {
+                Ok(batch) => (!batch.is_consolidated() || *batch != expected) as u64,
+                Err(_) => 1,
+            };
+        };
+        let all = merge(&inputs);
+        check(&all);
+
+        // One file at a time from the front, as a spine merges new batches
+        // into old ones, and from the back.
+        let mut forward = merge(&inputs[..1]).unwrap();
+        for file in &inputs[1..] {
+            forward = merge(&[&forward, file]).unwrap();
+        }
+        check(&Ok(forward));
+        let mut backward = merge(&inputs[n_files - 1..]).unwrap();
+        for file in inputs[..n_files - 1].iter().rev() {
+            backward = merge(&[file, &backward]).unwrap();
+        }
+        check(&Ok(backward));
+
+        // A random grouping, merging two or three adjacent batches at a time.
+        let mut batches: Vec<Batch> = files.iter().map(|file| merge(&[file]).unwrap()).collect();
+        while batches.len() > 1 {
+            let start = random() as usize % (batches.len() - 1);
+            let end = (start + 2 + random() as usize % 2).min(batches.len());
+            let group: Vec<Batch> = batches.drain(start..end).collect();
+            let group: Vec<&Batch> = group.iter().collect();
+            batches.insert(start, merge(&group).unwrap());
+        }
+        check(&Ok(batches.pop().unwrap()));
+
+        // Merging everything but the first file keeps the retractions of its
+        // rows, which only the full merge cancels.
+        let partial = merge(&inputs[1..]).unwrap();
+        failures += !partial.is_consolidated() as u64;
+        let partial_negative = partial
+            .rows
+            .iter()
+            .filter(|&&(_, weight)| weight < 0)
+            .count();
+
+        // Weights that overflow are refused.
+        let mut max = Batch::default();
+        max.push(0, 0, i64::MAX);
+        failures += merge(&[&max, &max]).is_ok() as u64;
+
+        let all = all.unwrap_or_default();
+        let input_rows: usize = files.iter().map(|file| file.rows.len()).sum();
+        let input_keys = files
+            .iter()
+            .flat_map(|file| file.keys.iter().map(|&(key, _)| key))
+            .collect::<BTreeSet<_>>()
+            .len();
+        println!(
+            "{:8.0}%  {:>7}  {:>7}  {:6.0}%  {:3.0}%  {:>8}  {failures:8}",
+            fraction * 100.0,
+            HumanCount(input_rows as u64),
+            HumanCount(all.rows.len() as u64),
+            (1.0 - all.rows.len() as f64 / input_rows as f64) * 100.0,
+            (1.0 - all.keys.len() as f64 / input_keys as f64) * 100.0,
+            HumanCount(partial_negative as u64),
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: ConsolidateArgs,
+    }
+
+    /// Returns a batch of `rows`, which must be consolidated and in order.
+    fn batch(rows: &[(u64, u64, i64)]) -> Batch {
+        let mut batch = Batch::default();
+        for &(key, value, weight) in rows {
+            batch.push(key, value, weight);
+        }
+        assert!(batch.is_consolidated());
+        batch
+    }
+
+    #[test]
+    fn partial_cancellation() {
+        // Two inputs: part of the weight survives, and a retraction of more
+        // than was inserted leaves a negative weight.
+        let a = batch(&[(1, 1, 3), (1, 2, 1), (2, 1, 1)]);
+        let b = batch(&[(1, 1, -2), (1, 2, -3), (3, 1, -1)]);
+        let expected = batch(&[(1, 1, 1), (1, 2, -2), (2, 1, 1), (3, 1, -1)]);
+        assert_eq!(merge(&[&a, &b]), Ok(expected));
+
+        // Three inputs, which only cancel fully when all three are merged.
+        let a = batch(&[(1, 1, 3), (2, 1, 2)]);
+        let b = batch(&[(1, 1, -1), (2, 1, -1)]);
+        let c = batch(&[(1, 1, -1), (2, 1, -1)]);
+        assert_eq!(merge(&[&a, &b, &c]), Ok(batch(&[(1, 1, 1)])));
+        assert_eq!(merge(&[&b, &c]), Ok(batch(&[(1, 1, -2), (2, 1, -2)])));
+        let ab = merge(&[&a, &b]).unwrap();
+        assert_eq!(merge(&[&ab, &c]), merge(&[&a, &b, &c]));
+    }
+
+    #[test]
+    fn drops_rows_and_keys_that_reach_zero() {
+        let a = batch(&[(1, 1, 2), (1, 2, 1), (2, 1, 1), (2, 2, 1)]);
+        let b = batch(&[(1, 1, -2), (1, 2, -1), (2, 1, -1)]);
+        // Key 1 loses all of its rows and is dropped.  Key 2 loses one and is
+        // kept.
+        let merged = merge(&[&a, &b]).unwrap();
+        assert!(merged.is_consolidated());
+        assert_eq!(merged, batch(&[(2, 2, 1)]));
+
+        // Cancelling everything leaves an empty batch.
+        let c = batch(&[(2, 2, -1)]);
+        assert_eq!(merge(&[&a, &b, &c]), Ok(Batch::default()));
+    }
+
+    #[test]
+    fn refuses_overflow() {
+        let max = batch(&[(0, 0, i64::MAX)]);
+        let one = batch(&[(0, 0, 1)]);
+        assert_eq!(
+            merge(&[&max, &one]),
+            Err("weight of (0, 0) overflows".into())
+        );
+        let min = batch(&[(0, 0, i64::MIN)]);
+        let minus_one = batch(&[(0, 0, -1)]);
+        assert!(merge(&[&min, &minus_one]).is_err());
+        assert_eq!(merge(&[&max, &min]), Ok(batch(&[(0, 0, -1)])));
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "consolidate",
+            "--files",
+            "3",
+            "--rows",
+            "2000",
+            "--keys",
+            "500",
+        ]);
+        assert_eq!(consolidate(&args), 0);
+    }
+}
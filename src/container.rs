@@ -0,0 +1,370 @@
+//! Pluggable containers for the blocks of layer files.
+//!
+//! The block format says nothing about where a layer file's blocks end up.
+//! A [Container] holds layer files, each of them a sequence of blocks
+//! appended by a writer and read back at offsets relative to the start of the
+//! layer file, so that block logic is the same whichever container is used.
+//! Two backends implement it:
+//!
+//! - `files` puts each layer file in a plain file of its own in a directory.
+//!
+//! - `packed` appends every layer file to a single container file.  When a
+//!   layer file is finalized, it appends a 4-kB directory entry block with the
+//!   layer file's name and extent, so that a reader can rebuild the directory
+//!   by following the entries from the end.  This trades a file per layer file
+//!   for 4 kB per layer file and an extra indirection on open.
+//!
+//! This writes the same layer files through each backend, then reads random
+//! blocks from random layer files, and reports the throughput and the space
+//! and files that each backend takes.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::rng;
+use crate::write::fill_block;
+use crate::HumanBytes;
+
+#[derive(ClapArgs, Debug)]
+pub struct ContainerArgs {
+    /// Backend to benchmark.
+    #[clap(long = "backend", default_values_t = vec![Backend::Files, Backend::Packed])]
+    backends: Vec<Backend>,
+
+    /// Number of layer files to write.
+    #[clap(long, default_value_t = 64)]
+    files: u64,
+
+    /// Size of each layer file, in bytes.
+    #[clap(long, default_value_t = 4 << 20)]
+    file_size: u64,
+
+    /// Size of each block, in bytes.
+    #[clap(long, default_value_t = 65536)]
+    block_size: u64,
+
+    /// Number of random blocks to read back.
+    #[clap(long, default_value_t = 10_000)]
+    reads: u64,
+
+    /// Directory in which to create the containers, which are deleted
+    /// afterward.  Defaults to the system's temporary directory.
+    #[clap(long)]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Backend {
+    /// A plain file per layer file.
+    Files,
+
+    /// Every layer file in one container file.
+    Packed,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Backend::Files => "files",
+            Backend::Packed => "packed",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// A place to keep layer files.
+///
+/// A writer calls [Container::create], then [Container::append_block] for
+/// each block in order, then [Container::finalize].  A reader calls
+/// [Container::open] and then [Container::read_block] as many times as it
+/// likes.  Offsets are relative to the start of the layer file in either
+/// case.
+trait Container {
+    /// Starts writing a new layer file called `name`, finalizing any layer
+    /// file that was being written.
+    fn create(&mut self, name: &str) -> IoResult<()>;
+
+    /// Appends `block` to the layer file being written and returns its
+    /// offset.
+    fn append_block(&mut self, block: &[u8]) -> IoResult<u64>;
+
+    /// Completes the layer file being written and makes it durable.
+    fn finalize(&mut self) -> IoResult<()>;
+
+    /// Opens the layer file called `name` for reading.
+    fn open(&mut self, name: &str) -> IoResult<()>;
+
+    /// Reads `block.len()` bytes at `offset` in the open layer file.
+    fn read_block(&mut self, offset: u64, block: &mut [u8]) -> IoResult<()>;
+
+    /// Returns the number of files and the number of bytes that the container
+    /// takes in the file system.
+    fn footprint(&self) -> IoResult<(u64, u64)>;
+}
+
+/// A [Container] with a plain file in `dir` for each layer file.
+struct Files {
+    dir: PathBuf,
+    writing: Option<File>,
+    reading: Option<File>,
+}
+
+impl Files {
+    fn new(dir: &Path) -> IoResult<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.into(),
+            writing: None,
+            reading: None,
+        })
+    }
+}
+
+impl Container for Files {
+    fn create(&mut self, name: &str) -> IoResult<()> {
+        self.finalize()?;
+        self.writing = Some(File::create(self.dir.join(name))?);
+        Ok(())
+    }
+
+    fn append_block(&mut self, block: &[u8]) -> IoResult<u64> {
+        let file = self.writing.as_mut().ok_or(ErrorKind::NotFound)?;
+        let offset = file.stream_position()?;
+        file.write_all(block)?;
+        Ok(offset)
+    }
+
+    fn finalize(&mut self) -> IoResult<()> {
+        if let Some(file) = self.writing.take() {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, name: &str) -> IoResult<()> {
+        self.reading = Some(File::open(self.dir.join(name))?);
+        Ok(())
+    }
+
+    fn read_block(&mut self, offset: u64, block: &mut [u8]) -> IoResult<()> {
+        let file = self.reading.as_mut().ok_or(ErrorKind::NotFound)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(block)
+    }
+
+    fn footprint(&self) -> IoResult<(u64, u64)> {
+        let mut files = 0;
+        let mut bytes = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            files += 1;
+            bytes += entry?.metadata()?.len();
+        }
+        Ok((files, bytes))
+    }
+}
+
+/// A [Container] that appends every layer file to the single file at `path`.
+struct Packed {
+    path: PathBuf,
+    file: File,
+
+    /// Name and start offset of the layer file being written.
+    writing: Option<(String, u64)>,
+
+    /// Start offset and length of each finalized layer file, by name.
+    directory: HashMap<String, (u64, u64)>,
+
+    /// Extent of the layer file open for reading.
+    reading: Option<(u64, u64)>,
+}
+
+impl Packed {
+    /// Size of the directory entry block appended after each layer file.
+    const ENTRY_SIZE: usize = 4096;
+
+    fn new(path: &Path) -> IoResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            path: path.into(),
+            file,
+            writing: None,
+            directory: HashMap::new(),
+            reading: None,
+        })
+    }
+}
+
+impl Container for Packed {
+    fn create(&mut self, name: &str) -> IoResult<()> {
+        self.finalize()?;
+        let start = self.file.seek(SeekFrom::End(0))?;
+        self.writing = Some((name.into(), start));
+        Ok(())
+    }
+
+    fn append_block(&mut self, block: &[u8]) -> IoResult<u64> {
+        let (_, start) = self.writing.as_ref().ok_or(ErrorKind::NotFound)?;
+        let start = *start;
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(block)?;
+        Ok(offset - start)
+    }
+
+    fn finalize(&mut self) -> IoResult<()> {
+        let Some((name, start)) = self.writing.take() else {
+            return Ok(());
+        };
+        let end = self.file.seek(SeekFrom::End(0))?;
+        let length = end - start;
+
+        // The entry block ends with the layer file's extent and the length of
+        // its name, so that a reader can find the previous entry from it.
+        let mut entry = vec![0; Self::ENTRY_SIZE];
+        let name_bytes = name.as_bytes();
+        if name_bytes.len() > Self::ENTRY_SIZE - 24 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "layer file name too long",
+            ));
+        }
+        entry[..name_bytes.len()].copy_from_slice(name_bytes);
+        let trailer = Self::ENTRY_SIZE - 24;
+        entry[trailer..trailer + 8].copy_from_slice(&start.to_le_bytes());
+        entry[trailer + 8..trailer + 16].copy_from_slice(&length.to_le_bytes());
+        entry[trailer + 16..].copy_from_slice(&(name_bytes.len() as u64).to_le_bytes());
+        self.file.write_all(&entry)?;
+        self.file.sync_data()?;
+        self.directory.insert(name, (start, length));
+        Ok(())
+    }
+
+    fn open(&mut self, name: &str) -> IoResult<()> {
+        self.reading = Some(*self.directory.get(name).ok_or(ErrorKind::NotFound)?);
+        Ok(())
+    }
+
+    fn read_block(&mut self, offset: u64, block: &mut [u8]) -> IoResult<()> {
+        let (start, length) = self.reading.ok_or(ErrorKind::NotFound)?;
+        if offset + block.len() as u64 > length {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.file.seek(SeekFrom::Start(start + offset))?;
+        self.file.read_exact(block)
+    }
+
+    fn footprint(&self) -> IoResult<(u64, u64)> {
+        Ok((1, fs::metadata(&self.path)?.len()))
+    }
+}
+
+/// Measurements of one backend.
+struct Measurement {
+    write_seconds: f64,
+    read_seconds: f64,
+    os_files: u64,
+    bytes: u64,
+}
+
+/// Writes the layer files in `args` through `container`, reads random blocks
+/// back, and checks that each one is the block that was written there.
+fn measure(container: &mut dyn Container, args: &ContainerArgs) -> IoResult<Measurement> {
+    let block_size = args.block_size.max(1);
+    let blocks_per_file = (args.file_size / block_size).max(1);
+    let mut block = vec![0; block_size as usize];
+
+    let start = Instant::now();
+    for file in 0..args.files {
+        container.create(&format!("{file}.layer"))?;
+        for i in 0..blocks_per_file {
+            fill_block(&mut block, file * blocks_per_file + i);
+            let offset = container.append_block(&block)?;
+            assert_eq!(offset, i * block_size);
+        }
+    }
+    container.finalize()?;
+    let write_seconds = start.elapsed().as_secs_f64();
+
+    let mut expected = vec![0; block_size as usize];
+    let mut random = rng::random(0x0123_4567_89ab_cdef);
+    let start = Instant::now();
+    for _ in 0..args.reads {
+        let file = random() % args.files;
+        let i = random() % blocks_per_file;
+        container.open(&format!("{file}.layer"))?;
+        container.read_block(i * block_size, &mut block)?;
+        fill_block(&mut expected, file * blocks_per_file + i);
+        if block != expected {
+            return Err(Error::new(ErrorKind::InvalidData, "read the wrong block"));
+        }
+    }
+    let read_seconds = start.elapsed().as_secs_f64();
+
+    let (os_files, bytes) = container.footprint()?;
+    Ok(Measurement {
+        write_seconds,
+        read_seconds,
+        os_files,
+        bytes,
+    })
+}
+
+/// Prints the throughput and footprint of each backend in `args`.
+pub fn container(args: &ContainerArgs) {
+    let args = ContainerArgs {
+        files: args.files.max(1),
+        backends: args.backends.clone(),
+        dir: args.dir.clone(),
+        ..*args
+    };
+    let dir = args.dir.clone().unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("storage-design-container-{}", std::process::id()));
+    let block_size = args.block_size.max(1);
+    let data = args.files * (args.file_size / block_size).max(1) * block_size;
+    println!(
+        "Containers for {} layer files of {} in {} blocks in {}, {} random reads:",
+        args.files,
+        HumanBytes(args.file_size),
+        HumanBytes(block_size),
+        dir.display(),
+        args.reads,
+    );
+    print!(
+        r#"
+           Write      Read         OS    Bytes
+Backend     MB/s  us/Block      Files  on Disk  Overhead
+-------  -------  --------  ---------  -------  --------
+"#
+    );
+    for &backend in &args.backends {
+        let result = match backend {
+            Backend::Files => {
+                Files::new(&path).and_then(|mut container| measure(&mut container, &args))
+            }
+            Backend::Packed => {
+                Packed::new(&path).and_then(|mut container| measure(&mut container, &args))
+            }
+        };
+        let _ = fs::remove_dir_all(&path).or_else(|_| fs::remove_file(&path));
+        match result {
+            Ok(m) => println!(
+                "{backend:>7}  {:7.0}  {:8.2}  {:9}  {:>7}  {:7.2}%",
+                data as f64 / m.write_seconds / 1e6,
+                m.read_seconds / args.reads.max(1) as f64 * 1e6,
+                m.os_files,
+                HumanBytes(m.bytes),
+                (m.bytes - data) as f64 / data as f64 * 100.0,
+            ),
+            Err(error) => println!("{backend:>7}  ({error})"),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Delta and varint encoding of sorted integer keys.
+//!
+//! In a data block of a column whose key is a single integer, consecutive
+//! keys are sorted and usually close together, so most of the bytes of each
+//! raw 8-byte key repeat the key before it.  Instead, a data block can store
+//! each key as its difference from the previous key, as a LEB128 varint, which
+//! takes 1 byte for a difference below 128, 2 bytes below 16,384, and so on.
+//!
+//! A reader can't binary search varints, so every `restart`th key is a restart
+//! point that is stored as a full 8-byte key, and the block ends with a 2-byte
+//! offset for each restart point followed by the number of restart points and
+//! of keys (2 bytes each).  A seek binary searches the restart points and then
+//! decodes at most `restart - 1` deltas after the one it lands on.
+//!
+//! This generates sorted keys with a given average gap, writes them into
+//! data blocks both raw and delta encoded, checks that a seek for every key in
+//! a sample finds it, and compares keys per block and seek time.
+
+use clap::Args as ClapArgs;
+use std::time::Instant;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct DeltasArgs {
+    /// Average difference between consecutive keys.
+    #[clap(long = "gap", default_values_t = vec![1, 10, 1000, 1_000_000])]
+    gaps: Vec<u64>,
+
+    /// Number of keys between restart points.
+    #[clap(long, default_value_t = 16)]
+    restart: u64,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of keys to generate for each gap.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Number of keys to seek for in each block layout.
+    #[clap(long, default_value_t = 100_000)]
+    seeks: u64,
+}
+
+/// Size of the counts at the end of a block: the number of restart points,
+/// then the number of keys.
+const COUNTS_SIZE: usize = 4;
+
+/// Appends `value` to `out` as a LEB128 varint.
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a LEB128 varint at `*pos` and advances `*pos` past it.
+fn get_varint(block: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = block[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+fn get_u16(block: &[u8], pos: usize) -> usize {
+    u16::from_le_bytes([block[pos], block[pos + 1]]) as usize
+}
+
+fn get_u64(block: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(block[pos..pos + 8].try_into().unwrap())
+}
+
+/// Returns the encoding of as many of `keys` as fit in a block of
+/// `block_size` bytes, with a restart point every `restart` keys, and the
+/// number of keys that it holds.
+fn encode_block(keys: &[u64], restart: usize, block_size: usize) -> (Vec<u8>, usize) {
+    let mut body = Vec::with_capacity(block_size);
+    let mut restarts: Vec<u16> = Vec::new();
+    let mut n = 0;
+    for (i, &key) in keys.iter().enumerate().take(u16::MAX as usize) {
+        let start = body.len();
+        let is_restart = i % restart == 0;
+        match is_restart {
+            true => body.extend_from_slice(&key.to_le_bytes()),
+            false => put_varint(&mut body, key - keys[i - 1]),
+        }
+        let trailer = (restarts.len() + is_restart as usize) * 2 + COUNTS_SIZE;
+        if body.len() + trailer > block_size && n > 0 {
+            body.truncate(start);
+            break;
+        }
+        if is_restart {
+            restarts.push(start as u16);
+        }
+        n += 1;
+    }
+    for &offset in &restarts {
+        body.extend_from_slice(&offset.to_le_bytes());
+    }
+    body.extend_from_slice(&(restarts.len() as u16).to_le_bytes());
+    body.extend_from_slice(&(n as u16).to_le_bytes());
+    (body, n)
+}
+
+/// A delta-encoded data block.
+struct DeltaBlock<'a> {
+    block: &'a [u8],
+    restart: usize,
+    n_restarts: usize,
+    n_keys: usize,
+}
+
+impl<'a> DeltaBlock<'a> {
+    fn new(block: &'a [u8], restart: usize) -> Self {
+        let end = block.len();
+        Self {
+            block,
+            restart,
+            n_restarts: get_u16(block, end - 4),
+            n_keys: get_u16(block, end - 2),
+        }
+    }
+
+    fn restart_offset(&self, i: usize) -> usize {
+        let restarts = self.block.len() - COUNTS_SIZE - self.n_restarts * 2;
+        get_u16(self.block, restarts + i * 2)
+    }
+
+    /// Returns the index of the first key in the block that is at least
+    /// `target`, or the number of keys if there is none, and that key.
+    fn seek(&self, target: u64) -> (usize, Option<u64>) {
+        // Find the last restart point whose key is at most `target`.
+        let (mut lo, mut hi) = (0, self.n_restarts);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            match get_u64(self.block, self.restart_offset(mid)) <= target {
+                true => lo = mid,
+                false => hi = mid,
+            }
+        }
+        let mut pos = self.restart_offset(lo);
+        let mut key = get_u64(self.block, pos);
+        pos += 8;
+        let mut index = lo * self.restart;
+        let end = self.n_keys.min(index + self.restart);
+        while key < target {
+            index += 1;
+            if index == end {
+                let next = (index < self.n_keys).then(|| get_u64(self.block, pos));
+                return (index, next);
+            }
+            key += get_varint(self.block, &mut pos);
+        }
+        (index, Some(key))
+    }
+}
+
+/// Returns `rows` sorted keys whose differences are uniform between 1 and
+/// `2 * gap - 1`, for an average of `gap`.
+fn generate(rows: u64, gap: u64) -> Vec<u64> {
+    let mut random = rng::random(rows ^ gap);
+    let mut key = 0u64;
+    (0..rows)
+        .map(|_| {
+            key += 1 + random() % (2 * gap - 1).max(1);
+            key
+        })
+        .collect()
+}
+
+/// Prints keys per block, bytes per key, and seek time for raw and
+/// delta-encoded data blocks, for each average gap in `args`.
+/// Returns the number of failed checks.
+pub fn deltas(args: &DeltasArgs) -> u64 {
+    let mut total_failures = 0;
+    let restart = args.restart.max(1) as usize;
+    let block_size = args.block_size.max(64) as usize;
+    let rows = args.rows.max(1);
+    let seeks = args.seeks.max(1);
+    println!(
+        "Delta-encoded integer keys, {} blocks, a restart point every {restart} keys, {} keys:",
+        HumanBytes(block_size as u64),
+        HumanCount(rows),
+    );
+    print!(
+        r#"
+          --Keys/Block--   -Bytes/Key-            --Seek (ns)--
+      Gap     Raw   Delta    Raw  Delta   Failures    Raw  Delta
+---------  ------  ------  -----  -----  ---------  -----  -----
+"#
+    );
+    for &gap in &args.gaps {
+        let gap = gap.max(1);
+        let keys = generate(rows, gap);
+
+        // Raw blocks hold a fixed number of keys and their count.
+        let raw_per_block = (block_size - 2) / 8;
+        let raw_blocks: Vec<&[u64]> = keys.chunks(raw_per_block).collect();
+
+        let mut blocks = Vec::new();
+        let mut first_keys = Vec::new();
+        let mut start = 0;
+        while start < keys.len() {
+            let (block, n) = encode_block(&keys[start..], restart, block_size);
+            first_keys.push(keys[start]);
+            blocks.push(block);
+            start += n;
+        }
+
+        // Seek for a sample of the keys.
+        let mut random = rng::random(gap);
+        let targets: Vec<(u64, usize)> = (0..seeks)
+            .map(|_| {
+                let i = (random() % rows) as usize;
+                (keys[i], i)
+            })
+            .collect();
+
+        let start = Instant::now();
+        let mut raw_found = 0usize;
+        for &(target, _) in &targets {
+            let block = raw_blocks.partition_point(|block| block[0] <= target) - 1;
+            raw_found += raw_blocks[block].partition_point(|&key| key < target);
+        }
+        let raw_time = start.elapsed().as_secs_f64();
+
+        let mut failures = 0;
+        let start = Instant::now();
+        let mut delta_found = 0usize;
+        for &(target, _) in &targets {
+            let block = first_keys.partition_point(|&key| key <= target) - 1;
+            let (index, key) = DeltaBlock::new(&blocks[block], restart).seek(target);
+            delta_found += index;
+            failures += (key != Some(target)) as u64;
+        }
+        let delta_time = start.elapsed().as_secs_f64();
+
+        // Also seek for the number just below each key, which isn't a key
+        // unless the gap is 1, and check that the seek finds the key after it.
+        for &(target, i) in &targets {
+            let missing = target - 1;
+            if i > 0 && keys[i - 1] == missing {
+                continue;
+            }
+            let block = first_keys.partition_point(|&key| key < target).max(1) - 1;
+            let found = DeltaBlock::new(&blocks[block], restart).seek(missing).1;
+            let found = found.or_else(|| first_keys.get(block + 1).copied());
+            failures += (found != Some(target)) as u64;
+        }
+        std::hint::black_box((raw_found, delta_found));
+
+        let delta_bytes: usize = blocks.iter().map(Vec::len).sum();
+        println!(
+            "{:>9}  {:6}  {:6}  {:5.2}  {:5.2}  {failures:9}  {:5.0}  {:5.0}",
+            HumanCount(gap),
+            raw_per_block,
+            rows as usize / blocks.len(),
+            8.0,
+            delta_bytes as f64 / rows as f64,
+            raw_time / seeks as f64 * 1e9,
+            delta_time / seeks as f64 * 1e9,
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: DeltasArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } =
+            Wrapper::parse_from(["deltas", "--rows", "20000", "--seeks", "1000"]);
+        assert_eq!(deltas(&args), 0);
+    }
+}
@@ -0,0 +1,300 @@
+//! Export of layer files and spines as CSV or ND-JSON.
+//!
+//! This streams the rows of a layer file (see `layer.rs`), or of a spine in a
+//! storage location (see `storage.rs`) as of its newest checkpoint, to
+//! standard output.  Each row is printed with the field names from the file's
+//! schema and its weight.  A spine's batches are merged and consolidated, so
+//! that the output is what an operator would see, unless `--raw` asks for
+//! each batch as is, with the name of the batch file as an extra column.
+//! `--from` and `--to` restrict the export to a range of keys, which the
+//! export seeks to through each file's index.
+//!
+//! Exporting only reads: it doesn't open the storage location, which would
+//! collect garbage, but reads the list of checkpoints and the checkpoint's
+//! list of batches itself.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::layer::{Merger, Reader, Row};
+use crate::storage::{read_checkpoint, read_checkpoints};
+
+#[derive(ClapArgs, Debug)]
+pub struct ExportArgs {
+    /// Layer file to export, or, with `--spine`, storage location.
+    path: PathBuf,
+
+    /// Spine to export from the storage location at PATH, as of its newest
+    /// checkpoint.
+    #[clap(long)]
+    spine: Option<String>,
+
+    /// Output format.
+    #[clap(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Exports each of the spine's batches as is, one after another, instead
+    /// of merging them.
+    #[clap(long)]
+    raw: bool,
+
+    /// Exports only keys greater than or equal to KEY.
+    #[clap(long, value_name = "KEY")]
+    from: Option<u64>,
+
+    /// Exports only keys less than or equal to KEY.
+    #[clap(long, value_name = "KEY")]
+    to: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Format {
+    /// Comma-separated values, with a header row.
+    Csv,
+
+    /// Newline-delimited JSON, one object per row.
+    #[clap(name = "ndjson")]
+    NdJson,
+}
+
+/// Returns `s` quoted for CSV, if it needs to be.
+fn csv_quote(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.into()
+    }
+}
+
+/// Returns `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if (c as u32) < 0x20 => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Writes rows in a format, with the columns given at creation.
+struct RowWriter<'a> {
+    out: &'a mut dyn Write,
+    format: Format,
+    columns: Vec<String>,
+}
+
+impl<'a> RowWriter<'a> {
+    fn new(out: &'a mut dyn Write, format: Format, columns: Vec<String>) -> std::io::Result<Self> {
+        if format == Format::Csv {
+            let header: Vec<_> = columns.iter().map(|name| csv_quote(name)).collect();
+            writeln!(out, "{}", header.join(","))?;
+        }
+        let columns = match format {
+            Format::Csv => columns,
+            Format::NdJson => columns.iter().map(|name| json_string(name)).collect(),
+        };
+        Ok(Self {
+            out,
+            format,
+            columns,
+        })
+    }
+
+    /// Writes `(key, value, weight)`, followed by `batch` if there is a batch
+    /// column.
+    fn write(&mut self, (key, value, weight): Row, batch: Option<&str>) -> std::io::Result<()> {
+        let values = [key.to_string(), value.to_string(), weight.to_string()];
+        match self.format {
+            Format::Csv => {
+                write!(self.out, "{}", values.join(","))?;
+                if let Some(batch) = batch {
+                    write!(self.out, ",{}", csv_quote(batch))?;
+                }
+                writeln!(self.out)
+            }
+            Format::NdJson => {
+                let mut pairs: Vec<_> = self
+                    .columns
+                    .iter()
+                    .zip(&values)
+                    .map(|(name, value)| format!("{name}:{value}"))
+                    .collect();
+                if let Some(batch) = batch {
+                    pairs.push(format!("{}:{}", self.columns[3], json_string(batch)));
+                }
+                writeln!(self.out, "{{{}}}", pairs.join(","))
+            }
+        }
+    }
+}
+
+pub fn export(args: &ExportArgs) -> Result<(), String> {
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    export_to(args, &mut out)?;
+    out.flush().map_err(|e| format!("standard output: {e}"))
+}
+
+fn export_to(args: &ExportArgs, out: &mut dyn Write) -> Result<(), String> {
+    let readers = match &args.spine {
+        None => {
+            if args.raw {
+                return Err("--raw only applies to a spine".into());
+            }
+            vec![(String::new(), Reader::open(&args.path)?)]
+        }
+        Some(spine) => open_spine(&args.path, spine)?,
+    };
+    let mut columns = match readers.first() {
+        Some((_, reader)) => reader
+            .fields()
+            .iter()
+            .map(|field| field.name.clone())
+            .collect(),
+        None => vec!["key".to_string(), "value".to_string()],
+    };
+    columns.push("weight".into());
+    if args.raw {
+        columns.push("batch".into());
+    }
+
+    let (from, to) = (args.from.unwrap_or(0), args.to.unwrap_or(u64::MAX));
+    let error = |e: std::io::Error| format!("standard output: {e}");
+    let mut writer = RowWriter::new(out, args.format, columns).map_err(error)?;
+    if args.raw {
+        for (name, reader) in &readers {
+            for row in reader.seek(from)? {
+                let row = row?;
+                if row.0 > to {
+                    break;
+                }
+                writer.write(row, Some(name)).map_err(error)?;
+            }
+        }
+    } else {
+        let cursors = readers
+            .iter()
+            .map(|(_, reader)| reader.seek(from))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in Merger::new(cursors)? {
+            let row = row?;
+            if row.0 > to {
+                break;
+            }
+            writer.write(row, None).map_err(error)?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens the batches of `spine` in the newest checkpoint in `root`, oldest
+/// first, with their names.
+fn open_spine(root: &Path, spine: &str) -> Result<Vec<(String, Reader)>, String> {
+    let checkpoints = read_checkpoints(root)?;
+    let checkpoint = checkpoints
+        .last()
+        .ok_or_else(|| format!("{}: no checkpoints", root.display()))?;
+    let spines = read_checkpoint(root, &checkpoint.uuid)?;
+    let batches = spines.get(spine).ok_or_else(|| {
+        let names: Vec<_> = spines.keys().map(String::as_str).collect();
+        format!(
+            "{}: checkpoint {} has no spine {spine} (it has {})",
+            root.display(),
+            checkpoint.uuid,
+            names.join(", ")
+        )
+    })?;
+    batches
+        .iter()
+        .map(|(name, _)| Ok((name.clone(), Reader::open(&root.join(name))?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::{unique, write, Options};
+    use crate::storage::{Storage, StorageOptions};
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: ExportArgs,
+    }
+
+    fn run(args: &[&str]) -> Result<String, String> {
+        let Wrapper { args } = Wrapper::parse_from(["export"].iter().chain(args));
+        let mut out = Vec::new();
+        export_to(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn layer_file() {
+        let path = std::env::temp_dir().join(format!(
+            "storage-design-export-{}-{}.feldera",
+            std::process::id(),
+            unique()
+        ));
+        let options = Options {
+            fields: ["id".into(), "has,comma".into()],
+            ..Options::default()
+        };
+        let rows = (0..1000).map(|key| Ok((key, key * 2, -1)));
+        write(&path, (0, 0), &options, rows).unwrap();
+        let path = path.to_str().unwrap();
+
+        let csv = run(&[path, "--from", "10", "--to", "12"]).unwrap();
+        assert_eq!(
+            csv,
+            "id,\"has,comma\",weight\n10,20,-1\n11,22,-1\n12,24,-1\n"
+        );
+        let json = run(&[path, "--format", "ndjson", "--from", "999"]).unwrap();
+        assert_eq!(json, "{\"id\":999,\"has,comma\":1998,\"weight\":-1}\n");
+        assert_eq!(run(&[path]).unwrap().lines().count(), 1001);
+        assert!(run(&[path, "--raw"]).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn spine() {
+        let root = std::env::temp_dir().join(format!(
+            "storage-design-export-{}-{}",
+            std::process::id(),
+            unique()
+        ));
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        storage.insert("s", 1, &[(1, 1, 1), (2, 2, 1)]).unwrap();
+        storage.insert("s", 2, &[(1, 1, -1), (3, 3, 1)]).unwrap();
+        storage.checkpoint(2).unwrap();
+        // Not in the checkpoint, so not exported.
+        storage.insert("s", 3, &[(4, 4, 1)]).unwrap();
+        let batches: Vec<String> = storage.spines()["s"]
+            .batches
+            .iter()
+            .map(|batch| batch.name.clone())
+            .collect();
+        let root_str = root.to_str().unwrap();
+
+        let merged = run(&[root_str, "--spine", "s"]).unwrap();
+        assert_eq!(merged, "key,value,weight\n2,2,1\n3,3,1\n");
+        let raw = run(&[root_str, "--spine", "s", "--raw", "--to", "2"]).unwrap();
+        assert_eq!(
+            raw,
+            format!(
+                "key,value,weight,batch\n1,1,1,{0}\n2,2,1,{0}\n1,1,-1,{1}\n",
+                batches[0], batches[1]
+            )
+        );
+        assert!(run(&[root_str, "--spine", "t"]).is_err());
+        drop(storage);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
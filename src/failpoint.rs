@@ -0,0 +1,142 @@
+//! Named fail points for crash tests.
+//!
+//! `fail_point!("name")` marks a point in the storage code at which a test
+//! can force a failure.  Unless the crate is built for tests or with the
+//! `failpoints` feature, the macro expands to nothing.  Otherwise, it looks
+//! up `name` in a per-thread registry, so that tests running in parallel
+//! don't see each other's fail points, and, if a test configured it with
+//! [configure] and this is the n-th time it is reached, either returns an
+//! error from the enclosing function or panics.  A panic stands in for the
+//! process aborting: it unwinds past the code that would otherwise clean up
+//! after an error, and leaves the storage location as a crash would.
+//!
+//! README.md lists the fail points and what recovery must do after each one.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// What a fail point does when it fires.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Returns an error from the function that contains the fail point.
+    Error,
+
+    /// Panics, like the process aborting.
+    Panic,
+}
+
+/// The error that a fail point returns.
+#[derive(Clone, Debug)]
+pub struct FailPoint(pub &'static str);
+
+impl Display for FailPoint {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "fail point {} fired", self.0)
+    }
+}
+
+impl From<FailPoint> for std::io::Error {
+    fn from(fail_point: FailPoint) -> Self {
+        std::io::Error::other(fail_point.to_string())
+    }
+}
+
+impl From<FailPoint> for String {
+    fn from(fail_point: FailPoint) -> Self {
+        fail_point.to_string()
+    }
+}
+
+#[cfg(any(test, feature = "failpoints"))]
+mod registry {
+    use super::{Action, FailPoint};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    thread_local! {
+        /// For each configured fail point, the number of times it still has
+        /// to be reached before it fires, and what it does then.
+        static REGISTRY: RefCell<HashMap<String, (u64, Action)>> = RefCell::new(HashMap::new());
+    }
+
+    /// Makes fail point `name` take `action` the `nth` time it is reached
+    /// from now on, counting from 1, on this thread.  It fires only once.
+    pub fn configure(name: &str, nth: u64, action: Action) {
+        REGISTRY.with(|registry| {
+            registry
+                .borrow_mut()
+                .insert(name.into(), (nth.max(1), action))
+        });
+    }
+
+    /// Removes every fail point configured on this thread.
+    pub fn clear() {
+        REGISTRY.with(|registry| registry.borrow_mut().clear());
+    }
+
+    /// Reaches fail point `name`.
+    pub fn hit(name: &'static str) -> Result<(), FailPoint> {
+        let action = REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let (remaining, action) = registry.get_mut(name)?;
+            *remaining -= 1;
+            let action = *action;
+            if *remaining == 0 {
+                registry.remove(name);
+                Some(action)
+            } else {
+                None
+            }
+        });
+        match action {
+            None => Ok(()),
+            Some(Action::Error) => Err(FailPoint(name)),
+            Some(Action::Panic) => panic!("{}", FailPoint(name)),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "failpoints"))]
+pub use registry::{clear, configure, hit};
+
+/// Marks a fail point named `$name`, which returns its error through `?`.
+#[cfg(any(test, feature = "failpoints"))]
+macro_rules! fail_point {
+    ($name:literal) => {
+        $crate::failpoint::hit($name)?
+    };
+}
+
+#[cfg(not(any(test, feature = "failpoints")))]
+macro_rules! fail_point {
+    ($name:literal) => {};
+}
+
+pub(crate) use fail_point;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reach() -> Result<(), String> {
+        fail_point!("test::a");
+        Ok(())
+    }
+
+    #[test]
+    fn fires_once_on_the_nth_hit() {
+        clear();
+        configure("test::a", 3, Action::Error);
+        assert!(reach().is_ok());
+        assert!(reach().is_ok());
+        assert_eq!(reach(), Err("fail point test::a fired".into()));
+        assert!(reach().is_ok());
+    }
+
+    #[test]
+    fn panics() {
+        clear();
+        configure("test::a", 1, Action::Panic);
+        assert!(std::panic::catch_unwind(reach).is_err());
+        assert!(reach().is_ok());
+    }
+}
@@ -0,0 +1,647 @@
+//! Bloom and xor filter blocks and the lookup fast path.
+//!
+//! The writer builds one of two kinds of filter, as the schema block records:
+//!
+//! - Bloom filters at index granularity.  Each time the writer finishes an L1
+//!   index block, if the values under the next one wouldn't fit in a filter
+//!   block along with those since the last filter block, at the configured
+//!   bits per value, it writes a filter block for the ones it has.  The L2
+//!   index block above records which filter covers each of its children in
+//!   its filter map, as runs of a child count and the filter block's
+//!   location, so that these filters are found through the index like
+//!   everything else.
+//!
+//! - A single xor filter for the whole file, which the writer builds when it
+//!   finalizes the file, since its construction needs every value at once,
+//!   and which the trailer points to.  It takes about `1.23 * f` bits per
+//!   value for a false-positive rate of `2^-f` with `f`-bit fingerprints,
+//!   against `1.44 * log2(1 / rate)` for a Bloom filter, and a probe reads
+//!   exactly 3 fingerprints, but the writer has to keep a hash of every value
+//!   (8 bytes each) until the end.
+//!
+//! The header's compatible `filters` flag says that a file has filters, and
+//! the header also holds the hash algorithm and seed.
+//!
+//! A Bloom filter block starts with the usual block header, whose count is
+//! the number of values, followed by the number of hash functions `k` (4
+//! bytes) and the number of bits `m` (4 bytes), then the bits as
+//! little-endian 64-bit words.  The writer sizes the block for the configured
+//! bits per value, rounds it up to a valid block size if a single L1 index
+//! block's values don't fit, and uses every bit of the padding, picking
+//! `k = m / n * ln 2` for the bits it actually has.  A value sets bits
+//! `(h1 + i * h2) mod m` for `i` from 0 to `k - 1`, where `h1` and `h2` are
+//! the low and high halves of its seeded xxHash64, with `h2` made odd.
+//!
+//! An xor filter block holds the block header, the seed that its
+//! construction settled on (8 bytes), the fingerprint size `f` (4 bytes), and
+//! the number of slots in each of its 3 segments (4 bytes), then the
+//! fingerprints, 1 byte each for `f` up to 8 and 2 bytes otherwise, padded to
+//! a multiple of 4 kB.  A value's slots, one per segment, and its fingerprint
+//! come from its xxHash64 with that seed, and the value is present if the xor
+//! of the fingerprints in its slots is its fingerprint.
+//!
+//! On a point lookup, the reader probes the xor filter before anything else,
+//! or descends the cached upper levels to the L2 block, finds the child's
+//! Bloom filter in the filter map, and probes it before reading the L1 index
+//! block and the data block, so a lookup for a missing value usually reads
+//! nothing.
+//!
+//! This generates sorted values, writes the data blocks, the L1 and L2
+//! indexes, and each kind of filter, reads the filter blocks back through
+//! their checksums, checks that a lookup finds every value that is present,
+//! and compares the false-positive rate on missing values against the
+//! model's, the block reads per missing value against a file without
+//! filters, and the time per missing value.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::f64::consts::LN_2;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Instant;
+
+use crate::checksum::{xxhash64, Algorithm};
+use crate::rng;
+use crate::superblock::{check_block, get, new_block, put, seal};
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct FiltersArgs {
+    /// Kind of filter to build.
+    #[clap(long = "filter", default_values_t = vec![FilterKind::Bloom, FilterKind::Xor])]
+    kinds: Vec<FilterKind>,
+
+    /// Bits per value to size each Bloom filter for, before rounding its
+    /// block up.
+    #[clap(long = "filter-bits", default_values_t = vec![4.0, 8.0, 10.0, 12.0, 16.0])]
+    bits_per_value: Vec<f64>,
+
+    /// Size of each xor filter fingerprint, in bits, from 1 to 16.
+    #[clap(long = "xor-bits", default_values_t = vec![8, 16])]
+    xor_bits: Vec<u32>,
+
+    /// Size of each Bloom filter block, in bytes.  A filter block covers as
+    /// many L1 index blocks as fit at the target bits per value, at least one.
+    #[clap(long, default_value_t = 8192)]
+    filter_block_size: u64,
+
+    /// Size of each value, in bytes.
+    #[clap(long, default_value_t = 256)]
+    value_size: u64,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of children of each index block.
+    #[clap(long, default_value_t = 32)]
+    branch: u64,
+
+    /// Number of values to generate.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Number of lookups for present values, and as many for missing values.
+    #[clap(long, default_value_t = 100_000)]
+    lookups: u64,
+}
+
+/// Kind of filter that a file has, as recorded in its schema block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum FilterKind {
+    /// Bloom filters, one per run of L1 index blocks.
+    Bloom,
+
+    /// One xor filter for the whole file.
+    Xor,
+}
+
+impl Display for FilterKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            FilterKind::Bloom => "bloom",
+            FilterKind::Xor => "xor",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+const BLOOM_MAGIC: u32 = u32::from_le_bytes(*b"LFbf");
+pub(crate) const XOR_MAGIC: u32 = u32::from_le_bytes(*b"LFxf");
+
+/// Size of the common block header, then of a Bloom filter block's `k` and
+/// `m`, and of an xor filter block's seed, `f`, and segment size.
+const HEADER_SIZE: usize = 32;
+const FILTER_HEADER_SIZE: usize = 8;
+const XOR_HEADER_SIZE: usize = 16;
+
+/// Epoch and hash seed of the generated file.
+const EPOCH: u64 = 0x0123_4567_89ab_cdef;
+const SEED: u64 = 0x5eed_5eed_5eed_5eed;
+
+/// Returns a block of `size` bytes with the common block header for `magic`
+/// and `count`, followed by `body`, and its checksum filled in.
+fn filter_block(magic: u32, count: u32, body: &[u8], size: usize) -> Vec<u8> {
+    let mut block = new_block(magic, EPOCH, count);
+    block.resize(size, 0);
+    put(&mut block, 4, &(size as u32).to_le_bytes());
+    put(&mut block, HEADER_SIZE, body);
+    seal(&mut block, Algorithm::XxHash64);
+    block
+}
+
+/// Checks the common block header, checksum, and epoch of a filter block.
+fn check_filter_block(block: &[u8], magic: u32) -> Result<(), String> {
+    if block.len() < HEADER_SIZE {
+        return Err("truncated filter block".into());
+    }
+    check_block(block, magic, Algorithm::XxHash64).map_err(|e| format!("filter block: {e}"))?;
+    if get(block, 16, 8) != EPOCH {
+        return Err("bad filter block epoch".into());
+    }
+    Ok(())
+}
+
+/// A Bloom filter, decoded from its block.
+struct Filter {
+    hashes: u32,
+    words: Vec<u64>,
+}
+
+impl Filter {
+    /// Returns the bits that `value` sets in a filter of `m` bits with `k`
+    /// hash functions.
+    fn probes(value: u64, m: u64, k: u32) -> impl Iterator<Item = u64> {
+        let hash = xxhash64(&value.to_le_bytes(), SEED);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..k as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % m)
+    }
+
+    /// Returns a filter block for `values`, sized for `bits_per_value`.
+    fn block(values: &[u64], bits_per_value: f64) -> Vec<u8> {
+        let n = values.len().max(1);
+        let bytes =
+            HEADER_SIZE + FILTER_HEADER_SIZE + (n as f64 * bits_per_value / 8.0).ceil() as usize;
+        let size = bytes.next_power_of_two().max(4096);
+        let m = ((size - HEADER_SIZE - FILTER_HEADER_SIZE) * 8) as u64;
+        let k = ((m as f64 / n as f64 * LN_2).round() as u32).clamp(1, 30);
+        let mut words = vec![0u64; m as usize / 64];
+        for &value in values {
+            for bit in Self::probes(value, m, k) {
+                words[bit as usize / 64] |= 1 << (bit % 64);
+            }
+        }
+        let mut body = Vec::with_capacity(size - HEADER_SIZE);
+        body.extend_from_slice(&k.to_le_bytes());
+        body.extend_from_slice(&(m as u32).to_le_bytes());
+        for word in words {
+            body.extend_from_slice(&word.to_le_bytes());
+        }
+        filter_block(BLOOM_MAGIC, values.len() as u32, &body, size)
+    }
+
+    /// Checks and decodes a filter block.
+    fn read(block: &[u8]) -> Result<Self, String> {
+        check_filter_block(block, BLOOM_MAGIC)?;
+        let get = |offset: usize| u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+        let (hashes, m) = (get(32), get(36) as usize);
+        if hashes == 0
+            || m == 0
+            || m % 64 != 0
+            || m / 8 != block.len() - HEADER_SIZE - FILTER_HEADER_SIZE
+        {
+            return Err(format!("bad filter with {hashes} hashes of {m} bits"));
+        }
+        let words = block[HEADER_SIZE + FILTER_HEADER_SIZE..]
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        Ok(Self { hashes, words })
+    }
+
+    fn contains(&self, value: u64) -> bool {
+        let m = self.words.len() as u64 * 64;
+        Self::probes(value, m, self.hashes)
+            .all(|bit| self.words[bit as usize / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// An xor filter, decoded from its block.
+pub(crate) struct XorFilter {
+    seed: u64,
+    bits: u32,
+    segment: usize,
+    fingerprints: Vec<u16>,
+}
+
+impl XorFilter {
+    /// Returns the fingerprint and the 3 slots of the value whose hash is
+    /// `hash`, in a filter with `f`-bit fingerprints and segments of
+    /// `segment` slots.
+    fn slots(hash: u64, bits: u32, segment: usize) -> (u16, [usize; 3]) {
+        let reduce = |x: u64| ((x as u32 as u64 * segment as u64) >> 32) as usize;
+        let fingerprint = ((hash ^ (hash >> 32)) & ((1 << bits) - 1)) as u16;
+        let slots = [
+            reduce(hash),
+            segment + reduce(hash.rotate_left(21)),
+            2 * segment + reduce(hash.rotate_left(42)),
+        ];
+        (fingerprint, slots)
+    }
+
+    /// Returns an xor filter block for `values`, which must be distinct,
+    /// with `bits`-bit fingerprints.
+    fn block(values: &[u64], bits: u32) -> Vec<u8> {
+        let body = Self::body(values, bits);
+        let size = (HEADER_SIZE + body.len()).next_multiple_of(4096);
+        filter_block(XOR_MAGIC, values.len() as u32, &body, size)
+    }
+
+    /// Returns what follows the block header in an xor filter block for
+    /// `values`, which must be distinct, with `bits`-bit fingerprints.
+    /// Construction fails for a small fraction of seeds, in which case this
+    /// tries the next.
+    pub(crate) fn body(values: &[u64], bits: u32) -> Vec<u8> {
+        let segment = ((values.len() as f64 * 1.23).ceil() as usize + 32).div_ceil(3);
+        for seed in SEED.. {
+            let hashes: Vec<u64> = values
+                .iter()
+                .map(|value| xxhash64(&value.to_le_bytes(), seed))
+                .collect();
+
+            // Peel off slots that only one value maps to, one at a time, and
+            // remember each value's slot in the order they come off.
+            let mut counts = vec![0u32; 3 * segment];
+            let mut xors = vec![0u64; 3 * segment];
+            for &hash in &hashes {
+                for slot in Self::slots(hash, bits, segment).1 {
+                    counts[slot] += 1;
+                    xors[slot] ^= hash;
+                }
+            }
+            let mut queue: Vec<usize> =
+                (0..3 * segment).filter(|&slot| counts[slot] == 1).collect();
+            let mut stack = Vec::with_capacity(values.len());
+            while let Some(slot) = queue.pop() {
+                if counts[slot] != 1 {
+                    continue;
+                }
+                let hash = xors[slot];
+                stack.push((hash, slot));
+                for other in Self::slots(hash, bits, segment).1 {
+                    counts[other] -= 1;
+                    xors[other] ^= hash;
+                    if counts[other] == 1 {
+                        queue.push(other);
+                    }
+                }
+            }
+            if stack.len() != values.len() {
+                continue;
+            }
+
+            // Assign fingerprints in the reverse order, so that each value's
+            // slot is the last of its 3 to be set.
+            let mut fingerprints = vec![0u16; 3 * segment];
+            for &(hash, slot) in stack.iter().rev() {
+                let (fingerprint, slots) = Self::slots(hash, bits, segment);
+                fingerprints[slot] = slots.iter().fold(fingerprint, |f, &s| f ^ fingerprints[s]);
+            }
+            let width = Self::width(bits);
+            let mut body = Vec::with_capacity(XOR_HEADER_SIZE + fingerprints.len() * width);
+            body.extend_from_slice(&seed.to_le_bytes());
+            body.extend_from_slice(&bits.to_le_bytes());
+            body.extend_from_slice(&(segment as u32).to_le_bytes());
+            for fingerprint in fingerprints {
+                body.extend_from_slice(&fingerprint.to_le_bytes()[..width]);
+            }
+            return body;
+        }
+        unreachable!()
+    }
+
+    /// Returns the number of bytes per fingerprint of `bits` bits.
+    fn width(bits: u32) -> usize {
+        match bits {
+            0..=8 => 1,
+            _ => 2,
+        }
+    }
+
+    /// Checks and decodes a filter block.
+    fn read(block: &[u8]) -> Result<Self, String> {
+        check_filter_block(block, XOR_MAGIC)?;
+        Self::decode(block)
+    }
+
+    /// Decodes an xor filter block whose header has already been checked.
+    pub(crate) fn decode(block: &[u8]) -> Result<Self, String> {
+        let get = |offset: usize| u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+        let seed = u64::from_le_bytes(block[32..40].try_into().unwrap());
+        let (bits, segment) = (get(40), get(44) as usize);
+        let end = HEADER_SIZE + XOR_HEADER_SIZE + 3 * segment * Self::width(bits);
+        if !(1..=16).contains(&bits) || segment == 0 || end > block.len() {
+            return Err(format!(
+                "bad xor filter with {bits}-bit fingerprints in segments of {segment}"
+            ));
+        }
+        let fingerprints = block[HEADER_SIZE + XOR_HEADER_SIZE..end]
+            .chunks_exact(Self::width(bits))
+            .map(|f| u16::from_le_bytes([f[0], *f.get(1).unwrap_or(&0)]))
+            .collect();
+        Ok(Self {
+            seed,
+            bits,
+            segment,
+            fingerprints,
+        })
+    }
+
+    pub(crate) fn contains(&self, value: u64) -> bool {
+        let hash = xxhash64(&value.to_le_bytes(), self.seed);
+        let (fingerprint, [a, b, c]) = Self::slots(hash, self.bits, self.segment);
+        fingerprint == self.fingerprints[a] ^ self.fingerprints[b] ^ self.fingerprints[c]
+    }
+}
+
+/// The filters that a reader has loaded for a file.
+enum Filters {
+    None,
+    Bloom(Vec<Filter>),
+    Xor(XorFilter),
+}
+
+/// An L2 index block, as the first value and L1 index block of each of its
+/// children, and its filter map, as runs of a number of children and the
+/// Bloom filter block that covers them.
+struct L2Block {
+    children: Vec<(u64, usize)>,
+    filter_map: Vec<(u32, usize)>,
+}
+
+/// A file's index over its data blocks, with its filter blocks.  Block
+/// numbers stand in for locations.
+struct File {
+    data: Vec<Vec<u64>>,
+
+    /// First value and data block of each L1 index block's children.
+    l1: Vec<Vec<(u64, usize)>>,
+
+    l2: Vec<L2Block>,
+
+    filters: Vec<Vec<u8>>,
+}
+
+impl File {
+    /// Writes a file of `values`, with Bloom filters of `bits` bits per
+    /// value if `kind` is `Bloom`, then finalizes it, with an xor filter of
+    /// `bits`-bit fingerprints if `kind` is `Xor`.
+    fn write(
+        values: &[u64],
+        per_block: usize,
+        branch: usize,
+        filter_block_size: usize,
+        kind: FilterKind,
+        bits: f64,
+    ) -> Self {
+        let data: Vec<Vec<u64>> = values.chunks(per_block).map(<[u64]>::to_vec).collect();
+        let entries: Vec<(u64, usize)> = data
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block[0], i))
+            .collect();
+        let l1: Vec<Vec<(u64, usize)>> = entries.chunks(branch).map(<[_]>::to_vec).collect();
+
+        // Write a Bloom filter block at the end of an L1 index block if the
+        // next one's values wouldn't fit in it too, and at the end of the
+        // file.
+        let capacity = ((filter_block_size - HEADER_SIZE - FILTER_HEADER_SIZE) * 8) as f64 / bits;
+        let values_under =
+            |block: &Vec<(u64, usize)>| block.iter().map(|&(_, i)| data[i].len()).sum::<usize>();
+        let mut filters = Vec::new();
+        let mut filter_of = Vec::with_capacity(l1.len());
+        let mut pending: Vec<u64> = Vec::new();
+        let mut pending_blocks = 0;
+        let bloom = kind == FilterKind::Bloom;
+        for (i, block) in l1.iter().enumerate().take_while(|_| bloom) {
+            for &(_, data_block) in block {
+                pending.extend_from_slice(&data[data_block]);
+            }
+            pending_blocks += 1;
+            let next = l1.get(i + 1).map_or(usize::MAX, values_under);
+            if pending.len().saturating_add(next) as f64 > capacity {
+                filter_of.extend(std::iter::repeat_n(filters.len(), pending_blocks));
+                filters.push(Filter::block(&pending, bits));
+                pending.clear();
+                pending_blocks = 0;
+            }
+        }
+
+        let l2 = l1
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block[0].0, i))
+            .collect::<Vec<_>>()
+            .chunks(branch)
+            .map(|children| {
+                let mut map: Vec<(u32, usize)> = Vec::new();
+                for &(_, child) in children.iter().take_while(|_| bloom) {
+                    match map.last_mut() {
+                        Some((count, filter)) if *filter == filter_of[child] => *count += 1,
+                        _ => map.push((1, filter_of[child])),
+                    }
+                }
+                L2Block {
+                    children: children.to_vec(),
+                    filter_map: map,
+                }
+            })
+            .collect();
+
+        if kind == FilterKind::Xor {
+            filters.push(XorFilter::block(values, bits as u32));
+        }
+        Self {
+            data,
+            l1,
+            l2,
+            filters,
+        }
+    }
+
+    /// Checks and decodes the file's filter blocks, of `kind`.
+    fn load(&self, kind: FilterKind) -> Result<Filters, String> {
+        match kind {
+            FilterKind::Bloom => Ok(Filters::Bloom(
+                self.filters
+                    .iter()
+                    .map(|block| Filter::read(block))
+                    .collect::<Result<_, _>>()?,
+            )),
+            FilterKind::Xor => Ok(Filters::Xor(XorFilter::read(&self.filters[0])?)),
+        }
+    }
+
+    /// Looks up `value`, probing `filters` first, and returns whether it's
+    /// present and the number of L1 index and data blocks read, with the
+    /// levels above L1 cached.
+    fn lookup(&self, value: u64, filters: &Filters) -> (bool, u32) {
+        if let Filters::Xor(filter) = filters {
+            if !filter.contains(value) {
+                return (false, 0);
+            }
+        }
+        let child = |entries: &[(u64, usize)]| {
+            let i = entries.partition_point(|&(first, _)| first <= value);
+            (i > 0).then(|| i - 1)
+        };
+        let Some(l2) = self
+            .l2
+            .partition_point(|block| block.children[0].0 <= value)
+            .checked_sub(1)
+        else {
+            return (false, 0);
+        };
+        let L2Block {
+            children,
+            filter_map,
+        } = &self.l2[l2];
+        let j = child(children).unwrap();
+        if let Filters::Bloom(filters) = filters {
+            let mut skip = j as u32;
+            let filter = filter_map
+                .iter()
+                .find(|&&(count, _)| match skip < count {
+                    true => true,
+                    false => {
+                        skip -= count;
+                        false
+                    }
+                })
+                .unwrap()
+                .1;
+            if !filters[filter].contains(value) {
+                return (false, 0);
+            }
+        }
+        let l1 = &self.l1[children[j].1];
+        let data = &self.data[l1[child(l1).unwrap()].1];
+        (data.binary_search(&value).is_ok(), 2)
+    }
+}
+
+/// Prints false-positive rates, block reads, and time for missing values,
+/// for each kind of filter and size in `args`.
+/// Returns the number of failed checks.
+pub fn filters(args: &FiltersArgs) -> u64 {
+    let mut total_failures = 0;
+    let per_block = (args.block_size / args.value_size.max(1)).max(1) as usize;
+    let branch = args.branch.max(2) as usize;
+    let rows = args.rows.max(1);
+    let filter_block_size = args.filter_block_size.next_power_of_two().max(4096) as usize;
+    println!(
+        "Filters for {} values of {} in {} data blocks, {} Bloom filter blocks:",
+        HumanCount(rows),
+        HumanBytes(args.value_size),
+        HumanBytes(args.block_size),
+        HumanBytes(filter_block_size as u64),
+    );
+    print!(
+        r#"
+        Bits/Value           Filter  Filter  -False Positive-  -Reads/Miss-    Miss
+Filter  Target  Actual  Hashes  Blocks    Size  Measured   Model   None  Filter    (ns)  Failures
+------  ------  ------  ------  ------  ------  --------  ------  -----  ------  ------  --------
+"#
+    );
+    let mut random = rng::random(rows);
+    let mut values: Vec<u64> = (0..rows).map(|_| random()).collect();
+    values.sort_unstable();
+    values.dedup();
+    let present: Vec<u64> = (0..args.lookups)
+        .map(|_| values[random() as usize % values.len()])
+        .collect();
+    let mut missing = Vec::new();
+    while (missing.len() as u64) < args.lookups {
+        let value = random();
+        if values.binary_search(&value).is_err() {
+            missing.push(value);
+        }
+    }
+
+    for &kind in &args.kinds {
+        let sizes: Vec<f64> = match kind {
+            FilterKind::Bloom => args
+                .bits_per_value
+                .iter()
+                .map(|&bits| bits.max(1.0))
+                .collect(),
+            FilterKind::Xor => args
+                .xor_bits
+                .iter()
+                .map(|&bits| bits.clamp(1, 16) as f64)
+                .collect(),
+        };
+        for bits in sizes {
+            let file = File::write(&values, per_block, branch, filter_block_size, kind, bits);
+            let filters = file.load(kind);
+            let mut failures = filters.is_err() as u64;
+            let filters = filters.unwrap_or(Filters::None);
+            let filter_bytes: usize = file.filters.iter().map(Vec::len).sum();
+
+            // Every value in the file has to be found, through the filters or
+            // not.
+            for &value in &present {
+                failures += !file.lookup(value, &filters).0 as u64;
+                failures += !file.lookup(value, &Filters::None).0 as u64;
+            }
+
+            let start = Instant::now();
+            let (mut filter_reads, mut false_positives) = (0, 0);
+            for &value in &missing {
+                let (found, reads) = file.lookup(value, &filters);
+                failures += found as u64;
+                filter_reads += reads as u64;
+                false_positives += (reads > 0) as u64;
+            }
+            let time = start.elapsed().as_secs_f64();
+            let none_reads: u64 = missing
+                .iter()
+                .map(|&value| file.lookup(value, &Filters::None).1 as u64)
+                .sum();
+
+            // A missing value falls under a Bloom filter in proportion to the
+            // range of values that it covers, which is in proportion to its
+            // values.
+            let (hashes, model) = match &filters {
+                Filters::None => (0, 0.0),
+                Filters::Bloom(filters) => {
+                    let model = filters
+                        .iter()
+                        .zip(&file.filters)
+                        .map(|(filter, block)| {
+                            let n = u32::from_le_bytes(block[24..28].try_into().unwrap()) as f64;
+                            let m = filter.words.len() as f64 * 64.0;
+                            let k = filter.hashes as f64;
+                            n * (1.0 - (-k * n / m).exp()).powf(k)
+                        })
+                        .sum::<f64>()
+                        / values.len() as f64;
+                    (filters.first().map_or(0, |filter| filter.hashes), model)
+                }
+                Filters::Xor(filter) => (3, 0.5f64.powi(filter.bits as i32)),
+            };
+            let misses = missing.len() as f64;
+            println!(
+                "{kind:6}  {bits:6.1}  {:6.1}  {hashes:6}  {:>6}  {:>6}  {:7.3}%  {:5.3}%  {:5.2}  {:6.3}  {:6.0}  {failures:8}",
+                filter_bytes as f64 * 8.0 / values.len() as f64,
+                HumanCount(file.filters.len() as u64),
+                HumanBytes(filter_bytes as u64),
+                false_positives as f64 / misses * 100.0,
+                model * 100.0,
+                none_reads as f64 / misses,
+                filter_reads as f64 / misses,
+                time / misses * 1e9,
+            );
+            total_failures += failures;
+        }
+    }
+    total_failures
+}
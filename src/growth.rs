@@ -0,0 +1,150 @@
+//! Projection of file sizes and index heights over time.
+//!
+//! Given a steady ingest rate, the spine's files grow as batches arrive and
+//! merge.  This uses the same size-tiered merge policy as the spine model: each
+//! batch becomes a file, and whenever `fanout` files accumulate at a level,
+//! they merge into one file at the next level.  The number of batches written
+//! so far, in base `fanout`, then gives the size of every file, and the
+//! largest file's index height only changes when a merge creates a new level.
+
+use clap::Args as ClapArgs;
+
+use crate::{HumanBytes, HumanCount, IndexType, LayerFile, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct GrowthArgs {
+    /// Ingest rate, in rows per second.
+    #[clap(long, default_value_t = 100_000)]
+    rows_per_second: u64,
+
+    /// Size of each row, in bytes.
+    #[clap(long, default_value_t = 64)]
+    value_size: u64,
+
+    /// Number of rows in each batch.
+    #[clap(long, default_value_t = 1 << 20)]
+    batch_rows: u64,
+
+    /// Number of files at one level that are merged into a single file at the
+    /// next level.
+    #[clap(long, default_value_t = 8)]
+    fanout: u64,
+
+    /// Number of days to project.
+    #[clap(long, default_value_t = 28)]
+    days: u64,
+
+    /// Number of days between rows in the timeline.
+    #[clap(long, default_value_t = 1)]
+    interval: u64,
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Prints a timeline of the files in a spine ingesting at a steady rate,
+/// followed by the points at which the largest file's data index gets taller.
+/// Index sizes include `indexes` across all of the files.
+pub fn growth(params: &Params, indexes: &[IndexType], args: &GrowthArgs) {
+    let value_size = args.value_size.max(1);
+    let batch_rows = args.batch_rows.max(1);
+    let fanout = args.fanout.max(2);
+    let batch_size = batch_rows * value_size;
+    let params = Params {
+        value_size,
+        ..params.clone()
+    };
+    let file = |batches: u64| {
+        LayerFile::new(&Params {
+            total_data_size: batches * batch_size,
+            ..params.clone()
+        })
+    };
+    let height = |batches: u64| file(batches).indexes[0].height;
+
+    println!(
+        "Growth at {} rows/s of {value_size} bytes each, in batches of {} rows merged with fanout {fanout}, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanCount(args.rows_per_second),
+        HumanCount(batch_rows),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                         ---Largest File---           Index
+  Day    Rows     Data    Rows     Data  Height  Files   Size
+-----  ------  -------  ------  -------  ------  -----  ------
+"#
+    );
+    let rows_per_day = args.rows_per_second * DAY;
+    let interval = args.interval.max(1);
+    for day in (interval..=args.days).step_by(interval as usize) {
+        let n_batches = rows_per_day * day / batch_rows;
+        if n_batches == 0 {
+            continue;
+        }
+
+        // Each digit of `n_batches` in base `fanout` is the number of files
+        // at that level, each of which holds `fanout^level` batches.
+        let mut files = Vec::new();
+        let (mut n, mut place) = (n_batches, 1);
+        while n > 0 {
+            for _ in 0..n % fanout {
+                files.push(place);
+            }
+            n /= fanout;
+            place *= fanout;
+        }
+        let largest = files.iter().copied().max().unwrap_or(0);
+        let index_size: u64 = files
+            .iter()
+            .map(|&batches| file(batches).index_size(indexes))
+            .sum();
+        println!(
+            "{day:5}  {:>6}  {:>7}  {:>6}  {:>7}  {:6}  {:5}  {:>6}",
+            HumanCount(n_batches * batch_rows),
+            HumanBytes(n_batches * batch_size),
+            HumanCount(largest * batch_rows),
+            HumanBytes(largest * batch_size),
+            height(largest),
+            files.len(),
+            HumanBytes(index_size),
+        );
+    }
+
+    // The largest file only grows when `n_batches` reaches a power of
+    // `fanout`, so those are the only points where its height can change.
+    println!();
+    let horizon = rows_per_day * args.days / batch_rows;
+    let mut batches: u64 = 1;
+    let mut last_height = height(1);
+    println!(
+        "The largest file starts with a data index of height {last_height}.  Within {} days, it grows to height:",
+        args.days
+    );
+    let mut any = false;
+    while let Some(next) = batches.checked_mul(fanout).filter(|&next| next <= horizon) {
+        batches = next;
+        let h = height(batches);
+        if h > last_height {
+            let seconds = batches * batch_rows / args.rows_per_second.max(1);
+            let when = if seconds < 3600 {
+                format!("after {} minutes", seconds.div_ceil(60))
+            } else if seconds < DAY {
+                format!("after {:.1} hours", seconds as f64 / 3600.0)
+            } else {
+                format!("on day {:.1}", seconds as f64 / DAY as f64)
+            };
+            println!(
+                "  {h} {when}, when a merge creates a {} file",
+                HumanBytes(batches * batch_size)
+            );
+            last_height = h;
+            any = true;
+        }
+    }
+    if !any {
+        println!("  (no change)");
+    }
+}
@@ -0,0 +1,347 @@
+//! Sustained ingest with background compaction.
+//!
+//! The spine model computes the total cost of merges, but not whether a
+//! device can keep up with them while also taking new batches and serving
+//! lookups.  This simulates ingesting at a fixed rate for a long period, one
+//! batch at a time, into the same size-tiered spine (each batch becomes a file,
+//! and `fanout` files at a level merge into one file at the next level), with a
+//! single background compaction thread that may use a limited share of the
+//! device's bandwidth.  The thread always works on the queued merge at the
+//! lowest level, so that a long merge of big files doesn't hold up the merges
+//! of small files behind it.  The remaining bytes of the queued merges are the
+//! compaction debt.  Until a merge finishes, its
+//! input files stay in the spine, so debt increases the number of files that
+//! each lookup consults.
+//!
+//! Lookups are modeled as checking every file's filter and reading one block,
+//! in parallel, from the file that holds the key and from each file whose
+//! filter gives a false positive.  Each read's latency is exponentially
+//! distributed with a mean of the device latency divided by
+//! `1 - utilization`, as in an M/M/1 queue, so the p99 latency of a lookup is
+//! the p99 of the slowest of its reads.
+
+use crate::{HumanBytes, HumanCount};
+use clap::Args as ClapArgs;
+
+#[derive(ClapArgs, Debug)]
+pub struct IngestArgs {
+    /// Ingest rate, in rows per second.
+    #[clap(long, default_value_t = 500_000)]
+    rows_per_second: u64,
+
+    /// Size of each row, in bytes.
+    #[clap(long, default_value_t = 64)]
+    value_size: u64,
+
+    /// Number of rows in each batch.
+    #[clap(long, default_value_t = 1 << 20)]
+    batch_rows: u64,
+
+    /// Number of files at one level that are merged into a single file at the
+    /// next level.
+    #[clap(long, default_value_t = 8)]
+    fanout: u64,
+
+    /// Number of hours to simulate.
+    #[clap(long, default_value_t = 24)]
+    hours: u64,
+
+    /// Number of hours between rows in the timeline.
+    #[clap(long, default_value_t = 2)]
+    interval: u64,
+
+    /// Device bandwidth, in MB/s, shared by ingest, compaction, and lookups.
+    #[clap(long, default_value_t = 2000)]
+    device_bandwidth: u64,
+
+    /// Fraction of the device bandwidth that compaction may use.
+    #[clap(long, default_value_t = 0.5)]
+    compaction_share: f64,
+
+    /// Latency of reading a block from an idle device, in microseconds.
+    #[clap(long, default_value_t = 100.0)]
+    read_latency: f64,
+
+    /// Number of point lookups per second.
+    #[clap(long, default_value_t = 10_000)]
+    lookups_per_second: u64,
+
+    /// False positive rate of each file's filter.  1 models files without
+    /// filters, so that a lookup reads a block from every file.
+    #[clap(long, default_value_t = 0.01)]
+    false_positive_rate: f64,
+
+    /// Size of the block that a lookup reads from each file, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Also triggers a merge whenever lookups consult more than N files and
+    /// compaction is otherwise idle, merging the files at the lowest level
+    /// that has more than one, even if there are fewer than `fanout`.  A
+    /// reader would measure files consulted per lookup; in this model, every
+    /// lookup consults every file.
+    #[clap(long, value_name = "N")]
+    max_files_per_lookup: Option<u64>,
+}
+
+/// A merge waiting for or in progress on the compaction thread.
+struct Merge {
+    /// Level of the input files.  The output goes to `level + 1`.
+    level: usize,
+
+    /// Number of input files.
+    inputs: u64,
+
+    /// Number of batches in the output file.
+    batches: u64,
+
+    /// Bytes left to read and write.
+    remaining: f64,
+}
+
+/// The files in a spine, with the merges queued for them.
+struct Spine {
+    fanout: u64,
+    batch_size: u64,
+
+    /// The number of batches in each file at each level that isn't the input
+    /// to a queued merge.
+    levels: Vec<Vec<u64>>,
+
+    /// Queued merges, in the order that they were queued.
+    merges: Vec<Merge>,
+
+    /// Number of merges queued by [Spine::trigger].
+    triggered: u64,
+}
+
+impl Spine {
+    fn new(fanout: u64, batch_size: u64) -> Self {
+        Self {
+            fanout,
+            batch_size,
+            levels: Vec::new(),
+            merges: Vec::new(),
+            triggered: 0,
+        }
+    }
+
+    /// Adds a file of `batches` batches at `level` and queues a merge if there
+    /// are enough files there that aren't already being merged.
+    fn add_file(&mut self, level: usize, batches: u64) {
+        if self.levels.len() <= level {
+            self.levels.resize_with(level + 1, Vec::new);
+        }
+        self.levels[level].push(batches);
+        if self.levels[level].len() as u64 >= self.fanout {
+            self.queue_merge(level);
+        }
+    }
+
+    /// Queues a merge of all of the unclaimed files at `level`.
+    fn queue_merge(&mut self, level: usize) {
+        let inputs = std::mem::take(&mut self.levels[level]);
+        let batches = inputs.iter().sum::<u64>();
+        self.merges.push(Merge {
+            level,
+            inputs: inputs.len() as u64,
+            batches,
+            remaining: 2.0 * (batches * self.batch_size) as f64,
+        });
+    }
+
+    /// If a lookup consults more than `max_files` files and no merge is
+    /// queued, queues a merge of the unclaimed files at the lowest level that
+    /// has more than one, even though there are fewer than `fanout` of them.
+    fn trigger(&mut self, max_files: u64) {
+        if self.n_files() <= max_files || !self.merges.is_empty() {
+            return;
+        }
+        if let Some(level) = (0..self.levels.len()).find(|&level| self.levels[level].len() > 1) {
+            self.queue_merge(level);
+            self.triggered += 1;
+        }
+    }
+
+    /// Runs the compaction thread for `bytes` bytes of I/O and returns the
+    /// number of bytes that it used.
+    fn compact(&mut self, mut bytes: f64) -> f64 {
+        let budget = bytes;
+        while let Some(index) = (0..self.merges.len()).min_by_key(|&i| self.merges[i].level) {
+            let merge = &mut self.merges[index];
+            if merge.remaining > bytes {
+                merge.remaining -= bytes;
+                return budget;
+            }
+            bytes -= merge.remaining;
+            let merge = self.merges.remove(index);
+            self.add_file(merge.level + 1, merge.batches);
+        }
+        budget - bytes
+    }
+
+    /// Returns the number of files in the spine, including the inputs to
+    /// queued merges, which is the number that a lookup consults.
+    fn n_files(&self) -> u64 {
+        let unclaimed = self.levels.iter().map(|files| files.len() as u64);
+        let claimed = self.merges.iter().map(|merge| merge.inputs);
+        unclaimed.chain(claimed).sum()
+    }
+
+    fn debt(&self) -> f64 {
+        self.merges.iter().map(|merge| merge.remaining).sum()
+    }
+}
+
+/// Returns the p99 latency of the slowest of `n` parallel reads, each with an
+/// exponentially distributed latency with mean `mean`.  `n` may be
+/// fractional, for an average over lookups.
+fn p99_of_max(n: f64, mean: f64) -> f64 {
+    -mean * (1.0 - 0.99f64.powf(1.0 / n.max(1.0))).ln()
+}
+
+/// Returns the number of merge levels that a spine reaches with `n_batches`
+/// batches and the given `fanout`.
+fn merge_levels(n_batches: u64, fanout: u64) -> u32 {
+    let mut levels = 0;
+    let mut span = fanout;
+    while span <= n_batches {
+        levels += 1;
+        span = match span.checked_mul(fanout) {
+            Some(span) => span,
+            None => break,
+        };
+    }
+    levels
+}
+
+/// Prints a timeline of ingesting at a steady rate with background compaction,
+/// preceded by an estimate of the highest rate that the device can sustain.
+pub fn ingest(args: &IngestArgs) {
+    let value_size = args.value_size.max(1);
+    let batch_rows = args.batch_rows.max(1);
+    let fanout = args.fanout.max(2);
+    let rows_per_second = args.rows_per_second.max(1);
+    let batch_size = batch_rows * value_size;
+    let seconds = args.hours * 3600;
+    let bandwidth = args.device_bandwidth as f64 * 1e6;
+    let compaction_bandwidth = bandwidth * args.compaction_share.clamp(0.0, 1.0);
+    let fp_rate = args.false_positive_rate.clamp(0.0, 1.0);
+    let reads_per_lookup = |files: f64| 1.0 + fp_rate * (files - 1.0).max(0.0);
+    let lookup_bandwidth = |files: f64| {
+        args.lookups_per_second as f64 * reads_per_lookup(files) * args.block_size as f64
+    };
+
+    // Over the whole period, every byte is merged once per level, and each
+    // merge reads and writes it, so ingesting at `rate` rows per second needs
+    // `rate * value_size * (1 + 2 * levels)` bytes per second.  Compaction
+    // must also fit in its share.  Lookups take bandwidth too, which we
+    // estimate for a spine with half of each level's files present.
+    let ceiling = |rows_per_second: u64| {
+        let n_batches = rows_per_second * seconds / batch_rows;
+        let levels = merge_levels(n_batches, fanout) as f64;
+        let files = 1.0 + levels * (fanout - 1) as f64 / 2.0;
+        let ingest = rows_per_second as f64 * value_size as f64;
+        ingest * (1.0 + 2.0 * levels) + lookup_bandwidth(files) <= bandwidth
+            && ingest * 2.0 * levels <= compaction_bandwidth
+    };
+    let (mut low, mut high) = (0u64, 1u64);
+    while ceiling(high) && high < 1 << 40 {
+        (low, high) = (high, high * 2);
+    }
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if ceiling(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    println!(
+        "Ingest at {} rows/s of {value_size} bytes ({}/s) in batches of {} rows merged with fanout {fanout}, {} MB/s device with {:.0}% for compaction, {} lookups/s with {}% false positives:",
+        HumanCount(rows_per_second),
+        HumanBytes(rows_per_second * value_size),
+        HumanCount(batch_rows),
+        args.device_bandwidth,
+        args.compaction_share * 100.0,
+        HumanCount(args.lookups_per_second),
+        fp_rate * 100.0,
+    );
+    println!(
+        "Estimated sustained ingest ceiling over {} hours: {} rows/s ({}/s).",
+        args.hours,
+        HumanCount(low),
+        HumanBytes(low * value_size)
+    );
+    print!(
+        r#"
+                  Queued  Compaction          Lookup
+ Hour  Ingested   Merges        Debt  Files  Util.   p99 (us)
+-----  --------  -------  ----------  -----  -----  ---------
+"#
+    );
+
+    let batch_seconds = batch_rows as f64 / rows_per_second as f64;
+    let n_batches = (seconds as f64 / batch_seconds) as u64;
+    let interval = args.interval.max(1) as f64 * 3600.0;
+    let mut next_report = interval;
+    let mut spine = Spine::new(fanout, batch_size);
+    let mut worst_p99 = 0.0f64;
+    let mut max_debt = 0.0f64;
+    let mut compaction_io = 0.0;
+    for batch in 1..=n_batches {
+        let now = batch as f64 * batch_seconds;
+        let lookup_bytes = lookup_bandwidth(spine.n_files() as f64).min(bandwidth);
+        let ingest_bytes = rows_per_second as f64 * value_size as f64;
+        let compaction_bytes = compaction_bandwidth
+            .min(bandwidth - lookup_bytes - ingest_bytes)
+            .max(0.0);
+        let used = spine.compact(compaction_bytes * batch_seconds) / batch_seconds;
+        compaction_io += used * batch_seconds;
+        spine.add_file(0, 1);
+        if let Some(max_files) = args.max_files_per_lookup {
+            spine.trigger(max_files);
+        }
+
+        let utilization = ((ingest_bytes + used + lookup_bytes) / bandwidth).min(0.99);
+        let mean = args.read_latency / (1.0 - utilization);
+        let p99 = p99_of_max(reads_per_lookup(spine.n_files() as f64), mean);
+        worst_p99 = worst_p99.max(p99);
+        max_debt = max_debt.max(spine.debt());
+
+        if now >= next_report || batch == n_batches {
+            println!(
+                "{:5.0}  {:>8}  {:7}  {:>10}  {:5}  {:4.0}%  {:9.0}",
+                now / 3600.0,
+                HumanBytes(batch * batch_size),
+                spine.merges.len(),
+                HumanBytes(spine.debt() as u64),
+                spine.n_files(),
+                utilization * 100.0,
+                p99
+            );
+            while next_report <= now {
+                next_report += interval;
+            }
+        }
+    }
+    println!();
+    println!(
+        "Worst lookup p99 {:.0} us, peak compaction debt {}.",
+        worst_p99,
+        HumanBytes(max_debt as u64)
+    );
+    let ingested = (n_batches * batch_size) as f64;
+    println!(
+        "Compaction read and wrote {}, for a write amplification of {:.1}.",
+        HumanBytes(compaction_io as u64),
+        1.0 + compaction_io / 2.0 / ingested
+    );
+    if let Some(max_files) = args.max_files_per_lookup {
+        println!(
+            "{} merges triggered by lookups consulting more than {max_files} files.",
+            spine.triggered
+        );
+    }
+}
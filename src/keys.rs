@@ -0,0 +1,433 @@
+//! Order-preserving key normalization.
+//!
+//! A value index compares keys on every step of a search, and a merge compares
+//! them for every row it writes.  If each column type brings its own
+//! comparator, every comparison dispatches on the schema and decodes the
+//! fields it compares.  Instead, a writer can normalize each key into a byte
+//! string whose lexicographic order (as with `memcmp`) is the key's SQL order,
+//! so that the hot path only ever compares bytes:
+//!
+//! - A nullable field starts with a byte that is 0 for NULL and 1 otherwise
+//!   (or the reverse, for NULLS LAST), and a NULL has nothing after it.
+//!
+//! - An integer is stored big-endian with its sign bit flipped, so that
+//!   negative values sort before positive ones.
+//!
+//! - A floating-point value is stored big-endian with its sign bit flipped if
+//!   it is positive and with all of its bits flipped if it is negative.  -0 is
+//!   encoded as 0 and every NaN as the same positive NaN, which sorts after
+//!   infinity, as SQL requires.
+//!
+//! - A string is stored as its UTF-8 bytes with each 0 byte escaped as 0, 255,
+//!   followed by 0, 0, so that a string sorts before every string that it is
+//!   a prefix of.
+//!
+//! - A composite key is its fields' encodings concatenated, and a descending
+//!   field has every byte of its encoding inverted.
+//!
+//! The file's schema metadata records the encoding of each field, that is,
+//! its type, whether it's nullable, its direction, and its NULL ordering,
+//! which is enough to decode a key back into its fields without any other
+//! information.  This generates random keys of each kind of schema, checks
+//! that the encoding round-trips and preserves order against a typed
+//! comparator, and compares the time to sort by each.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Instant;
+
+use crate::rng::SplitMix64;
+use crate::HumanCount;
+
+#[derive(ClapArgs, Debug)]
+pub struct KeysArgs {
+    /// Kind of key to generate.
+    #[clap(long = "schema", default_values_t = vec![SchemaKind::Int, SchemaKind::Double, SchemaKind::Varchar, SchemaKind::Composite])]
+    schemas: Vec<SchemaKind>,
+
+    /// Number of keys to generate for each schema.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Sorts every field in descending order.
+    #[clap(long)]
+    descending: bool,
+
+    /// Sorts NULLs after other values instead of before them.
+    #[clap(long)]
+    nulls_last: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum SchemaKind {
+    /// A single BIGINT.
+    Int,
+
+    /// A single DOUBLE, including infinities, NaNs, and both zeros.
+    Double,
+
+    /// A single VARCHAR, including strings with embedded 0 bytes.
+    Varchar,
+
+    /// A nullable INT, a VARCHAR, and a DOUBLE, with many ties in the first
+    /// two fields so that comparisons often reach the last one.
+    Composite,
+}
+
+impl SchemaKind {
+    fn fields(&self, descending: bool, nulls_last: bool) -> Vec<Field> {
+        let field = |field_type, nullable| Field {
+            field_type,
+            nullable,
+            descending,
+            nulls_last,
+        };
+        match self {
+            SchemaKind::Int => vec![field(FieldType::BigInt, false)],
+            SchemaKind::Double => vec![field(FieldType::Double, false)],
+            SchemaKind::Varchar => vec![field(FieldType::Varchar, false)],
+            SchemaKind::Composite => vec![
+                field(FieldType::Int, true),
+                field(FieldType::Varchar, false),
+                field(FieldType::Double, false),
+            ],
+        }
+    }
+}
+
+impl Display for SchemaKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            SchemaKind::Int => "int",
+            SchemaKind::Double => "double",
+            SchemaKind::Varchar => "varchar",
+            SchemaKind::Composite => "composite",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FieldType {
+    Int,
+    BigInt,
+    Double,
+    Varchar,
+}
+
+/// Encoding of one field of a key, as recorded in the schema metadata.
+#[derive(Clone, Copy, Debug)]
+struct Field {
+    field_type: FieldType,
+    nullable: bool,
+    descending: bool,
+    nulls_last: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Null,
+    Int(i64),
+    Double(f64),
+    Varchar(String),
+}
+
+impl Value {
+    /// Returns the size of this value in a data block without normalization,
+    /// as the fixed-size archived value or a string's length and pointer.
+    fn native_size(&self, field: &Field) -> usize {
+        let size = match (field.field_type, self) {
+            (FieldType::Int, _) => 4,
+            (FieldType::BigInt | FieldType::Double, _) => 8,
+            (FieldType::Varchar, Value::Varchar(s)) => 8 + s.len(),
+            (FieldType::Varchar, _) => 8,
+        };
+        size + field.nullable as usize
+    }
+}
+
+/// Compares `a` and `b` in SQL order for `field`, the way a per-type
+/// comparator would.
+fn compare_value(field: &Field, a: &Value, b: &Value) -> Ordering {
+    let ordering = match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => match field.nulls_last {
+            false => Ordering::Less,
+            true => Ordering::Greater,
+        },
+        (_, Value::Null) => match field.nulls_last {
+            false => Ordering::Greater,
+            true => Ordering::Less,
+        },
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::Double(a), Value::Double(b)) => match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.partial_cmp(b).unwrap(),
+        },
+        (Value::Varchar(a), Value::Varchar(b)) => a.as_bytes().cmp(b.as_bytes()),
+        _ => unreachable!(),
+    };
+    // NULL ordering doesn't change with the direction.
+    match (field.descending, a, b) {
+        (true, Value::Null, _) | (true, _, Value::Null) => ordering,
+        (true, _, _) => ordering.reverse(),
+        (false, _, _) => ordering,
+    }
+}
+
+fn compare_key(fields: &[Field], a: &[Value], b: &[Value]) -> Ordering {
+    for ((field, a), b) in fields.iter().zip(a).zip(b) {
+        match compare_value(field, a, b) {
+            Ordering::Equal => (),
+            ordering => return ordering,
+        }
+    }
+    Ordering::Equal
+}
+
+/// Appends the normalized form of `value` to `out`.
+fn encode_value(field: &Field, value: &Value, out: &mut Vec<u8>) {
+    if field.nullable {
+        // The NULL marker isn't inverted for descending fields, because NULL
+        // ordering doesn't depend on the direction.
+        let is_null = matches!(value, Value::Null);
+        out.push((is_null == field.nulls_last) as u8);
+        if is_null {
+            return;
+        }
+    }
+    let start = out.len();
+    match (field.field_type, value) {
+        (FieldType::Int, &Value::Int(x)) => {
+            out.extend(((x as i32 as u32) ^ (1 << 31)).to_be_bytes())
+        }
+        (FieldType::BigInt, &Value::Int(x)) => out.extend(((x as u64) ^ (1 << 63)).to_be_bytes()),
+        (FieldType::Double, &Value::Double(x)) => {
+            let bits = match x {
+                _ if x.is_nan() => f64::NAN.to_bits(),
+                _ if x == 0.0 => 0,
+                _ => x.to_bits(),
+            };
+            let bits = match bits >> 63 {
+                0 => bits ^ (1 << 63),
+                _ => !bits,
+            };
+            out.extend(bits.to_be_bytes());
+        }
+        (FieldType::Varchar, Value::Varchar(s)) => {
+            for &byte in s.as_bytes() {
+                out.push(byte);
+                if byte == 0 {
+                    out.push(255);
+                }
+            }
+            out.extend([0, 0]);
+        }
+        _ => unreachable!(),
+    }
+    if field.descending {
+        for byte in &mut out[start..] {
+            *byte = !*byte;
+        }
+    }
+}
+
+fn encode_key(fields: &[Field], key: &[Value]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (field, value) in fields.iter().zip(key) {
+        encode_value(field, value, &mut out);
+    }
+    out
+}
+
+/// Decodes a key that [encode_key] produced for `fields`.
+fn decode_key(fields: &[Field], mut bytes: &[u8]) -> Vec<Value> {
+    let mut key = Vec::with_capacity(fields.len());
+    for field in fields {
+        if field.nullable {
+            let is_null = (bytes[0] == 1) == field.nulls_last;
+            bytes = &bytes[1..];
+            if is_null {
+                key.push(Value::Null);
+                continue;
+            }
+        }
+        let byte = |b: u8| if field.descending { !b } else { b };
+        let mut fixed = |n: usize| {
+            let mut buf = [0; 8];
+            for (out, &b) in buf[8 - n..].iter_mut().zip(&bytes[..n]) {
+                *out = byte(b);
+            }
+            bytes = &bytes[n..];
+            u64::from_be_bytes(buf)
+        };
+        let value = match field.field_type {
+            FieldType::Int => Value::Int(((fixed(4) as u32) ^ (1 << 31)) as i32 as i64),
+            FieldType::BigInt => Value::Int((fixed(8) ^ (1 << 63)) as i64),
+            FieldType::Double => {
+                let bits = fixed(8);
+                let bits = match bits >> 63 {
+                    1 => bits ^ (1 << 63),
+                    _ => !bits,
+                };
+                Value::Double(f64::from_bits(bits))
+            }
+            FieldType::Varchar => {
+                let mut s = Vec::new();
+                loop {
+                    match (byte(bytes[0]), byte(bytes[1])) {
+                        (0, 0) => break,
+                        (0, _) => {
+                            s.push(0);
+                            bytes = &bytes[2..];
+                        }
+                        (b, _) => {
+                            s.push(b);
+                            bytes = &bytes[1..];
+                        }
+                    }
+                }
+                bytes = &bytes[2..];
+                Value::Varchar(String::from_utf8(s).unwrap())
+            }
+        };
+        key.push(value);
+    }
+    key
+}
+
+/// Generator of random keys, from a [SplitMix64].
+struct Rng(SplitMix64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(SplitMix64::new(seed))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn int(&mut self, bits: u32) -> i64 {
+        match self.below(8) {
+            0 => [i64::MIN, -1, 0, 1, i64::MAX][self.below(5) as usize] >> (64 - bits),
+            1..=3 => self.below(200) as i64 - 100,
+            _ => (self.next() as i64) >> (64 - bits),
+        }
+    }
+
+    fn double(&mut self) -> f64 {
+        match self.below(16) {
+            0 => [
+                f64::NAN,
+                -f64::NAN,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                0.0,
+                -0.0,
+                f64::MIN_POSITIVE,
+                -f64::MIN_POSITIVE,
+            ][self.below(8) as usize],
+            1..=7 => (self.below(2000) as f64 - 1000.0) / 8.0,
+            _ => f64::from_bits(self.next()),
+        }
+    }
+
+    fn varchar(&mut self, distinct: u64) -> String {
+        const CHARS: [char; 8] = ['\0', 'a', 'b', 'z', '~', 'é', 'ß', '中'];
+        let mut rng = Rng::new(self.below(distinct));
+        let len = rng.below(24);
+        (0..len)
+            .map(|_| CHARS[rng.below(CHARS.len() as u64) as usize])
+            .collect()
+    }
+
+    fn key(&mut self, kind: SchemaKind) -> Vec<Value> {
+        match kind {
+            SchemaKind::Int => vec![Value::Int(self.int(64))],
+            SchemaKind::Double => vec![Value::Double(self.double())],
+            SchemaKind::Varchar => vec![Value::Varchar(self.varchar(u64::MAX))],
+            SchemaKind::Composite => vec![
+                match self.below(10) {
+                    0 => Value::Null,
+                    _ => Value::Int(self.below(16) as i64 - 8),
+                },
+                Value::Varchar(self.varchar(64)),
+                Value::Double(self.double()),
+            ],
+        }
+    }
+}
+
+/// Generates keys for each schema in `args`, checks that normalization
+/// round-trips and preserves their order, and prints their sizes and the
+/// time to sort them with each comparison.
+/// Returns the number of failed checks.
+pub fn keys(args: &KeysArgs) -> u64 {
+    let mut total_failures = 0;
+    println!(
+        "Normalized keys, {} per schema{}{}:",
+        HumanCount(args.rows),
+        if args.descending { ", descending" } else { "" },
+        if args.nulls_last { ", nulls last" } else { "" },
+    );
+    print!(
+        r#"
+           -Bytes per Key-   Round-trip     Order   -Sort Time (ms)-
+   Schema  Native  Normal.     Failures  Failures   Typed   Normal.
+---------  ------  -------   ----------  --------   -----   -------
+"#
+    );
+    let n = args.rows.max(2) as usize;
+    for &kind in &args.schemas {
+        let fields = kind.fields(args.descending, args.nulls_last);
+        let mut rng = Rng::new(n as u64);
+        let keys: Vec<Vec<Value>> = (0..n).map(|_| rng.key(kind)).collect();
+        let encoded: Vec<Vec<u8>> = keys.iter().map(|key| encode_key(&fields, key)).collect();
+
+        let native: usize = keys
+            .iter()
+            .flat_map(|key| key.iter().zip(&fields))
+            .map(|(value, field)| value.native_size(field))
+            .sum();
+        let normalized: usize = encoded.iter().map(Vec::len).sum();
+        let round_trip_failures = keys
+            .iter()
+            .zip(&encoded)
+            .filter(|(key, bytes)| {
+                compare_key(&fields, key, &decode_key(&fields, bytes)) != Ordering::Equal
+            })
+            .count();
+
+        let mut typed: Vec<usize> = (0..n).collect();
+        let start = Instant::now();
+        typed.sort_by(|&a, &b| compare_key(&fields, &keys[a], &keys[b]));
+        let typed_time = start.elapsed().as_secs_f64();
+
+        let mut normal: Vec<usize> = (0..n).collect();
+        let start = Instant::now();
+        normal.sort_by(|&a, &b| encoded[a].cmp(&encoded[b]));
+        let normal_time = start.elapsed().as_secs_f64();
+
+        // Both sorts are stable, so they agree exactly if the orders are the
+        // same, ties included.
+        let order_failures = typed.iter().zip(&normal).filter(|(a, b)| a != b).count();
+
+        println!(
+            "{kind:>9}  {:6.1}  {:7.1}   {round_trip_failures:10}  {order_failures:8}   {:5.0}   {:7.0}",
+            native as f64 / n as f64,
+            normalized as f64 / n as f64,
+            typed_time * 1e3,
+            normal_time * 1e3,
+        );
+        total_failures += (round_trip_failures + order_failures) as u64;
+    }
+    total_failures
+}
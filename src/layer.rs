@@ -0,0 +1,1436 @@
+//! Layer files of (key, value, weight) rows on disk.
+//!
+//! The rest of the crate mostly models layer files.  This writes and reads
+//! real ones, for the storage location and the tools built on it, in the
+//! format that the header, schema, and trailer blocks describe:
+//!
+//! - The header block, at offset 0, and the schema block after it.  The
+//!   schema has a single column of two non-nullable `BIGINT` fields, both of
+//!   them key fields, named as the writer was told.
+//!
+//! - Data blocks of the configured size, each of which starts with the usual
+//!   block header, with the number of rows as its count, followed by the rows,
+//!   24 bytes each: the two fields and the row's weight, little-endian.  Rows
+//!   are in strictly increasing order of their fields and never have weight
+//!   0, that is, a layer file is consolidated.
+//!
+//!   If the schema gives the column a codec, each data block instead follows
+//!   its header with the size of the rows uncompressed (4 bytes), their size
+//!   compressed (4 bytes), and the compressed rows, and is padded to the next
+//!   power-of-2 multiple of 4 kB.  The writer closes a block when the rows'
+//!   size times its running estimate of the compression ratio reaches 90% of
+//!   the block size, as format.md describes.
+//!
+//! - A single index block with an entry per data block, with the data
+//!   block's first row's fields, its offset and size, and its number of rows.
+//!   The trailer points to it as both the column's value root and its row
+//!   root, since the entries give both the block that a key is in and the
+//!   block that a row number is in.
+//!
+//! - If the writer was given [KeyFilter::Xor], an xor filter block over the
+//!   file's keys, as filters.rs describes, which the trailer points to.  A
+//!   point lookup through [Reader::get] probes it before reading any data
+//!   block.  Bloom filters are found through an L2 index block's filter map,
+//!   and these files have only one index level, so the writer doesn't build
+//!   those.
+//!
+//! - The trailer block.
+//!
+//! A reader checks every block's checksum as it reads it from storage,
+//! unless it is opened with [Verify::Trust], which checks only the header,
+//! schema, and trailer blocks' checksums, as format.md describes.  Opened
+//! with [Verify::Paranoid], it also checks that rows are in order within and
+//! across blocks, every time it accesses a block, as the README describes.
+//!
+//! A reader keeps the last few data blocks that it read, as rows, in a block
+//! cache, so that a block is read, verified, and decompressed once per miss.
+//! A cursor may share a [Budget] with others, which limits the blocks that
+//! they read from storage rather than from the cache.
+//!
+//! A writer writes the file under its name with `.mut` appended and renames
+//! it once the trailer is on disk, so that a crash never leaves a file under
+//! its final name without a valid trailer.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::batches::padded;
+use crate::checksum::{xxhash64, Algorithm};
+use crate::compression::{compress, decompress};
+use crate::failpoint::fail_point;
+use crate::filters::{XorFilter, XOR_MAGIC};
+use crate::superblock::{
+    check_block, check_block_header, get, new_block, put, seal, Column, ColumnSchema, Compression,
+    Encoding, Field, FieldType, KeyFilter, Root, Superblock, BLOCK_SIZE, FILTERS, VERSION,
+};
+
+/// Magic numbers of data and index blocks.
+const DATA_MAGIC: u32 = u32::from_le_bytes(*b"LFdb");
+const INDEX_MAGIC: u32 = u32::from_le_bytes(*b"LFix");
+
+/// Size of the common block header, and of that plus the sizes in a
+/// compressed data block.
+const HEADER_SIZE: usize = 32;
+const COMPRESSED_HEADER_SIZE: usize = HEADER_SIZE + 8;
+
+/// Most data blocks that a reader caches.
+const CACHE_BLOCKS: usize = 8;
+
+/// Size of a row in a data block, and of an entry in the index block.
+pub const ROW_SIZE: usize = 24;
+const ENTRY_SIZE: usize = 32;
+
+/// A row: its key, its value, and its weight.
+pub type Row = (u64, u64, i64);
+
+/// Returns `path` with `.mut` appended, the name under which a file is
+/// written.
+pub fn mut_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".mut");
+    PathBuf::from(name)
+}
+
+/// Returns `e` as an error message about `path`.
+pub fn io_error(path: &Path) -> impl Fn(std::io::Error) -> String + '_ {
+    move |e| format!("{}: {e}", path.display())
+}
+
+/// Returns a value that differs from one call to the next and one process to
+/// the next, for epochs and file names.
+pub fn unique() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let seed = [nanos, count, std::process::id() as u64];
+    xxhash64(
+        &seed
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>(),
+        0,
+    )
+}
+
+/// Options for writing a layer file.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Size of each data block, a power-of-2 multiple of 4 kB.
+    pub block_size: usize,
+
+    /// Checksum algorithm for every block but the header.
+    pub algorithm: Algorithm,
+
+    /// Names of the two fields.
+    pub fields: [String; 2],
+
+    /// Codec and level of the data blocks.
+    pub(crate) compression: Compression,
+
+    /// Size past which [write_parts] finishes a file, at the next new key,
+    /// and continues in another, or `None` for no limit.  A file can exceed
+    /// it by a data block, the index block, the filter, and the trailer.
+    pub max_size: Option<u64>,
+
+    /// Filter over the keys, which must be [KeyFilter::None] or
+    /// [KeyFilter::Xor].
+    pub(crate) filter: KeyFilter,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            block_size: 8192,
+            algorithm: Algorithm::Crc32c,
+            fields: ["key".into(), "value".into()],
+            compression: Compression::None,
+            max_size: None,
+            filter: KeyFilter::None,
+        }
+    }
+}
+
+/// How much of a layer file a reader checks.  It always checks the header,
+/// schema, and trailer blocks, including their checksums, and the structure
+/// of the index and data blocks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Verify {
+    /// Skips the checksums of index and data blocks, for benchmarks and for
+    /// storage that already checks end to end.
+    Trust,
+
+    /// Checks the checksum of every block that the reader gets from storage
+    /// before using it.
+    #[default]
+    Checksums,
+
+    /// Checks checksums, and also that the index entries and the rows are in
+    /// strictly increasing order, within each block and across blocks, and
+    /// that no row has weight 0, every time the reader accesses a block,
+    /// including a block in the cache.
+    Paranoid,
+}
+
+/// Checks the header of `block` and, unless `verify` is [Verify::Trust], its
+/// checksum.
+fn check(block: &[u8], magic: u32, algorithm: Algorithm, verify: Verify) -> Result<(), String> {
+    match verify {
+        Verify::Trust => check_block_header(block, magic),
+        Verify::Checksums | Verify::Paranoid => check_block(block, magic, algorithm),
+    }
+}
+
+/// An entry in the index block.
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    first: (u64, u64),
+    offset: u64,
+    size: u32,
+    rows: u32,
+}
+
+/// Writes a layer file, one row at a time.
+pub struct Writer {
+    path: PathBuf,
+    file: BufWriter<File>,
+    options: Options,
+    superblock: Superblock,
+    offset: u64,
+    block: Vec<Row>,
+    entries: Vec<Entry>,
+    last: Option<(u64, u64)>,
+    rows: u64,
+
+    /// Distinct keys so far, for the filter, if there is one.
+    keys: Vec<u64>,
+
+    /// Bytes of rows compressed so far, before and after compression, for
+    /// estimating the compression ratio.
+    compressed: (u64, u64),
+}
+
+impl Writer {
+    /// Starts writing a layer file at `path`, for the updates of `steps`.
+    pub fn create(path: &Path, steps: (u64, u64), options: &Options) -> IoResult<Self> {
+        assert!(options.block_size >= 4096 && (options.block_size / 4096).is_power_of_two());
+        if let KeyFilter::Bloom(_) | KeyFilter::Xor(0 | 17..) = options.filter {
+            return Err(std::io::Error::other(format!(
+                "{}: layer files support only xor filters of 1 to 16 bits",
+                path.display()
+            )));
+        }
+        let field = |name: &str| Field {
+            name: name.into(),
+            field_type: FieldType::BigInt,
+            nullable: false,
+            descending: false,
+            nulls_last: false,
+        };
+        let mut superblock = Superblock {
+            version: VERSION,
+            epoch: unique(),
+            algorithm: options.algorithm,
+            compatible: match options.filter {
+                KeyFilter::None => 0,
+                _ => FILTERS,
+            },
+            incompatible: 0,
+            steps,
+            schema_root: Root::default(),
+            filter: options.filter,
+            schema: vec![ColumnSchema {
+                encoding: Encoding::Rkyv,
+                compression: options.compression,
+                fields: vec![field(&options.fields[0]), field(&options.fields[1])],
+                key: vec![0, 1],
+            }],
+            generation: 0,
+            previous_trailer: 0,
+            filter_root: Root::default(),
+            directory_root: Root::default(),
+            columns: vec![Column {
+                value_root: Root::default(),
+                row_root: Root::default(),
+                row_sample: 1,
+                rows: 0,
+                dictionary_root: Root::default(),
+            }],
+        };
+        let schema = superblock.schema_block();
+        superblock.schema_root = Root {
+            offset: BLOCK_SIZE as u64,
+            size: schema.len() as u32,
+        };
+        let mut file = BufWriter::new(File::create(mut_path(path))?);
+        file.write_all(&superblock.header())?;
+        file.write_all(&schema)?;
+        Ok(Self {
+            path: path.into(),
+            file,
+            options: options.clone(),
+            superblock,
+            offset: (BLOCK_SIZE + schema.len()) as u64,
+            block: Vec::new(),
+            entries: Vec::new(),
+            last: None,
+            rows: 0,
+            keys: Vec::new(),
+            compressed: (0, 0),
+        })
+    }
+
+    /// Appends a row, which must sort after the one before it and have a
+    /// nonzero weight.
+    pub fn push(&mut self, (key, value, weight): Row) -> IoResult<()> {
+        if self.last.is_some_and(|last| last >= (key, value)) || weight == 0 {
+            return Err(std::io::Error::other(format!(
+                "{}: row ({key}, {value}, {weight}) out of order or with weight 0",
+                self.path.display()
+            )));
+        }
+        if self.options.filter != KeyFilter::None && self.last.is_none_or(|last| last.0 != key) {
+            self.keys.push(key);
+        }
+        self.last = Some((key, value));
+        self.block.push((key, value, weight));
+        let block_size = self.options.block_size;
+        let full = match self.options.compression {
+            Compression::None => self.block.len() == (block_size - HEADER_SIZE) / ROW_SIZE,
+            // However well the rows compress, a block holds no more than 64
+            // times the block size of them.
+            _ => {
+                let (uncompressed, compressed) = self.compressed;
+                let ratio = match uncompressed {
+                    0 => 1.0,
+                    _ => compressed as f64 / uncompressed as f64,
+                };
+                let bytes = self.block.len() * ROW_SIZE;
+                bytes as f64 * ratio >= 0.9 * block_size as f64 || bytes >= 64 * block_size
+            }
+        };
+        if full {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> IoResult<()> {
+        let mut rows = Vec::with_capacity(self.block.len() * ROW_SIZE);
+        for &(key, value, weight) in &self.block {
+            rows.extend(key.to_le_bytes());
+            rows.extend(value.to_le_bytes());
+            rows.extend(weight.to_le_bytes());
+        }
+        let mut block = new_block(DATA_MAGIC, self.superblock.epoch, self.block.len() as u32);
+        if self.options.compression == Compression::None {
+            block.resize(self.options.block_size, 0);
+            put(&mut block, HEADER_SIZE, &rows);
+        } else {
+            let data = compress(self.options.compression, &rows).map_err(std::io::Error::other)?;
+            self.compressed.0 += rows.len() as u64;
+            self.compressed.1 += data.len() as u64;
+            block.resize(
+                padded((COMPRESSED_HEADER_SIZE + data.len()) as u64) as usize,
+                0,
+            );
+            put(&mut block, HEADER_SIZE, &(rows.len() as u32).to_le_bytes());
+            put(
+                &mut block,
+                HEADER_SIZE + 4,
+                &(data.len() as u32).to_le_bytes(),
+            );
+            put(&mut block, COMPRESSED_HEADER_SIZE, &data);
+        }
+        let size = block.len() as u32;
+        put(&mut block, 4, &size.to_le_bytes());
+        seal(&mut block, self.options.algorithm);
+        self.file.write_all(&block)?;
+        fail_point!("writer::data-block");
+        let (key, value, _) = self.block[0];
+        self.entries.push(Entry {
+            first: (key, value),
+            offset: self.offset,
+            size: block.len() as u32,
+            rows: self.block.len() as u32,
+        });
+        self.offset += block.len() as u64;
+        self.rows += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Writes the rest of the file, syncs it, and renames it to its final
+    /// name.  Returns the file's size.
+    pub fn finish(mut self) -> IoResult<u64> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+
+        // An empty file still has an index block, with no entries.
+        let size = padded((HEADER_SIZE + self.entries.len() * ENTRY_SIZE) as u64) as usize;
+        let mut index = new_block(
+            INDEX_MAGIC,
+            self.superblock.epoch,
+            self.entries.len() as u32,
+        );
+        index.resize(size, 0);
+        put(&mut index, 4, &(size as u32).to_le_bytes());
+        for (i, entry) in self.entries.iter().enumerate() {
+            let offset = HEADER_SIZE + i * ENTRY_SIZE;
+            put(&mut index, offset, &entry.first.0.to_le_bytes());
+            put(&mut index, offset + 8, &entry.first.1.to_le_bytes());
+            put(&mut index, offset + 16, &entry.offset.to_le_bytes());
+            put(&mut index, offset + 24, &entry.size.to_le_bytes());
+            put(&mut index, offset + 28, &entry.rows.to_le_bytes());
+        }
+        seal(&mut index, self.options.algorithm);
+        self.file.write_all(&index)?;
+        let root = Root {
+            offset: self.offset,
+            size: size as u32,
+        };
+        self.offset += size as u64;
+
+        if let KeyFilter::Xor(bits) = self.options.filter {
+            let body = XorFilter::body(&self.keys, bits as u32);
+            let size = padded((HEADER_SIZE + body.len()) as u64) as usize;
+            let mut filter = new_block(XOR_MAGIC, self.superblock.epoch, self.keys.len() as u32);
+            filter.resize(size, 0);
+            put(&mut filter, 4, &(size as u32).to_le_bytes());
+            put(&mut filter, HEADER_SIZE, &body);
+            seal(&mut filter, self.options.algorithm);
+            self.file.write_all(&filter)?;
+            self.superblock.filter_root = Root {
+                offset: self.offset,
+                size: size as u32,
+            };
+            self.offset += size as u64;
+        }
+        fail_point!("writer::before-footer");
+
+        let column = &mut self.superblock.columns[0];
+        column.value_root = root;
+        column.row_root = root;
+        column.rows = self.rows;
+        self.file.write_all(&self.superblock.trailer())?;
+        self.offset += BLOCK_SIZE as u64;
+        let file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fail_point!("writer::before-rename");
+        fs::rename(mut_path(&self.path), &self.path)?;
+        Ok(self.offset)
+    }
+}
+
+impl Writer {
+    /// Returns the first and last keys written so far, or `None` if no rows
+    /// have been.
+    fn keys(&self) -> Option<(u64, u64)> {
+        let first = self.entries.first().map(|entry| entry.first.0);
+        let first = first.or(self.block.first().map(|row| row.0))?;
+        Some((first, self.last?.0))
+    }
+}
+
+/// Writes `rows`, which must be consolidated and in order, as a layer file at
+/// `path` and returns its size.  This writes a single file however big it
+/// gets, ignoring `options.max_size`.
+pub fn write(
+    path: &Path,
+    steps: (u64, u64),
+    options: &Options,
+    rows: impl IntoIterator<Item = Result<Row, String>>,
+) -> Result<u64, String> {
+    let error = |e: std::io::Error| format!("{}: {e}", path.display());
+    let mut writer = Writer::create(path, steps, options).map_err(error)?;
+    for row in rows {
+        writer.push(row?).map_err(error)?;
+    }
+    writer.finish().map_err(error)
+}
+
+/// One of the layer files that [write_parts] wrote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub path: PathBuf,
+
+    /// Size of the file in bytes.
+    pub size: u64,
+
+    /// First and last keys in the file, or `None` if it is empty.
+    pub keys: Option<(u64, u64)>,
+}
+
+/// Writes `rows`, which must be consolidated and in order, as one or more
+/// layer files, starting a new file at the first new key after the current
+/// one reaches `options.max_size`, so that every key is in exactly one file,
+/// and returns the files in key order.  The `n`th file (counting from 0) is
+/// at `path(n)`.  With no rows, this writes one empty file.
+pub fn write_parts(
+    mut path: impl FnMut(usize) -> PathBuf,
+    steps: (u64, u64),
+    options: &Options,
+    rows: impl IntoIterator<Item = Result<Row, String>>,
+) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut part_path = path(0);
+    let mut writer = Writer::create(&part_path, steps, options).map_err(io_error(&part_path))?;
+    for row in rows {
+        let row = row?;
+        let full = options
+            .max_size
+            .is_some_and(|max_size| writer.offset >= max_size);
+        if full && writer.last.is_some_and(|(key, _)| key != row.0) {
+            let keys = writer.keys();
+            let size = writer.finish().map_err(io_error(&part_path))?;
+            parts.push(Part {
+                path: part_path,
+                size,
+                keys,
+            });
+            part_path = path(parts.len());
+            writer = Writer::create(&part_path, steps, options).map_err(io_error(&part_path))?;
+        }
+        writer.push(row).map_err(io_error(&part_path))?;
+    }
+    let keys = writer.keys();
+    let size = writer.finish().map_err(io_error(&part_path))?;
+    parts.push(Part {
+        path: part_path,
+        size,
+        keys,
+    });
+    Ok(parts)
+}
+
+/// An open layer file.
+pub struct Reader {
+    path: PathBuf,
+    file: File,
+    superblock: Superblock,
+    entries: Vec<Entry>,
+    size: u64,
+    verify: Verify,
+    filter: Option<XorFilter>,
+    cache: RefCell<BlockCache>,
+
+    /// Whether to cross-check each seek, which only debug builds do, and
+    /// only with `DBSP_VERIFY_SEEKS` set.
+    verify_seeks: bool,
+}
+
+/// The data blocks that a reader read most recently, most recent last, with
+/// counts of hits and misses.
+#[derive(Default)]
+struct BlockCache {
+    blocks: VecDeque<(usize, Rc<[Row]>)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl Reader {
+    /// Opens and validates the layer file at `path`: its header, schema,
+    /// trailer, and index block.  Data blocks are validated as they are read.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Self::open_with(path, Verify::default())
+    }
+
+    /// Opens the layer file at `path`, like [Reader::open], checking as much
+    /// as `verify` says.
+    pub fn open_with(path: &Path, verify: Verify) -> Result<Self, String> {
+        let error = |e: String| format!("{}: {e}", path.display());
+        let file = File::open(path).map_err(|e| error(e.to_string()))?;
+        let size = file.metadata().map_err(|e| error(e.to_string()))?.len();
+        let superblock =
+            Superblock::read_from(size, &mut |offset, len| read_at(&file, offset, len))
+                .map_err(error)?;
+        let fields = match &superblock.schema[..] {
+            [column] => &column.fields,
+            _ => return Err(error("not a file of one column".into())),
+        };
+        if fields.len() != 2
+            || fields
+                .iter()
+                .any(|field| field.field_type != FieldType::BigInt)
+        {
+            return Err(error("not a file of two BIGINT fields".into()));
+        }
+
+        let root = superblock.columns[0].row_root;
+        let index = read_at(&file, root.offset, root.size as usize).map_err(error)?;
+        check(&index, INDEX_MAGIC, superblock.algorithm, verify)
+            .map_err(|e| error(format!("index block: {e}")))?;
+        let n_entries = get(&index, 24, 4) as usize;
+        if HEADER_SIZE + n_entries * ENTRY_SIZE > index.len() {
+            return Err(error(format!("index block: bad count {n_entries}")));
+        }
+        let entries: Vec<Entry> = (0..n_entries)
+            .map(|i| {
+                let offset = HEADER_SIZE + i * ENTRY_SIZE;
+                Entry {
+                    first: (get(&index, offset, 8), get(&index, offset + 8, 8)),
+                    offset: get(&index, offset + 16, 8),
+                    size: get(&index, offset + 24, 4) as u32,
+                    rows: get(&index, offset + 28, 4) as u32,
+                }
+            })
+            .collect();
+        if verify == Verify::Paranoid {
+            if let Some(i) = (1..entries.len()).find(|&i| entries[i - 1].first >= entries[i].first)
+            {
+                return Err(error(format!("index block: entry {i} out of order")));
+            }
+        }
+        let filter = match superblock.filter {
+            KeyFilter::Xor(_) => {
+                let root = superblock.filter_root;
+                let error = |e: String| error(format!("filter block: {e}"));
+                let block = read_at(&file, root.offset, root.size as usize).map_err(error)?;
+                check(&block, XOR_MAGIC, superblock.algorithm, verify).map_err(error)?;
+                if get(&block, 16, 8) != superblock.epoch {
+                    return Err(error("epoch differs from header".into()));
+                }
+                Some(XorFilter::decode(&block).map_err(error)?)
+            }
+            _ => None,
+        };
+        let rows: u64 = entries.iter().map(|entry| entry.rows as u64).sum();
+        if rows != superblock.columns[0].rows {
+            return Err(error(format!(
+                "index block: {rows} rows but trailer says {}",
+                superblock.columns[0].rows
+            )));
+        }
+        Ok(Self {
+            path: path.into(),
+            file,
+            superblock,
+            entries,
+            size,
+            verify,
+            filter,
+            cache: RefCell::default(),
+            verify_seeks: cfg!(debug_assertions) && std::env::var_os("DBSP_VERIFY_SEEKS").is_some(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the file's size in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> u64 {
+        self.superblock.columns[0].rows
+    }
+
+    /// Returns the range of steps whose updates the file holds.
+    pub fn steps(&self) -> (u64, u64) {
+        self.superblock.steps
+    }
+
+    /// Returns the codec and level of the data blocks, from the schema.
+    pub(crate) fn compression(&self) -> Compression {
+        self.superblock.schema[0].compression
+    }
+
+    /// Returns the number of block cache hits and misses so far.
+    pub fn cache_stats(&self) -> (u64, u64) {
+        let cache = self.cache.borrow();
+        (cache.hits, cache.misses)
+    }
+
+    /// Returns the file's fields, from its schema.
+    pub(crate) fn fields(&self) -> &[Field] {
+        &self.superblock.schema[0].fields
+    }
+
+    /// Returns the bytes taken by data blocks and by the index block.
+    pub fn data_and_index_bytes(&self) -> (u64, u64) {
+        let data = self.entries.iter().map(|entry| entry.size as u64).sum();
+        (data, self.superblock.columns[0].row_root.size as u64)
+    }
+
+    /// Returns the rows of data block `i`, from the block cache or else by
+    /// reading it, and whether it was in the cache.
+    fn block(&self, i: usize) -> Result<(Rc<[Row]>, bool), String> {
+        let (rows, hit) = self.cached_block(i)?;
+        if self.verify == Verify::Paranoid {
+            self.check_order(i, &rows)?;
+        }
+        Ok((rows, hit))
+    }
+
+    /// Returns whether data block `i` is in the block cache.
+    fn is_cached(&self, i: usize) -> bool {
+        self.cache.borrow().blocks.iter().any(|&(j, _)| j == i)
+    }
+
+    fn cached_block(&self, i: usize) -> Result<(Rc<[Row]>, bool), String> {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(position) = cache.blocks.iter().position(|&(j, _)| j == i) {
+            let block = cache.blocks.remove(position).unwrap();
+            cache.blocks.push_back(block.clone());
+            cache.hits += 1;
+            return Ok((block.1, true));
+        }
+        cache.misses += 1;
+        let rows: Rc<[Row]> = self.read_block(i)?.into();
+        if cache.blocks.len() == CACHE_BLOCKS {
+            cache.blocks.pop_front();
+        }
+        cache.blocks.push_back((i, rows.clone()));
+        Ok((rows, false))
+    }
+
+    /// Checks that the rows of data block `i` are in strictly increasing
+    /// order, with nonzero weights, and that the last of them sorts before
+    /// the first row of the next block, which its index entry gives.  The
+    /// first row of block `i` is the one in its own index entry, which is in
+    /// order with the entry before it, so this also orders it after the rows
+    /// of the block before.
+    fn check_order(&self, i: usize, rows: &[Row]) -> Result<(), String> {
+        let error = |e: String| {
+            format!(
+                "{}: data block at {}: {e}",
+                self.path.display(),
+                self.entries[i].offset
+            )
+        };
+        if let Some(j) = rows.iter().position(|&(_, _, weight)| weight == 0) {
+            return Err(error(format!("row {j} has weight 0")));
+        }
+        let keys = rows.iter().map(|&(key, value, _)| (key, value));
+        let next = self.entries.get(i + 1).map(|entry| entry.first);
+        if let Some(j) = keys
+            .clone()
+            .zip(keys.skip(1).map(Some).chain([next]))
+            .position(|(row, next)| next.is_some_and(|next| row >= next))
+        {
+            return Err(error(match j + 1 == rows.len() {
+                true => "last row is not before the next block's first row".into(),
+                false => format!("row {} is out of order", j + 1),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Reads, validates, and decompresses data block `i` and returns its
+    /// rows.
+    fn read_block(&self, i: usize) -> Result<Vec<Row>, String> {
+        let entry = self.entries[i];
+        let error = |e: String| {
+            format!(
+                "{}: data block at {}: {e}",
+                self.path.display(),
+                entry.offset
+            )
+        };
+        let block = read_at(&self.file, entry.offset, entry.size as usize).map_err(error)?;
+        check(&block, DATA_MAGIC, self.superblock.algorithm, self.verify).map_err(error)?;
+        if get(&block, 16, 8) != self.superblock.epoch {
+            return Err(error("epoch differs from header".into()));
+        }
+        let n_rows = get(&block, 24, 4) as usize;
+        let compression = self.compression();
+        let data = if compression == Compression::None {
+            if n_rows != entry.rows as usize || HEADER_SIZE + n_rows * ROW_SIZE > block.len() {
+                return Err(error(format!("bad count {n_rows}")));
+            }
+            block[HEADER_SIZE..HEADER_SIZE + n_rows * ROW_SIZE].to_vec()
+        } else {
+            let uncompressed = get(&block, HEADER_SIZE, 4) as usize;
+            let compressed = get(&block, HEADER_SIZE + 4, 4) as usize;
+            if n_rows != entry.rows as usize || uncompressed != n_rows * ROW_SIZE {
+                return Err(error(format!("bad count {n_rows}")));
+            }
+            if COMPRESSED_HEADER_SIZE + compressed > block.len() {
+                return Err(error(format!("bad compressed size {compressed}")));
+            }
+            let data = &block[COMPRESSED_HEADER_SIZE..COMPRESSED_HEADER_SIZE + compressed];
+            decompress(compression, data, uncompressed).map_err(error)?
+        };
+        let rows: Vec<Row> = data
+            .chunks_exact(ROW_SIZE)
+            .map(|row| (get(row, 0, 8), get(row, 8, 8), get(row, 16, 8) as i64))
+            .collect();
+        if rows.first().map(|&(key, value, _)| (key, value)) != Some(entry.first) {
+            return Err(error("first row differs from the index".into()));
+        }
+        Ok(rows)
+    }
+
+    /// Returns a cursor at the first row.
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor {
+            reader: self,
+            block: 0,
+            rows: Rc::new([]),
+            position: 0,
+            stats: CursorStats::default(),
+            budget: None,
+        }
+    }
+
+    /// Returns the rows whose key is `key`.  If the file has a filter, this
+    /// probes it first, and reads nothing if it says that `key` is absent.
+    pub fn get(&self, key: u64) -> Result<Vec<Row>, String> {
+        self.get_within(key, None)
+    }
+
+    /// Like [Reader::get], but reads data blocks from storage only within
+    /// `budget`, if there is one.
+    pub fn get_within(&self, key: u64, budget: Option<&Budget>) -> Result<Vec<Row>, String> {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.contains(key))
+        {
+            return Ok(Vec::new());
+        }
+        self.seek_within(key, budget)?
+            .take_while(|row| row.as_ref().map_or(true, |&(k, _, _)| k == key))
+            .collect()
+    }
+
+    /// Returns a cursor at the first row whose key is at least `key`.  It
+    /// starts from the last data block that the index says starts before
+    /// `key`, since the one after it may start with `key` itself.
+    pub fn seek(&self, key: u64) -> Result<Cursor<'_>, String> {
+        self.seek_within(key, None)
+    }
+
+    /// Like [Reader::seek], but the cursor reads data blocks from storage
+    /// only within `budget`, if there is one.
+    pub fn seek_within<'a>(
+        &'a self,
+        key: u64,
+        budget: Option<&'a Budget>,
+    ) -> Result<Cursor<'a>, String> {
+        let block = self
+            .entries
+            .partition_point(|entry| entry.first < (key, 0))
+            .saturating_sub(1);
+        if cfg!(debug_assertions) && self.verify_seeks {
+            self.verify_seek(key, block)?;
+        }
+        let mut cursor = Cursor {
+            reader: self,
+            block,
+            rows: Rc::new([]),
+            position: 0,
+            stats: CursorStats::default(),
+            budget,
+        };
+        while let Some((k, _, _)) = cursor.peek()? {
+            if k >= key {
+                break;
+            }
+            cursor.position += 1;
+        }
+        Ok(cursor)
+    }
+
+    /// Cross-checks data block `block`, which the index picked for a seek to
+    /// `key`, against the block's first and last rows: unless it is the first
+    /// block, it has to start before `key`, and if it ends before `key`, the
+    /// next block can't start before `key` either.  Panics with both answers
+    /// if not, since that is a bug in the seek or an index that got past the
+    /// other checks.
+    fn verify_seek(&self, key: u64, block: usize) -> Result<(), String> {
+        if block >= self.entries.len() {
+            return Ok(());
+        }
+        let (rows, _) = self.block(block)?;
+        let (first, last) = (rows[0], rows[rows.len() - 1]);
+        let too_far = block > 0 && (first.0, first.1) >= (key, 0);
+        let too_early = (last.0, last.1) < (key, 0)
+            && self
+                .entries
+                .get(block + 1)
+                .is_some_and(|next| next.first < (key, 0));
+        if too_far || too_early {
+            panic!(
+                "{}: seek to {key} picked data block {block} at {}, which holds {first:?} through {last:?}",
+                self.path.display(),
+                self.entries[block].offset
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reads `len` bytes of `file` at `offset`.
+fn read_at(mut file: &File, offset: u64, len: usize) -> Result<Vec<u8>, String> {
+    let mut buffer = vec![0; len];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut buffer))
+        .map_err(|e| format!("read of {len} bytes at {offset}: {e}"))?;
+    Ok(buffer)
+}
+
+/// A position in a layer file.  It reads one data block at a time, through
+/// the reader's block cache.
+pub struct Cursor<'a> {
+    reader: &'a Reader,
+    block: usize,
+    rows: Rc<[Row]>,
+    position: usize,
+    stats: CursorStats,
+    budget: Option<&'a Budget>,
+}
+
+/// Counts of the data blocks that a cursor accessed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CursorStats {
+    /// Data blocks accessed, whether from the cache or from storage.
+    pub blocks: u64,
+
+    /// Data blocks found in the reader's block cache.
+    pub hits: u64,
+
+    /// Bytes read from storage for the data blocks that weren't in the cache.
+    pub bytes: u64,
+}
+
+/// A limit on the data blocks, and on their bytes, that the cursors that
+/// share it may read from storage.  Blocks in the block cache cost nothing,
+/// so a budget of 0 blocks allows only what is cached.
+#[derive(Debug, Default)]
+pub struct Budget {
+    max_blocks: Option<u64>,
+    max_bytes: Option<u64>,
+    used: Cell<(u64, u64)>,
+    exhausted: Cell<bool>,
+}
+
+impl Budget {
+    pub fn new(max_blocks: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_blocks,
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the blocks and bytes read from storage within the budget.
+    pub fn used(&self) -> (u64, u64) {
+        self.used.get()
+    }
+
+    /// Returns whether a read was refused because it would have exceeded the
+    /// budget.
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    /// Charges a read of `bytes` from storage against the budget, or refuses
+    /// it, before it is issued.
+    fn charge(&self, bytes: u64) -> Result<(), String> {
+        let (blocks, used) = self.used.get();
+        if self.max_blocks.is_some_and(|max| blocks + 1 > max)
+            || self.max_bytes.is_some_and(|max| used + bytes > max)
+        {
+            self.exhausted.set(true);
+            return Err(format!(
+                "budget exhausted after {blocks} blocks and {used} bytes"
+            ));
+        }
+        self.used.set((blocks + 1, used + bytes));
+        Ok(())
+    }
+}
+
+impl Cursor<'_> {
+    /// Returns the row at the cursor, reading its data block if necessary,
+    /// or `None` past the last row.
+    pub fn peek(&mut self) -> Result<Option<Row>, String> {
+        while self.position >= self.rows.len() {
+            if !self.rows.is_empty() {
+                self.block += 1;
+            }
+            if self.block >= self.reader.entries.len() {
+                return Ok(None);
+            }
+            if let Some(budget) = self.budget {
+                if !self.reader.is_cached(self.block) {
+                    budget.charge(self.reader.entries[self.block].size as u64)?;
+                }
+            }
+            let (rows, hit) = self.reader.block(self.block)?;
+            self.stats.blocks += 1;
+            match hit {
+                true => self.stats.hits += 1,
+                false => self.stats.bytes += self.reader.entries[self.block].size as u64,
+            }
+            self.rows = rows;
+            self.position = 0;
+        }
+        Ok(Some(self.rows[self.position]))
+    }
+
+    /// Returns counts of the data blocks that the cursor has accessed.
+    pub fn stats(&self) -> CursorStats {
+        self.stats
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Result<Row, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.peek().transpose()?;
+        self.position += 1;
+        Some(row)
+    }
+}
+
+/// Merges `inputs`, each of which is in order and consolidated, into one
+/// consolidated sequence of rows, by the same rules as any other merge: rows
+/// with equal key and value sum their weights, a row whose sum is 0 is
+/// dropped, and a sum that overflows is an error.
+pub struct Merger<I> {
+    inputs: Vec<I>,
+    heap: BinaryHeap<Reverse<(u64, u64, usize, i64)>>,
+    failed: bool,
+}
+
+impl<I: Iterator<Item = Result<Row, String>>> Merger<I> {
+    pub fn new(inputs: Vec<I>) -> Result<Self, String> {
+        let mut merger = Self {
+            inputs,
+            heap: BinaryHeap::new(),
+            failed: false,
+        };
+        for i in 0..merger.inputs.len() {
+            merger.refill(i)?;
+        }
+        Ok(merger)
+    }
+
+    fn refill(&mut self, i: usize) -> Result<(), String> {
+        if let Some(row) = self.inputs[i].next() {
+            let (key, value, weight) = row?;
+            self.heap.push(Reverse((key, value, i, weight)));
+        }
+        Ok(())
+    }
+
+    fn merge_next(&mut self) -> Result<Option<Row>, String> {
+        while let Some(Reverse((key, value, i, weight))) = self.heap.pop() {
+            self.refill(i)?;
+            let mut sum = weight;
+            while let Some(&Reverse((k, v, j, w))) = self.heap.peek() {
+                if (k, v) != (key, value) {
+                    break;
+                }
+                self.heap.pop();
+                self.refill(j)?;
+                sum = sum
+                    .checked_add(w)
+                    .ok_or_else(|| format!("weight of ({key}, {value}) overflows"))?;
+            }
+            if sum != 0 {
+                return Ok(Some((key, value, sum)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<I: Iterator<Item = Result<Row, String>>> Iterator for Merger<I> {
+    type Item = Result<Row, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let row = self.merge_next().transpose();
+        self.failed = matches!(row, Some(Err(_)));
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "storage-design-layer-{name}-{}-{}.feldera",
+            std::process::id(),
+            unique()
+        ))
+    }
+
+    #[test]
+    fn write_read_seek() {
+        let path = temp_path("seek");
+        let options = Options {
+            block_size: 4096,
+            ..Options::default()
+        };
+        let rows: Vec<Row> = (0..5000)
+            .map(|i| (i / 3 * 2, i % 3, 1 + i as i64 % 5))
+            .collect();
+        write(&path, (1, 7), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+        assert!(!mut_path(&path).exists());
+
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.rows(), rows.len() as u64);
+        assert_eq!(reader.steps(), (1, 7));
+        let read: Vec<Row> = reader.cursor().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, rows);
+        for key in [0, 1, 2, 1000, 1001, 3332, 3333, 5000] {
+            let first = rows.iter().position(|row| row.0 >= key);
+            let found = reader.seek(key).unwrap().next().transpose().unwrap();
+            assert_eq!(found, first.map(|i| rows[i]), "seek to {key}");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writer_rejects_bad_rows() {
+        let path = temp_path("order");
+        let mut writer = Writer::create(&path, (0, 0), &Options::default()).unwrap();
+        writer.push((1, 1, 1)).unwrap();
+        assert!(writer.push((1, 1, 1)).is_err());
+        assert!(writer.push((0, 5, 1)).is_err());
+        assert!(writer.push((2, 0, 0)).is_err());
+        drop(writer);
+        std::fs::remove_file(mut_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn write_parts_rolls_over_at_key_boundaries() {
+        let base = temp_path("parts");
+        let path = |n: usize| base.with_extension(format!("{n}.feldera"));
+        let options = Options {
+            block_size: 4096,
+            max_size: Some(8 * 4096),
+            ..Options::default()
+        };
+        // Runs of values for each key, so that a block boundary can fall in
+        // the middle of a key.
+        let rows: Vec<Row> = (0..20_000)
+            .map(|i| (i / 7, i % 7, 1 + i as i64 % 3))
+            .collect();
+        let parts = write_parts(path, (0, 0), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+        assert!(parts.len() >= 3, "{parts:?}");
+
+        let mut read = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part.path, path(i));
+            let reader = Reader::open(&part.path).unwrap();
+            assert_eq!(reader.size(), part.size);
+            assert!(part.size <= 16 * 4096, "{part:?}");
+            let rows: Vec<Row> = reader.cursor().collect::<Result<_, _>>().unwrap();
+            let (first, last) = part.keys.unwrap();
+            assert_eq!((rows[0].0, rows[rows.len() - 1].0), (first, last));
+            read.extend(rows);
+        }
+        assert_eq!(read, rows);
+        for pair in parts.windows(2) {
+            assert!(
+                pair[0].keys.unwrap().1 < pair[1].keys.unwrap().0,
+                "{pair:?}"
+            );
+        }
+        for part in &parts {
+            std::fs::remove_file(&part.path).unwrap();
+        }
+
+        // Without a limit, or without rows, there is a single file.
+        let options = Options::default();
+        let parts = write_parts(path, (0, 0), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+        assert_eq!(parts.len(), 1);
+        std::fs::remove_file(&parts[0].path).unwrap();
+        let parts = write_parts(path, (0, 0), &options, []).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].keys, None);
+        std::fs::remove_file(&parts[0].path).unwrap();
+    }
+
+    #[test]
+    fn xor_filter_skips_missing_keys() {
+        let path = temp_path("filter");
+        let options = Options {
+            filter: KeyFilter::Xor(8),
+            ..Options::default()
+        };
+        let rows: Vec<Row> = (0..3000).map(|i| (i / 3 * 2, i % 3, 1)).collect();
+        write(&path, (0, 0), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        for key in (0..2000).step_by(2) {
+            let expected: Vec<Row> = rows.iter().filter(|row| row.0 == key).copied().collect();
+            assert_eq!(reader.get(key).unwrap(), expected);
+        }
+        let (hits, misses) = reader.cache_stats();
+
+        // At 8 bits, about 1 in 256 missing keys gets past the filter.
+        for key in (1..2000).step_by(2).chain(2000..3000) {
+            assert_eq!(reader.get(key).unwrap(), []);
+        }
+        let (more_hits, more_misses) = reader.cache_stats();
+        assert!(more_hits + more_misses - hits - misses < 20);
+
+        // The filter block has a checksum like any other.
+        let mut file = std::fs::read(&path).unwrap();
+        let root = reader.superblock.filter_root;
+        file[root.offset as usize + HEADER_SIZE] ^= 1;
+        std::fs::write(&path, &file).unwrap();
+        let error = Reader::open(&path).err().unwrap();
+        assert!(error.contains("filter block"), "{error}");
+        std::fs::remove_file(&path).unwrap();
+
+        let options = Options {
+            filter: KeyFilter::Bloom(10),
+            ..Options::default()
+        };
+        assert!(Writer::create(&path, (0, 0), &options).is_err());
+    }
+
+    #[test]
+    fn merger_consolidates() {
+        let a = vec![Ok((1, 1, 2)), Ok((2, 1, 1)), Ok((3, 1, 1))];
+        let b = vec![Ok((1, 1, -2)), Ok((2, 1, 1)), Ok((4, 1, 1))];
+        let merged: Vec<Row> = Merger::new(vec![a.into_iter(), b.into_iter()])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(merged, vec![(2, 1, 2), (3, 1, 1), (4, 1, 1)]);
+
+        let a = vec![Ok((1, 1, i64::MAX))];
+        let b = vec![Ok((1, 1, 1))];
+        let mut merger = Merger::new(vec![a.into_iter(), b.into_iter()]).unwrap();
+        assert!(merger.next().unwrap().is_err());
+        assert!(merger.next().is_none());
+    }
+
+    #[test]
+    fn compressed_blocks() {
+        for compression in [
+            Compression::Zstd(3),
+            Compression::Lz4(0),
+            Compression::Lz4(9),
+        ] {
+            check_compressed_blocks(compression);
+        }
+    }
+
+    fn check_compressed_blocks(compression: Compression) {
+        let path = temp_path("compressed");
+        let options = Options {
+            compression,
+            ..Options::default()
+        };
+        let rows: Vec<Row> = (0..50_000).map(|i| (i * 3, i % 7, 1)).collect();
+        write(&path, (0, 0), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+
+        let reader = Reader::open(&path).unwrap();
+        assert_eq!(reader.compression(), compression);
+        let mut cursor = reader.cursor();
+        let read: Vec<Row> = cursor.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, rows);
+        let (data, _) = reader.data_and_index_bytes();
+        let stats = CursorStats {
+            blocks: reader.entries.len() as u64,
+            hits: 0,
+            bytes: data,
+        };
+        assert_eq!(cursor.stats(), stats);
+        assert!(data * 2 < rows.len() as u64 * ROW_SIZE as u64, "{data}");
+        // Once the writer's estimate settles, blocks come out at the target
+        // size and hold more rows than uncompressed ones.
+        let last = reader.entries.len() - 1;
+        for entry in &reader.entries[4..last] {
+            assert_eq!(entry.size as usize, options.block_size, "{compression:?}");
+            assert!(entry.rows as usize > 2 * (options.block_size - HEADER_SIZE) / ROW_SIZE);
+        }
+
+        // A second seek into the same block hits the cache.
+        reader.seek(30_000).unwrap().next().unwrap().unwrap();
+        let (hits, misses) = reader.cache_stats();
+        let mut cursor = reader.seek(30_003).unwrap();
+        assert_eq!(cursor.next().unwrap(), Ok((30_003, 5, 1)));
+        assert_eq!(reader.cache_stats(), (hits + 1, misses));
+        let stats = CursorStats {
+            blocks: 1,
+            hits: 1,
+            bytes: 0,
+        };
+        assert_eq!(cursor.stats(), stats);
+
+        // A corrupt block fails its checksum before it reaches the codec.
+        let mut file = std::fs::read(&path).unwrap();
+        file[reader.entries[1].offset as usize + COMPRESSED_HEADER_SIZE + 10] ^= 1;
+        drop(reader);
+        std::fs::write(&path, &file).unwrap();
+        let reader = Reader::open(&path).unwrap();
+        let error = reader.cursor().collect::<Result<Vec<_>, _>>().unwrap_err();
+        assert!(error.contains("checksum"), "{error}");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn trust_skips_data_block_checksums() {
+        let path = temp_path("trust");
+        let rows: Vec<Row> = (0..100).map(|key| (key, 0, 1)).collect();
+        write(
+            &path,
+            (0, 0),
+            &Options::default(),
+            rows.iter().map(|&row| Ok(row)),
+        )
+        .unwrap();
+
+        // Flip a bit in the value of the second row.
+        let mut file = std::fs::read(&path).unwrap();
+        file[2 * BLOCK_SIZE + HEADER_SIZE + ROW_SIZE + 8] ^= 1;
+        std::fs::write(&path, &file).unwrap();
+
+        let reader = Reader::open_with(&path, Verify::Checksums).unwrap();
+        let error = reader.cursor().collect::<Result<Vec<_>, _>>().unwrap_err();
+        assert!(error.contains("checksum"), "{error}");
+
+        let reader = Reader::open_with(&path, Verify::Trust).unwrap();
+        let read: Vec<Row> = reader.cursor().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read[1], (1, 1, 1));
+        assert_eq!(read[2..], rows[2..]);
+
+        // The trailer is checked regardless.
+        let n = file.len();
+        file[n - 100] ^= 1;
+        std::fs::write(&path, &file).unwrap();
+        assert!(Reader::open_with(&path, Verify::Trust).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn paranoid_checks_order() {
+        let path = temp_path("paranoid");
+        let options = Options {
+            block_size: 4096,
+            ..Options::default()
+        };
+        let rows: Vec<Row> = (0..1000).map(|key| (key * 2, 0, 1)).collect();
+        write(&path, (0, 0), &options, rows.iter().map(|&row| Ok(row))).unwrap();
+        let good = std::fs::read(&path).unwrap();
+
+        // Rewrites the key of row `row` of data block `block` and reseals the
+        // block, so that only the order is wrong.
+        let rewrite = |block: usize, row: usize, key: u64| {
+            let reader = Reader::open(&path).unwrap();
+            let entry = reader.entries[block];
+            let (start, end) = (
+                entry.offset as usize,
+                (entry.offset + entry.size as u64) as usize,
+            );
+            let mut file = good.clone();
+            put(
+                &mut file[start..end],
+                HEADER_SIZE + row * ROW_SIZE,
+                &key.to_le_bytes(),
+            );
+            seal(&mut file[start..end], options.algorithm);
+            std::fs::write(&path, &file).unwrap();
+        };
+        let per_block = (options.block_size - HEADER_SIZE) / ROW_SIZE;
+        for (block, row, key, expected) in [
+            (0, 5, 7, "row 5 is out of order"),
+            (1, 1, 0, "row 1 is out of order"),
+            (1, per_block - 1, u64::MAX, "next block's first row"),
+        ] {
+            rewrite(block, row, key);
+            let reader = Reader::open(&path).unwrap();
+            assert!(reader.cursor().collect::<Result<Vec<_>, _>>().is_ok());
+
+            let reader = Reader::open_with(&path, Verify::Paranoid).unwrap();
+            let error = reader.cursor().collect::<Result<Vec<_>, _>>().unwrap_err();
+            assert!(error.contains(expected), "{error}");
+
+            // A block in the cache is checked again.
+            let error = reader.cursor().collect::<Result<Vec<_>, _>>().unwrap_err();
+            assert!(error.contains(expected), "{error}");
+        }
+        std::fs::write(&path, &good).unwrap();
+        let reader = Reader::open_with(&path, Verify::Paranoid).unwrap();
+        let read: Vec<Row> = reader.cursor().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, rows);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "seek to 450 picked data block 3")]
+    fn seek_cross_check_catches_bad_index_entry() {
+        let path = temp_path("verify-seek");
+        let options = Options {
+            block_size: 4096,
+            ..Options::default()
+        };
+        write(
+            &path,
+            (0, 0),
+            &options,
+            (0..1000).map(|key| Ok((key, 0, 1))),
+        )
+        .unwrap();
+        let mut reader = Reader::open(&path).unwrap();
+        reader.verify_seeks = true;
+        assert_eq!(reader.seek(450).unwrap().next(), Some(Ok((450, 0, 1))));
+
+        // With every block in the cache, already verified, an index entry
+        // that starts block 3 too early sends the seek there, past the key.
+        assert!(reader.entries.len() <= CACHE_BLOCKS);
+        assert!(reader.cursor().all(|row| row.is_ok()));
+        std::fs::remove_file(&path).unwrap();
+        reader.entries[3].first = (400, 0);
+        let _ = reader.seek(450);
+    }
+
+    #[test]
+    fn reader_rejects_data_block_header_flips() {
+        let path = temp_path("flips");
+        let rows = (0..100).map(|key| Ok((key, 0, 1)));
+        write(&path, (0, 0), &Options::default(), rows).unwrap();
+        let good = std::fs::read(&path).unwrap();
+        let first_block = 2 * BLOCK_SIZE;
+        for bit in 0..HEADER_SIZE * 8 {
+            let mut file = good.clone();
+            file[first_block + bit / 8] ^= 1 << (bit % 8);
+            std::fs::write(&path, &file).unwrap();
+            let reader = Reader::open(&path).unwrap();
+            let result: Result<Vec<Row>, String> = reader.cursor().collect();
+            assert!(result.is_err(), "flip of header bit {bit} not detected");
+        }
+
+        // A schema that lost a field, under a valid checksum, is still
+        // rejected when the file is opened.
+        let mut superblock = Superblock::read(&good).unwrap();
+        superblock.schema[0].fields.truncate(1);
+        superblock.schema[0].key.truncate(1);
+        let schema = superblock.schema_block();
+        assert_eq!(schema.len(), BLOCK_SIZE);
+        let mut file = good.clone();
+        file[BLOCK_SIZE..2 * BLOCK_SIZE].copy_from_slice(&schema);
+        std::fs::write(&path, &file).unwrap();
+        let error = Reader::open(&path).err().unwrap();
+        assert!(error.contains("two BIGINT fields"), "{error}");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,3989 @@
+//! Models of the storage format that README.md and format.md describe, the
+//! subcommands that print and check them, and the prototype of the storage
+//! engine in [storage] and [layer], which `examples/pipeline.rs` uses.
+
+#![allow(unused)]
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+mod append;
+mod batches;
+mod checksum;
+mod columns;
+mod compression;
+mod consolidate;
+mod container;
+mod deltas;
+mod export;
+mod failpoint;
+mod filters;
+mod growth;
+mod ingest;
+mod keys;
+pub mod layer;
+mod packing;
+pub mod rng;
+mod separators;
+mod simulate;
+mod soak;
+mod sort;
+mod spine;
+pub mod storage;
+mod summary;
+mod superblock;
+mod traces;
+mod upserts;
+mod validate;
+mod varlen;
+mod weights;
+mod write;
+mod zonemaps;
+mod zset;
+
+use append::AppendArgs;
+use batches::SmallBatchesArgs;
+use checksum::ChecksumArgs;
+use columns::ColumnsArgs;
+use compression::CompressionArgs;
+use consolidate::ConsolidateArgs;
+use container::ContainerArgs;
+use deltas::DeltasArgs;
+use export::ExportArgs;
+use filters::FiltersArgs;
+use growth::GrowthArgs;
+use ingest::IngestArgs;
+use keys::KeysArgs;
+use packing::PackingArgs;
+use separators::SeparatorsArgs;
+use simulate::SimulateArgs;
+use soak::SoakArgs;
+use spine::SpineArgs;
+use summary::SummaryArgs;
+use superblock::SuperblockArgs;
+use traces::TracesArgs;
+use upserts::UpsertsArgs;
+use validate::ValidateArgs;
+use varlen::VarlenArgs;
+use weights::WeightsArgs;
+use write::WriteArgs;
+use zonemaps::ZoneMapsArgs;
+use zset::ZSetArgs;
+
+const TB: u64 = 1 << 40;
+const GB: u64 = 1 << 30;
+const MB: u64 = 1 << 20;
+const KB: u64 = 1 << 10;
+
+#[derive(Clone)]
+struct Params {
+    // Total size of all of the data stored in the file, in bytes.
+    total_data_size: u64,
+
+    /// Size of each individual value in the file, in bytes.  There are
+    /// `total_data_size / value_size` of these values.
+    value_size: u64,
+
+    /// Minimum size of a data block, in bytes.  This should be a power of 2,
+    /// 4096 or greater, probably no more than a few megabytes.
+    min_data_block: u64,
+
+    /// Minimum size of an index block, in bytes.  This should be a power of 2,
+    /// 4096 or greater, probably no more than a few megabytes.
+    min_index_block: u64,
+
+    /// Minimum branching factor.  This should be at least 4 and probably no
+    /// more than 100 or so.
+    min_branch: u64,
+
+    /// Fraction of the slots in a hash index that are occupied, between 0
+    /// and 1.
+    hash_load_factor: f64,
+
+    /// Size of a bucket in a hash index, in bytes.
+    hash_bucket_size: u64,
+
+    /// Size of a pointer from a hash index entry to its data block, in bytes.
+    hash_pointer_size: u64,
+
+    /// Size of the fingerprint (a hash of the value) that each hash index
+    /// entry holds in place of the value, in bytes, or `None` to hold the
+    /// whole value.
+    hash_fingerprint: Option<u64>,
+
+    /// Number of values covered by each entry in the data index.  If this is
+    /// `None`, the data index is sparse, with one entry per data block.
+    /// Otherwise, there is one entry per run of this many values (but no
+    /// more than one per data block).
+    index_run: Option<u64>,
+
+    /// Values in each data index entry.
+    data_entry: DataEntry,
+
+    /// How index block sizes are rounded up from the space their entries
+    /// take.
+    block_rounding: BlockRounding,
+
+    /// Fraction of each data block that is filled with values, up to 1.  A
+    /// streaming writer that closes blocks early, e.g. at the end of each
+    /// batch or on a timer, leaves them partly full.
+    fill_factor: f64,
+
+    /// Number of data blocks covered by each entry in the row indexes.  With
+    /// 1, the row indexes have an entry for every data block.  Otherwise, they
+    /// have one for every run of this many data blocks, and seeking to a row
+    /// reads forward through the run to the block that holds it.
+    row_sample: u64,
+
+    /// Size of the checksum of its child that each index entry carries, in
+    /// bytes, or 0 if entries carry none.
+    child_checksum: u64,
+
+    /// Minimum index block size for index levels above L1, starting with L2,
+    /// with the last one applying to all of the levels above it, if they
+    /// differ from `min_index_block`.  Big L1 blocks keep the index short,
+    /// whereas the upper levels are usually cached, so small blocks there
+    /// waste less memory.
+    upper_index_block: Vec<u64>,
+
+    /// Minimum branching factor for index levels above L1, if it differs from
+    /// `min_branch`.
+    upper_min_branch: Option<u64>,
+
+    /// Bits of entropy per byte of a value, for the trie index.  Random
+    /// binary keys have 8; text keys typically have much less.
+    key_entropy: f64,
+
+    /// Value sizes that the tables report on, one row (or group of rows) for
+    /// each, in bytes.
+    value_sizes: Vec<u64>,
+}
+
+/// Parses `--index-run`, which must be positive, since an index entry per
+/// run of 0 values would never cover the data.
+fn parse_index_run(s: &str) -> Result<u64, String> {
+    match s.trim().parse::<u64>() {
+        Ok(0) => Err(format!("{s}: index run must be positive")),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{s}: {e}")),
+    }
+}
+
+/// A list of value sizes, parsed from comma-separated items that are each
+/// either a size, `LOW..HIGH` for the powers of 2 times `LOW` up to
+/// `HIGH`, or `LOW..HIGH+STEP` for every `STEP` bytes from `LOW` up to
+/// `HIGH`, e.g. `24,56,200` or `16..1024,1000..4000+1000`.
+#[derive(Clone, Debug)]
+struct ValueSizes(Vec<u64>);
+
+impl FromStr for ValueSizes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| match n.trim().parse::<u64>() {
+            Ok(0) => Err(format!("{n}: value size must be positive")),
+            Ok(n) => Ok(n),
+            Err(e) => Err(format!("{n}: {e}")),
+        };
+        let mut sizes = Vec::new();
+        for item in s.split(',').filter(|item| !item.is_empty()) {
+            let Some((low, high)) = item.split_once("..") else {
+                sizes.push(number(item)?);
+                continue;
+            };
+            let (high, step) = match high.split_once('+') {
+                Some((high, step)) => (high, Some(number(step)?)),
+                None => (high, None),
+            };
+            let (mut size, high) = (number(low)?, number(high)?);
+            while size <= high {
+                sizes.push(size);
+                size = match step {
+                    Some(step) => size + step,
+                    None => size * 2,
+                };
+            }
+        }
+        if sizes.is_empty() {
+            return Err(format!("{s}: no value sizes"));
+        }
+        Ok(ValueSizes(sizes))
+    }
+}
+
+/// The values that each data index entry holds from its child.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum DataEntry {
+    /// Only the first value in the child, as a separator.
+    First,
+
+    /// The first and the last value in the child.
+    FirstLast,
+}
+
+/// How to round up the size of an index block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum BlockRounding {
+    /// No rounding: an index block is exactly as big as its entries.
+    None,
+
+    /// Up to the next power of 2.
+    #[clap(name = "pow2")]
+    PowerOfTwo,
+
+    /// Up to the next multiple of 4 kB.
+    #[clap(name = "4k")]
+    Page,
+}
+
+impl BlockRounding {
+    /// Returns `size` rounded up.
+    fn round(&self, size: u64) -> u64 {
+        match self {
+            BlockRounding::None => size,
+            BlockRounding::PowerOfTwo => size.next_power_of_two(),
+            BlockRounding::Page => size.next_multiple_of(4096),
+        }
+    }
+}
+
+impl DataEntry {
+    /// Returns the number of values per data index entry.
+    fn values(&self) -> u64 {
+        match self {
+            DataEntry::First => 1,
+            DataEntry::FirstLast => 2,
+        }
+    }
+}
+
+impl Params {
+    /// Returns the total number of values stored in the file.
+    fn total_values(&self) -> u64 {
+        self.total_data_size / self.value_size
+    }
+
+    /// Returns a description of those of the fill factor, child checksum,
+    /// block rounding, and upper index block and branching parameters that
+    /// don't have their default values, to append to a description of the
+    /// other parameters, or an empty string if they all have their defaults.
+    fn describe_non_defaults(&self) -> String {
+        let mut s = String::new();
+        if self.fill_factor < 1.0 {
+            s += &format!(", fill_factor={}", self.fill_factor);
+        }
+        if self.child_checksum > 0 {
+            s += &format!(", child_checksum={}", self.child_checksum);
+        }
+        if self.block_rounding != BlockRounding::None {
+            let rounding = self.block_rounding.to_possible_value().unwrap();
+            s += &format!(", block_rounding={}", rounding.get_name());
+        }
+        if !self.upper_index_block.is_empty() {
+            let sizes: Vec<_> = self
+                .upper_index_block
+                .iter()
+                .map(|size| size.to_string())
+                .collect();
+            s += &format!(", upper_index_block={}", sizes.join(","));
+        }
+        if let Some(upper_min_branch) = self.upper_min_branch {
+            s += &format!(", upper_min_branch={upper_min_branch}");
+        }
+        s
+    }
+}
+
+struct Index {
+    params: Params,
+
+    /// Type of the index.  For [IndexType::Hash], the index has a single
+    /// level of buckets rather than a tree, but the rest of the members are
+    /// still meaningful, treating a bucket as an index block.
+    index_type: IndexType,
+
+    /// Size of each index entry in bytes.
+    index_entry_size: u64,
+
+    /// Number of values covered by each entry in a level-1 index block.
+    /// Usually, this is the number of values in a data block.
+    values_per_entry: u64,
+
+    /// Number of `index_entry_size` items that fit in a level-1 index block.
+    entries_per_block: u64,
+
+    /// Size of a level-1 index block.
+    block_size: u64,
+
+    /// Size of an index block at each level, starting with level 1.  Same
+    /// length as `coverage`.
+    level_block_sizes: Vec<u64>,
+
+    /// `coverage[0]` is the number of values covered by a level-1 index block,
+    /// that is, `values_per_data_block * entries_per_index_block`.
+    ///
+    /// `coverage[1]` is the number of values covered by a level-2 index block,
+    /// that is, the number of entries in a level-2 index block times
+    /// `coverage[0]`.
+    ///
+    /// There are as many elements as necessary so that the final element is
+    /// greater than or equal to `params.total_values()`.
+    coverage: Vec<u64>,
+
+    /// Height of the index.  Same as `coverage.len()`.
+    height: usize,
+}
+
+impl Index {
+    fn new(
+        params: &Params,
+        index_type: IndexType,
+        index_entry_size: u64,
+        values_per_entry: u64,
+    ) -> Self {
+        let params = params.clone();
+
+        // Rounding a block up leaves room for more entries, and whatever is
+        // left after the last entry that fits is slack.
+        let round = |entries: u64| {
+            let block_size = params.block_rounding.round(entries * index_entry_size);
+            (block_size / index_entry_size, block_size)
+        };
+        let (entries_per_index_block, index_block_size) =
+            round((params.min_index_block / index_entry_size).max(params.min_branch));
+        let upper_entries_per_block = |level: usize| {
+            let min_block = params
+                .upper_index_block
+                .get(level - 1)
+                .or(params.upper_index_block.last())
+                .copied()
+                .unwrap_or(params.min_index_block);
+            (min_block / index_entry_size).max(params.upper_min_branch.unwrap_or(params.min_branch))
+        };
+
+        let mut coverage = Vec::new();
+        let mut level_block_sizes = Vec::new();
+        loop {
+            let last = coverage.last().copied().unwrap_or(values_per_entry);
+            if last >= params.total_values() {
+                break;
+            }
+            let (entries, block_size) = match coverage.len() {
+                0 => (entries_per_index_block, index_block_size),
+                level => round(upper_entries_per_block(level)),
+            };
+            coverage.push(last.saturating_mul(entries));
+            level_block_sizes.push(block_size);
+        }
+        let height = coverage.len();
+
+        Index {
+            params,
+            index_type,
+            index_entry_size,
+            values_per_entry,
+            entries_per_block: entries_per_index_block,
+            block_size: index_block_size,
+            level_block_sizes,
+            coverage,
+            height,
+        }
+    }
+
+    /// Returns a hash index over all of the values, with each entry holding a
+    /// value (or its fingerprint) and a pointer to its data block.
+    fn new_hash(params: &Params) -> Self {
+        let params = params.clone();
+
+        let key_size = params
+            .hash_fingerprint
+            .map_or(params.value_size, |size| size.min(params.value_size));
+        let index_entry_size = key_size + params.hash_pointer_size;
+        let entries_per_bucket = (params.hash_bucket_size / index_entry_size).max(1);
+        let bucket_size = index_entry_size * entries_per_bucket;
+        let values_per_bucket =
+            ((entries_per_bucket as f64 * params.hash_load_factor) as u64).max(1);
+
+        Index {
+            params,
+            index_type: IndexType::Hash,
+            index_entry_size,
+            values_per_entry: 1,
+            entries_per_block: entries_per_bucket,
+            block_size: bucket_size,
+            level_block_sizes: vec![bucket_size],
+            coverage: vec![values_per_bucket],
+            height: 1,
+        }
+    }
+
+    /// For a hash index, returns the expected number of overflow buckets that
+    /// a lookup of a value in the file reads, in addition to its home bucket.
+    ///
+    /// Assuming a uniform hash, the number of values that hash to a bucket is
+    /// Poisson distributed with mean `coverage[0]`.  A bucket holds
+    /// `entries_per_block` of them and chains the rest into overflow buckets
+    /// of the same size, so the `j`th value (counting from 0) in a bucket
+    /// costs `j / entries_per_block` extra reads.
+    fn overflow_reads(&self) -> f64 {
+        if self.index_type != IndexType::Hash {
+            return 0.0;
+        }
+        let mean = self.coverage[0] as f64;
+        let capacity = self.entries_per_block;
+        let limit = (mean + 20.0 * mean.sqrt() + 20.0) as u64;
+        let mut ln_factorial = 0.0;
+        let mut extra = 0.0;
+        for k in 0..=limit {
+            if k > 0 {
+                ln_factorial += (k as f64).ln();
+            }
+            let probability = (k as f64 * mean.ln() - mean - ln_factorial).exp();
+            let (q, r) = (k / capacity, k % capacity);
+            extra += probability * (capacity * q * q.saturating_sub(1) / 2 + q * r) as f64;
+        }
+        extra / mean
+    }
+
+    /// Returns the number of index blocks at `level`, where level 0 is the
+    /// level just above the data blocks and `self.height - 1` is the root, or
+    /// 0 if the index doesn't reach `level`.
+    fn blocks_at_level(&self, level: usize) -> u64 {
+        self.coverage
+            .get(level)
+            .map_or(0, |&coverage| self.params.total_values().div_ceil(coverage))
+    }
+
+    /// Returns the number of values covered by the last index block at
+    /// `level`.  Every other block at the level is full and covers
+    /// `self.coverage[level]` values, but the last one gets whatever is left.
+    /// Returns 0 if the index doesn't reach `level`.
+    fn last_block_coverage(&self, level: usize) -> u64 {
+        let Some(&coverage) = self.coverage.get(level) else {
+            return 0;
+        };
+        let total = self.params.total_values();
+        total - (self.blocks_at_level(level) - 1) * coverage
+    }
+
+    /// Returns the fraction of the root block's capacity for entries that is
+    /// used, which is small just past a transition to a taller index, or
+    /// `None` if the index has no blocks because one child covers the file.
+    fn root_fill(&self) -> Option<f64> {
+        let root = self.height.checked_sub(1)?;
+        let child_coverage = match root {
+            0 => self.values_per_entry,
+            _ => self.coverage[root - 1],
+        };
+        let entries = self.params.total_values().div_ceil(child_coverage);
+        let capacity = self.level_block_sizes[root] / self.index_entry_size;
+        Some(entries as f64 / capacity as f64)
+    }
+
+    /// Returns the bytes at the end of each index block at `level` that are
+    /// too few for another entry.
+    fn slack_at_level(&self, level: usize) -> u64 {
+        self.level_block_sizes[level] % self.index_entry_size
+    }
+
+    /// Returns the size of an index block at `level`.
+    fn block_size_at_level(&self, level: usize) -> u64 {
+        self.level_block_sizes[level]
+    }
+
+    /// Returns the number of bytes in the index, across all levels of the
+    /// index.
+    fn total_size(&self) -> u64 {
+        (0..self.height)
+            .map(|level| self.blocks_at_level(level) * self.block_size_at_level(level))
+            .sum()
+    }
+
+    /// Returns the number of bytes in the top `levels` levels of the index,
+    /// that is, the memory needed to keep those levels resident.  If `levels`
+    /// is greater than the height of the index, this is the whole index.
+    fn pinned_size(&self, levels: usize) -> u64 {
+        (self.height.saturating_sub(levels)..self.height)
+            .map(|level| self.blocks_at_level(level) * self.block_size_at_level(level))
+            .sum()
+    }
+
+    /// Models replacing every level of this index above level 0 by a learned
+    /// index, that is, a piecewise-linear model that predicts the position of
+    /// a key among the level-0 index blocks to within `max_error` blocks.
+    ///
+    /// Also models replacing the whole index, level 0 included, by a model
+    /// that predicts the position of a key among the blocks that the index
+    /// points to, which eliminates a level of the index if the prediction is
+    /// good enough.
+    fn learned(&self, max_error: u64, keys: LearnedKeys) -> LearnedIndex {
+        // Returns the size of a model that predicts positions among `blocks`
+        // blocks to within `max_error`, and the number of blocks that a lookup
+        // has to read to binary search the candidates.
+        let model = |blocks: u64| {
+            let (segments, key_size) = match (self.index_type, keys) {
+                // Row numbers advance by a fixed amount per data block, and so
+                // do sequential keys, so a single segment predicts them
+                // exactly.
+                (IndexType::C1Row | IndexType::Row, _) => (1, 8),
+                (_, LearnedKeys::Sequential) => (1, self.params.value_size),
+
+                // For keys from a random distribution, the expected number of
+                // keys covered by a segment grows with the square of the error
+                // bound (see the PGM-index paper).
+                (_, LearnedKeys::Random) => (
+                    blocks.div_ceil((max_error * max_error).max(1)),
+                    self.params.value_size,
+                ),
+            };
+
+            // Each segment has a first key, a slope, and an intercept.
+            let model_size = segments * (key_size + 16);
+
+            let candidates = match (segments, self.index_type) {
+                (1, _) | (_, IndexType::C1Row | IndexType::Row) => 1,
+                _ => (2 * max_error + 1).min(blocks),
+            };
+            let reads = (candidates + 1).next_power_of_two().ilog2() as u64;
+            (model_size, reads)
+        };
+
+        // A lookup binary searches the candidate level-0 blocks, where the
+        // tree with its upper levels pinned reads just one.
+        let (model_size, extra_probes, replaced_size) = if self.height > 1 {
+            let (model_size, reads) = model(self.blocks_at_level(0));
+            (model_size, reads - 1, self.pinned_size(self.height - 1))
+        } else {
+            (0, 0, 0)
+        };
+
+        // The blocks that level 0 points to.  For the data index, these are
+        // data blocks; otherwise, treat them the same way.
+        let children = self.params.total_values().div_ceil(self.values_per_entry);
+        let (whole_model_size, whole_reads) = model(children);
+
+        LearnedIndex {
+            replaced_size,
+            model_size,
+            extra_probes,
+            whole_model_size,
+            whole_reads,
+        }
+    }
+}
+
+/// Distribution of the keys for modeling a learned index.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum LearnedKeys {
+    /// Keys drawn at random from some distribution.
+    Random,
+
+    /// Numeric keys that increase by a fixed step, such as sequence numbers.
+    Sequential,
+}
+
+/// Order for the rows of the coverage tables.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum SortBy {
+    /// Size of the index.
+    IndexSize,
+
+    /// Height of the index.
+    Height,
+
+    /// Size of the whole layer file, that is, its data blocks plus every
+    /// index in the table.
+    TotalSize,
+}
+
+/// A learned index replacing the upper levels of an [Index].
+struct LearnedIndex {
+    /// Size of the index levels that the model replaces.
+    replaced_size: u64,
+
+    /// Size of the model.
+    model_size: u64,
+
+    /// Worst-case number of additional index block reads per lookup, compared
+    /// to an index whose upper levels are kept in memory.
+    extra_probes: u64,
+
+    /// Size of a model that replaces the whole index.
+    whole_model_size: u64,
+
+    /// Worst-case number of block reads per lookup with the whole index
+    /// replaced by a model in memory, compared to 2 (one level-0 index block
+    /// and one data block) for an index whose upper levels are in memory.  If
+    /// this is 1, the model eliminates an index level.
+    whole_reads: u64,
+}
+
+struct LayerFile {
+    params: Params,
+
+    /// Number of data values that fit in a data block.
+    values_per_data_block: u64,
+
+    /// Size of a data block.
+    data_block_size: u64,
+
+    /// Number of data blocks to fill up `TOTAL_DATA_SIZE`.
+    total_data_blocks: u64,
+
+    indexes: Vec<Index>,
+}
+
+impl LayerFile {
+    fn new(params: &Params) -> Self {
+        let values_per_data_block =
+            (params.min_data_block / params.value_size).max(params.min_branch);
+        Self::with_values_per_data_block(params, values_per_data_block)
+    }
+
+    /// Returns a layer file with `values_per_data_block` values in each data
+    /// block, for when something other than the values themselves (such as
+    /// weights) also takes space in the data blocks.
+    ///
+    /// With a fill factor below 1, each data block still takes the space of
+    /// `values_per_data_block` values but holds only that fraction of them.
+    fn with_values_per_data_block(params: &Params, values_per_data_block: u64) -> Self {
+        let data_block_size = params.value_size * values_per_data_block;
+        let values_per_data_block =
+            ((values_per_data_block as f64 * params.fill_factor.clamp(0.0, 1.0)) as u64).max(1);
+        let total_data_blocks = params.total_values() / values_per_data_block;
+
+        // Each entry in the data index contains one value (the first in the
+        // child block, or in the run of values that it covers), or two values
+        // if it also contains the last value.
+        let values_per_data_entry = params
+            .index_run
+            .map_or(values_per_data_block, |run| run.min(values_per_data_block));
+        //
+        // Each entry in the data index, the row indexes, and the trie index
+        // can also carry its child's checksum.
+        let data_index = Index::new(
+            params,
+            IndexType::Data,
+            params.value_size * params.data_entry.values() + params.child_checksum,
+            values_per_data_entry,
+        );
+
+        // The row index in column 1 contains the child block's offset, size,
+        // and whether it is an index or data block.  6 bytes is enough.  A
+        // sparse row index covers a run of data blocks with each entry, which
+        // a reader finds through the offset and size of the first one.
+        let values_per_row_entry = values_per_data_block * params.row_sample.max(1);
+        let c1row_index = Index::new(
+            params,
+            IndexType::C1Row,
+            6 + params.child_checksum,
+            values_per_row_entry,
+        );
+
+        // The row index in other columns also needs the child's starting row
+        // number.
+        let row_index = Index::new(
+            params,
+            IndexType::Row,
+            12 + params.child_checksum,
+            values_per_row_entry,
+        );
+
+        // This assumes that we put 32k values into each filter block, which
+        // means that the filter blocks are between about 32 kB (8 bits per
+        // value) and 64 kB (16 bits per value) each.
+        //
+        // This probably won't be how we organize the filters, so this index
+        // isn't shown by default.
+        let filter_index = Index::new(params, IndexType::Filter, params.value_size, 32768);
+
+        // A hash index over every value, for comparison against the data
+        // index for point lookups.
+        let hash_index = Index::new_hash(params);
+
+        // A trie-style index stores only as much of each separator as it
+        // takes to distinguish it from its neighbors, sharing the common
+        // prefix among the entries in a node, as an adaptive radix tree does.
+        // Among `n` values with `key_entropy` bits per byte, adjacent values
+        // first differ after about `log2(n) / key_entropy` bytes, and one more
+        // byte distinguishes them.  Each entry also has a 1-byte length and a
+        // 6-byte child pointer.
+        let distinguishing = ((params.total_values() as f64).log2()
+            / params.key_entropy.clamp(0.1, 8.0))
+        .ceil() as u64
+            + 1;
+        let trie_index = Index::new(
+            params,
+            IndexType::Trie,
+            distinguishing.min(params.value_size) + 7 + params.child_checksum,
+            values_per_data_entry,
+        );
+
+        // A secondary index is a separate tree sorted by value, for a column
+        // whose rows aren't in value order.  It has to be dense, so its
+        // bottom level has an entry for every value, with the value and its
+        // row number and data block (12 bytes, as in the row index).  The
+        // entries in the levels above have the same form, pointing to the
+        // first entry in the child.
+        let secondary_index = Index::new(params, IndexType::Secondary, params.value_size + 12, 1);
+
+        Self {
+            params: params.clone(),
+            values_per_data_block,
+            data_block_size,
+            total_data_blocks,
+            indexes: vec![
+                data_index,
+                c1row_index,
+                row_index,
+                filter_index,
+                hash_index,
+                secondary_index,
+                trie_index,
+            ],
+        }
+    }
+
+    /// Returns the index of type `index_type`.
+    fn index(&self, index_type: IndexType) -> &Index {
+        self.indexes
+            .iter()
+            .find(|index| index.index_type == index_type)
+            .unwrap()
+    }
+
+    /// Returns the number of bytes in those of the indexes whose types are in
+    /// `indexes`.
+    fn index_size(&self, indexes: &[IndexType]) -> u64 {
+        self.indexes
+            .iter()
+            .filter(|index| indexes.contains(&index.index_type))
+            .map(Index::total_size)
+            .sum()
+    }
+
+    /// Returns the number of bytes in data blocks, which is more than the
+    /// data itself if the blocks are only partly full.
+    fn data_size(&self) -> u64 {
+        (self.params.total_data_size as f64 / self.params.fill_factor.clamp(0.01, 1.0)) as u64
+    }
+}
+
+struct HumanBytes(u64);
+impl Display for HumanBytes {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = if self.0 >= 10 * TB - TB / 10 || (self.0 >= TB && self.0.is_multiple_of(TB)) {
+            format!("{} TB", self.0 / TB)
+        } else if self.0 >= TB {
+            format!("{:.1} TB", self.0 as f64 / TB as f64)
+        } else if self.0 >= 10 * GB - GB / 10 {
+            format!("{} GB", self.0 / GB)
+        } else if self.0 >= GB {
+            format!("{:.1} GB", self.0 as f64 / GB as f64)
+        } else if self.0 >= 10 * MB - MB / 10 {
+            format!("{} MB", self.0 / MB)
+        } else if self.0 >= MB {
+            format!("{:.1} MB", self.0 as f64 / MB as f64)
+        } else if self.0 >= KB {
+            format!("{} kB", self.0 / KB)
+        } else {
+            format!("{}", self.0)
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+struct HumanCount(u64);
+impl Display for HumanCount {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        const QUADRILLION: u64 = 1_000_000_000_000_000;
+        const TRILLION: u64 = 1_000_000_000_000;
+        const BILLION: u64 = 1_000_000_000;
+        const MILLION: u64 = 1_000_000;
+        const THOUSAND: u64 = 1_000;
+        let s = if self.0 >= QUADRILLION {
+            format!("{} Q", self.0 / QUADRILLION)
+        } else if self.0 >= TRILLION {
+            format!("{} T", self.0 / TRILLION)
+        } else if self.0 >= BILLION {
+            format!("{} B", self.0 / BILLION)
+        } else if self.0 >= MILLION {
+            format!("{} M", self.0 / MILLION)
+        } else if self.0 >= THOUSAND {
+            format!("{} k", self.0 / THOUSAND)
+        } else {
+            format!("{}", self.0)
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Minimum branching factor in data and index blocks.
+    #[clap(long, default_value_t = 32)]
+    min_branch: u64,
+
+    /// Minimum data block size, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    min_data_block: u64,
+
+    /// Minimum index block size, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    min_index_block: u64,
+
+    /// Minimum index block size for index levels above L1, in bytes.  By
+    /// default, this is the same as `--min-index-block`.  Given more than
+    /// once, each one applies to the next level up, starting with L2, and the
+    /// last one applies to all of the levels above it, e.g. for big L1 and L2
+    /// blocks in object storage and small blocks above them in RAM.
+    #[clap(long)]
+    upper_index_block: Vec<u64>,
+
+    /// Minimum branching factor for index levels above L1.  By default, this
+    /// is the same as `--min-branch`.
+    #[clap(long)]
+    upper_min_branch: Option<u64>,
+
+    /// Total data size, as a power of 2 exponent, e.g. 30 for 1 GB, 37 for 128
+    /// GB, 40 for 1 TB.
+    #[clap(long, default_value_t = 40)]
+    total_data_size: u32,
+
+    /// Value sizes to report on, in bytes, as comma-separated sizes and
+    /// ranges: `LOW..HIGH` doubles from `LOW` up to `HIGH`, and
+    /// `LOW..HIGH+STEP` counts up by `STEP`, e.g. `24,56,200`.
+    #[clap(long, value_name = "SIZES", default_value = "16..65536")]
+    value_sizes: ValueSizes,
+
+    /// Index(es) to display.
+    ///
+    /// We probably won't organize filters using their own index, so it isn't
+    /// shown by default.
+    #[clap(long="index", default_values_t = vec![IndexType::Data, IndexType::C1Row, IndexType::Row])]
+    indexes: Vec<IndexType>,
+
+    /// Parameter group to model, as comma-separated `name=value` pairs that
+    /// override `--min-branch`, `--min-data-block`, `--min-index-block`,
+    /// `--index-run`, `--row-sample`, `--fill-factor`, `--child-checksum`,
+    /// `--data-entry`, `--block-rounding`, `--upper-index-block`, and
+    /// `--upper-min-branch`, e.g. `min-branch=16,min-data-block=4096`.
+    ///
+    /// If this is given more than once, the results for each group are
+    /// interleaved in a single table, with a column comparing each group's
+    /// index size against the first.
+    #[clap(long = "preset")]
+    presets: Vec<Preset>,
+
+    /// Reports the memory needed to keep the top K levels of each index
+    /// resident in RAM.
+    #[clap(long, value_name = "K")]
+    pin_levels: Option<usize>,
+
+    /// Number of values covered by each data index entry.  By default, the
+    /// data index is sparse, with one entry per data block, which means that a
+    /// lookup has to search the whole data block for its key.  With this
+    /// option, there is one entry per run of N values, which makes the index
+    /// bigger but reduces the in-block search to N values.
+    ///
+    /// This also adds a column that reports the average number of values
+    /// scanned within a data block per lookup.
+    #[clap(long, value_name = "N", value_parser = parse_index_run)]
+    index_run: Option<u64>,
+
+    /// Values from its child that each data index entry holds: just the first
+    /// value, as a separator, or both the first and last values, which lets
+    /// lookups for missing values stop early in rare cases but doubles the
+    /// size of the data index.
+    #[clap(long, value_enum, default_value_t = DataEntry::First)]
+    data_entry: DataEntry,
+
+    /// Rounds each index block up to the next power of 2 (`pow2`) or
+    /// multiple of 4 kB (`4k`), fitting as many more entries as the rounding
+    /// makes room for, and adds columns with the slack left at the end of
+    /// each block.
+    #[clap(long, value_enum, default_value_t = BlockRounding::None)]
+    block_rounding: BlockRounding,
+
+    /// Number of data blocks covered by each row index entry.  By default,
+    /// the row indexes have one entry per data block.  With N > 1, they only
+    /// index every Nth data block, which makes them about N times smaller but
+    /// means that seeking to a row reads up to N data blocks instead of 1.
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    row_sample: u64,
+
+    /// Fraction of each data block that holds values, e.g. 0.8 for the
+    /// partly full blocks of a streaming writer.  Blocks keep their size, so
+    /// there are more of them, which increases both the data on disk and the
+    /// number of index entries.
+    #[clap(long, default_value_t = 1.0)]
+    fill_factor: f64,
+
+    /// Size of a checksum of the child block to put in each index entry, in
+    /// bytes, e.g. 8 for xxHash64 or 32 for BLAKE3, which lets a reader
+    /// verify every block against its parent, from the trailer down.
+    #[clap(long, value_name = "BYTES", default_value_t = 0)]
+    child_checksum: u64,
+
+    /// Fraction of occupied slots in the hash index, for `--index hash`.
+    #[clap(long, default_value_t = 0.75)]
+    hash_load_factor: f64,
+
+    /// Size of a hash index bucket, in bytes, for `--index hash`.
+    #[clap(long, default_value_t = 4096)]
+    hash_bucket_size: u64,
+
+    /// Size of the pointer to a data block in each hash index entry, in bytes,
+    /// for `--index hash`.
+    #[clap(long, default_value_t = 6)]
+    hash_pointer_size: u64,
+
+    /// Size of a fingerprint of the value to store in each hash index entry
+    /// in place of the value itself, in bytes, for `--index hash`.  A lookup
+    /// checks the value in the data block anyway, so a fingerprint only has
+    /// to make false matches rare.
+    #[clap(long, value_name = "BYTES")]
+    hash_fingerprint: Option<u64>,
+
+    /// Bits of entropy per byte of each value, for `--index trie`.  This is
+    /// 8 for random binary keys, less for text.  Values are assumed to share
+    /// no common prefix (a prefix shared by every value costs a trie nothing).
+    #[clap(long, default_value_t = 8.0)]
+    key_entropy: f64,
+
+    /// Number of simultaneously open files to assume for `--pin-levels`.
+    #[clap(long, default_value_t = 1)]
+    open_files: u64,
+
+    /// Models replacing the index levels above L1 by a learned index whose
+    /// predictions are within E level-1 index blocks of the right one, and
+    /// reports the memory saved and the extra probes per lookup.
+    ///
+    /// An interpolation search over uniformly distributed keys behaves like a
+    /// learned index with a single segment.
+    #[clap(long, value_name = "E")]
+    learned_error: Option<u64>,
+
+    /// Distribution of the keys for `--learned-error`.
+    #[clap(long, value_enum, default_value_t = LearnedKeys::Random)]
+    learned_keys: LearnedKeys,
+
+    /// Adds a column that reports the size of the index blocks at each level,
+    /// from L1 up, and the total bytes at each level.
+    #[clap(long)]
+    level_sizes: bool,
+
+    /// Adds columns with the blocks that a lookup by key and a lookup by row
+    /// number read through each index, counting every index level and the
+    /// data blocks, for the indexes that support each kind of lookup.
+    #[clap(long)]
+    access_paths: bool,
+
+    /// Adds columns with the number of data blocks in the layer file and the
+    /// number of blocks in each index, across all of its levels.
+    #[clap(long)]
+    block_counts: bool,
+
+    /// Adds columns with the worst case of the coverage columns, which are
+    /// the values covered by a full block at each level: the values covered
+    /// by the last block at each level, which is only partly full unless the
+    /// values happen to divide evenly, and how full the root block is.
+    #[clap(long)]
+    partial_coverage: bool,
+
+    /// Sorts the rows of the coverage table, in increasing order, instead of
+    /// listing them by value size.
+    #[clap(long, value_enum)]
+    sort_by: Option<SortBy>,
+
+    /// Leaves rows for indexes taller than N out of the coverage table.
+    #[clap(long, value_name = "N")]
+    max_height: Option<usize>,
+
+    /// Leaves rows for indexes bigger than BYTES out of the coverage table.
+    #[clap(long, value_name = "BYTES")]
+    max_index_size: Option<u64>,
+
+    /// Fraction of rows that are retractions (rows with negative weights that
+    /// cancel earlier rows), between 0 and 0.5.  Adds a table of the space
+    /// that they take before consolidation and the reduction in file size
+    /// after a full merge.
+    #[clap(long, value_name = "FRACTION")]
+    retractions: Option<f64>,
+
+    /// Fraction of rows whose weight is +1, between 0 and 1.  Adds a table of
+    /// the space that the weight column takes in the data blocks, stored
+    /// either as one `--weight-size` integer per row or with runs of +1
+    /// weights run-length encoded.
+    #[clap(long, value_name = "FRACTION")]
+    unit_weights: Option<f64>,
+
+    /// Size of each weight, in bytes, for `--unit-weights`.
+    #[clap(long, default_value_t = 8)]
+    weight_size: u64,
+
+    /// Average length of a run of consecutive +1 weights, for
+    /// `--unit-weights`.  By default, weights are independent, so that the
+    /// average run length is `1 / (1 - FRACTION)`.  Longer runs, e.g. from
+    /// inserting keys in order, make run-length encoding more effective.
+    #[clap(long, value_name = "N")]
+    unit_run: Option<f64>,
+
+    /// Compression ratio of data blocks, e.g. 3 for blocks that zstd
+    /// compresses to a third of their size, or about 2 for LZ4.  Adds a
+    /// table comparing uncompressed data blocks against compressed ones that
+    /// hold enough values to fill a block after compression.
+    #[clap(long, value_name = "R")]
+    compression_ratio: Option<f64>,
+
+    /// Number of distinct values in a column, for modeling dictionary
+    /// encoding.  Adds a table comparing plain data blocks against a
+    /// dictionary plus data blocks of fixed-width codes.
+    #[clap(long, value_name = "N")]
+    cardinality: Option<u64>,
+
+    /// Average number of distinct times per key, for modeling a trace in
+    /// which each key in column 1 has a row group of `(time, diff)` pairs in
+    /// column 2.  Adds a table of the index levels and space that the time
+    /// column adds.
+    #[clap(long, value_name = "T")]
+    times_per_key: Option<u64>,
+
+    /// Size of each `(time, diff)` pair in the time column, in bytes, for
+    /// `--times-per-key`.
+    #[clap(long, default_value_t = 16)]
+    time_size: u64,
+
+    /// Average number of rows per distinct key in column 1, as in an indexed
+    /// Z-set with D values per key.  Adds a table comparing a data index over
+    /// every row against a key-only index over the distinct keys, in which
+    /// each key in the data blocks carries the length of its run of rows.
+    #[clap(long, value_name = "D")]
+    duplicates_per_key: Option<f64>,
+
+    /// Adds a table comparing the size of the row index against succinct
+    /// structures that map row numbers to data blocks without a tree.
+    #[clap(long)]
+    rank_select: bool,
+
+    /// Adds a table comparing the column 1 row index against a flat table of
+    /// data block pointers in the file trailer, treating a table bigger than
+    /// BYTES as too big to read when the file is opened and keep in RAM.
+    #[clap(long, value_name = "BYTES")]
+    flat_row_limit: Option<u64>,
+
+    /// Adds a table that recommends, for the index levels pinned in RAM (the
+    /// top `--pin-levels` levels, or just the root without it), the number
+    /// of entries per block for which a binary search touches at most N
+    /// cache lines, and flags pinned levels too big for the L2 cache.
+    #[clap(long, value_name = "N")]
+    cache_lines: Option<u64>,
+
+    /// Adds a table comparing the overhead of the data in a single layer file
+    /// against the same data split evenly across N files, each with its own
+    /// top index levels, header and trailer blocks, and partly filled last
+    /// blocks.  Can be given more than once.
+    #[clap(long = "file-count", value_name = "N")]
+    file_counts: Vec<u64>,
+
+    /// Adds a table of the data index's lookup throughput with this many
+    /// reader threads, with all of the index cached, when every lookup takes
+    /// and releases a reference to each block that it visits.  Can be given
+    /// more than once.
+    #[clap(long = "reader-threads", value_name = "N")]
+    reader_threads: Vec<u64>,
+
+    /// Time to search a cached block, in nanoseconds, for `--reader-threads`.
+    #[clap(long, default_value_t = 100.0)]
+    probe_ns: f64,
+
+    /// Time for an atomic operation on a cache line that another core last
+    /// wrote, in nanoseconds, for `--reader-threads`.
+    #[clap(long, default_value_t = 50.0)]
+    atomic_ns: f64,
+
+    /// Adds columns with the p50 and p99 latency of a lookup, assuming that
+    /// every index level above L1 is cached and that the L1 index block and
+    /// the data block that a lookup reads are each missing from the cache,
+    /// and read from the device, with probability P.
+    #[clap(long, value_name = "P")]
+    leaf_miss: Option<f64>,
+
+    /// Mean latency of a block read from the device, in microseconds, for
+    /// `--leaf-miss`.  Device reads are modeled as exponentially distributed.
+    #[clap(long, default_value_t = 100.0)]
+    device_latency: f64,
+
+    /// Latency of reading and searching a cached block, in microseconds, for
+    /// `--leaf-miss`.
+    #[clap(long, default_value_t = 1.0)]
+    cached_latency: f64,
+
+    /// Adds a table comparing data index entries that hold only the first
+    /// value of each child against entries that hold its first and last
+    /// values, with the probability that a lookup for a missing value reads a
+    /// data block that first and last values would have ruled out.
+    #[clap(long)]
+    data_entries: bool,
+
+    /// Adds a table comparing filters with B bits per value placed one per
+    /// file, one per L1 index block, and one per data block, with their size,
+    /// false positive rate, where each has to live to save reads, and the
+    /// block reads for a lookup of a missing value.
+    #[clap(long, value_name = "B")]
+    filter_bits: Option<f64>,
+
+    /// Adds a table comparing the RAM needed to keep all of the filter
+    /// blocks and filter index blocks, for filters with B bits per value,
+    /// resident against paging them from the device, with the mean latency
+    /// of a lookup either way.  Paged reads miss the cache with the
+    /// probability given by `--leaf-miss`, or always without it.
+    #[clap(long, value_name = "B")]
+    filter_residency: Option<f64>,
+
+    /// Adds a table with a suggested placement of the data index levels,
+    /// the filter index levels, the filter blocks, and the data blocks in
+    /// RAM, on NVMe, and on S3, given BYTES of RAM, with the resulting mean
+    /// latency of lookups.  RAM reads take `--cached-latency` and NVMe reads
+    /// take `--device-latency`.
+    #[clap(long, value_name = "BYTES")]
+    ram_capacity: Option<u64>,
+
+    /// Bytes of NVMe for `--ram-capacity`, by default unlimited.
+    #[clap(long, value_name = "BYTES")]
+    nvme_capacity: Option<u64>,
+
+    /// Mean latency of a block read from S3, in microseconds, for
+    /// `--ram-capacity`.
+    #[clap(long, default_value_t = 20_000.0)]
+    s3_latency: f64,
+
+    /// Bits per value in the filters, for `--ram-capacity`.
+    #[clap(long, value_name = "B", default_value_t = 16.0)]
+    plan_filter_bits: f64,
+
+    /// Adds a table comparing one index tree over all of the data against
+    /// partitioning the data into stripes (row groups) of this many bytes,
+    /// each indexed independently, with a directory of the stripes in the
+    /// file trailer.
+    #[clap(long, value_name = "BYTES")]
+    stripe_size: Option<u64>,
+
+    /// Adds a table for values of 1 to 32 bytes comparing data blocks, whose
+    /// trailers hold an offset per value, against storing the values
+    /// directly as fixed-length entries in L1 index blocks, with no data
+    /// blocks.
+    #[clap(long)]
+    inline_values: bool,
+
+    /// Adds a table of the cost of keeping the minimum and maximum value of
+    /// each data block, of BYTES bytes each (e.g. 8 for an integer column),
+    /// so that scans can skip blocks that a predicate rules out, either in
+    /// the data index entries or in a separate statistics region.
+    #[clap(long, value_name = "BYTES")]
+    zone_maps: Option<u64>,
+
+    /// Number of columns with minimum and maximum values, for `--zone-maps`.
+    #[clap(long, default_value_t = 1)]
+    zone_map_columns: u64,
+
+    /// Size of a cache line, in bytes, for `--cache-lines`.
+    #[clap(long, default_value_t = 64)]
+    cache_line_size: u64,
+
+    /// Size of the L2 cache, in bytes, for `--cache-lines`.
+    #[clap(long, default_value_t = 1 << 20)]
+    l2_size: u64,
+
+    /// Declares a group of columns that are always read together, as a
+    /// comma-separated list of their value sizes in bytes, e.g. `8,8,16`.
+    /// Adds a table comparing the group stored interleaved, as one value per
+    /// row in shared data blocks, against one column chunk per column, each
+    /// with its own data blocks and row index.  May be given more than once.
+    #[clap(long = "column-group", value_name = "SIZES")]
+    column_groups: Vec<ColumnGroup>,
+}
+
+/// By default, prints a table of index coverage.  The subcommands use the same
+/// parameters for other kinds of output.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Simulates point lookups under a Zipf distribution through the index and
+    /// a block cache, and reports measured block reads and cache hit rates.
+    Simulate(SimulateArgs),
+
+    /// Models the compaction cost of ingesting the data as a stream of batches
+    /// into a spine that merges layer files.
+    Spine(SpineArgs),
+
+    /// Models the dead data that builds up in a spine under merge policies
+    /// when a fixed key population is overwritten over and over.
+    Upserts(UpsertsArgs),
+
+    /// Models the fixed per-file overhead of ingesting a stream of small
+    /// batches, each of which becomes its own layer file.
+    SmallBatches(SmallBatchesArgs),
+
+    /// Benchmarks the block checksum algorithms at typical block sizes.
+    Checksum(ChecksumArgs),
+
+    /// Benchmarks the ratio and speed of the data block codecs on a
+    /// synthetic table.
+    Compression(CompressionArgs),
+
+    /// Checks that order-preserving key normalization round-trips and sorts
+    /// like SQL, and compares sorting normalized keys against typed
+    /// comparisons.
+    Keys(KeysArgs),
+
+    /// Checks that run-length encoded weights read back row by row, and
+    /// compares their space and read time against raw weights.
+    Weights(WeightsArgs),
+
+    /// Checks that seeks find keys in delta-encoded integer key blocks, and
+    /// compares their density and seek time against raw keys.
+    Deltas(DeltasArgs),
+
+    /// Checks that frame-of-reference bit-packed integer blocks read back,
+    /// and compares their density and scan time against raw integers.
+    Packing(PackingArgs),
+
+    /// Checks that seeks find variable-length values in data blocks with
+    /// offset arrays, and reports how full the blocks are.
+    Varlen(VarlenArgs),
+
+    /// Checks that searches through prefix-truncated index separators find
+    /// the right data blocks, and compares their size against full keys.
+    Separators(SeparatorsArgs),
+
+    /// Checks that appending sorted batches to a layer file keeps every
+    /// generation readable, and compares the bytes written against rewriting.
+    Append(AppendArgs),
+
+    /// Checks that merges of files with retractions consolidate weights the
+    /// same way in any grouping, and reports the rows and keys they drop.
+    Consolidate(ConsolidateArgs),
+
+    /// Checks that range scans that skip subtrees through a hierarchical
+    /// zone map return every matching row, and reports the blocks they read.
+    ZoneMaps(ZoneMapsArgs),
+
+    /// Checks that point lookups through Bloom or xor filters find every
+    /// present value, and reports false positives, reads, and time per miss.
+    Filters(FiltersArgs),
+
+    /// Checks that a multi-column file's chunk directory and row group
+    /// navigation find every row, and reports scan reads by chunk size.
+    Columns(ColumnsArgs),
+
+    /// Checks that a cursor over an indexed Z-set finds every key's run of
+    /// values, and reports the blocks that seeks and scans read.
+    #[clap(name = "zset")]
+    ZSet(ZSetArgs),
+
+    /// Checks that weights as of a time, with runs pruned by their time
+    /// summaries, match the trace's, and reports the time blocks read.
+    Traces(TracesArgs),
+
+    /// Projects file sizes and index heights over time for a steady ingest
+    /// rate.
+    Growth(GrowthArgs),
+
+    /// Simulates ingesting at a fixed rate with background compaction and
+    /// reports the sustained ingest ceiling, compaction debt, and lookup
+    /// latency over time.
+    Ingest(IngestArgs),
+
+    /// Compares the model against a summary of the statistics of a real layer
+    /// file and reports the model's error.
+    ValidateModel(ValidateArgs),
+
+    /// Benchmarks buffered, direct, and memory-mapped ways to write a file's
+    /// blocks.
+    Write(WriteArgs),
+
+    /// Benchmarks the containers that can hold layer files' blocks, a plain
+    /// file per layer file against one file packing all of them.
+    Container(ContainerArgs),
+
+    /// Writes, inspects, or checks the layout of the file header and trailer
+    /// blocks.
+    Superblock(SuperblockArgs),
+
+    /// Runs ingest, lookups, compaction, and checkpoints against a real
+    /// storage location and checks its invariants and resource usage.
+    Soak(SoakArgs),
+
+    /// Streams the rows of a layer file, or of a spine as of a storage
+    /// location's newest checkpoint, as CSV or newline-delimited JSON.
+    Export(ExportArgs),
+
+    /// Prints the checkpoints, files, and compaction debt of a storage
+    /// location, and fails if a file its newest checkpoint needs is missing.
+    Summary(SummaryArgs),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum IndexType {
+    /// Data values.
+    Data,
+
+    /// Column 1 row number.
+    #[clap(alias = "c1row")]
+    C1Row,
+
+    /// Row number in columns other than 1.
+    Row,
+
+    /// Filter.
+    ///
+    /// We probably won't organize filters using their own index, so this isn't
+    /// shown by default.
+    Filter,
+
+    /// Hash table over the values, as an alternative to the data index for
+    /// point lookups.  This is a single level of buckets, so a lookup reads
+    /// one bucket (if it did not overflow) instead of descending a tree.
+    ///
+    /// This is only for comparison, so it isn't shown by default.
+    Hash,
+
+    /// Secondary index from values back to row numbers, for a column that
+    /// isn't sorted by value.  This isn't part of the format, so it isn't
+    /// shown by default.
+    #[clap(alias = "sec")]
+    Secondary,
+
+    /// Data index whose entries keep only the bytes of each value needed to
+    /// distinguish it from its neighbors, like a trie or adaptive radix tree.
+    /// This is only for comparison, so it isn't shown by default.
+    Trie,
+}
+
+impl Display for IndexType {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            IndexType::Data => "data",
+            IndexType::C1Row => "c1row",
+            IndexType::Row => "row",
+            IndexType::Filter => "filter",
+            IndexType::Hash => "hash",
+            IndexType::Secondary => "sec",
+            IndexType::Trie => "trie",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// A group of parameters that override the corresponding command-line
+/// options, written as comma-separated `name=value` pairs, e.g.
+/// `min-branch=16,min-data-block=4096`.
+#[derive(Clone, Debug)]
+struct Preset {
+    /// The text of the preset, as given on the command line.
+    spec: String,
+    min_branch: Option<u64>,
+    min_data_block: Option<u64>,
+    min_index_block: Option<u64>,
+    index_run: Option<u64>,
+    data_entry: Option<DataEntry>,
+    block_rounding: Option<BlockRounding>,
+    row_sample: Option<u64>,
+    fill_factor: Option<f64>,
+    child_checksum: Option<u64>,
+    upper_index_block: Option<u64>,
+    upper_min_branch: Option<u64>,
+}
+
+impl Preset {
+    /// Returns `params` with the values in this preset substituted.
+    fn apply(&self, params: &Params) -> Params {
+        Params {
+            min_branch: self.min_branch.unwrap_or(params.min_branch),
+            min_data_block: self.min_data_block.unwrap_or(params.min_data_block),
+            min_index_block: self.min_index_block.unwrap_or(params.min_index_block),
+            index_run: self.index_run.or(params.index_run),
+            data_entry: self.data_entry.unwrap_or(params.data_entry),
+            block_rounding: self.block_rounding.unwrap_or(params.block_rounding),
+            row_sample: self.row_sample.unwrap_or(params.row_sample),
+            fill_factor: self.fill_factor.unwrap_or(params.fill_factor),
+            child_checksum: self.child_checksum.unwrap_or(params.child_checksum),
+            upper_index_block: self
+                .upper_index_block
+                .map_or_else(|| params.upper_index_block.clone(), |size| vec![size]),
+            upper_min_branch: self.upper_min_branch.or(params.upper_min_branch),
+            ..params.clone()
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut preset = Preset {
+            spec: s.into(),
+            min_branch: None,
+            min_data_block: None,
+            min_index_block: None,
+            index_run: None,
+            data_entry: None,
+            block_rounding: None,
+            row_sample: None,
+            fill_factor: None,
+            child_checksum: None,
+            upper_index_block: None,
+            upper_min_branch: None,
+        };
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("{pair}: expected name=value"))?;
+            let number = || value.parse().map_err(|e| format!("{pair}: {e}"));
+            match name.replace('_', "-").as_str() {
+                "min-branch" => preset.min_branch = Some(number()?),
+                "min-data-block" => preset.min_data_block = Some(number()?),
+                "min-index-block" => preset.min_index_block = Some(number()?),
+                "index-run" => {
+                    preset.index_run = Some(parse_index_run(value).map_err(|e| format!("{pair}: {e}"))?)
+                }
+                "row-sample" => preset.row_sample = Some(number()?),
+                "child-checksum" => preset.child_checksum = Some(number()?),
+                "fill-factor" => {
+                    preset.fill_factor = Some(value.parse().map_err(|e| format!("{pair}: {e}"))?)
+                }
+                "upper-index-block" => preset.upper_index_block = Some(number()?),
+                "upper-min-branch" => preset.upper_min_branch = Some(number()?),
+                "data-entry" => {
+                    preset.data_entry = Some(
+                        DataEntry::from_str(value, true).map_err(|e| format!("{pair}: {e}"))?,
+                    )
+                }
+                "block-rounding" => {
+                    preset.block_rounding = Some(
+                        BlockRounding::from_str(value, true)
+                            .map_err(|e| format!("{pair}: {e}"))?,
+                    )
+                }
+                _ => return Err(format!("{name}: unknown parameter (expected min-branch, min-data-block, min-index-block, index-run, row-sample, fill-factor, child-checksum, data-entry, block-rounding, upper-index-block, or upper-min-branch)")),
+            }
+        }
+        Ok(preset)
+    }
+}
+
+/// A group of columns that are always read together, written as a
+/// comma-separated list of the columns' value sizes in bytes, e.g. `8,8,16`.
+#[derive(Clone, Debug)]
+struct ColumnGroup {
+    /// The text of the group, as given on the command line.
+    spec: String,
+    value_sizes: Vec<u64>,
+}
+
+impl FromStr for ColumnGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value_sizes = s
+            .split(',')
+            .map(|size| match size.parse() {
+                Ok(0) => Err(format!("{size}: value size must be at least 1")),
+                Ok(size) => Ok(size),
+                Err(e) => Err(format!("{size}: {e}")),
+            })
+            .collect::<Result<Vec<u64>, _>>()?;
+        Ok(ColumnGroup {
+            spec: s.into(),
+            value_sizes,
+        })
+    }
+}
+
+/// Returns the name used for the `i`th preset in comparison output: `A`,
+/// `B`, ..., `Z`, `AA`, `AB`, ..., as spreadsheet columns are named.
+fn preset_label(i: usize) -> String {
+    let mut label = Vec::new();
+    let mut i = i + 1;
+    while i > 0 {
+        i -= 1;
+        label.push(b'A' + (i % 26) as u8);
+        i /= 26;
+    }
+    label.iter().rev().map(|&c| c as char).collect()
+}
+
+/// Difference between `value` and `base`, as a percentage of `base`.
+struct Delta {
+    value: u64,
+    base: u64,
+}
+impl Display for Delta {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = if self.value == self.base {
+            "=".to_string()
+        } else if self.base == 0 {
+            "new".to_string()
+        } else if self.value >= 10 * self.base {
+            format!("{:.0}x", self.value as f64 / self.base as f64)
+        } else {
+            let percent = (self.value as f64 - self.base as f64) / self.base as f64 * 100.0;
+            if percent.abs() < 100.0 {
+                format!("{percent:+.1}%")
+            } else {
+                format!("{percent:+.0}%")
+            }
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// Exits with status 1 if `failures`, the number of checks that a subcommand
+/// failed, is nonzero.
+fn exit_on_failures(failures: u64) {
+    if failures > 0 {
+        eprintln!("{failures} checks failed");
+        std::process::exit(1);
+    }
+}
+
+/// Runs the command that the command line names.
+pub fn main() {
+    let Args {
+        command,
+        min_branch,
+        min_data_block,
+        min_index_block,
+        upper_index_block,
+        upper_min_branch,
+        total_data_size,
+        value_sizes,
+        indexes,
+        presets,
+        index_run,
+        data_entry,
+        block_rounding,
+        row_sample,
+        fill_factor,
+        child_checksum,
+        hash_load_factor,
+        hash_bucket_size,
+        hash_pointer_size,
+        hash_fingerprint,
+        key_entropy,
+        pin_levels,
+        open_files,
+        learned_error,
+        learned_keys,
+        level_sizes,
+        access_paths,
+        block_counts,
+        partial_coverage,
+        sort_by,
+        max_height,
+        max_index_size,
+        retractions,
+        unit_weights,
+        duplicates_per_key,
+        compression_ratio,
+        cardinality,
+        weight_size,
+        unit_run,
+        times_per_key,
+        time_size,
+        rank_select,
+        flat_row_limit,
+        ram_capacity,
+        nvme_capacity,
+        s3_latency,
+        plan_filter_bits,
+        cache_lines,
+        file_counts,
+        reader_threads,
+        probe_ns,
+        atomic_ns,
+        leaf_miss,
+        device_latency,
+        cached_latency,
+        data_entries,
+        filter_bits,
+        filter_residency,
+        stripe_size,
+        inline_values,
+        zone_maps,
+        zone_map_columns,
+        cache_line_size,
+        l2_size,
+        column_groups,
+    } = Args::parse();
+
+    let columns = Columns {
+        scan: index_run.is_some() || presets.iter().any(|preset| preset.index_run.is_some()),
+        slack: block_rounding != BlockRounding::None
+            || presets.iter().any(|preset| {
+                preset
+                    .block_rounding
+                    .is_some_and(|rounding| rounding != BlockRounding::None)
+            }),
+        pin_levels,
+        open_files,
+        learned_error,
+        learned_keys,
+        level_sizes,
+        access_paths,
+        block_counts,
+        partial_coverage,
+        selection: Selection {
+            sort_by,
+            max_height,
+            max_index_size,
+        },
+        latency: leaf_miss.map(|leaf_miss| Latency {
+            leaf_miss: leaf_miss.clamp(0.0, 1.0),
+            device: device_latency,
+            cached: cached_latency,
+        }),
+    };
+
+    let base = Params {
+        total_data_size: 1 << total_data_size,
+        value_size: 0,
+        min_data_block,
+        min_index_block,
+        min_branch,
+        hash_load_factor,
+        hash_bucket_size,
+        hash_pointer_size,
+        hash_fingerprint,
+        index_run,
+        data_entry,
+        block_rounding,
+        row_sample,
+        fill_factor,
+        child_checksum,
+        upper_index_block,
+        upper_min_branch,
+        key_entropy,
+        value_sizes: value_sizes.0,
+    };
+    let groups: Vec<_> = if presets.is_empty() {
+        vec![base]
+    } else {
+        presets.iter().map(|preset| preset.apply(&base)).collect()
+    };
+    match command {
+        Some(Command::Spine(args)) => spine::spine(&groups[0], &args),
+        Some(Command::Upserts(args)) => upserts::upserts(&groups[0], &args),
+        Some(Command::SmallBatches(args)) => batches::small_batches(&groups[0], &indexes, &args),
+        Some(Command::Checksum(args)) => checksum::checksum(&args),
+        Some(Command::Keys(args)) => exit_on_failures(keys::keys(&args)),
+        Some(Command::Weights(args)) => exit_on_failures(weights::weights(&args)),
+        Some(Command::Deltas(args)) => exit_on_failures(deltas::deltas(&args)),
+        Some(Command::Packing(args)) => exit_on_failures(packing::packing(&args)),
+        Some(Command::Varlen(args)) => exit_on_failures(varlen::varlen(&args)),
+        Some(Command::Separators(args)) => exit_on_failures(separators::separators(&args)),
+        Some(Command::Append(args)) => exit_on_failures(append::append(&args)),
+        Some(Command::Consolidate(args)) => exit_on_failures(consolidate::consolidate(&args)),
+        Some(Command::ZoneMaps(args)) => exit_on_failures(zonemaps::zone_maps(&args)),
+        Some(Command::Filters(args)) => exit_on_failures(filters::filters(&args)),
+        Some(Command::Columns(args)) => exit_on_failures(columns::columns(&args)),
+        Some(Command::ZSet(args)) => exit_on_failures(zset::zset(&args)),
+        Some(Command::Traces(args)) => exit_on_failures(traces::traces(&args)),
+        Some(Command::Write(args)) => write::write(&args),
+        Some(Command::Container(args)) => container::container(&args),
+        Some(Command::Ingest(args)) => ingest::ingest(&args),
+        Some(Command::Growth(args)) => growth::growth(&groups[0], &indexes, &args),
+        Some(Command::ValidateModel(args)) => {
+            if let Err(error) = validate::validate(&groups[0], &args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Superblock(args)) => {
+            if let Err(error) = superblock::superblock(&args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Compression(args)) => {
+            if let Err(error) = compression::compression(&args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Export(args)) => {
+            if let Err(error) = export::export(&args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Summary(args)) => {
+            if let Err(error) = summary::summary(&args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Soak(args)) => {
+            if let Err(error) = soak::soak(&args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Simulate(args)) => {
+            if let Err(error) = simulate::simulate(&groups, &indexes, &args) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        None => {
+            if let [params] = groups.as_slice() {
+                print_table(params, &indexes, &columns);
+            } else {
+                print_comparison(&presets, &groups, &indexes, &columns);
+            }
+            if let Some(fraction) = retractions {
+                for params in &groups {
+                    println!();
+                    print_retractions(params, &indexes, fraction);
+                }
+            }
+            if let Some(unit_weights) = unit_weights {
+                let weights = Weights::new(unit_weights, weight_size, unit_run);
+                for params in &groups {
+                    println!();
+                    print_weights(params, &indexes, &weights);
+                }
+            }
+            if let Some(ratio) = compression_ratio {
+                for params in &groups {
+                    println!();
+                    print_compression(params, &indexes, ratio);
+                }
+            }
+            if let Some(cardinality) = cardinality {
+                for params in &groups {
+                    println!();
+                    print_dictionary(params, &indexes, cardinality);
+                }
+            }
+            if let Some(times_per_key) = times_per_key {
+                for params in &groups {
+                    println!();
+                    print_times(params, times_per_key, time_size);
+                }
+            }
+            if let Some(duplicates_per_key) = duplicates_per_key {
+                for params in &groups {
+                    println!();
+                    print_duplicates(params, duplicates_per_key);
+                }
+            }
+            if rank_select {
+                for params in &groups {
+                    println!();
+                    print_rank_select(params);
+                }
+            }
+            if let Some(ram_capacity) = ram_capacity {
+                let tiers = [
+                    (ram_capacity, cached_latency),
+                    (nvme_capacity.unwrap_or(u64::MAX), device_latency),
+                    (u64::MAX, s3_latency),
+                ];
+                for params in &groups {
+                    println!();
+                    print_placement(params, plan_filter_bits, &tiers);
+                }
+            }
+            if let Some(flat_row_limit) = flat_row_limit {
+                for params in &groups {
+                    println!();
+                    print_flat_row_table(params, flat_row_limit);
+                }
+            }
+            if data_entries {
+                for params in &groups {
+                    println!();
+                    print_data_entries(params);
+                }
+            }
+            if let Some(bits) = filter_bits {
+                for params in &groups {
+                    println!();
+                    print_filter_placement(params, bits);
+                }
+            }
+            if let Some(bits) = filter_residency {
+                let latency = Latency {
+                    leaf_miss: leaf_miss.unwrap_or(1.0).clamp(0.0, 1.0),
+                    device: device_latency,
+                    cached: cached_latency,
+                };
+                for params in &groups {
+                    println!();
+                    print_filter_residency(params, bits, &latency);
+                }
+            }
+            if let Some(stripe_size) = stripe_size {
+                for params in &groups {
+                    println!();
+                    print_stripes(params, &indexes, stripe_size);
+                }
+            }
+            if inline_values {
+                for params in &groups {
+                    println!();
+                    print_inline_values(params);
+                }
+            }
+            if let Some(stat_size) = zone_maps {
+                for params in &groups {
+                    println!();
+                    print_zone_maps(params, stat_size * 2 * zone_map_columns);
+                }
+            }
+            for &file_count in &file_counts {
+                for params in &groups {
+                    println!();
+                    batches::print_file_count(params, &indexes, file_count);
+                }
+            }
+            if !reader_threads.is_empty() {
+                let contention = Contention {
+                    threads: reader_threads.clone(),
+                    probe_ns,
+                    atomic_ns,
+                };
+                for params in &groups {
+                    println!();
+                    print_contention(params, &contention);
+                }
+            }
+            if let Some(cache_lines) = cache_lines {
+                let cache = Cache {
+                    lines: cache_lines.max(1),
+                    line_size: cache_line_size.max(1),
+                    l2_size,
+                };
+                for params in &groups {
+                    println!();
+                    print_cache_lines(params, &indexes, pin_levels.unwrap_or(1), &cache);
+                }
+            }
+            if !column_groups.is_empty() {
+                for params in &groups {
+                    println!();
+                    print_column_groups(params, &column_groups);
+                }
+            }
+        }
+    }
+}
+
+/// Prints a table of the space taken by retractions, which are rows with
+/// negative weights that cancel earlier rows, before and after a full merge
+/// consolidates them.  `fraction` of the rows in `params.total_data_size` are
+/// retractions, each of which cancels one positive row, so a full merge
+/// removes `2 * fraction` of the rows.  The file size includes the data blocks
+/// and `indexes`.
+fn print_retractions(params: &Params, indexes: &[IndexType], fraction: f64) {
+    let fraction = fraction.clamp(0.0, 0.5);
+    println!(
+        "Retraction overhead for {} data, {:.1}% retractions, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        fraction * 100.0,
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                     ---Before merge---   ---After merge----
+ Value   Retracted     Data      File       Data      File    Reduction
+------   ---------   --------  --------   --------  --------  ---------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let before = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let merged_values = (before.params.total_values() as f64 * (1.0 - 2.0 * fraction)) as u64;
+        let after = LayerFile::new(&Params {
+            value_size,
+            total_data_size: (merged_values * value_size).max(value_size),
+            ..params.clone()
+        });
+        let file_size = |layer_file: &LayerFile| {
+            layer_file.params.total_data_size + layer_file.index_size(indexes)
+        };
+        let (before_size, after_size) = (file_size(&before), file_size(&after));
+        let retracted = (before.params.total_data_size as f64 * fraction) as u64;
+        println!(
+            "{:5}     {:>7}     {:>6}    {:>6}     {:>6}    {:>6}     {:>5.1}%",
+            HumanBytes(value_size),
+            HumanBytes(retracted),
+            HumanBytes(before.params.total_data_size),
+            HumanBytes(before_size),
+            HumanBytes(after.params.total_data_size),
+            HumanBytes(after_size),
+            (1.0 - after_size as f64 / before_size as f64) * 100.0
+        );
+    }
+}
+
+/// A distribution of weights, for modeling the weight column.
+struct Weights {
+    /// Fraction of rows whose weight is +1.
+    unit_fraction: f64,
+
+    /// Size of a weight, in bytes.
+    size: u64,
+
+    /// Average length of a run of consecutive +1 weights.
+    unit_run: f64,
+}
+
+impl Weights {
+    /// Size of the tag that begins each run in the run-length encoding.
+    const TAG_SIZE: f64 = 1.0;
+
+    /// Size of the count in a run of +1 weights.
+    const COUNT_SIZE: f64 = 2.0;
+
+    fn new(unit_fraction: f64, size: u64, unit_run: Option<f64>) -> Self {
+        let unit_fraction = unit_fraction.clamp(0.0, 1.0);
+        let unit_run = unit_run.unwrap_or(1.0 / (1.0 - unit_fraction)).max(1.0);
+        Self {
+            unit_fraction,
+            size: size.max(1),
+            unit_run,
+        }
+    }
+
+    /// Returns the average number of bytes per row taken by run-length
+    /// encoded weights.  Each weight other than +1 takes a tag and the weight
+    /// itself, and each run of +1 weights takes a tag and a count.  A block
+    /// for which this is bigger than raw weights stores them raw instead.
+    fn rle_bytes_per_row(&self) -> f64 {
+        let p = self.unit_fraction;
+        let rle = (1.0 - p) * (Self::TAG_SIZE + self.size as f64)
+            + p / self.unit_run * (Self::TAG_SIZE + Self::COUNT_SIZE);
+        rle.min(self.size as f64)
+    }
+}
+
+/// Prints a table of the space taken by the weight column, stored raw and
+/// run-length encoded, for the distribution in `weights`.  Weights take
+/// space in the data blocks, so that fewer values fit in each block, which
+/// also makes the indexes bigger.  The file size includes the data blocks
+/// and `indexes`.
+fn print_weights(params: &Params, indexes: &[IndexType], weights: &Weights) {
+    println!(
+        "Weight column for {} data, {}-byte weights, {:.1}% +1 weights in runs of {:.1}, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        weights.size,
+        weights.unit_fraction * 100.0,
+        weights.unit_run,
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        ---Values/Block---   Bytes    --Weight Column--   -----File Size-----
+ Value   None   Raw    RLE   /Row      Raw       RLE       Raw       RLE
+------  -----  -----  -----  -----   -------   -------   -------   -------
+"#
+    );
+    let rle_bytes_per_row = weights.rle_bytes_per_row();
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let values_per_block = |weight_size: f64| {
+            ((params.min_data_block as f64 / (value_size as f64 + weight_size)) as u64)
+                .max(params.min_branch)
+        };
+        let raw =
+            LayerFile::with_values_per_data_block(&params, values_per_block(weights.size as f64));
+        let rle =
+            LayerFile::with_values_per_data_block(&params, values_per_block(rle_bytes_per_row));
+        let n_values = params.total_values();
+        let raw_weights = n_values * weights.size;
+        let rle_weights = (n_values as f64 * rle_bytes_per_row) as u64;
+        let file_size = |layer_file: &LayerFile, weight_bytes: u64| {
+            layer_file.params.total_data_size + weight_bytes + layer_file.index_size(indexes)
+        };
+        println!(
+            "{:5}   {:5}  {:5}  {:5}  {:5.2}   {:>7}   {:>7}   {:>7}   {:>7}",
+            HumanBytes(value_size),
+            LayerFile::new(&params).values_per_data_block,
+            raw.values_per_data_block,
+            rle.values_per_data_block,
+            rle_bytes_per_row,
+            HumanBytes(raw_weights),
+            HumanBytes(rle_weights),
+            HumanBytes(file_size(&raw, raw_weights)),
+            HumanBytes(file_size(&rle, rle_weights)),
+        );
+    }
+}
+
+/// Fraction of a compressed data block that the writer aims to fill.  The
+/// writer can't know a block's compressed size until it compresses it, so it
+/// estimates it from the compression ratio of the blocks so far and leaves
+/// room for blocks that compress worse than the estimate.
+const COMPRESSION_TARGET: f64 = 0.9;
+
+/// Prints a table comparing uncompressed data blocks against data blocks
+/// compressed with `ratio`.  A compressed block is the smallest power-of-2
+/// multiple of 4 kB, and at least `params.min_data_block`, that holds
+/// `params.min_branch` values, filled to [COMPRESSION_TARGET] with as many
+/// values as compress into it.  The indexes point to fewer, fuller blocks, so
+/// they shrink too.  The index size includes `indexes`.
+fn print_compression(params: &Params, indexes: &[IndexType], ratio: f64) {
+    let ratio = ratio.max(1.0);
+    println!(
+        "Data block compression for {} data with ratio {ratio}, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        -Values/Block-   Disk   ---Data on Disk---   ---Index Size----   --Height--
+ Value    Raw    Comp   Block      Raw      Comp        Raw      Comp    Raw  Comp
+------  -----  ------  ------  -------  --------   --------  --------   ---  ----
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let raw = LayerFile::new(&params);
+        // Pick the smallest block that holds `min_branch` compressed values,
+        // and then fill it.
+        let fill = ratio * COMPRESSION_TARGET;
+        let min_bytes = (params.min_branch as f64 * value_size as f64 / fill).ceil() as u64;
+        let disk_block = batches::padded(min_bytes).max(params.min_data_block);
+        let values_per_block = (disk_block as f64 * fill / value_size as f64) as u64;
+        let zstd = LayerFile::with_values_per_data_block(&params, values_per_block);
+        println!(
+            "{:5}  {:5}  {:6}  {:>6}  {:>7}  {:>8}   {:>8}  {:>8}   {:3}  {:4}",
+            HumanBytes(value_size),
+            raw.values_per_data_block,
+            zstd.values_per_data_block,
+            HumanBytes(disk_block),
+            HumanBytes(raw.total_data_blocks * raw.data_block_size),
+            HumanBytes(zstd.total_data_blocks * disk_block),
+            HumanBytes(raw.index_size(indexes)),
+            HumanBytes(zstd.index_size(indexes)),
+            raw.indexes[0].height,
+            zstd.indexes[0].height,
+        );
+    }
+}
+
+/// Largest dictionary that the writer builds for a column, in bytes.  Past
+/// this, it stops adding values to the dictionary and writes the rest of the
+/// column's data blocks plain.
+const DICTIONARY_LIMIT: u64 = 1 << 20;
+
+/// Prints a table comparing plain data blocks against dictionary encoding for
+/// a column with `cardinality` distinct values, for each value size.  Each
+/// value becomes a code of 1, 2, or 4 bytes, so that a data block holds
+/// codes without per-value offsets, and the index entries still hold
+/// decoded values.  A column falls back to plain data blocks if its
+/// dictionary would exceed [DICTIONARY_LIMIT] or its codes would be no
+/// smaller than its values.  The index size includes `indexes`.
+fn print_dictionary(params: &Params, indexes: &[IndexType], cardinality: u64) {
+    let cardinality = cardinality.max(1);
+    let code_size = match cardinality {
+        0..=0x100 => 1,
+        0x101..=0x1_0000 => 2,
+        _ => 4,
+    };
+    println!(
+        "Dictionary encoding for {} data with {} distinct values in {code_size}-byte codes, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        HumanCount(cardinality),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                   -Values/Block-   ---Data on Disk---   ---Index Size----   --Height--
+ Value  Dictionary    Plain    Dict    Plain      Dict      Plain      Dict  Plain  Dict
+------  ----------  -------  ------  -------  --------   --------  --------  -----  ----
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let plain = LayerFile::new(&params);
+        let dictionary = cardinality * value_size;
+        let plain_data = plain.total_data_blocks * plain.data_block_size;
+        if dictionary > DICTIONARY_LIMIT || code_size >= value_size {
+            println!(
+                "{:5}  {:>10}  {:7}  {:>6}  {:>7}  {:>8}   {:>8}  {:>8}  {:5}  {:>4}",
+                HumanBytes(value_size),
+                "plain",
+                plain.values_per_data_block,
+                "-",
+                HumanBytes(plain_data),
+                "-",
+                HumanBytes(plain.index_size(indexes)),
+                "-",
+                plain.indexes[0].height,
+                "-",
+            );
+            continue;
+        }
+        let codes_per_block = (params.min_data_block / code_size).max(params.min_branch);
+        let coded = LayerFile::with_values_per_data_block(&params, codes_per_block);
+        let coded_data =
+            coded.total_data_blocks * codes_per_block * code_size + batches::padded(dictionary);
+        println!(
+            "{:5}  {:>10}  {:7}  {:6}  {:>7}  {:>8}   {:>8}  {:>8}  {:5}  {:4}",
+            HumanBytes(value_size),
+            HumanBytes(dictionary),
+            plain.values_per_data_block,
+            codes_per_block,
+            HumanBytes(plain_data),
+            HumanBytes(coded_data),
+            HumanBytes(plain.index_size(indexes)),
+            HumanBytes(coded.index_size(indexes)),
+            plain.indexes[0].height,
+            coded.indexes[0].height,
+        );
+    }
+}
+
+/// Returns the size in bytes of an Elias-Fano encoding of `n` increasing
+/// integers less than `universe`, including one 8-byte select sample per 256
+/// integers.
+fn elias_fano_size(n: u64, universe: u64) -> u64 {
+    let n = n.max(1);
+    let low_bits = (universe / n).max(1).ilog2() as u64;
+    (n * (2 + low_bits)).div_ceil(8) + n.div_ceil(256) * 8
+}
+
+/// Prints a table comparing the row index (for columns other than column 1)
+/// against two succinct structures that map a row number to its data block's
+/// number, from which the block's offset follows.  Neither is a tree, so
+/// either can be kept in memory and answer in `O(1)` without reading any
+/// index blocks:
+///
+/// - A bitmap with a bit per row that marks the first row in each data block,
+///   with one 8-byte rank sample per 512 bits, so that a block's number is the
+///   rank of the row.
+///
+/// - An Elias-Fano encoding of the first row number in each data block, so
+///   that a block's number comes from a successor search.
+///
+/// Both also need the data blocks' offsets in the file, which increase, so
+/// they are Elias-Fano encoded as well.
+fn print_rank_select(params: &Params) {
+    println!(
+        "Row number to data block mapping for {} data, min_branch={}, min_data_block={}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+    );
+    print!(
+        r#"
+          Data     Row    Block                   Rank/    Elias-
+ Value  Blocks   Index  Offsets   Bitmap    Select    Fano    Saved
+------  ------  ------  -------  -------   -------  ------   ------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let row_index = &layer_file.indexes[2];
+        assert_eq!(row_index.index_type, IndexType::Row);
+        let n_rows = layer_file.params.total_values();
+        let n_blocks = n_rows.div_ceil(layer_file.values_per_data_block);
+
+        let offsets = elias_fano_size(n_blocks, layer_file.params.total_data_size);
+        let bitmap = n_rows.div_ceil(8) + n_rows.div_ceil(512) * 8;
+        let elias_fano = elias_fano_size(n_blocks, n_rows);
+        let best = (bitmap + offsets).min(elias_fano + offsets);
+        println!(
+            "{:5}  {:>6}  {:>6}  {:>7}  {:>7}   {:>7}  {:>6}   {:5.1}%",
+            HumanBytes(value_size),
+            HumanCount(n_blocks),
+            HumanBytes(row_index.total_size()),
+            HumanBytes(offsets),
+            HumanBytes(bitmap),
+            HumanBytes(bitmap + offsets),
+            HumanBytes(elias_fano + offsets),
+            (1.0 - best as f64 / row_index.total_size() as f64) * 100.0
+        );
+    }
+}
+
+/// Size of a data block pointer in a flat row table, the same as the child
+/// pointer in an index entry.
+const FLAT_ROW_ENTRY_SIZE: u64 = 6;
+
+/// Prints a table comparing the column 1 row index against a flat table in
+/// the file trailer with a [FLAT_ROW_ENTRY_SIZE]-byte pointer for each data
+/// block in order.  Column 1 is never looked up by row number, so the table
+/// doesn't need row numbers either: it only has to enumerate the data
+/// blocks.
+///
+/// The table has to be read in full when the file is opened and kept in RAM
+/// while the file is open, whereas the tree only needs its levels above L1
+/// in RAM to read any data block with one more index block read.  A table
+/// bigger than `limit` bytes is impractical, so this also reports the most
+/// data that a file can hold before its table reaches that size.
+fn print_flat_row_table(params: &Params, limit: u64) {
+    println!(
+        "Flat row table versus column 1 row index for {} data, up to {} of table, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        HumanBytes(limit),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                ------C1Row Index------     Flat   Practical
+ Value  Blocks  Height     Size   Upper    Table    Up To
+------  ------  ------  -------  ------  -------  ---------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let c1row_index = &layer_file.indexes[1];
+        assert_eq!(c1row_index.index_type, IndexType::C1Row);
+        let n_blocks = layer_file.total_data_blocks;
+        let table = n_blocks * FLAT_ROW_ENTRY_SIZE;
+        let practical = limit / FLAT_ROW_ENTRY_SIZE * layer_file.values_per_data_block * value_size;
+        let upper = match c1row_index.height {
+            0 => "-".into(),
+            height => HumanBytes(c1row_index.pinned_size(height - 1)).to_string(),
+        };
+        println!(
+            "{:5}  {:>6}  {:6}  {:>7}  {:>6}  {:>7}  {:>9}",
+            HumanBytes(value_size),
+            HumanCount(n_blocks),
+            c1row_index.height,
+            HumanBytes(c1row_index.total_size()),
+            upper,
+            HumanBytes(table),
+            HumanBytes(practical),
+        );
+    }
+}
+
+/// Prints a table comparing the data index with [DataEntry::First] entries
+/// against [DataEntry::FirstLast] entries.
+///
+/// With first values only, a lookup for a value that isn't in the file but
+/// falls between the last value of one data block and the first value of the
+/// next has to read the earlier block to find out, whereas first and last
+/// values let it stop at the index.  For missing values at random, that gap
+/// is one of the `values_per_data_block` gaps between adjacent values per
+/// block, so that is the probability of an extra data block read.  (There is
+/// a similar gap between index blocks, but it's smaller by the branching
+/// factor at each level, so this ignores it.)
+fn print_data_entries(params: &Params) {
+    println!(
+        "Data index entries for {} data, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        ----First Only----   ----First+Last----   Extra Read
+ Value  Height  Index Size   Height  Index Size    per Miss
+------  ------  ----------   ------  ----------   ----------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let data_index = |data_entry| {
+            let layer_file = LayerFile::new(&Params {
+                value_size,
+                data_entry,
+                ..params.clone()
+            });
+            let values_per_data_block = layer_file.values_per_data_block;
+            let index = layer_file.indexes.into_iter().next().unwrap();
+            assert_eq!(index.index_type, IndexType::Data);
+            (index, values_per_data_block)
+        };
+        let (first, values_per_data_block) = data_index(DataEntry::First);
+        let (first_last, _) = data_index(DataEntry::FirstLast);
+        println!(
+            "{:5}  {:6}  {:>10}   {:6}  {:>10}   {:9.3}%",
+            HumanBytes(value_size),
+            first.height,
+            HumanBytes(first.total_size()),
+            first_last.height,
+            HumanBytes(first_last.total_size()),
+            100.0 / values_per_data_block as f64
+        );
+    }
+}
+
+/// Returns the false positive rate of a quotient filter with `bits` bits per
+/// value, interpolated geometrically between an RSQF's 1.5% at 8 bits and
+/// .02% at 16 bits.
+fn filter_fpr(bits: f64) -> f64 {
+    0.015 * (0.0002f64 / 0.015).powf((bits - 8.0) / 8.0)
+}
+
+/// Prints a table comparing three places to put filters with `bits` bits per
+/// value.  A lookup for a missing value, with the index levels above L1
+/// cached, reads an L1 index block and a data block without a filter:
+///
+/// - One filter per file has to be in RAM, since a lookup checks it first.
+///   A false positive costs the 2 reads.
+///
+/// - One filter per L1 index block lives on disk in its own block, which a
+///   lookup reads before the L1 index block.  Padding the filter out to a
+///   whole block gives it more bits per value.
+///
+/// - One filter per data block lives inline in the data block's L1 index
+///   entry, where a lookup finds it after reading the L1 index block.  This
+///   makes L1 index entries bigger, so it can make the index taller.
+fn print_filter_placement(params: &Params, bits: f64) {
+    println!(
+        "Filter placement for {} data with {bits} bits per value, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                     Values   Filter    Total                              Reads  Index
+ Value  Placement   /Filter     Size     Size      FPR  Location      RAM  /Miss  Height
+------  ----------  -------  -------  -------  -------  --------  -------  -----  ------
+"#
+    );
+    let bytes = |values: u64| (values as f64 * bits / 8.0).ceil() as u64;
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let data_index = &layer_file.indexes[0];
+        assert_eq!(data_index.index_type, IndexType::Data);
+        let n = layer_file.params.total_values();
+        let values_per_data_block = layer_file.values_per_data_block;
+
+        let file_size = bytes(n);
+        let file_fpr = filter_fpr(bits);
+
+        // A file that fits in one data block has no L1 block to hold a filter.
+        let l1_row = data_index.coverage.first().map(|&l1_values| {
+            let l1_size = batches::padded(bytes(l1_values));
+            let l1_fpr = filter_fpr(l1_size as f64 * 8.0 / l1_values as f64);
+            (
+                l1_values,
+                l1_size,
+                n.div_ceil(l1_values) * l1_size,
+                l1_fpr,
+                "disk",
+                0,
+                1.0 + 2.0 * l1_fpr,
+                data_index.height,
+            )
+        });
+
+        // Inline filters shrink the branching factor of L1 index blocks, but
+        // the levels above them keep theirs.
+        let block_size = bytes(values_per_data_block);
+        let l1_entries = (params.min_index_block / (data_index.index_entry_size + block_size))
+            .max(params.min_branch);
+        let mut covered = values_per_data_block * l1_entries;
+        let mut block_height = 1;
+        while covered < n {
+            let branch = match block_height {
+                level if level < data_index.height => {
+                    data_index.coverage[level] / data_index.coverage[level - 1]
+                }
+                _ => data_index.entries_per_block,
+            };
+            covered = covered.saturating_mul(branch);
+            block_height += 1;
+        }
+
+        let rows = [
+            (
+                "file",
+                Some((
+                    n,
+                    file_size,
+                    file_size,
+                    file_fpr,
+                    "RAM",
+                    file_size,
+                    2.0 * file_fpr,
+                    data_index.height,
+                )),
+            ),
+            ("L1 block", l1_row),
+            (
+                "data block",
+                Some((
+                    values_per_data_block,
+                    block_size,
+                    n.div_ceil(values_per_data_block) * block_size,
+                    file_fpr,
+                    "L1 index",
+                    0,
+                    1.0 + file_fpr,
+                    block_height,
+                )),
+            ),
+        ];
+        for (i, (placement, row)) in rows.into_iter().enumerate() {
+            if i == 0 {
+                print!("{:5}", HumanBytes(value_size));
+            } else {
+                print!("{:5}", "");
+            }
+            let Some((values, size, total, fpr, location, ram, reads, height)) = row else {
+                println!(
+                    "  {placement:>10}  {:>7}  {:>7}  {:>7}  {:>7}  {:>8}  {:>7}  {:>5}  {:>6}",
+                    "-", "-", "-", "-", "-", "-", "-", "-"
+                );
+                continue;
+            };
+            println!(
+                "  {placement:>10}  {:>7}  {:>7}  {:>7}  {:6.4}%  {location:>8}  {:>7}  {:5.3}  {height:6}",
+                HumanCount(values),
+                HumanBytes(size),
+                HumanBytes(total),
+                fpr * 100.0,
+                HumanBytes(ram),
+                reads,
+            );
+        }
+    }
+}
+
+/// Number of values that each filter block covers, as assumed by the filter
+/// index.
+const VALUES_PER_FILTER_BLOCK: u64 = 32768;
+
+/// Prints a table comparing two ways to hold filters with `bits` bits per
+/// value in filter blocks, each covering [VALUES_PER_FILTER_BLOCK] values,
+/// found through the filter index.  A lookup checks the filter first, and
+/// only if the filter says the value might be present (for every present
+/// value and for a fraction of the missing ones) descends the data index,
+/// whose levels above L1 are cached.
+///
+/// - Resident keeps every filter block and filter index block in RAM, so
+///   checking the filter reads nothing from the device.
+///
+/// - Paged keeps only the filter index's levels above L1 in RAM, like the
+///   data index, so checking the filter can miss the cache twice, once for
+///   the L1 filter index block and once for the filter block.  Every lookup
+///   pays for that, including lookups for present values, which the filter
+///   can't save anything.
+///
+/// Latencies are means, with each read that can miss the cache taking
+/// `latency.device` with probability `latency.leaf_miss` and
+/// `latency.cached` otherwise.
+fn print_filter_residency(params: &Params, bits: f64, latency: &Latency) {
+    println!(
+        "Filter residency for {} data with {bits} bits per value, leaf miss {}%, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        latency.leaf_miss * 100.0,
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                         --Filter Index--   ---RAM Needed---   ---Miss Latency (us)---     Hit
+ Value  Filters     Size   Height     Size   Resident   Paged   No Filter  Resident  Paged   Extra
+------  -------  -------   ------  -------   --------  ------   ---------  --------  -----   -----
+"#
+    );
+    let leaf = latency.leaf_miss * latency.device + (1.0 - latency.leaf_miss) * latency.cached;
+    let fpr = filter_fpr(bits);
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let data_index = &layer_file.indexes[0];
+        let filter_index = &layer_file.indexes[3];
+        assert_eq!(data_index.index_type, IndexType::Data);
+        assert_eq!(filter_index.index_type, IndexType::Filter);
+        let n = layer_file.params.total_values();
+
+        let n_filters = n.div_ceil(VALUES_PER_FILTER_BLOCK);
+        let filter_block = (VALUES_PER_FILTER_BLOCK as f64 * bits / 8.0).ceil() as u64;
+        let filters = n_filters * batches::padded(filter_block);
+        let resident = filters + filter_index.total_size();
+        let paged = match filter_index.height {
+            0 => "-".into(),
+            height => HumanBytes(filter_index.pinned_size(height - 1)).to_string(),
+        };
+
+        // An index's cached levels, then its L1 block and the block below,
+        // either of which can miss.  An index of height 0 has no blocks, so
+        // only the block below it can miss.
+        let lookup = |index: &Index| {
+            index.height.saturating_sub(1) as f64 * latency.cached
+                + (index.height.min(1) + 1) as f64 * leaf
+        };
+        let data_lookup = lookup(data_index);
+        let resident_check = (filter_index.height + 1) as f64 * latency.cached;
+        let paged_check = lookup(filter_index);
+        println!(
+            "{:5}  {:>7}  {:>7}   {:6}  {:>7}   {:>8}  {:>6}   {:9.1}  {:8.1}  {:5.1}   {:5.1}",
+            HumanBytes(value_size),
+            HumanCount(n_filters),
+            HumanBytes(filters),
+            filter_index.height,
+            HumanBytes(filter_index.total_size()),
+            HumanBytes(resident),
+            paged,
+            data_lookup,
+            resident_check + fpr * data_lookup,
+            paged_check + fpr * data_lookup,
+            paged_check - resident_check,
+        );
+    }
+}
+
+/// Fraction of lookups that are for missing values, in the workload that
+/// [print_placement] plans for.
+const PLAN_MISS_FRACTION: f64 = 0.5;
+
+/// Names of the storage tiers for [print_placement], fastest first.
+const TIER_NAMES: [&str; 3] = ["RAM", "NVMe", "S3"];
+
+/// Prints a suggested placement plan for each value size, which puts each
+/// data index level, each filter index level, the filter blocks, and the data
+/// blocks, as a whole, in one of the storage `tiers`, given as `(capacity,
+/// latency)` pairs, fastest first, with latencies in microseconds.
+///
+/// Every lookup checks a filter with `bits` bits per value, reading a block
+/// at each filter index level and a filter block.  A lookup for a present
+/// value, or for a missing value that the filter doesn't rule out, then reads
+/// a block at each data index level and a data block.  Each part's benefit
+/// from a faster tier is the number of reads per lookup that it would save,
+/// weighted by [PLAN_MISS_FRACTION], so the plan fills each tier in turn with
+/// the parts that have the most reads per byte.  Since every part is read
+/// about once per lookup, this favors the smallest ones, which are the upper
+/// index levels and then the filters.
+fn print_placement(params: &Params, bits: f64, tiers: &[(u64, f64); 3]) {
+    println!(
+        "Placement plan for {} data with {bits}-bit filters, {} RAM, {} NVMe, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        HumanBytes(tiers[0].0),
+        match tiers[1].0 {
+            u64::MAX => "unlimited".into(),
+            capacity => HumanBytes(capacity).to_string(),
+        },
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        --Data Index--   -Filter Index-   Filter    Data   -----Used-----   -Latency (us)-
+ Value  RAM  NVMe   S3   RAM  NVMe   S3   Blocks  Blocks      RAM    NVMe      Hit    Miss
+------  ---  ----  ---   ---  ----  ---   ------  ------  -------  ------   ------  ------
+"#
+    );
+    let fpr = filter_fpr(bits);
+    let data_weight = 1.0 - PLAN_MISS_FRACTION + PLAN_MISS_FRACTION * fpr;
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let data_index = &layer_file.indexes[0];
+        let filter_index = &layer_file.indexes[3];
+        assert_eq!(data_index.index_type, IndexType::Data);
+        assert_eq!(filter_index.index_type, IndexType::Filter);
+        let n = layer_file.params.total_values();
+        let filter_block = (VALUES_PER_FILTER_BLOCK as f64 * bits / 8.0).ceil() as u64;
+        let filter_blocks = n.div_ceil(VALUES_PER_FILTER_BLOCK) * batches::padded(filter_block);
+        let data_blocks = layer_file.total_data_blocks * layer_file.data_block_size;
+
+        // Each part is `(size, weight)`, in the order data index levels,
+        // filter index levels, filter blocks, data blocks.
+        let level_size = |index: &Index, level: usize| {
+            index.blocks_at_level(level) * index.block_size_at_level(level)
+        };
+        let mut parts: Vec<(u64, f64)> = Vec::new();
+        parts.extend(
+            (0..data_index.height).map(|level| (level_size(data_index, level), data_weight)),
+        );
+        parts.extend((0..filter_index.height).map(|level| (level_size(filter_index, level), 1.0)));
+        parts.push((filter_blocks, 1.0));
+        parts.push((data_blocks, data_weight));
+
+        let mut order: Vec<usize> = (0..parts.len()).collect();
+        order.sort_by(|&a, &b| {
+            let density = |(size, weight): (u64, f64)| weight / size.max(1) as f64;
+            density(parts[b]).total_cmp(&density(parts[a]))
+        });
+        let mut used = [0; 3];
+        let mut tier_of = vec![tiers.len() - 1; parts.len()];
+        for i in order {
+            let size = parts[i].0;
+            if let Some(tier) = (0..tiers.len()).find(|&t| tiers[t].0 - used[t] >= size) {
+                used[tier] += size;
+                tier_of[i] = tier;
+            }
+        }
+
+        let (data_levels, rest) = tier_of.split_at(data_index.height);
+        let (filter_levels, rest) = rest.split_at(filter_index.height);
+        let latency = |levels: &[usize]| levels.iter().map(|&t| tiers[t].1).sum::<f64>();
+        let filter_check = latency(filter_levels) + tiers[rest[0]].1;
+        let data_lookup = latency(data_levels) + tiers[rest[1]].1;
+        let count = |levels: &[usize], tier| levels.iter().filter(|&&t| t == tier).count();
+        println!(
+            "{:5}  {:3}  {:4}  {:3}   {:3}  {:4}  {:3}   {:>6}  {:>6}  {:>7}  {:>6}   {:6.0}  {:6.0}",
+            HumanBytes(value_size),
+            count(data_levels, 0),
+            count(data_levels, 1),
+            count(data_levels, 2),
+            count(filter_levels, 0),
+            count(filter_levels, 1),
+            count(filter_levels, 2),
+            TIER_NAMES[rest[0]],
+            TIER_NAMES[rest[1]],
+            HumanBytes(used[0]),
+            HumanBytes(used[1]),
+            filter_check + data_lookup,
+            filter_check + fpr * data_lookup,
+        );
+    }
+}
+
+/// Prints a table comparing `indexes` as single trees over all of
+/// `params.total_data_size` against independent trees over stripes of
+/// `stripe_size` bytes each.  A directory in the file trailer, with an entry
+/// per stripe holding the stripe's first key (a value, or a row number for
+/// the row indexes) and the location of its root, takes the place of the
+/// levels above the stripes' roots.  It is small enough to keep in RAM, so a
+/// lookup reads one stripe's tree.  Each stripe can be written by its own
+/// writer.
+fn print_stripes(params: &Params, indexes: &[IndexType], stripe_size: u64) {
+    let stripe_size = stripe_size.clamp(1, params.total_data_size);
+    let n_stripes = params.total_data_size.div_ceil(stripe_size);
+    println!(
+        "Index size for {} data in {} stripes of {}, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        HumanCount(n_stripes),
+        HumanBytes(stripe_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+               ---Monolithic---   -------------Striped--------------
+ Value  Index  Height      Size   Height      Size  Directory   Delta
+------  -----  ------   -------   ------   -------  ---------  ------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let monolithic = LayerFile::new(&params);
+        let striped = LayerFile::new(&Params {
+            total_data_size: stripe_size,
+            ..params.clone()
+        });
+        for (i, (whole, stripe)) in monolithic
+            .indexes
+            .iter()
+            .zip(&striped.indexes)
+            .filter(|(index, _)| indexes.contains(&index.index_type))
+            .enumerate()
+        {
+            if i == 0 {
+                print!("{:5}", HumanBytes(value_size));
+            } else {
+                print!("{:5}", "");
+            }
+            let key_size = match whole.index_type {
+                IndexType::C1Row | IndexType::Row => 8,
+                _ => value_size,
+            };
+            let directory = n_stripes * (key_size + 6);
+            let size = stripe.total_size() * n_stripes + directory;
+            println!(
+                "  {:>6}  {:6}   {:>7}   {:6}   {:>7}  {:>9}  {:>6}",
+                whole.index_type,
+                whole.height,
+                HumanBytes(whole.total_size()),
+                stripe.height,
+                HumanBytes(size),
+                HumanBytes(directory),
+                Delta {
+                    value: size,
+                    base: whole.total_size()
+                },
+            );
+        }
+    }
+}
+
+/// Size of the header of a data block or an index block: the magic, size,
+/// and checksum that begin every block, plus a count of values or entries.
+const BLOCK_HEADER_SIZE: u64 = 16;
+
+/// Size of the offset of each value in a data block's trailer.
+const VALUE_OFFSET_SIZE: u64 = 4;
+
+/// Prints a table comparing, for tiny values, a data index over data blocks
+/// against a tree whose L1 index blocks hold the values themselves.  A data
+/// block spends `VALUE_OFFSET_SIZE` bytes per value on its trailer, which an
+/// index block with fixed-length entries doesn't need, so the inline layout
+/// fits more values in each block.  Either way, a lookup reads one block per
+/// level of the height, which counts the blocks that hold the values.
+fn print_inline_values(params: &Params) {
+    println!(
+        "Inline values for {} data, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+        -----Data Blocks------   --Values in L1 Index--
+        Values                   Values
+ Value  /Block     Size  Height   /Block     Size  Height    Delta
+------  ------  -------  ------   ------  -------  ------   ------
+"#
+    );
+    for value_size in (0..=5).map(|shift| 1 << shift) {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let n = params.total_values();
+
+        // Returns the total size and the number of levels, counting the
+        // blocks that hold the values, of a tree whose blocks of
+        // `block_size` bytes hold values taking `per_value` bytes each.
+        let tree = |block_size: u64, per_value: u64| {
+            let values_per_block =
+                ((block_size - BLOCK_HEADER_SIZE) / per_value).max(params.min_branch);
+            let block_size = values_per_block * per_value + BLOCK_HEADER_SIZE;
+            let index = Index::new(&params, IndexType::Data, value_size, values_per_block);
+            let size = n.div_ceil(values_per_block) * block_size + index.total_size();
+            (values_per_block, size, index.height + 1)
+        };
+        let (data_values, data_size, data_height) =
+            tree(params.min_data_block, value_size + VALUE_OFFSET_SIZE);
+        let (inline_values, inline_size, inline_height) = tree(params.min_index_block, value_size);
+        println!(
+            "{:5}  {:6}  {:>7}  {:6}   {:6}  {:>7}  {:6}   {:>6}",
+            HumanBytes(value_size),
+            data_values,
+            HumanBytes(data_size),
+            data_height,
+            inline_values,
+            HumanBytes(inline_size),
+            inline_height,
+            Delta {
+                value: inline_size,
+                base: data_size
+            },
+        );
+    }
+}
+
+/// Prints a table of the cost of `stats_size` bytes of minimum and maximum
+/// values per data block, stored in one of two places:
+///
+/// - In each data index entry, which makes the entries bigger.  The entries
+///   above L1 then hold the minimum and maximum over their child's subtree,
+///   so that a scan can skip whole subtrees.
+///
+/// - In a separate statistics region, an array indexed by data block number
+///   that a scan reads sequentially alongside the data.
+fn print_zone_maps(params: &Params, stats_size: u64) {
+    println!(
+        "Zone maps of {stats_size} bytes per data block for {} data, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                 ---Data Index---   ---In Index Entries---   --Separate--
+          Data                                                        % of
+ Value  Blocks   Height      Size   Height      Size   Delta     Size   Data
+------  ------   ------   -------   ------   -------  ------  -------  -----
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let data_index = &layer_file.indexes[0];
+        assert_eq!(data_index.index_type, IndexType::Data);
+        let with_stats = Index::new(
+            &layer_file.params,
+            IndexType::Data,
+            data_index.index_entry_size + stats_size,
+            data_index.values_per_entry,
+        );
+        let n_blocks = layer_file
+            .params
+            .total_values()
+            .div_ceil(layer_file.values_per_data_block);
+        let region = n_blocks * stats_size;
+        println!(
+            "{:5}  {:>6}   {:6}   {:>7}   {:6}   {:>7}  {:>6}  {:>7}  {:4.2}%",
+            HumanBytes(value_size),
+            HumanCount(n_blocks),
+            data_index.height,
+            HumanBytes(data_index.total_size()),
+            with_stats.height,
+            HumanBytes(with_stats.total_size()),
+            Delta {
+                value: with_stats.total_size(),
+                base: data_index.total_size()
+            },
+            HumanBytes(region),
+            region as f64 / params.total_data_size as f64 * 100.0
+        );
+    }
+}
+
+/// Parameters for modeling contention among concurrent readers.
+struct Contention {
+    /// Numbers of reader threads to report.
+    threads: Vec<u64>,
+
+    /// Time to search a cached block, in nanoseconds.
+    probe_ns: f64,
+
+    /// Time for an atomic operation on a contended cache line, in
+    /// nanoseconds.
+    atomic_ns: f64,
+}
+
+impl Contention {
+    /// Returns the lookups per second that `threads` threads can do through
+    /// `index`, if the top `replicated` levels are replicated per thread.
+    ///
+    /// Each lookup searches one block per level and increments and
+    /// decrements the reference count of each one, so a thread by itself
+    /// does one lookup per `height * (probe_ns + 2 * atomic_ns)`.  (Without
+    /// other threads, the reference counts stay in the thread's cache, but we
+    /// charge the contended cost anyway, which makes this conservative.)
+    /// With more than one thread, the reference count in each shared block is
+    /// a serial resource, so the `n` blocks at a level can take at most `n /
+    /// (2 * atomic_ns)` lookups per nanosecond, and that is worst at the
+    /// root, which is a single block.
+    fn lookups_per_second(&self, index: &Index, threads: u64, replicated: usize) -> f64 {
+        let per_lookup = index.height as f64 * (self.probe_ns + 2.0 * self.atomic_ns);
+        let mut rate = threads as f64 / per_lookup;
+        if threads > 1 {
+            for level in 0..index.height.saturating_sub(replicated) {
+                let blocks = index.blocks_at_level(level) as f64;
+                rate = rate.min(blocks / (2.0 * self.atomic_ns));
+            }
+        }
+        rate * 1e9
+    }
+}
+
+/// Prints a table of the lookup throughput of the data index for each number
+/// of threads in `contention.threads`, with shared index blocks and with the
+/// root block replicated per thread.
+fn print_contention(params: &Params, contention: &Contention) {
+    println!(
+        "Data index lookups per second by reader threads for {} data, {:.0} ns per probe, {:.0} ns per contended atomic, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        contention.probe_ns,
+        contention.atomic_ns,
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    let max_threads = contention.threads.iter().copied().max().unwrap_or(1);
+    let mut header = [
+        String::from("      "),
+        String::from(" Value"),
+        String::from("------"),
+    ];
+    for threads in &contention.threads {
+        header[0] += "   Shared";
+        header[1] += &format!("  {:>7}", format!("x{threads}"));
+        header[2] += "  -------";
+    }
+    header[0] += "  Replicated Root";
+    header[1] += &format!("  {:>7}   Memory", format!("x{max_threads}"));
+    header[2] += "  -------  -------";
+    println!();
+    for line in &header {
+        println!("{line}");
+    }
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let index = &layer_file.indexes[0];
+        assert_eq!(index.index_type, IndexType::Data);
+        print!("{:5} ", HumanBytes(value_size));
+        for &threads in &contention.threads {
+            let rate = contention.lookups_per_second(index, threads.max(1), 0);
+            print!("  {:>7}", HumanCount(rate as u64));
+        }
+        let rate = contention.lookups_per_second(index, max_threads, 1);
+        println!(
+            "  {:>7}  {:>7}",
+            HumanCount(rate as u64),
+            HumanBytes(index.pinned_size(1) * max_threads)
+        );
+    }
+}
+
+/// CPU cache parameters, for modeling binary search within pinned index
+/// blocks.
+struct Cache {
+    /// Number of cache lines that a binary search within a block may touch.
+    lines: u64,
+
+    /// Size of a cache line, in bytes.
+    line_size: u64,
+
+    /// Size of the L2 cache, in bytes.
+    l2_size: u64,
+}
+
+impl Cache {
+    /// Returns the number of entries of `entry_size` bytes in a cache line,
+    /// or 1 if an entry is bigger than a cache line.  (A comparison only
+    /// needs the start of an entry, so it touches one line per probe.)
+    fn entries_per_line(&self, entry_size: u64) -> u64 {
+        (self.line_size / entry_size).max(1)
+    }
+
+    /// Returns the number of distinct cache lines that a binary search over
+    /// `entries` entries of `entry_size` bytes touches.  Each probe halves the
+    /// range, and once the range fits in a single line, the rest of the
+    /// search stays in that line.
+    fn search_lines(&self, entries: u64, entry_size: u64) -> u64 {
+        let per_line = self.entries_per_line(entry_size);
+        if entries <= per_line {
+            1
+        } else {
+            entries.div_ceil(per_line).next_power_of_two().ilog2() as u64 + 1
+        }
+    }
+
+    /// Returns the largest number of entries of `entry_size` bytes whose
+    /// binary search touches at most `self.lines` cache lines.
+    fn max_entries(&self, entry_size: u64) -> u64 {
+        (self.entries_per_line(entry_size) << (self.lines - 1).min(32)).max(2)
+    }
+}
+
+/// Prints a table of the cache behavior of binary search within the top
+/// `pin_levels` levels of `indexes` (which are the levels pinned in RAM), the
+/// number of entries per block that would keep each search within
+/// `cache.lines` cache lines, and whether the pinned levels fit in the L2
+/// cache.  A recommended block size for the upper levels can be passed back in
+/// with `--upper-index-block`.
+fn print_cache_lines(params: &Params, indexes: &[IndexType], pin_levels: usize, cache: &Cache) {
+    println!(
+        "Binary search within the top {pin_levels} pinned levels for {} data, {} cache lines of {} bytes, {} L2, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        cache.lines,
+        cache.line_size,
+        HumanBytes(cache.l2_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+               Entry  Entries   Lines/  ---Recommended---  Pinned
+ Value  Index   Size   /Block   Search  Entries     Block   Bytes  L2
+------  -----  -----  -------  -------  -------  --------  ------  ----
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let layer_file = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let mut first = true;
+        for index in layer_file
+            .indexes
+            .iter()
+            .filter(|index| indexes.contains(&index.index_type))
+            .filter(|index| index.index_type != IndexType::Hash)
+        {
+            let entry_size = index.index_entry_size;
+            let entries = (index.height.saturating_sub(pin_levels)..index.height)
+                .map(|level| index.block_size_at_level(level) / entry_size)
+                .max()
+                .unwrap_or(0);
+            let recommended = cache.max_entries(entry_size);
+            let pinned = index.pinned_size(pin_levels);
+            if first {
+                print!("{:5}", HumanBytes(value_size));
+                first = false;
+            } else {
+                print!("{:5}", "");
+            }
+            println!(
+                "  {:>5}  {:>5}  {:7}  {:7}  {:7}  {:>8}  {:>6}  {}",
+                index.index_type,
+                HumanBytes(entry_size),
+                entries,
+                cache.search_lines(entries, entry_size),
+                recommended,
+                HumanBytes(recommended * entry_size),
+                HumanBytes(pinned),
+                if pinned > cache.l2_size { "over" } else { "ok" }
+            );
+        }
+    }
+}
+
+/// Prints a table of the cost of a time column, modeling a trace as a 2-layer
+/// file in which column 1 holds `params.total_data_size` bytes of keys and
+/// each key has a row group of `times_per_key` values of `time_size` bytes in
+/// column 2.
+///
+/// Finding a key's times means descending column 2's row index to the key's
+/// first row, and if the row group spans more than one data block, a seek to
+/// a particular time within it also descends that row group's own data index.
+fn print_times(params: &Params, times_per_key: u64, time_size: u64) {
+    let times_per_key = times_per_key.max(1);
+    let time_size = time_size.max(1);
+    println!(
+        "Time column for {} keys, {times_per_key} times per key of {time_size} bytes each, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                  Time     Time    ---Row Index---  ---Sub-Index---   Extra    Extra
+  Key    Keys     Rows     Data   Height     Size  Height     Size  Levels     Size
+------  ------  ------  -------  ------  -------  ------  -------  ------  -------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let keys = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let n_keys = keys.params.total_values();
+        let n_times = n_keys.saturating_mul(times_per_key);
+
+        // Column 2 is found through its row index, since column 1's data
+        // blocks record each key's starting row.
+        let times = LayerFile::new(&Params {
+            value_size: time_size,
+            total_data_size: n_times.saturating_mul(time_size),
+            index_run: None,
+            ..params.clone()
+        });
+        let row_index = times.index(IndexType::Row);
+
+        // Each row group is sorted and indexed by time.  A row group that fits
+        // in a single data block needs no index of its own.  Otherwise, its
+        // index blocks only need to be big enough for the row group's entries,
+        // since there is no point in padding a small index to a full block.
+        let row_group = LayerFile::new(&Params {
+            value_size: time_size,
+            total_data_size: times_per_key.saturating_mul(time_size),
+            index_run: None,
+            ..params.clone()
+        });
+        let sub_index = row_group.index(IndexType::Data);
+        let sub_index_entries: u64 = (0..sub_index.height)
+            .map(|level| match level {
+                0 => times_per_key.div_ceil(row_group.values_per_data_block),
+                _ => sub_index.blocks_at_level(level - 1),
+            })
+            .sum();
+        let sub_index_size = sub_index_entries
+            .saturating_mul(sub_index.index_entry_size)
+            .saturating_mul(n_keys);
+
+        let extra_size = times
+            .params
+            .total_data_size
+            .saturating_add(row_index.total_size())
+            .saturating_add(sub_index_size);
+        println!(
+            "{:5}  {:>6}  {:>6}  {:>7}  {:6}  {:>7}  {:6}  {:>7}  {:6}  {:>7}",
+            HumanBytes(value_size),
+            HumanCount(n_keys),
+            HumanCount(n_times),
+            HumanBytes(times.params.total_data_size),
+            row_index.height,
+            HumanBytes(row_index.total_size()),
+            sub_index.height,
+            HumanBytes(sub_index_size),
+            row_index.height + sub_index.height,
+            HumanBytes(extra_size),
+        );
+    }
+}
+
+/// Prints a table comparing two layouts for each of `column_groups`, with
+/// `params.total_data_size` bytes of data in each group:
+///
+/// - Interleaved, with each row's values for all of the group's columns
+///   stored together as a single value, in shared data blocks with a single
+///   row index.
+///
+/// - Separate, with one column chunk per column, each with its own data
+///   blocks and row index.
+///
+/// A scan of the group reads every data block and, to find them, every L1
+/// block of each row index.  Fetching a single row descends each row index
+/// from the root, with nothing cached, and reads a data block.
+fn print_column_groups(params: &Params, column_groups: &[ColumnGroup]) {
+    println!(
+        "Column groups for {} data per group, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                      Data  ---Row Index---     Scan     Row
+Layout         Rows  Blocks  Height     Size    Reads   Reads  Group
+-----------  ------  ------  ------  -------  -------  ------  -----
+"#
+    );
+    for group in column_groups {
+        let row_size: u64 = group.value_sizes.iter().sum();
+        let n_rows = params.total_data_size / row_size;
+        let chunks = |value_sizes: &[u64]| -> Vec<LayerFile> {
+            value_sizes
+                .iter()
+                .map(|&value_size| {
+                    LayerFile::new(&Params {
+                        value_size,
+                        total_data_size: n_rows * value_size,
+                        index_run: None,
+                        ..params.clone()
+                    })
+                })
+                .collect()
+        };
+        for (layout, files) in [
+            ("interleaved", chunks(&[row_size])),
+            ("separate", chunks(&group.value_sizes)),
+        ] {
+            let row_indexes: Vec<&Index> = files
+                .iter()
+                .map(|file| {
+                    let row_index = &file.indexes[2];
+                    assert_eq!(row_index.index_type, IndexType::Row);
+                    row_index
+                })
+                .collect();
+            let data_blocks: u64 = files.iter().map(|file| file.total_data_blocks).sum();
+            let l1_blocks: u64 = row_indexes
+                .iter()
+                .map(|index| index.blocks_at_level(0))
+                .sum();
+            let line = format!(
+                "{layout:11}  {:>6}  {:>6}  {:6}  {:>7}  {:>7}  {:6}  {}",
+                HumanCount(n_rows),
+                HumanCount(data_blocks),
+                row_indexes
+                    .iter()
+                    .map(|index| index.height)
+                    .max()
+                    .unwrap_or(0),
+                HumanBytes(row_indexes.iter().map(|index| index.total_size()).sum()),
+                HumanCount(data_blocks + l1_blocks),
+                row_indexes
+                    .iter()
+                    .map(|index| index.height as u64 + 1)
+                    .sum::<u64>(),
+                match layout {
+                    "interleaved" => group.spec.as_str(),
+                    _ => "",
+                },
+            );
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+/// Size of the run length that each distinct key carries in a key-only
+/// column, in bytes.
+const RUN_LENGTH_SIZE: u64 = 4;
+
+/// Prints a table comparing the data index over every row of
+/// `params.total_data_size` bytes, in which each key repeats
+/// `duplicates_per_key` times on average, against a key-only index over the
+/// distinct keys.  In the key-only layout, each data block entry is a key
+/// plus a [RUN_LENGTH_SIZE]-byte count of its rows, so fewer keys fit in each
+/// data block, but there are `duplicates_per_key` times fewer of them.
+fn print_duplicates(params: &Params, duplicates_per_key: f64) {
+    let duplicates_per_key = duplicates_per_key.max(1.0);
+    println!(
+        "Data index over every row versus distinct keys for {} data, {duplicates_per_key} rows per key, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+                 ----Every Row-----   ---------Distinct Keys---------      Data
+ Value    Rows   Height  Index Size     Keys   Height  Index Size     Saved     Delta
+------  ------   ------  ----------   ------   ------  ----------   -------   -------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let rows = LayerFile::new(&Params {
+            value_size,
+            ..params.clone()
+        });
+        let n_rows = rows.params.total_values();
+        let n_keys = ((n_rows as f64 / duplicates_per_key).ceil() as u64).max(1);
+        let keys_per_data_block =
+            (params.min_data_block / (value_size + RUN_LENGTH_SIZE)).max(params.min_branch);
+        let keys = LayerFile::with_values_per_data_block(
+            &Params {
+                value_size,
+                total_data_size: n_keys * value_size,
+                ..params.clone()
+            },
+            keys_per_data_block,
+        );
+        let (row_index, key_index) = (&rows.indexes[0], &keys.indexes[0]);
+        assert_eq!(row_index.index_type, IndexType::Data);
+        assert_eq!(key_index.index_type, IndexType::Data);
+        let key_data = n_keys * (value_size + RUN_LENGTH_SIZE);
+        println!(
+            "{:5}  {:>6}   {:6}  {:>10}   {:>6}   {:6}  {:>10}   {:>7}   {:6.1}%",
+            HumanBytes(value_size),
+            HumanCount(n_rows),
+            row_index.height,
+            HumanBytes(row_index.total_size()),
+            HumanCount(n_keys),
+            key_index.height,
+            HumanBytes(key_index.total_size()),
+            HumanBytes(rows.params.total_data_size.saturating_sub(key_data)),
+            (key_index.total_size() as f64 / row_index.total_size() as f64 - 1.0) * 100.0
+        );
+    }
+}
+
+/// A row of a coverage table, for one index at one value size.
+struct TableRow {
+    value_size: u64,
+
+    /// Number of values in the layer file.
+    total_values: u64,
+
+    values_per_data_block: u64,
+
+    /// Number of data blocks in the layer file.
+    data_blocks: u64,
+
+    /// Index into the parameter groups, for a table that compares them.
+    group: usize,
+
+    index: Index,
+
+    /// Size of the same index in the first parameter group, for comparing
+    /// against it.
+    base_size: u64,
+
+    /// Size of the layer file's data blocks plus every index in the table.
+    total_size: u64,
+}
+
+/// Which rows of a coverage table to print, and in what order.
+struct Selection {
+    sort_by: Option<SortBy>,
+    max_height: Option<usize>,
+    max_index_size: Option<u64>,
+}
+
+impl Selection {
+    /// Drops the rows in `rows` that this selection leaves out, and sorts the
+    /// rest if requested.  The sort is stable, so rows that compare equal stay
+    /// in order of value size.
+    fn apply(&self, rows: &mut Vec<TableRow>) {
+        rows.retain(|row| {
+            self.max_height.is_none_or(|max| row.index.height <= max)
+                && self
+                    .max_index_size
+                    .is_none_or(|max| row.index.total_size() <= max)
+        });
+        match self.sort_by {
+            None => (),
+            Some(SortBy::IndexSize) => rows.sort_by_key(|row| row.index.total_size()),
+            Some(SortBy::Height) => rows.sort_by_key(|row| row.index.height),
+            Some(SortBy::TotalSize) => rows.sort_by_key(|row| row.total_size),
+        }
+    }
+}
+
+/// Returns the rows of a coverage table for `indexes`, in that order, with
+/// the rows for each value size and index interleaved across `groups`.
+fn table_rows(groups: &[Params], indexes: &[IndexType]) -> Vec<TableRow> {
+    let mut rows = Vec::new();
+    for &value_size in &groups[0].value_sizes {
+        let layer_files: Vec<_> = groups
+            .iter()
+            .map(|params| {
+                LayerFile::new(&Params {
+                    value_size,
+                    ..params.clone()
+                })
+            })
+            .collect();
+        let total_sizes: Vec<u64> = layer_files
+            .iter()
+            .map(|layer_file| layer_file.data_size() + layer_file.index_size(indexes))
+            .collect();
+        let mut layer_files: Vec<_> = layer_files
+            .into_iter()
+            .map(|layer_file| {
+                let total_values = layer_file.params.total_values();
+                let data_blocks = (
+                    layer_file.values_per_data_block,
+                    layer_file.total_data_blocks,
+                );
+                let indexes = layer_file.indexes.into_iter().map(Some).collect::<Vec<_>>();
+                (total_values, data_blocks, indexes)
+            })
+            .collect();
+        for &index_type in indexes {
+            let mut base_size = None;
+            for (group, (total_values, (values_per_data_block, data_blocks), group_indexes)) in
+                layer_files.iter_mut().enumerate()
+            {
+                let Some(index) = group_indexes
+                    .iter_mut()
+                    .find(|index| {
+                        index
+                            .as_ref()
+                            .is_some_and(|index| index.index_type == index_type)
+                    })
+                    .and_then(Option::take)
+                else {
+                    continue;
+                };
+                let base_size = *base_size.get_or_insert(index.total_size());
+                rows.push(TableRow {
+                    value_size,
+                    total_values: *total_values,
+                    values_per_data_block: *values_per_data_block,
+                    data_blocks: *data_blocks,
+                    group,
+                    index,
+                    base_size,
+                    total_size: total_sizes[group],
+                });
+            }
+        }
+    }
+    rows
+}
+
+/// Prints the coverage table for `indexes` using `params` (ignoring
+/// `params.value_size`, which varies by row).
+fn print_table(params: &Params, indexes: &[IndexType], columns: &Columns) {
+    let Params {
+        total_data_size,
+        min_data_block,
+        min_index_block,
+        min_branch,
+        ..
+    } = *params;
+
+    println!("Index coverage for {} data, min_branch={min_branch}, min_data_block={min_data_block}, min_index_block={min_index_block}{}:",
+             HumanBytes(total_data_size), params.describe_non_defaults());
+    columns.print_header([
+        "         # of   Values        Entries            # of values covered by a single index block",
+        " Value  Values   /Data         /Index  Index   -----------------------------------------------   Index",
+        "  Size  in 1TB   Block  Index   Block  Height    L1     L2     L3     L4     L5     L6     L7     Size",
+        "------  ------  ------  -----  ------  ------  -----  -----  -----  -----  -----  -----  -----  ------",
+    ]);
+    // A single table lists the indexes in the layer file's order.
+    let indexes: Vec<_> = LayerFile::new(&Params {
+        value_size: 16,
+        ..params.clone()
+    })
+    .indexes
+    .iter()
+    .map(|index| index.index_type)
+    .filter(|index_type| indexes.contains(index_type))
+    .collect();
+    let mut rows = table_rows(std::slice::from_ref(params), &indexes);
+    columns.selection.apply(&mut rows);
+    let mut last_value_size = None;
+    for row in &rows {
+        // Each value size is labeled once, on the first of its rows, unless
+        // sorting separates its rows.
+        if last_value_size != Some(row.value_size) {
+            print!(
+                "{:5}  {:7}  {:6}",
+                HumanBytes(row.value_size),
+                HumanCount(row.total_values),
+                row.values_per_data_block
+            );
+            last_value_size = Some(row.value_size);
+        } else {
+            print!("{:5}  {:7}  {:6}", "", "", "");
+        }
+        print_index_columns(&row.index);
+        columns.print(row);
+        println!();
+    }
+}
+
+/// Prints the coverage table for `indexes` once per parameter group in
+/// `groups`, interleaving the rows for each value size and index so that
+/// they can be compared directly.  The delta column compares each group's
+/// index size against the first group's.
+fn print_comparison(
+    presets: &[Preset],
+    groups: &[Params],
+    indexes: &[IndexType],
+    columns: &Columns,
+) {
+    println!(
+        "Index coverage for {} data, comparing presets:",
+        HumanBytes(groups[0].total_data_size)
+    );
+    for (i, params) in groups.iter().enumerate() {
+        println!(
+            "  {}: {} (min_branch={}, min_data_block={}, min_index_block={}{})",
+            preset_label(i),
+            presets[i].spec,
+            params.min_branch,
+            params.min_data_block,
+            params.min_index_block,
+            params.describe_non_defaults()
+        );
+    }
+    columns.print_header([
+        "         # of          Values        Entries            # of values covered by a single index block",
+        " Value  Values          /Data         /Index  Index   -----------------------------------------------   Index   Delta",
+        "  Size  in 1TB  Preset  Block  Index   Block  Height    L1     L2     L3     L4     L5     L6     L7     Size  vs. A",
+        "------  ------  ------  ------  -----  ------  ------  -----  -----  -----  -----  -----  -----  -----  ------  ------",
+    ]);
+    let mut rows = table_rows(groups, indexes);
+    columns.selection.apply(&mut rows);
+    let mut last_value_size = None;
+    for row in &rows {
+        if last_value_size != Some(row.value_size) {
+            print!(
+                "{:5}  {:7}",
+                HumanBytes(row.value_size),
+                HumanCount(row.total_values),
+            );
+            last_value_size = Some(row.value_size);
+        } else {
+            print!("{:5}  {:7}", "", "");
+        }
+        print!(
+            "  {:>6}  {:6}",
+            preset_label(row.group),
+            row.values_per_data_block
+        );
+        print_index_columns(&row.index);
+        print!(
+            "  {:6}",
+            Delta {
+                value: row.index.total_size(),
+                base: row.base_size
+            }
+        );
+        columns.print(row);
+        println!();
+    }
+}
+
+/// Prints the columns that describe `index` in the coverage tables, from the
+/// index type through the index size, without a trailing new-line.
+fn print_index_columns(index: &Index) {
+    print!(
+        "  {:6} {:6}  {:6}",
+        index.index_type, index.entries_per_block, index.height
+    );
+    for &coverage in &index.coverage {
+        print!("  {:5}", HumanCount(coverage));
+    }
+    for _ in index.height..7 {
+        print!("       ");
+    }
+    print!("  {:6}", HumanBytes(index.total_size()));
+}
+
+/// Optional columns in the coverage tables, which follow the columns printed
+/// by [print_index_columns].
+struct Columns {
+    /// Whether to report the number of values scanned within a data block per
+    /// lookup.
+    scan: bool,
+
+    /// Whether to report the slack at the end of each index block.
+    slack: bool,
+
+    /// Number of index levels to report as pinned in RAM, if any.
+    pin_levels: Option<usize>,
+
+    /// Number of open files to multiply the pinned memory by.
+    open_files: u64,
+
+    /// Error bound for modeling a learned index, if any.
+    learned_error: Option<u64>,
+
+    /// Key distribution for modeling a learned index.
+    learned_keys: LearnedKeys,
+
+    /// Whether to report block sizes and bytes per level.
+    level_sizes: bool,
+
+    /// Whether to report the blocks read by lookups by key and by row number.
+    access_paths: bool,
+
+    /// Whether to report the number of data blocks and index blocks.
+    block_counts: bool,
+
+    /// Whether to report the values covered by the last block at each level
+    /// and the fill of the root block.
+    partial_coverage: bool,
+
+    /// Which rows to print, and in what order.
+    selection: Selection,
+
+    /// Latency model for reporting lookup latency percentiles, if any.
+    latency: Option<Latency>,
+}
+
+/// A model of the latency of a lookup, in which the index levels above L1 are
+/// always cached and each of the remaining reads independently misses the
+/// cache.
+struct Latency {
+    /// Probability that the L1 index block, or the data block, isn't cached.
+    leaf_miss: f64,
+
+    /// Mean latency of reading a block from the device, in microseconds.
+    device: f64,
+
+    /// Latency of a cached block, in microseconds.
+    cached: f64,
+}
+
+impl Latency {
+    /// Number of reads per lookup that may miss the cache: the L1 index block
+    /// and the data block.
+    const LEAF_READS: u64 = 2;
+
+    /// Returns the latency, in microseconds, within which a fraction
+    /// `percentile` of lookups through `index` complete.
+    ///
+    /// A lookup reads `index.height + 1` blocks.  The number `k` of them that
+    /// miss the cache is binomial, and the sum of `k` exponential device
+    /// reads is Erlang distributed, so the latency's distribution is a mixture
+    /// of Erlang distributions shifted by the cached reads.  This finds the
+    /// percentile by bisection on that mixture.
+    fn percentile(&self, index: &Index, percentile: f64) -> f64 {
+        let reads = index.height as u64 + 1;
+        let leaf_reads = Self::LEAF_READS.min(reads);
+        let p = self.leaf_miss;
+        let binomial = |k: u64| {
+            let choose = (0..k).fold(1.0, |c, i| c * (leaf_reads - i) as f64 / (i + 1) as f64);
+            choose * p.powi(k as i32) * (1.0 - p).powi((leaf_reads - k) as i32)
+        };
+        let erlang_cdf = |k: u64, x: f64| {
+            if k == 0 {
+                return 1.0;
+            }
+            let y = x / self.device;
+            let (mut term, mut sum) = (1.0, 1.0);
+            for i in 1..k {
+                term *= y / i as f64;
+                sum += term;
+            }
+            1.0 - (-y).exp() * sum
+        };
+        let cdf = |x: f64| {
+            (0..=leaf_reads)
+                .map(|k| {
+                    let cached = (reads - k) as f64 * self.cached;
+                    if x < cached {
+                        0.0
+                    } else {
+                        binomial(k) * erlang_cdf(k, x - cached)
+                    }
+                })
+                .sum::<f64>()
+        };
+        let (mut low, mut high) = (0.0, reads as f64 * (self.cached + 50.0 * self.device));
+        for _ in 0..100 {
+            let mid = (low + high) / 2.0;
+            if cdf(mid) < percentile {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        high
+    }
+}
+
+impl Columns {
+    /// Number of levels to report with `level_sizes`.
+    const LEVELS: usize = 7;
+
+    /// Prints `header`, a table header with 4 lines, extended to include
+    /// headers for the optional columns.  Prints a blank line first.
+    fn print_header(&self, header: [&str; 4]) {
+        let mut extra: [String; 4] = Default::default();
+        // Options without a title in the top row need a blank one of their
+        // width, so that the titles of later options line up.
+        let spacer = |extra: &mut [String; 4]| {
+            let width = extra[3].len() - extra[0].len();
+            extra[0] += &" ".repeat(width);
+        };
+        if self.scan {
+            extra[1] += "  In-blk";
+            extra[2] += "    Scan";
+            extra[3] += "  ------";
+            spacer(&mut extra);
+        }
+        if self.slack {
+            extra[1] += "   Slack   Slack";
+            extra[2] += "  /Block   Total";
+            extra[3] += "  ------  ------";
+            spacer(&mut extra);
+        }
+        if let Some(k) = self.pin_levels {
+            let top = format!("Top {k}");
+            extra[1] += &format!("  {:>6}", "Pinned");
+            extra[2] += &format!("  {top:>6}");
+            extra[3] += "  ------";
+            if self.open_files > 1 {
+                let files = format!("x{}", self.open_files);
+                extra[1] += &format!("  {:>6}", "Pinned");
+                extra[2] += &format!("  {files:>6}");
+                extra[3] += "  ------";
+            }
+            spacer(&mut extra);
+        }
+        if self.learned_error.is_some() {
+            extra[0] += "  --------Learned index--------  --Whole Index--";
+            extra[1] += "   Upper                  Extra   Whole  Reads /";
+            extra[2] += "  Levels   Model   Saved Probes   Model  Lookup";
+            extra[3] += "  ------  ------  ------ ------  ------  ------";
+        }
+        if self.access_paths {
+            extra[1] += "  -Reads by-";
+            extra[2] += "   Key   Row";
+            extra[3] += "  ----  ----";
+            spacer(&mut extra);
+        }
+        if self.block_counts {
+            extra[1] += "    Data   Index";
+            extra[2] += "  Blocks  Blocks";
+            extra[3] += "  ------  ------";
+            spacer(&mut extra);
+        }
+        if self.partial_coverage {
+            extra[1] += &format!("  {:-^47}   Root", "Values covered by the last block");
+            extra[2] += "     L1     L2     L3     L4     L5     L6     L7   Fill";
+            extra[3] += "  -----  -----  -----  -----  -----  -----  -----  -----";
+            spacer(&mut extra);
+        }
+        if self.latency.is_some() {
+            extra[1] += "  -Latency (us)-";
+            extra[2] += "      p50     p99";
+            extra[3] += "  ------  ------";
+            spacer(&mut extra);
+        }
+        if self.level_sizes {
+            for level in 1..=Self::LEVELS {
+                extra[0] += &format!("  ------L{level}------");
+                extra[1] += "   Block   Level";
+                extra[2] += "    Size   Bytes";
+                extra[3] += "  ------  ------";
+            }
+        }
+
+        println!();
+        let width = header.iter().map(|line| line.len()).max().unwrap_or(0);
+        for (line, extra) in header.iter().zip(extra.iter()) {
+            let extra = extra.trim_end();
+            if extra.is_empty() {
+                println!("{line}");
+            } else {
+                println!("{line:width$}{extra}");
+            }
+        }
+    }
+
+    /// Prints the optional columns for `row`, without a trailing new-line.
+    fn print(&self, row: &TableRow) {
+        let index = &row.index;
+        if self.scan {
+            // A lookup scans, on average, half of the values covered by the
+            // index entry that it lands on.
+            match index.index_type {
+                IndexType::Data | IndexType::Trie => {
+                    print!("  {:6}", index.values_per_entry.div_ceil(2))
+                }
+                _ => print!("  {:6}", ""),
+            }
+        }
+        if self.slack {
+            // Per L1 block, which also holds for every block of a uniform
+            // index, and across all of the levels.
+            let total: u64 = (0..index.height)
+                .map(|level| index.blocks_at_level(level) * index.slack_at_level(level))
+                .sum();
+            print!(
+                "  {:6}  {:6}",
+                HumanBytes(index.slack_at_level(0)),
+                HumanBytes(total)
+            );
+        }
+        if let Some(k) = self.pin_levels {
+            let pinned = index.pinned_size(k);
+            print!("  {:6}", HumanBytes(pinned));
+            if self.open_files > 1 {
+                print!("  {:6}", HumanBytes(pinned * self.open_files));
+            }
+        }
+        if let Some(max_error) = self.learned_error {
+            let learned = index.learned(max_error, self.learned_keys);
+            print!(
+                "  {:6}  {:6}  {:6} {:6}  {:6}  {:6}",
+                HumanBytes(learned.replaced_size),
+                HumanBytes(learned.model_size),
+                HumanBytes(learned.replaced_size.saturating_sub(learned.model_size)),
+                learned.extra_probes,
+                HumanBytes(learned.whole_model_size),
+                learned.whole_reads,
+            );
+        }
+        if self.access_paths {
+            // A lookup reads a block at each level of the index and then the
+            // data block, except that a row lookup through a sparse row index
+            // reads the entry's whole run of data blocks.
+            let reads = index.height as u64 + 1;
+            let (key, row) = match index.index_type {
+                IndexType::Data | IndexType::Trie | IndexType::Hash | IndexType::Secondary => {
+                    (Some(reads), None)
+                }
+                IndexType::C1Row | IndexType::Row => {
+                    (None, Some(reads - 1 + index.params.row_sample.max(1)))
+                }
+                IndexType::Filter => (None, None),
+            };
+            let reads = |reads: Option<u64>| reads.map_or_else(String::new, |n| n.to_string());
+            print!("  {:>4}  {:>4}", reads(key), reads(row));
+        }
+        if self.block_counts {
+            let index_blocks: u64 = (0..index.height)
+                .map(|level| index.blocks_at_level(level))
+                .sum();
+            print!(
+                "  {:>6}  {:>6}",
+                HumanCount(row.data_blocks),
+                HumanCount(index_blocks)
+            );
+        }
+        if self.partial_coverage {
+            for level in 0..Self::LEVELS {
+                match level < index.height {
+                    true => print!("  {:5}", HumanCount(index.last_block_coverage(level))),
+                    false => print!("       "),
+                }
+            }
+            match index.root_fill() {
+                Some(fill) => print!("  {:4.0}%", fill * 100.0),
+                None => print!("  {:>5}", "-"),
+            }
+        }
+        if let Some(latency) = &self.latency {
+            print!(
+                "  {:6.0}  {:6.0}",
+                latency.percentile(index, 0.5),
+                latency.percentile(index, 0.99)
+            );
+        }
+        if self.level_sizes {
+            // Levels that the index doesn't have get blank cells, so that the
+            // columns after them stay under their headings.
+            for level in 0..Self::LEVELS {
+                if level < index.height {
+                    let block_size = index.block_size_at_level(level);
+                    print!(
+                        "  {:6}  {:6}",
+                        HumanBytes(block_size),
+                        HumanBytes(index.blocks_at_level(level) * block_size)
+                    );
+                } else {
+                    print!("  {:6}  {:6}", "", "");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the default parameters for `total_data_size` bytes of 16-byte
+    /// values.
+    fn params(total_data_size: u64) -> Params {
+        Params {
+            total_data_size,
+            value_size: 16,
+            min_data_block: 8192,
+            min_index_block: 8192,
+            min_branch: 32,
+            hash_load_factor: 0.75,
+            hash_bucket_size: 4096,
+            hash_pointer_size: 6,
+            hash_fingerprint: None,
+            index_run: None,
+            data_entry: DataEntry::First,
+            block_rounding: BlockRounding::None,
+            fill_factor: 1.0,
+            row_sample: 1,
+            child_checksum: 0,
+            upper_index_block: Vec::new(),
+            upper_min_branch: None,
+            key_entropy: 8.0,
+            value_sizes: vec![16],
+        }
+    }
+
+    #[test]
+    fn one_block_index_has_no_root() {
+        // 4 kB of data fits in one data block, so the data index has no
+        // blocks at all.
+        let layer_file = LayerFile::new(&params(4 * KB));
+        let data_index = &layer_file.indexes[0];
+        assert_eq!(data_index.height, 0);
+        assert_eq!(data_index.root_fill(), None);
+
+        // 1 MB of data takes 128 data blocks, which one index block covers.
+        let layer_file = LayerFile::new(&params(MB));
+        let data_index = &layer_file.indexes[0];
+        assert_eq!(data_index.height, 1);
+        assert_eq!(data_index.root_fill(), Some(128.0 / 512.0));
+    }
+
+    #[test]
+    fn flat_row_table_with_one_block() {
+        print_flat_row_table(&params(4 * KB), MB);
+    }
+
+    #[test]
+    fn filter_placement_with_one_block() {
+        print_filter_placement(&params(4 * KB), 10.0);
+    }
+
+    #[test]
+    fn filter_residency_with_one_block() {
+        let latency = Latency {
+            leaf_miss: 1.0,
+            device: 100.0,
+            cached: 1.0,
+        };
+        print_filter_residency(&params(4 * KB), 10.0, &latency);
+    }
+
+    #[test]
+    fn levels_beyond_the_root() {
+        let layer_file = LayerFile::new(&params(4 * KB));
+        let row_index = &layer_file.indexes[2];
+        assert_eq!(row_index.height, 0);
+        assert_eq!(row_index.blocks_at_level(0), 0);
+        assert_eq!(row_index.last_block_coverage(0), 0);
+
+        let layer_file = LayerFile::new(&params(MB));
+        let data_index = &layer_file.indexes[0];
+        assert_eq!(data_index.blocks_at_level(0), 1);
+        assert_eq!(data_index.last_block_coverage(0), MB / 16);
+        assert_eq!(data_index.blocks_at_level(1), 0);
+
+        let groups = ["8,8".parse().unwrap(), "16".parse().unwrap()];
+        print_column_groups(&params(4 * KB), &groups);
+    }
+
+    #[test]
+    fn preset_labels() {
+        let labels: Vec<String> = [0, 1, 25, 26, 27, 51, 52, 701, 702]
+            .into_iter()
+            .map(preset_label)
+            .collect();
+        assert_eq!(labels, ["A", "B", "Z", "AA", "AB", "AZ", "BA", "ZZ", "AAA"]);
+    }
+
+    #[test]
+    fn index_run_must_be_positive() {
+        assert_eq!(parse_index_run("16"), Ok(16));
+        assert!(parse_index_run("0").is_err());
+        assert!("index-run=0".parse::<Preset>().is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Frame-of-reference bit packing of integer columns.
+//!
+//! A column of integers that aren't sorted, such as a count, an amount in
+//! cents, or a foreign key, can't use delta encoding, but the values in one
+//! data block usually span a much smaller range than their type allows.  So a
+//! data block can store its minimum value (8 bytes), a bit width (1 byte), and
+//! the number of values (2 bytes), followed by each value minus the minimum in
+//! exactly that many bits, packed little-endian into 64-bit words.  Unlike a
+//! varint, the `i`th value is always at bit `i` times the width, so a reader
+//! can still read any value directly.
+//!
+//! This generates columns of random integers within a given range around a
+//! large base value, packs them block by block, checks that every value reads
+//! back both by position and in a sequential scan, and compares the space and
+//! read time against raw 8-byte values.
+
+use clap::Args as ClapArgs;
+use std::time::Instant;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct PackingArgs {
+    /// Range of the values in the column, that is, the maximum minus the
+    /// minimum plus 1.
+    #[clap(long = "range", default_values_t = vec![2, 100, 10_000, 1_000_000, 1 << 32])]
+    ranges: Vec<u64>,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of values to generate for each range.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+}
+
+/// Size of the header of a packed block: the minimum value, the bit width,
+/// and the number of values.
+const HEADER_SIZE: usize = 11;
+
+/// Returns the number of bits needed to store every value from 0 to
+/// `spread`.
+fn bit_width(spread: u64) -> u32 {
+    64 - spread.leading_zeros()
+}
+
+/// Returns a packed block of `values`.
+fn pack(values: &[u64]) -> Vec<u8> {
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    let width = bit_width(max - min);
+    let mut words = vec![0u64; (values.len() * width as usize).div_ceil(64)];
+    if width > 0 {
+        for (i, &value) in values.iter().enumerate() {
+            let (bit, delta) = (i * width as usize, value - min);
+            words[bit / 64] |= delta << (bit % 64);
+            if bit % 64 + width as usize > 64 {
+                words[bit / 64 + 1] |= delta >> (64 - bit % 64);
+            }
+        }
+    }
+    let mut block = Vec::with_capacity(HEADER_SIZE + words.len() * 8);
+    block.extend_from_slice(&min.to_le_bytes());
+    block.push(width as u8);
+    block.extend_from_slice(&(values.len() as u16).to_le_bytes());
+    for word in words {
+        block.extend_from_slice(&word.to_le_bytes());
+    }
+    block
+}
+
+/// A packed block, decoded into its header and its words.
+struct PackedBlock {
+    min: u64,
+    width: u32,
+    len: usize,
+    words: Vec<u64>,
+}
+
+impl PackedBlock {
+    fn new(block: &[u8]) -> Self {
+        let words = block[HEADER_SIZE..]
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        Self {
+            min: u64::from_le_bytes(block[..8].try_into().unwrap()),
+            width: block[8] as u32,
+            len: u16::from_le_bytes([block[9], block[10]]) as usize,
+            words,
+        }
+    }
+
+    /// Returns the value at position `i`.
+    fn get(&self, i: usize) -> u64 {
+        if self.width == 0 {
+            return self.min;
+        }
+        let bit = i * self.width as usize;
+        let (word, shift) = (bit / 64, bit % 64);
+        let mut delta = self.words[word] >> shift;
+        if shift + self.width as usize > 64 {
+            delta |= self.words[word + 1] << (64 - shift);
+        }
+        let mask = u64::MAX >> (64 - self.width);
+        self.min + (delta & mask)
+    }
+}
+
+/// Returns the number of values, at most `values.len()`, whose packed block
+/// fits in `block_size` bytes.  Packing more values can only widen the range,
+/// so this tries the most that could fit at the current width and shrinks
+/// until they do.
+fn fit(values: &[u64], block_size: usize) -> usize {
+    let capacity = |width: u32| match width {
+        0 => u16::MAX as usize,
+        _ => ((block_size - HEADER_SIZE) / 8 * 64 / width as usize).min(u16::MAX as usize),
+    };
+    let mut n = capacity(0).min(values.len());
+    loop {
+        let min = values[..n].iter().copied().min().unwrap();
+        let max = values[..n].iter().copied().max().unwrap();
+        let fits = capacity(bit_width(max - min));
+        if fits >= n {
+            return n;
+        }
+        n = fits.max(1);
+    }
+}
+
+/// Prints space and read time of raw and bit-packed integer columns, for
+/// each range of values in `args`.  Returns the number of failed checks.
+pub fn packing(args: &PackingArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = args.block_size.max(HEADER_SIZE as u64 + 8) as usize;
+    let rows = args.rows.max(1);
+    println!(
+        "Bit-packed integer columns, {} blocks, {} values:",
+        HumanBytes(block_size as u64),
+        HumanCount(rows),
+    );
+    print!(
+        r#"
+           -Values/Block-   Bits           -Scan (ns/value)-
+    Range     Raw  Packed  /Value  Failures      Raw  Packed
+---------  ------  ------  ------  --------  -------  ------
+"#
+    );
+    for &range in &args.ranges {
+        let range = range.max(1);
+        let mut random = rng::random(range);
+        let values: Vec<u64> = (0..rows).map(|_| (1 << 40) + random() % range).collect();
+
+        let raw_per_block = block_size / 8;
+        let raw_blocks: Vec<Vec<u8>> = values
+            .chunks(raw_per_block)
+            .map(|chunk| chunk.iter().flat_map(|value| value.to_le_bytes()).collect())
+            .collect();
+        let mut blocks = Vec::new();
+        let mut start = 0;
+        while start < values.len() {
+            let n = fit(&values[start..], block_size);
+            blocks.push(pack(&values[start..start + n]));
+            start += n;
+        }
+
+        let mut failures = 0;
+        let mut i = 0;
+        for block in &blocks {
+            let block = PackedBlock::new(block);
+            failures += (block.words.len() * 8 + HEADER_SIZE > block_size) as u64;
+            for j in 0..block.len {
+                failures += (block.get(j) != values[i]) as u64;
+                i += 1;
+            }
+        }
+        failures += (i != values.len()) as u64;
+
+        let start = Instant::now();
+        let mut raw_sum = 0u64;
+        for block in &raw_blocks {
+            for value in block.chunks_exact(8) {
+                raw_sum = raw_sum.wrapping_add(u64::from_le_bytes(value.try_into().unwrap()));
+            }
+        }
+        let raw_time = start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        let mut packed_sum = 0u64;
+        for block in &blocks {
+            let block = PackedBlock::new(block);
+            for j in 0..block.len {
+                packed_sum = packed_sum.wrapping_add(block.get(j));
+            }
+        }
+        let packed_time = start.elapsed().as_secs_f64();
+        failures += (raw_sum != packed_sum) as u64;
+
+        let packed_bytes: usize = blocks.iter().map(Vec::len).sum();
+        println!(
+            "{:>9}  {:6}  {:6}  {:6.2}  {failures:8}  {:7.2}  {:6.2}",
+            HumanCount(range),
+            raw_per_block,
+            rows as usize / blocks.len(),
+            packed_bytes as f64 * 8.0 / rows as f64,
+            raw_time / rows as f64 * 1e9,
+            packed_time / rows as f64 * 1e9,
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: PackingArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from(["packing", "--rows", "20000"]);
+        assert_eq!(packing(&args), 0);
+    }
+}
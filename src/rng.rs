@@ -0,0 +1,33 @@
+//! The pseudo-random number generator that the checks, benchmarks, tests, and
+//! examples share, so that they all draw from the same well-mixed sequence
+//! for a given seed.
+
+/// The SplitMix64 pseudo-random number generator.
+#[derive(Clone, Debug)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Returns a function that returns the successive values of a SplitMix64
+/// generator seeded with `seed`.
+pub fn random(seed: u64) -> impl FnMut() -> u64 {
+    let mut rng = SplitMix64::new(seed);
+    move || rng.next_u64()
+}
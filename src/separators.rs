@@ -0,0 +1,155 @@
+//! Prefix truncation of separators in index blocks.
+//!
+//! An entry in the data index only has to tell a search which child to
+//! descend into, so instead of the first value in its child, it can hold the
+//! shortest prefix of that value that is still greater than the last value in
+//! the child before it.  Any such separator `s` between the previous child's
+//! last value `a` and this child's first value `b` satisfies `a < s <= b`, so
+//! a search that picks the last entry whose separator is at most its target
+//! finds the right child for every value that's in the file, and for a value
+//! that isn't, lands on a child next to the one it would have anyway, which
+//! doesn't contain it either.  Comparisons are plain lexicographic byte
+//! comparisons, with a prefix sorting before every longer string it starts,
+//! so the search itself doesn't change.  Each truncated entry needs a 1-byte
+//! length.
+//!
+//! This generates sorted keys with a given entropy per byte, splits them into
+//! data blocks, computes each block's separator, checks that searches through
+//! the truncated separators find the same data block as through the first
+//! values, and compares the size of the bottom index level against full keys
+//! and against the model's estimate for `--index trie`.
+
+use clap::Args as ClapArgs;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct SeparatorsArgs {
+    /// Size of each key, in bytes.
+    #[clap(long = "value-size", default_values_t = vec![16, 64, 256])]
+    value_sizes: Vec<u64>,
+
+    /// Bits of entropy per byte of a key, from 1 to 8.  Keys are drawn from
+    /// an alphabet of `2^ENTROPY` byte values.
+    #[clap(long = "key-entropy", default_values_t = vec![8, 4])]
+    key_entropies: Vec<u32>,
+
+    /// Number of keys to generate.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+}
+
+/// Size of the child pointer in each index entry.
+const POINTER_SIZE: usize = 6;
+
+/// Returns the shortest prefix of `b` that is greater than `a`, given that
+/// `a < b`.
+fn separator<'a>(a: &[u8], b: &'a [u8]) -> &'a [u8] {
+    let common = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    &b[..(common + 1).min(b.len())]
+}
+
+/// Prints separator lengths and index sizes for each key size and entropy in
+/// `args`.  Returns the number of failed checks.
+pub fn separators(args: &SeparatorsArgs) -> u64 {
+    let mut total_failures = 0;
+    let rows = args.rows.max(2);
+    println!(
+        "Truncated separators for {} keys in {} data blocks:",
+        HumanCount(rows),
+        HumanBytes(args.block_size),
+    );
+    print!(
+        r#"
+ Entropy            Data   -Separator Bytes-   ---Index Level 1---
+Bits/Byte  Value  Blocks   Measured    Model    Full  Truncated  Saved  Failures
+---------  -----  ------   --------   ------  ------  ---------  -----  --------
+"#
+    );
+    for &entropy in &args.key_entropies {
+        let entropy = entropy.clamp(1, 8);
+        for &value_size in &args.value_sizes {
+            let value_size = value_size.max(1) as usize;
+            let mut random = rng::random(rows ^ value_size as u64 ^ entropy as u64);
+            let alphabet = 1u64 << entropy;
+            let symbols = |r: u64| (b'a' as u64 + r % alphabet) as u8;
+            let mut keys: Vec<Vec<u8>> = (0..rows)
+                .map(|_| {
+                    (0..value_size)
+                        .map(|_| match entropy {
+                            8 => random() as u8,
+                            _ => symbols(random()),
+                        })
+                        .collect()
+                })
+                .collect();
+            keys.sort();
+            keys.dedup();
+
+            let per_block = (args.block_size as usize / value_size).max(1);
+            let first_keys: Vec<&[u8]> = keys.chunks(per_block).map(|c| c[0].as_slice()).collect();
+            let separators: Vec<&[u8]> = std::iter::once(&[][..])
+                .chain(
+                    keys.chunks(per_block)
+                        .zip(keys.chunks(per_block).skip(1))
+                        .map(|(prev, next)| separator(prev.last().unwrap(), &next[0])),
+                )
+                .collect();
+
+            // Search for every key, which has to find its own block, and for
+            // a key just past each one, which has to land on the same block
+            // as through the first keys or the one after.
+            let mut failures = 0;
+            for (i, key) in keys.iter().enumerate() {
+                let block = separators.partition_point(|&s| s <= key.as_slice()) - 1;
+                failures += (block != i / per_block) as u64;
+
+                let mut absent = key.clone();
+                absent.push(0);
+                let full = first_keys.partition_point(|&k| k <= absent.as_slice()) - 1;
+                let truncated = separators.partition_point(|&s| s <= absent.as_slice()) - 1;
+                failures += (truncated != full && truncated != full + 1) as u64;
+            }
+
+            let n = separators.len() - 1;
+            let measured = separators.iter().map(|s| s.len()).sum::<usize>() as f64 / n as f64;
+            let model =
+                (((keys.len() as f64).log2() / entropy as f64).ceil() + 1.0).min(value_size as f64);
+            let full = first_keys.len() * (value_size + POINTER_SIZE);
+            let truncated: usize = separators.iter().map(|s| s.len() + 1 + POINTER_SIZE).sum();
+            println!(
+                "{entropy:9}  {:5}  {:>6}   {measured:8.2}   {model:6.0}  {:>6}  {:>9}  {:4.0}%  {failures:8}",
+                HumanBytes(value_size as u64),
+                HumanCount(first_keys.len() as u64),
+                HumanBytes(full as u64),
+                HumanBytes(truncated as u64),
+                (1.0 - truncated as f64 / full as f64) * 100.0,
+            );
+            total_failures += failures;
+        }
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: SeparatorsArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from(["separators", "--rows", "20000"]);
+        assert_eq!(separators(&args), 0);
+    }
+}
@@ -0,0 +1,544 @@
+//! Monte Carlo simulation of point lookups through an index and a block
+//! cache.
+//!
+//! The analytic model in the main table says how many blocks a lookup has to
+//! read if nothing is cached.  With a cache, the answer depends on the key
+//! distribution: the upper index levels are hot, the leaves mostly cold.  This
+//! simulates lookups of Zipf-distributed keys (or replays a trace of lookups
+//! and inserts) through an LRU cache of index and data blocks and reports the
+//! block reads that miss the cache.
+
+use clap::Args as ClapArgs;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::rng::SplitMix64;
+use crate::{HumanBytes, HumanCount, Index, IndexType, LayerFile, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct SimulateArgs {
+    /// Number of lookups to simulate per configuration.  Ignored with
+    /// `--trace`, which replays the whole trace.
+    #[clap(long, default_value_t = 200_000)]
+    lookups: u64,
+
+    /// Number of operations to run before measuring, to warm up the cache.
+    /// With `--trace`, these are the first operations in the trace
+    /// (by default, none).  Otherwise, the default is 200,000 lookups.
+    #[clap(long)]
+    warmup: Option<u64>,
+
+    /// Cache size, as a power of 2 exponent, e.g. 30 for 1 GB.
+    #[clap(long, default_value_t = 30)]
+    cache_size: u32,
+
+    /// Exponent of the Zipf distribution of looked-up keys.  0 is uniform;
+    /// larger values concentrate lookups on fewer keys.
+    #[clap(long, default_value_t = 0.99)]
+    zipf: f64,
+
+    /// Seed for the random number generator.
+    #[clap(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Replays the operations in FILE instead of generating Zipf-distributed
+    /// lookups.  Each line of FILE is `lookup KEY` or `insert KEY` (or just
+    /// `KEY`, for a lookup), where KEY is an unsigned integer that is reduced
+    /// modulo the number of values in the file to obtain its position.  Blank
+    /// lines and lines that start with `#` are ignored.
+    ///
+    /// Inserts are counted but don't read any blocks, because they go into a
+    /// new batch rather than the file being looked up.
+    #[clap(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+}
+
+/// An operation in a trace.
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Lookup(u64),
+    Insert(u64),
+}
+
+/// Reads a trace in the format described for `--trace`.
+fn read_trace(path: &Path) -> Result<Vec<Op>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut ops = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("{}:{}: {message}", path.display(), i + 1);
+        let mut words = line.split_whitespace();
+        let (kind, key) = match (words.next(), words.next(), words.next()) {
+            (Some(key), None, None) => ("lookup", key),
+            (Some(kind), Some(key), None) => (kind, key),
+            _ => return Err(error("expected `lookup KEY` or `insert KEY`")),
+        };
+        let key = key
+            .parse()
+            .map_err(|e| error(&format!("{key}: invalid key ({e})")))?;
+        ops.push(match kind {
+            "lookup" => Op::Lookup(key),
+            "insert" => Op::Insert(key),
+            _ => return Err(error(&format!("{kind}: unknown operation"))),
+        });
+    }
+    Ok(ops)
+}
+
+/// Simulates lookups with each of `groups` of parameters and prints a table of
+/// the results for each.
+pub fn simulate(
+    groups: &[Params],
+    indexes: &[IndexType],
+    args: &SimulateArgs,
+) -> Result<(), String> {
+    let trace = args.trace.as_deref().map(read_trace).transpose()?;
+    for (i, params) in groups.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        simulate_params(params, indexes, args, trace.as_deref());
+    }
+    Ok(())
+}
+
+/// Simulates lookups for each of `indexes` (except filters, which aren't used
+/// to find values) with `params` and prints a table of the results.
+fn simulate_params(
+    params: &Params,
+    indexes: &[IndexType],
+    args: &SimulateArgs,
+    trace: Option<&[Op]>,
+) {
+    let cache_size = 1 << args.cache_size;
+    println!(
+        "Simulated lookups for {} data, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        HumanBytes(params.total_data_size),
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    let warmup = match trace {
+        Some(trace) => {
+            let warmup = args.warmup.unwrap_or(0);
+            let (lookups, inserts) =
+                trace
+                    .iter()
+                    .skip(warmup as usize)
+                    .fold((0, 0), |(l, i), op| match op {
+                        Op::Lookup(_) => (l + 1, i),
+                        Op::Insert(_) => (l, i + 1),
+                    });
+            println!(
+                "{lookups} lookups and {inserts} inserts (after {warmup} warmup) from {}, {} LRU cache",
+                args.trace.as_ref().unwrap().display(),
+                HumanBytes(cache_size)
+            );
+            warmup
+        }
+        None => {
+            let warmup = args.warmup.unwrap_or(200_000);
+            println!(
+                "{} lookups (after {warmup} warmup) with Zipf exponent {}, {} LRU cache",
+                args.lookups,
+                args.zipf,
+                HumanBytes(cache_size)
+            );
+            warmup
+        }
+    };
+    print!(
+        r#"
+                         Block reads/lookup   Cache hit rate    Total
+ Value          Index   -------------------  ---------------   Device
+  Size  Index  Height   Analytic   Measured   Index     Data    Reads
+------  -----  ------   --------   --------  ------   ------   ------
+"#
+    );
+    for &value_size in &params.value_sizes {
+        let params = Params {
+            value_size,
+            ..params.clone()
+        };
+        let layer_file = LayerFile::new(&params);
+        for (i, index) in layer_file
+            .indexes
+            .iter()
+            .filter(|index| {
+                indexes.contains(&index.index_type) && index.index_type != IndexType::Filter
+            })
+            .enumerate()
+        {
+            let total_values = layer_file.params.total_values();
+            let workload = match trace {
+                Some(trace) => Workload::Trace(trace.iter()),
+                None => Workload::Zipf {
+                    zipf: Zipf::new(total_values, args.zipf),
+                    rng: SplitMix64::new(args.seed),
+                    remaining: warmup + args.lookups,
+                },
+            };
+            let result = Simulation::new(&layer_file, index, cache_size).run(workload, warmup);
+            if i == 0 {
+                print!("{:5}", HumanBytes(value_size));
+            } else {
+                print!("{:5}", "");
+            }
+            println!(
+                "  {:6}  {:6}   {:8.2}   {:8.2}  {:5.1}%   {:5.1}%  {:7}",
+                index.index_type,
+                index.height,
+                (index.height + 1) as f64 + index.overflow_reads(),
+                result.misses_per_lookup(),
+                result.index.hit_rate(),
+                result.data.hit_rate(),
+                HumanCount(result.index.misses + result.data.misses),
+            );
+        }
+    }
+}
+
+/// A source of operations to simulate.
+enum Workload<'a> {
+    /// `remaining` lookups of Zipf-distributed keys.
+    Zipf {
+        zipf: Zipf,
+        rng: SplitMix64,
+        remaining: u64,
+    },
+
+    /// Operations replayed from a trace.
+    Trace(std::slice::Iter<'a, Op>),
+}
+
+impl Iterator for Workload<'_> {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        match self {
+            Workload::Zipf {
+                zipf,
+                rng,
+                remaining,
+            } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                *remaining -= 1;
+                let rank = zipf.sample(rng);
+
+                // Scatter the popular keys across the file, instead of putting
+                // them all next to each other at the beginning of the file.
+                Some(Op::Lookup(rank.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+            }
+            Workload::Trace(iter) => iter.next().copied(),
+        }
+    }
+}
+
+/// A block in the simulated file.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+enum Block {
+    /// The `n`th index block at a level, where level 0 is just above the
+    /// data blocks.
+    Index { level: usize, n: u64 },
+
+    /// The `n`th data block.
+    Data(u64),
+}
+
+/// A least-recently-used cache of blocks, with a capacity in bytes.
+///
+/// The entries form a doubly linked list, from most to least recently used,
+/// threaded through `nodes` by index.
+struct LruCache {
+    capacity: u64,
+    used: u64,
+    map: HashMap<Block, usize, BuildHasherDefault<BlockHasher>>,
+    nodes: Vec<Node>,
+    /// Indexes of unused elements of `nodes`.
+    free: Vec<usize>,
+    /// Most recently used entry.
+    head: Option<usize>,
+    /// Least recently used entry.
+    tail: Option<usize>,
+}
+
+struct Node {
+    block: Block,
+    size: u64,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl LruCache {
+    fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            used: 0,
+            map: HashMap::default(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+
+    /// Accesses `block`, which is `size` bytes, and returns whether it was
+    /// already in the cache.  Afterward, `block` is the most recently used
+    /// entry in the cache.
+    fn access(&mut self, block: Block, size: u64) -> bool {
+        if let Some(&i) = self.map.get(&block) {
+            self.unlink(i);
+            self.push_front(i);
+            return true;
+        }
+
+        let node = Node {
+            block,
+            size,
+            prev: None,
+            next: None,
+        };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.map.insert(block, i);
+        self.push_front(i);
+        self.used += size;
+        while self.used > self.capacity {
+            let Some(oldest) = self.tail else {
+                break;
+            };
+            self.unlink(oldest);
+            self.map.remove(&self.nodes[oldest].block);
+            self.used -= self.nodes[oldest].size;
+            self.free.push(oldest);
+        }
+        false
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let Node { prev, next, .. } = self.nodes[i];
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.nodes[i].prev = None;
+        self.nodes[i].next = self.head;
+        match self.head {
+            Some(head) => self.nodes[head].prev = Some(i),
+            None => self.tail = Some(i),
+        }
+        self.head = Some(i);
+    }
+}
+
+/// A fast, non-cryptographic hasher for [Block]s.  The standard library's
+/// default hasher dominates the simulation's run time otherwise.
+#[derive(Default)]
+struct BlockHasher(u64);
+
+impl Hasher for BlockHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+}
+
+/// Hit and miss counts for a kind of block.
+#[derive(Default)]
+struct Counts {
+    hits: u64,
+    misses: u64,
+}
+
+impl Counts {
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    /// Returns the hit rate as a percentage.
+    fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64 * 100.0
+        }
+    }
+}
+
+struct SimulationResult {
+    lookups: u64,
+    index: Counts,
+    data: Counts,
+}
+
+impl SimulationResult {
+    /// Returns the average number of blocks read from the device per lookup.
+    fn misses_per_lookup(&self) -> f64 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            (self.index.misses + self.data.misses) as f64 / self.lookups as f64
+        }
+    }
+}
+
+struct Simulation<'a> {
+    layer_file: &'a LayerFile,
+    index: &'a Index,
+    cache: LruCache,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(layer_file: &'a LayerFile, index: &'a Index, cache_size: u64) -> Self {
+        Self {
+            layer_file,
+            index,
+            cache: LruCache::new(cache_size),
+        }
+    }
+
+    /// Runs the operations in `workload`, measuring all but the first
+    /// `warmup` of them.
+    fn run(mut self, workload: Workload, warmup: u64) -> SimulationResult {
+        let mut result = SimulationResult {
+            lookups: 0,
+            index: Counts::default(),
+            data: Counts::default(),
+        };
+        let total_values = self.layer_file.params.total_values();
+        for (i, op) in workload.enumerate() {
+            let measure = i as u64 >= warmup;
+            let Op::Lookup(key) = op else {
+                continue;
+            };
+            if measure {
+                result.lookups += 1;
+            }
+            for (block, size) in self.path(key % total_values) {
+                let hit = self.cache.access(block, size);
+                if measure {
+                    match block {
+                        Block::Index { .. } => result.index.record(hit),
+                        Block::Data(_) => result.data.record(hit),
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns the blocks, with their sizes, that a lookup of the value at
+    /// `position` reads, from the root down to the data block.
+    fn path(&self, position: u64) -> Vec<(Block, u64)> {
+        let index = self.index;
+        let mut path: Vec<_> = (0..index.height)
+            .rev()
+            .map(|level| {
+                let n = match index.index_type {
+                    // The hash spreads values across buckets without regard to
+                    // their position.
+                    IndexType::Hash => {
+                        position.wrapping_mul(0xbf58_476d_1ce4_e5b9) % index.blocks_at_level(level)
+                    }
+                    _ => position / index.coverage[level],
+                };
+                (Block::Index { level, n }, index.block_size_at_level(level))
+            })
+            .collect();
+        if index.index_type == IndexType::Hash {
+            // Some values are in an overflow bucket chained from their home
+            // bucket.  Choose them pseudo-randomly, at the rate that the
+            // analytic model predicts.  This only approximates longer chains,
+            // which are rare at reasonable load factors.
+            let overflow = index.overflow_reads().min(1.0);
+            let hash = position.wrapping_mul(0x94d0_49bb_1331_11eb);
+            if ((hash >> 11) as f64 / (1u64 << 53) as f64) < overflow {
+                let Block::Index { n, .. } = path[0].0 else {
+                    unreachable!()
+                };
+                path.push((Block::Index { level: 1, n }, index.block_size));
+            }
+        }
+        // A secondary index is sorted by value, but the rows it points to
+        // aren't, so consecutive values land in unrelated data blocks.
+        let row = match index.index_type {
+            IndexType::Secondary => {
+                position.wrapping_mul(0x9e37_79b9_7f4a_7c15) % self.layer_file.params.total_values()
+            }
+            _ => position,
+        };
+        path.push((
+            Block::Data(row / self.layer_file.values_per_data_block),
+            self.layer_file.data_block_size,
+        ));
+        path
+    }
+}
+
+/// Approximate Zipf distribution over ranks `0..n`, sampled by inverting the
+/// CDF of the continuous distribution with density `x^-s` over `[1, n + 1)`.
+/// This is accurate enough for our purposes and, unlike an exact sampler,
+/// works for `n` in the trillions.
+struct Zipf {
+    n: u64,
+    s: f64,
+}
+
+impl Zipf {
+    fn new(n: u64, s: f64) -> Self {
+        Self { n, s }
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> u64 {
+        let u = rng.next_f64();
+        let n = self.n as f64 + 1.0;
+        let x = if (self.s - 1.0).abs() < 1e-9 {
+            n.powf(u)
+        } else {
+            let t = 1.0 - self.s;
+            (1.0 + u * (n.powf(t) - 1.0)).powf(1.0 / t)
+        };
+        (x as u64).saturating_sub(1).min(self.n - 1)
+    }
+}
@@ -0,0 +1,421 @@
+//! Soak test of a storage location.
+//!
+//! This runs continuous ingest, lookups, scans, compaction, and checkpointing
+//! against a storage location (see `storage.rs`) for a fixed time or number of
+//! steps.  Each step adds a random batch, with insertions and retractions of
+//! earlier rows in random order, to each spine, compacts the spine, and looks
+//! up random keys.  With a small `--memory-budget`, batches go through the
+//! external sort.
+//! Every `--check-every` steps, it checks that:
+//!
+//! - Lookups and a random range scan return the same rows as an in-memory
+//!   model of the inserted data.
+//!
+//! - Every file in the storage location is referenced by a spine or a
+//!   checkpoint, and every referenced file exists.
+//!
+//! - The number of batches in each spine stays within what a size-tiered spine
+//!   with the given fanout holds after that many steps, i.e. compaction keeps
+//!   up with ingest.
+//!
+//! - On Linux, the number of open file descriptors (counted in
+//!   `/proc/self/fd`) stays within a few of the number of batches, and the
+//!   resident memory (from `/proc/self/statm`) stays below `--max-rss`.
+//!
+//! Every `--restart-every` steps, it drops the storage location and reopens it
+//! from its last checkpoint, rolls the model back to the same checkpoint, and
+//! checks a full scan and that the reopened location holds no garbage.
+//!
+//! The first failed check stops the test with an error.
+
+use clap::Args as ClapArgs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::layer::Row;
+use crate::rng;
+use crate::storage::{Storage, StorageOptions};
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct SoakArgs {
+    /// Storage location to use, which must not already hold one.  By default,
+    /// a directory in the system's temporary directory, deleted when the test
+    /// passes.
+    #[clap(long)]
+    dir: Option<PathBuf>,
+
+    /// Seconds to run for.
+    #[clap(long, default_value_t = 10)]
+    seconds: u64,
+
+    /// Stops after N steps, even if `--seconds` haven't passed.  Steps that
+    /// are rolled back by a restart count toward N.
+    #[clap(long)]
+    steps: Option<u64>,
+
+    /// Number of spines.
+    #[clap(long, default_value_t = 2)]
+    spines: usize,
+
+    /// Number of rows in each step's batch for each spine.
+    #[clap(long, default_value_t = 1000)]
+    batch_rows: u64,
+
+    /// Number of distinct keys.
+    #[clap(long, default_value_t = 100_000)]
+    keys: u64,
+
+    /// Fraction of each batch's rows that retract earlier rows.
+    #[clap(long, default_value_t = 0.25)]
+    retractions: f64,
+
+    /// Number of batches at a level that merge into one at the next level.
+    #[clap(long, default_value_t = 4)]
+    fanout: usize,
+
+    /// Memory budget for sorting each batch, in kB.  A batch of rows that
+    /// takes more, at 24 bytes per row, is sorted externally in runs.
+    #[clap(long, default_value_t = 65536)]
+    memory_budget: usize,
+
+    /// Number of point lookups per step.
+    #[clap(long, default_value_t = 100)]
+    lookups: u64,
+
+    /// Number of steps between checkpoints.
+    #[clap(long, default_value_t = 10)]
+    checkpoint_every: u64,
+
+    /// Number of steps between restarts from the last checkpoint, or 0 to
+    /// never restart.
+    #[clap(long, default_value_t = 50)]
+    restart_every: u64,
+
+    /// Number of steps between checks of the storage location and resource
+    /// usage, which are also the rows of the report.
+    #[clap(long, default_value_t = 100)]
+    check_every: u64,
+
+    /// Resident memory limit, in MB.
+    #[clap(long, default_value_t = 1024)]
+    max_rss: u64,
+
+    /// Seed for the random number generator.
+    #[clap(long, default_value_t = 1)]
+    seed: u64,
+}
+
+/// The rows of the data, as a map from (key, value) to weight.
+type Model = BTreeMap<(u64, u64), i64>;
+
+/// Returns the number of open file descriptors, if the system says.
+fn open_files() -> Option<usize> {
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|dir| dir.count())
+}
+
+/// Returns the resident memory, in bytes, if the system says.
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
+
+/// Returns the most batches that a size-tiered spine with `fanout` holds
+/// after `steps` batches: fewer than `fanout` at each level.
+fn max_batches(fanout: usize, steps: u64) -> usize {
+    let mut levels = 1;
+    let mut capacity = fanout as u64;
+    while capacity <= steps {
+        capacity = capacity.saturating_mul(fanout as u64);
+        levels += 1;
+    }
+    levels * (fanout - 1)
+}
+
+/// Checks that `rows` match the rows of `model` with keys from `from` to
+/// `to`, inclusive.
+fn check_rows(
+    what: &str,
+    rows: impl Iterator<Item = Result<Row, String>>,
+    model: &Model,
+    from: u64,
+    to: u64,
+) -> Result<(), String> {
+    let mut expected = model
+        .range((from, 0)..=(to, u64::MAX))
+        .map(|(&(key, value), &weight)| (key, value, weight));
+    for row in rows {
+        let row = row?;
+        match expected.next() {
+            Some(expected) if expected == row => (),
+            Some(expected) => {
+                return Err(format!(
+                    "{what}: read {row:?} where the model has {expected:?}"
+                ))
+            }
+            None => return Err(format!("{what}: read {row:?} after the model's last row")),
+        }
+    }
+    match expected.next() {
+        Some(expected) => Err(format!("{what}: missing {expected:?}")),
+        None => Ok(()),
+    }
+}
+
+pub fn soak(args: &SoakArgs) -> Result<(), String> {
+    let temporary = args.dir.is_none();
+    let root = args.dir.clone().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("storage-design-soak-{}", std::process::id()))
+    });
+    if root.exists() {
+        return Err(format!("{}: already exists", root.display()));
+    }
+    if args.spines == 0 || args.keys == 0 {
+        return Err("--spines and --keys must be positive".into());
+    }
+    let fanout = args.fanout.max(2);
+    let options = StorageOptions {
+        fanout,
+        memory_budget: args.memory_budget << 10,
+        ..StorageOptions::default()
+    };
+    println!(
+        "Soak test of {} spines in {}, {} rows per batch over {} keys with {:.0}% retractions, fanout {fanout}, {} lookups per step:",
+        args.spines,
+        root.display(),
+        HumanCount(args.batch_rows),
+        HumanCount(args.keys),
+        args.retractions.clamp(0.0, 1.0) * 100.0,
+        HumanCount(args.lookups),
+    );
+    print!(
+        r#"
+                                                 Open
+ Step  Seconds       Rows  Batches  Files  Checkpoints  Files       RSS  Restarts
+-----  -------  ---------  -------  -----  -----------  -----  --------  --------
+"#
+    );
+    run(&root, args, &options)?;
+    if temporary {
+        std::fs::remove_dir_all(&root).map_err(|e| format!("{}: {e}", root.display()))?;
+    }
+    println!();
+    println!("All checks passed.");
+    Ok(())
+}
+
+fn run(root: &Path, args: &SoakArgs, options: &StorageOptions) -> Result<(), String> {
+    let mut random = rng::random(args.seed);
+    let spines: Vec<String> = (0..args.spines).map(|i| format!("s{i}")).collect();
+    let retractions = (args.retractions.clamp(0.0, 1.0) * 1000.0) as u64;
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(args.seconds);
+    let baseline_files = open_files();
+    let mut storage = Storage::open(root, options)?;
+    let mut models: Vec<Model> = vec![Model::new(); spines.len()];
+    let mut checkpointed: Option<(u64, Vec<Model>)> = None;
+    let mut restarts = 0;
+    let mut batches_written = vec![0; spines.len()];
+    let mut step = 0;
+    let mut iterations = 0;
+    while args.steps.is_none_or(|steps| iterations < steps) && Instant::now() < deadline {
+        step += 1;
+        iterations += 1;
+        for (i, (spine, model)) in spines.iter().zip(models.iter_mut()).enumerate() {
+            let mut batch = Model::new();
+            let mut rows: Vec<Row> = Vec::new();
+            for _ in 0..args.batch_rows {
+                let key = random() % args.keys;
+                let retraction = if random() % 1000 < retractions {
+                    model.range((key, 0)..).next()
+                } else {
+                    None
+                };
+                let (row, weight) = match retraction {
+                    Some((&row, &weight)) => (row, -weight),
+                    None => ((key, random() % 4), 1 + (random() % 3) as i64),
+                };
+                *batch.entry(row).or_default() += weight;
+                rows.push((row.0, row.1, weight));
+            }
+            batch.retain(|_, weight| *weight != 0);
+            for (&row, &weight) in &batch {
+                let sum = model.entry(row).or_default();
+                *sum += weight;
+                if *sum == 0 {
+                    model.remove(&row);
+                }
+            }
+            storage.insert_batch(spine, step, rows)?;
+            batches_written[i] += 1;
+            storage.compact(spine)?;
+
+            for _ in 0..args.lookups {
+                let key = random() % args.keys;
+                let what = format!("step {step}: lookup of {key} in {spine}");
+                check_rows(&what, storage.scan(spine, key, key)?, model, key, key)?;
+            }
+        }
+
+        if step % args.checkpoint_every.max(1) == 0 {
+            storage.checkpoint(step)?;
+            checkpointed = Some((step, models.clone()));
+        }
+
+        if args.restart_every > 0 && iterations % args.restart_every == 0 {
+            if let Some((checkpoint_step, checkpoint_models)) = &checkpointed {
+                drop(storage);
+                storage = Storage::open(root, options)?;
+                step = *checkpoint_step;
+                models = checkpoint_models.clone();
+                restarts += 1;
+                if let Some(path) = storage.garbage()?.first() {
+                    return Err(format!(
+                        "step {step}: {} left over after restarting",
+                        path.display()
+                    ));
+                }
+                for (spine, model) in spines.iter().zip(&models) {
+                    let what = format!("step {step}: scan of {spine} after restarting");
+                    check_rows(&what, storage.scan(spine, 0, u64::MAX)?, model, 0, u64::MAX)?;
+                }
+            }
+        }
+
+        let done =
+            args.steps.is_some_and(|steps| iterations >= steps) || Instant::now() >= deadline;
+        if iterations % args.check_every.max(1) == 0 || done {
+            check(
+                &storage,
+                &spines,
+                &models,
+                step,
+                &batches_written,
+                args,
+                &mut random,
+            )?;
+            let n_batches: usize = storage
+                .spines()
+                .values()
+                .map(|spine| spine.batches.len())
+                .sum();
+            let max_files = baseline_files.map(|files| files + n_batches + 16);
+            let open = open_files();
+            if let (Some(open), Some(max_files)) = (open, max_files) {
+                if open > max_files {
+                    return Err(format!(
+                        "step {step}: {open} open files for {n_batches} batches"
+                    ));
+                }
+            }
+            let rss = resident_bytes();
+            if let Some(rss) = rss {
+                if rss > args.max_rss << 20 {
+                    return Err(format!(
+                        "step {step}: resident memory {} exceeds {} MB",
+                        HumanBytes(rss),
+                        args.max_rss
+                    ));
+                }
+            }
+            let n_files = std::fs::read_dir(root)
+                .map_err(|e| format!("{}: {e}", root.display()))?
+                .count();
+            println!(
+                "{step:5}  {:7.1}  {:>9}  {n_batches:7}  {n_files:5}  {:11}  {:>5}  {:>8}  {restarts:8}",
+                start.elapsed().as_secs_f64(),
+                HumanCount(models.iter().map(|model| model.len() as u64).sum()),
+                storage.checkpoints().len(),
+                open.map_or("-".into(), |open| open.to_string()),
+                rss.map_or("-".into(), |rss| HumanBytes(rss).to_string()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks a random range scan of every spine, that the storage location
+/// holds only what it refers to, and that no spine has too many batches
+/// for the number of batches written to it, in `batches_written`.
+fn check(
+    storage: &Storage,
+    spines: &[String],
+    models: &[Model],
+    step: u64,
+    batches_written: &[u64],
+    args: &SoakArgs,
+    random: &mut impl FnMut() -> u64,
+) -> Result<(), String> {
+    storage.check().map_err(|e| format!("step {step}: {e}"))?;
+    let fanout = args.fanout.max(2);
+    for ((spine, model), &written) in spines.iter().zip(models).zip(batches_written) {
+        let from = random() % args.keys;
+        let to = from + args.keys / 100;
+        let what = format!("step {step}: scan of {spine} from {from} to {to}");
+        check_rows(&what, storage.scan(spine, from, to)?, model, from, to)?;
+
+        let n_batches = storage
+            .spines()
+            .get(spine)
+            .map_or(0, |spine| spine.batches.len());
+        let limit = max_batches(fanout, written);
+        if n_batches > limit {
+            return Err(format!(
+                "step {step}: {spine} has {n_batches} batches, more than {limit}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: SoakArgs,
+    }
+
+    #[test]
+    fn soak_with_restarts() {
+        let root = std::env::temp_dir().join(format!(
+            "storage-design-soak-test-{}-{}",
+            std::process::id(),
+            crate::layer::unique()
+        ));
+        let Wrapper { args } = Wrapper::parse_from([
+            "soak",
+            "--steps",
+            "60",
+            "--seconds",
+            "600",
+            "--batch-rows",
+            "200",
+            "--keys",
+            "2000",
+            "--lookups",
+            "20",
+            "--checkpoint-every",
+            "7",
+            "--restart-every",
+            "25",
+            "--check-every",
+            "10",
+        ]);
+        let options = StorageOptions {
+            memory_budget: 64 * crate::layer::ROW_SIZE,
+            ..StorageOptions::default()
+        };
+        let result = run(&root, &args, &options);
+        std::fs::remove_dir_all(&root).ok();
+        result.unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! External merge sort of rows with bounded memory.
+//!
+//! This is the external sort that README.md describes for batches that don't
+//! fit in memory.  Rows arrive in any order.  They accumulate in memory until
+//! they reach the memory budget, at which point they are sorted, consolidated,
+//! and written as a run, a temporary layer file whose name ends in `.mut`, so
+//! that recovery deletes it if we crash.  Finishing the sort merges groups of
+//! runs into larger runs while there are more runs than the fan-in, and then
+//! yields the merge of the remaining runs, consolidated across runs.  The runs
+//! are deleted when the sorted output is dropped.
+//!
+//! Because the writer and the merger are the ones that spines use, a sort
+//! without enough memory writes the same bytes as one with enough, only more
+//! times: with memory budget `M`, fan-in `F`, and `N` bytes of input, each
+//! byte is written as a run `ceil(log_F(N / M))` times before the final file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::layer::{unique, Merger, Options, Reader, Row, Writer, ROW_SIZE};
+
+/// Statistics of a sort.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SortStats {
+    /// Number of runs written from memory.
+    pub runs: usize,
+
+    /// Number of merges of runs into larger runs.
+    pub merges: usize,
+
+    /// Number of rows written to runs, by both spills and merges.
+    pub rows_written: u64,
+}
+
+/// Sorts and consolidates rows, one at a time.
+pub struct ExternalSort {
+    dir: PathBuf,
+    options: Options,
+    budget: usize,
+    fanin: usize,
+    buffer: Vec<Row>,
+    in_order: bool,
+    runs: Vec<Reader>,
+    stats: SortStats,
+}
+
+/// Sorts and consolidates `rows`, which may be in any order, in place.
+fn consolidate(rows: &mut Vec<Row>, in_order: bool) -> Result<(), String> {
+    if !in_order {
+        rows.sort_unstable_by_key(|&(key, value, _)| (key, value));
+    }
+    // Rows before `n` are consolidated, except that the last of them may
+    // have weight 0 until the next row with different fields comes along.
+    let mut n: usize = 0;
+    for i in 0..rows.len() {
+        let (key, value, weight) = rows[i];
+        if n > 0 && (rows[n - 1].0, rows[n - 1].1) == (key, value) {
+            rows[n - 1].2 = rows[n - 1]
+                .2
+                .checked_add(weight)
+                .ok_or_else(|| format!("weight of ({key}, {value}) overflows"))?;
+            continue;
+        }
+        if n > 0 && rows[n - 1].2 == 0 {
+            n -= 1;
+        }
+        rows[n] = (key, value, weight);
+        n += 1;
+    }
+    if n > 0 && rows[n - 1].2 == 0 {
+        n -= 1;
+    }
+    rows.truncate(n);
+    Ok(())
+}
+
+impl ExternalSort {
+    /// Starts a sort that keeps up to `memory_budget` bytes of rows in memory,
+    /// writes its runs in `dir` with `options`, and merges up to `fanin` runs
+    /// at a time.
+    pub fn new(dir: &Path, memory_budget: usize, fanin: usize, options: &Options) -> Self {
+        Self {
+            dir: dir.into(),
+            options: options.clone(),
+            budget: (memory_budget / ROW_SIZE).max(2),
+            fanin: fanin.max(2),
+            buffer: Vec::new(),
+            in_order: true,
+            runs: Vec::new(),
+            stats: SortStats::default(),
+        }
+    }
+
+    /// Adds `row`.
+    pub fn push(&mut self, row: Row) -> Result<(), String> {
+        if let Some(&(key, value, _)) = self.buffer.last() {
+            self.in_order &= (key, value) <= (row.0, row.1);
+        }
+        self.buffer.push(row);
+        if self.buffer.len() >= self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Writes `rows` as a new run, unless there are none.
+    fn write_run(
+        &mut self,
+        rows: impl Iterator<Item = Result<Row, String>>,
+    ) -> Result<Option<Reader>, String> {
+        let path = self.dir.join(format!("sort-{:016x}.feldera.mut", unique()));
+        let error = |e: std::io::Error| format!("{}: {e}", path.display());
+        let mut writer = Writer::create(&path, (0, 0), &self.options).map_err(error)?;
+        let mut n_rows = 0;
+        for row in rows {
+            writer.push(row?).map_err(error)?;
+            n_rows += 1;
+        }
+        writer.finish().map_err(error)?;
+        if n_rows == 0 {
+            fs::remove_file(&path).map_err(error)?;
+            return Ok(None);
+        }
+        self.stats.rows_written += n_rows;
+        Reader::open(&path).map(Some)
+    }
+
+    /// Sorts the rows in memory and writes them as a run.
+    fn spill(&mut self) -> Result<(), String> {
+        let mut rows = std::mem::take(&mut self.buffer);
+        consolidate(&mut rows, self.in_order)?;
+        self.in_order = true;
+        if let Some(run) = self.write_run(rows.into_iter().map(Ok))? {
+            self.runs.push(run);
+            self.stats.runs += 1;
+        }
+        Ok(())
+    }
+
+    /// Finishes the sort, merging runs until there are no more than the
+    /// fan-in.
+    pub fn finish(mut self) -> Result<Sorted, String> {
+        if self.runs.is_empty() {
+            let mut rows = std::mem::take(&mut self.buffer);
+            consolidate(&mut rows, self.in_order)?;
+            return Ok(Sorted {
+                memory: rows,
+                runs: Vec::new(),
+                stats: self.stats,
+            });
+        }
+        self.spill()?;
+        while self.runs.len() > self.fanin {
+            let group: Vec<Reader> = self.runs.drain(..self.fanin).collect();
+            let merged = {
+                let cursors = group.iter().map(|run| run.cursor()).collect();
+                self.write_run(Merger::new(cursors)?)
+            };
+            delete_runs(&group);
+            self.runs.extend(merged?);
+            self.stats.merges += 1;
+        }
+        Ok(Sorted {
+            memory: Vec::new(),
+            runs: std::mem::take(&mut self.runs),
+            stats: self.stats,
+        })
+    }
+}
+
+impl Drop for ExternalSort {
+    fn drop(&mut self) {
+        delete_runs(&self.runs);
+    }
+}
+
+fn delete_runs(runs: &[Reader]) {
+    for run in runs {
+        let _ = fs::remove_file(run.path());
+    }
+}
+
+/// The output of a sort, whose runs are deleted when it is dropped.
+pub struct Sorted {
+    memory: Vec<Row>,
+    runs: Vec<Reader>,
+    stats: SortStats,
+}
+
+impl Sorted {
+    pub fn stats(&self) -> SortStats {
+        self.stats
+    }
+
+    /// Returns the sorted, consolidated rows.
+    pub fn rows(&self) -> Result<Box<dyn Iterator<Item = Result<Row, String>> + '_>, String> {
+        if self.runs.is_empty() {
+            return Ok(Box::new(self.memory.iter().map(|&row| Ok(row))));
+        }
+        let cursors = self.runs.iter().map(|run| run.cursor()).collect();
+        Ok(Box::new(Merger::new(cursors)?))
+    }
+}
+
+impl Drop for Sorted {
+    fn drop(&mut self) {
+        delete_runs(&self.runs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng;
+    use std::collections::BTreeMap;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "storage-design-sort-{name}-{}-{}",
+            std::process::id(),
+            unique()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Sorts `rows` with `memory_budget` and a fan-in of 3, checks the result
+    /// against a `BTreeMap`, and returns the statistics.
+    fn check_sort(dir: &Path, memory_budget: usize, rows: &[Row]) -> SortStats {
+        let mut expected = BTreeMap::new();
+        for &(key, value, weight) in rows {
+            *expected.entry((key, value)).or_insert(0) += weight;
+        }
+        let expected: Vec<Row> = expected
+            .into_iter()
+            .filter(|&(_, weight)| weight != 0)
+            .map(|((key, value), weight)| (key, value, weight))
+            .collect();
+
+        let mut sort = ExternalSort::new(dir, memory_budget, 3, &Options::default());
+        for &row in rows {
+            sort.push(row).unwrap();
+        }
+        let sorted = sort.finish().unwrap();
+        let actual: Vec<Row> = sorted.rows().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(actual, expected);
+        let stats = sorted.stats();
+        drop(sorted);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0, "runs left over");
+        stats
+    }
+
+    fn random_rows(n: u64) -> Vec<Row> {
+        let mut random = rng::random(1);
+        (0..n)
+            .map(|_| {
+                (
+                    random() % 500,
+                    random() % 3,
+                    [-1, 1, 2][random() as usize % 3],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_in_memory() {
+        let dir = temp_dir("memory");
+        let stats = check_sort(&dir, 1 << 20, &random_rows(5000));
+        assert_eq!(stats, SortStats::default());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn spills_and_merges_runs() {
+        let dir = temp_dir("spill");
+        // 100 rows of memory for 5,000 rows makes 50 runs, which take two
+        // levels of merges with a fan-in of 3 to get down to 3 or fewer.
+        let stats = check_sort(&dir, 100 * ROW_SIZE, &random_rows(5000));
+        assert_eq!(stats.runs, 50);
+        assert!(stats.merges >= 16, "{stats:?}");
+        assert!(stats.rows_written > 5000, "{stats:?}");
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn runs_that_cancel() {
+        let dir = temp_dir("cancel");
+        let mut rows: Vec<Row> = (0..1000).map(|key| (key, 0, 1)).collect();
+        rows.extend((0..1000).rev().map(|key| (key, 0, -1)));
+        rows.push((7, 7, 7));
+        let stats = check_sort(&dir, 64 * ROW_SIZE, &rows);
+        assert!(stats.runs > 3);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Compaction cost model for a spine of layer files.
+//!
+//! The rest of the model looks at a single layer file.  A spine holds many of
+//! them: every batch becomes a new file, and the spine merges files in the
+//! background to keep their number down.  This models a size-tiered spine in
+//! which each batch arrives as a level-0 file and, whenever `fanout` files
+//! accumulate at a level, they are merged into a single file at the next level.
+//! The files in the spine then behave like the digits of the number of batches
+//! written in base `fanout`, which lets us compute the costs exactly without
+//! simulating every merge.
+
+use clap::Args as ClapArgs;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{HumanBytes, HumanCount, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct SpineArgs {
+    /// Size of each arriving batch, as a power of 2 exponent, e.g. 24 for 16
+    /// MB.  The number of batches is the total data size divided by this.
+    #[clap(long, default_value_t = 24)]
+    batch_size: u32,
+
+    /// Number of files at one level that are merged into a single file at the
+    /// next level.
+    #[clap(long = "fanout", default_values_t = vec![2, 4, 8, 16, 32])]
+    fanouts: Vec<u64>,
+
+    /// Fraction of the keys in each batch (after the first) that update a key
+    /// already in the spine, rather than inserting a new one.  The updated key
+    /// is chosen uniformly from all of the live keys.  Until the files holding
+    /// the old and new versions merge, both take up space.
+    #[clap(long, default_value_t = 0.25)]
+    update_fraction: f64,
+
+    /// Sustained ingest rate, in bytes per second.  Adds a table of the
+    /// background read and write bandwidth that merges consume at that rate.
+    #[clap(long, value_name = "BYTES")]
+    ingest_rate: Option<u64>,
+
+    /// Device bandwidth budget for reads and writes together, in bytes per
+    /// second, for `--ingest-rate`.
+    #[clap(long, value_name = "BYTES", default_value_t = 2 << 30)]
+    device_bandwidth: u64,
+}
+
+/// Costs of building a spine by ingesting batches with a particular fanout.
+struct SpineCost {
+    /// Number of levels of merged files, not counting level 0.
+    levels: u32,
+
+    /// Total bytes written by merges, not counting writing the batches.
+    /// Merges read the same number of bytes.  This doesn't account for
+    /// consolidating updates, which makes merge output smaller than its input,
+    /// so it is an upper bound when there are updates.
+    merge_bytes: u64,
+
+    /// Number of files in the spine after the last batch.
+    final_files: u64,
+
+    /// Maximum number of files in the spine at any point.
+    max_files: u64,
+
+    /// Space amplification (bytes in files divided by bytes of live data)
+    /// after the last batch, the worst at any point, and on average over
+    /// time.
+    final_space_amp: f64,
+    worst_space_amp: f64,
+    average_space_amp: f64,
+}
+
+impl SpineCost {
+    fn new(n_batches: u64, batch_size: u64, fanout: u64, update_fraction: f64) -> Self {
+        // A merge into level `i + 1` happens once every `fanout^(i + 1)`
+        // batches and writes `fanout^(i + 1)` batches worth of data.
+        let mut levels = 0;
+        let mut merge_bytes = 0;
+        let mut span = fanout;
+        while span <= n_batches {
+            levels += 1;
+            merge_bytes += (n_batches / span) * span * batch_size;
+            span = match span.checked_mul(fanout) {
+                Some(span) => span,
+                None => break,
+            };
+        }
+
+        // Each level holds up to `fanout - 1` files between merges, so the
+        // number of files after `n` batches is the sum of the digits of `n`
+        // in base `fanout`.  The most files we ever see is at `n_batches` or
+        // at some number below it with one digit decremented and all of the
+        // digits below it at their maximum.
+        let final_files = digit_sum(n_batches, fanout);
+        let mut max_files = final_files;
+        let mut place = 1;
+        let mut peaks = Vec::new();
+        while place <= n_batches / fanout {
+            place *= fanout;
+            let n = (n_batches / place) * place - 1;
+            max_files = max_files.max(digit_sum(n, fanout));
+            peaks.push(n);
+        }
+
+        // Space amplification is worst when there are the most unmerged files,
+        // that is, at the same points where the file count peaks.  For the
+        // average, sample evenly spaced points in time.
+        const SAMPLES: u64 = 200;
+        let samples: Vec<_> = (1..=SAMPLES)
+            .map(|i| (n_batches * i / SAMPLES).max(1))
+            .collect();
+        let peaks: Vec<_> = peaks.into_iter().filter(|&n| n > 0).collect();
+        let points = samples.iter().chain(&peaks).copied().chain([n_batches]);
+        let space_amp = space_amps(points.collect(), fanout, update_fraction);
+        let final_space_amp = space_amp[&n_batches];
+        let worst_space_amp = peaks
+            .iter()
+            .map(|n| space_amp[n])
+            .fold(final_space_amp, f64::max);
+        let average_space_amp =
+            samples.iter().map(|n| space_amp[n]).sum::<f64>() / samples.len() as f64;
+
+        Self {
+            levels,
+            merge_bytes,
+            final_files,
+            max_files,
+            final_space_amp,
+            worst_space_amp,
+            average_space_amp,
+        }
+    }
+}
+
+/// Returns the expected space amplification after each number of batches in
+/// `points`, with the given `fanout` and `update_fraction`, in units of one
+/// batch.
+///
+/// The spine's files are contiguous runs of batches: from oldest to newest,
+/// one file of `fanout^i` batches for each unit in digit `i` of the number of
+/// batches in base `fanout`.  Merging consolidates duplicate keys within a
+/// file, but not across files.
+///
+/// A file's distinct keys depend only on the batches it holds, so this takes
+/// a single pass over the batches, keeping the distinct keys of the file
+/// being filled at each level and of the files that haven't been merged yet.
+fn space_amps(points: BTreeSet<u64>, fanout: u64, update_fraction: f64) -> BTreeMap<u64, f64> {
+    let u = update_fraction.clamp(0.0, 1.0);
+
+    // Number of live keys after `j` batches.  The first batch is all inserts.
+    let population = |j: u64| {
+        if j == 0 {
+            0.0
+        } else {
+            1.0 + (j - 1) as f64 * (1.0 - u)
+        }
+    };
+
+    let last = points.last().copied().unwrap_or(0);
+    let mut places = vec![1];
+    while places[places.len() - 1] <= last / fanout {
+        places.push(places[places.len() - 1] * fanout);
+    }
+
+    // For each level, the distinct keys in the file being filled, and in the
+    // complete files that haven't been merged, oldest first.
+    let mut filling = vec![0.0; places.len()];
+    let mut complete: Vec<Vec<f64>> = vec![Vec::new(); places.len()];
+    let mut result = BTreeMap::new();
+    if points.contains(&0) {
+        result.insert(0, 0.0 / population(0));
+    }
+    for j in 0..last {
+        // An update in a file only adds a key if it hits a key that isn't
+        // already in the file.
+        for (distinct, &place) in filling.iter_mut().zip(&places) {
+            if j % place == 0 {
+                *distinct = 0.0;
+            }
+            *distinct += if j == 0 {
+                1.0
+            } else {
+                (1.0 - u) + u * (1.0 - *distinct / population(j))
+            };
+        }
+
+        // After `n` batches, the file at the highest level whose size divides
+        // `n` is complete, and it absorbs the files below it.
+        let n = j + 1;
+        let level = places.iter().rposition(|&place| n % place == 0).unwrap();
+        complete[level].push(filling[level]);
+        for files in &mut complete[..level] {
+            files.clear();
+        }
+        if points.contains(&n) {
+            let on_disk: f64 = complete.iter().rev().flatten().sum();
+            result.insert(n, on_disk / population(n));
+        }
+    }
+    result
+}
+
+/// Returns the sum of the digits of `n` written in base `base`.
+fn digit_sum(mut n: u64, base: u64) -> u64 {
+    let mut sum = 0;
+    while n > 0 {
+        sum += n % base;
+        n /= base;
+    }
+    sum
+}
+
+/// Prints the compaction cost of ingesting `params.total_data_size` bytes in
+/// batches, for each fanout in `args`.
+pub fn spine(params: &Params, args: &SpineArgs) {
+    let batch_size = 1u64 << args.batch_size;
+    let total_data_size = params.total_data_size;
+    let n_batches = (total_data_size / batch_size).max(1);
+    println!(
+        "Compaction cost for {} data arriving in {} batches of {}, {:.0}% updates:",
+        HumanBytes(total_data_size),
+        HumanCount(n_batches),
+        HumanBytes(batch_size),
+        args.update_fraction * 100.0,
+    );
+    print!(
+        r#"
+                 Merge   Merge I/O   Write    --Files--    ---Space Amp.---
+Fanout  Levels  Writes    (R + W)    Amp.     Final   Max    Final  Worst   Avg
+------  ------  ------   --------   -----    -----  ----    -----  -----  ----
+"#
+    );
+    for &fanout in &args.fanouts {
+        let fanout = fanout.max(2);
+        let cost = SpineCost::new(n_batches, batch_size, fanout, args.update_fraction);
+        let ingested = n_batches * batch_size;
+        let write_amp = (ingested + cost.merge_bytes) as f64 / ingested as f64;
+        println!(
+            "{fanout:6}  {:6}  {:6}   {:8}   {:5.1}    {:5}  {:4}    {:5.2}  {:5.2}  {:4.2}",
+            cost.levels,
+            HumanBytes(cost.merge_bytes),
+            HumanBytes(cost.merge_bytes * 2),
+            write_amp,
+            cost.final_files,
+            cost.max_files,
+            cost.final_space_amp,
+            cost.worst_space_amp,
+            cost.average_space_amp,
+        );
+    }
+    if let Some(ingest_rate) = args.ingest_rate {
+        println!();
+        print_bandwidth(n_batches, batch_size, args, ingest_rate);
+    }
+}
+
+/// Prints the sustained bandwidth that merges consume when batches arrive at
+/// `ingest_rate` bytes per second, for each fanout in `args`.  In the long
+/// run, merges have to keep up with ingest, so each byte ingested costs as
+/// many bytes of merge reads, and of merge writes, as it is merged times.
+/// The total adds the writes of the batches themselves.
+fn print_bandwidth(n_batches: u64, batch_size: u64, args: &SpineArgs, ingest_rate: u64) {
+    let rate = |bytes_per_second: f64| format!("{}/s", HumanBytes(bytes_per_second as u64));
+    println!(
+        "Background merge bandwidth at {} ingest, {} device budget:",
+        rate(ingest_rate as f64),
+        rate(args.device_bandwidth as f64),
+    );
+    print!(
+        r#"
+          ---Merge Bandwidth---      Total    Budget
+Fanout       Read       Write      (R + W)      Used
+------   ---------  ---------   ---------   -------
+"#
+    );
+    for &fanout in &args.fanouts {
+        let fanout = fanout.max(2);
+        let cost = SpineCost::new(n_batches, batch_size, fanout, args.update_fraction);
+        let merges_per_byte = cost.merge_bytes as f64 / (n_batches * batch_size) as f64;
+        let merge = ingest_rate as f64 * merges_per_byte;
+        let total = ingest_rate as f64 + 2.0 * merge;
+        println!(
+            "{fanout:6}   {:>9}  {:>9}   {:>9}   {:6.1}%",
+            rate(merge),
+            rate(merge),
+            rate(total),
+            total / args.device_bandwidth as f64 * 100.0,
+        );
+    }
+}
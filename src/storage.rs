@@ -0,0 +1,1347 @@
+//! A storage location: batch files, spines, and checkpoints.
+//!
+//! This implements the storage location that README.md describes, in a
+//! directory:
+//!
+//! - `checkpoints.feldera` lists the checkpoints, oldest first, each with its
+//!   uuid, step id, and the time it was taken (in seconds since the Unix
+//!   epoch).  It is a single block with the usual block header, whose count
+//!   is the number of checkpoints, followed by 56 bytes per checkpoint: the
+//!   uuid (36 bytes of ASCII), the number of batch ids (4 bytes), the step
+//!   id, and the time, and then the batch ids, in 8 bytes each, of the
+//!   batches ingested since the previous checkpoint.  It is replaced by writing `checkpoints.feldera.mut` and renaming
+//!   it, which is the moment a checkpoint is committed.
+//!
+//! - `<uuid>.feldera` is a batch file, a layer file that any number of
+//!   spines and checkpoints may refer to.
+//!
+//! - `<uuid>/pspine-batches-<spine>.dat` lists the batches of a spine as of
+//!   checkpoint `<uuid>`.  It is a single block whose count is the number of
+//!   batches, followed, for each batch, by its level in the spine (1 byte),
+//!   the length of its file name (1 byte), and the file name.
+//!
+//! - `*.mut` are files still being written.
+//!
+//! Each spine is size-tiered: every batch arrives at level 0, and whenever
+//! `fanout` batches accumulate at a level, the oldest `fanout` of them merge
+//! into a single batch at the next level.  A merge's inputs stay on disk for
+//! as long as a checkpoint refers to them.
+//!
+//! Opening a storage location restores its newest checkpoint and collects
+//! garbage: it deletes every `*.mut` file, every checkpoint directory that
+//! `checkpoints.feldera` doesn't list, and every batch file that no
+//! checkpoint refers to.  Taking a checkpoint deletes, in the same way, the
+//! checkpoints beyond the number that the location retains.
+//!
+//! [Storage::ingest] adds a batch with a caller-chosen batch id, and drops a
+//! batch whose id a retained checkpoint, or an ingestion since the newest
+//! one, already recorded, so that re-submitting a batch after a crash doesn't
+//! apply it twice.
+//!
+//! [Storage::resume] continues a scan of a spine as of a checkpoint from a
+//! [ScanToken], which may come from another process, as long as the
+//! checkpoint is retained.
+//!
+//! [Storage::subscribe] registers a callback for the [Event]s that the README
+//! describes: files created and deleted, merges, committed checkpoints, and
+//! batch files that fail to read.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::batches::padded;
+use crate::checksum::{xxhash64, Algorithm};
+use crate::failpoint::fail_point;
+use crate::layer::{io_error, mut_path, unique, Budget, Merger, Options, Reader, Row, Writer};
+use crate::sort::{ExternalSort, SortStats};
+use crate::superblock::{check_block, get, new_block, put, seal};
+
+/// Name of the list of checkpoints.
+pub const CHECKPOINTS: &str = "checkpoints.feldera";
+
+/// Extension of batch files.
+pub const BATCH_EXTENSION: &str = "feldera";
+
+/// Magic numbers of the list of checkpoints and of a spine's list of batches.
+const CHECKPOINTS_MAGIC: u32 = u32::from_le_bytes(*b"LFck");
+const BATCHES_MAGIC: u32 = u32::from_le_bytes(*b"LFsb");
+
+/// Size of a checkpoint's entry in the list of checkpoints, not counting its
+/// batch ids.
+const CHECKPOINT_SIZE: usize = 56;
+
+/// Options for a storage location.
+#[derive(Clone, Debug)]
+pub struct StorageOptions {
+    /// Number of batches at a level that merge into one at the next level.
+    pub fanout: usize,
+
+    /// Number of checkpoints to keep.
+    pub retain: usize,
+
+    /// Memory budget, in bytes, for sorting a batch given in any order.  A
+    /// bigger batch spills sorted runs to disk.
+    pub memory_budget: usize,
+
+    /// Number of runs that a sort merges at a time.
+    pub sort_fanin: usize,
+
+    /// Options for writing batch files.
+    pub layer: Options,
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        Self {
+            fanout: 4,
+            retain: 2,
+            memory_budget: 64 << 20,
+            sort_fanin: 64,
+            layer: Options::default(),
+        }
+    }
+}
+
+/// A checkpoint, as `checkpoints.feldera` lists it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    pub uuid: String,
+    pub step: u64,
+    pub time: u64,
+
+    /// Ids of the batches ingested since the previous checkpoint, in order.
+    pub ids: Vec<u64>,
+}
+
+/// A batch in a spine.
+pub struct Batch {
+    pub name: String,
+    pub level: usize,
+    pub reader: Reader,
+}
+
+/// A spine's batches, oldest first.
+#[derive(Default)]
+pub struct Spine {
+    pub batches: Vec<Batch>,
+}
+
+/// Something that happened in a storage location, as [Storage::subscribe]
+/// reports it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// Batch file `name` was written.
+    FileCreated { name: String },
+
+    /// `path` was deleted, because nothing refers to it any longer.
+    FileDeleted { path: PathBuf },
+
+    /// A merge of the batch files `inputs` in `spine` started.
+    MergeStarted { spine: String, inputs: Vec<String> },
+
+    /// A merge in `spine` finished, with batch file `output`, or with none if
+    /// its inputs cancelled out.
+    MergeFinished {
+        spine: String,
+        output: Option<String>,
+    },
+
+    /// Checkpoint `uuid` at `step` was committed.
+    CheckpointCommitted { uuid: String, step: u64 },
+
+    /// Reading batch file `name` failed with `error`, because it failed a
+    /// checksum or another check, or because of an I/O error.  The operation
+    /// returns the error as well.
+    CorruptionDetected { name: String, error: String },
+}
+
+/// Version of the encoding of a [ScanToken].
+const TOKEN_VERSION: u32 = 1;
+
+/// A position in a scan of a spine as of a checkpoint, which
+/// [Storage::resume] continues from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScanToken {
+    /// The checkpoint, which names the batch files that the scan reads.
+    pub uuid: String,
+    pub spine: String,
+
+    /// Key and value of the last row that the scan returned, if any.
+    pub last: Option<(u64, u64)>,
+}
+
+impl ScanToken {
+    /// Returns a token for the start of `spine` as of checkpoint `uuid`.
+    pub fn start(uuid: &str, spine: &str) -> Self {
+        Self {
+            uuid: uuid.into(),
+            spine: spine.into(),
+            last: None,
+        }
+    }
+
+    /// Returns the token's encoding: the version (4 bytes), the uuid (36
+    /// bytes), the length of the spine's name (1 byte) and the name, whether
+    /// there is a last row (1 byte) and its key and value (8 bytes each), and
+    /// an xxHash64 checksum of all of that (8 bytes).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut token = Vec::new();
+        token.extend(TOKEN_VERSION.to_le_bytes());
+        token.extend(self.uuid.as_bytes());
+        token.push(self.spine.len() as u8);
+        token.extend(self.spine.as_bytes());
+        let (key, value) = self.last.unwrap_or_default();
+        token.push(self.last.is_some() as u8);
+        token.extend(key.to_le_bytes());
+        token.extend(value.to_le_bytes());
+        token.extend(xxhash64(&token, 0).to_le_bytes());
+        token
+    }
+
+    /// Decodes `token`, which [ScanToken::encode] returned.
+    pub fn decode(token: &[u8]) -> Result<Self, String> {
+        let error = |e: &str| format!("scan token: {e}");
+        let Some((token, checksum)) = token.split_last_chunk::<8>() else {
+            return Err(error("truncated"));
+        };
+        if xxhash64(token, 0) != u64::from_le_bytes(*checksum) {
+            return Err(error("bad checksum"));
+        }
+        if token.len() < 41 || get(token, 0, 4) != TOKEN_VERSION as u64 {
+            return Err(error("unknown version"));
+        }
+        let uuid = std::str::from_utf8(&token[4..40]).map_err(|_| error("bad uuid"))?;
+        let len = token[40] as usize;
+        if token.len() != 41 + len + 17 {
+            return Err(error("bad length"));
+        }
+        let spine = std::str::from_utf8(&token[41..41 + len]).map_err(|_| error("bad spine"))?;
+        let rest = &token[41 + len..];
+        Ok(Self {
+            uuid: uuid.into(),
+            spine: spine.into(),
+            last: (rest[0] != 0).then(|| (get(rest, 1, 8), get(rest, 9, 8))),
+        })
+    }
+}
+
+/// A callback that [Storage::subscribe] registers.
+type Subscriber = Box<dyn Fn(&Event)>;
+
+/// An open storage location.
+pub struct Storage {
+    root: PathBuf,
+    options: StorageOptions,
+    spines: BTreeMap<String, Spine>,
+    checkpoints: Vec<Checkpoint>,
+
+    /// Ids of the batches ingested since the newest checkpoint.
+    ids: BTreeSet<u64>,
+
+    subscribers: Vec<Subscriber>,
+}
+
+/// Returns a new uuid, formatted the usual way.
+fn new_uuid() -> String {
+    let (high, low) = (unique(), unique());
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Writes `contents` to `path` and syncs it.
+fn write_synced(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let mut file = File::create(path).map_err(io_error(path))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(io_error(path))
+}
+
+/// Writes `contents` to `path` through a `.mut` file, syncs it, and renames
+/// it.
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let temporary = mut_path(path);
+    write_synced(&temporary, contents)?;
+    fs::rename(&temporary, path).map_err(io_error(path))
+}
+
+/// Syncs the directory `dir`, so that the names of the files in it are
+/// durable.
+fn sync_dir(dir: &Path) -> Result<(), String> {
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(io_error(dir))
+}
+
+/// Returns a block, of a power-of-2 multiple of 4 kB, with `magic` and
+/// `count` in its header and `body` after it.
+fn list_block(magic: u32, count: usize, body: &[u8]) -> Vec<u8> {
+    let size = padded(32 + body.len() as u64) as usize;
+    let mut block = new_block(magic, 0, count as u32);
+    block.resize(size, 0);
+    put(&mut block, 4, &(size as u32).to_le_bytes());
+    put(&mut block, 32, body);
+    seal(&mut block, Algorithm::Crc32c);
+    block
+}
+
+/// Reads the list of checkpoints in `root`, which is empty if there is none.
+pub fn read_checkpoints(root: &Path) -> Result<Vec<Checkpoint>, String> {
+    let path = root.join(CHECKPOINTS);
+    let block = match fs::read(&path) {
+        Ok(block) => block,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(&path)(e)),
+    };
+    let error = |e: String| format!("{}: {e}", path.display());
+    if block.len() < 32 {
+        return Err(error("truncated".into()));
+    }
+    check_block(&block, CHECKPOINTS_MAGIC, Algorithm::Crc32c).map_err(error)?;
+    let count = get(&block, 24, 4) as usize;
+    let mut offset = 32;
+    (0..count)
+        .map(|i| {
+            if offset + CHECKPOINT_SIZE > block.len() {
+                return Err(error(format!("bad count {count}")));
+            }
+            let uuid = std::str::from_utf8(&block[offset..offset + 36])
+                .ok()
+                .filter(|uuid| uuid.bytes().all(|b| b.is_ascii_hexdigit() || b == b'-'))
+                .ok_or_else(|| error(format!("checkpoint {i} has a bad uuid")))?;
+            let n_ids = get(&block, offset + 36, 4) as usize;
+            let ids_offset = offset + CHECKPOINT_SIZE;
+            if ids_offset + n_ids * 8 > block.len() {
+                return Err(error(format!("checkpoint {i} has a bad number of ids")));
+            }
+            let checkpoint = Checkpoint {
+                uuid: uuid.into(),
+                step: get(&block, offset + 40, 8),
+                time: get(&block, offset + 48, 8),
+                ids: (0..n_ids)
+                    .map(|j| get(&block, ids_offset + j * 8, 8))
+                    .collect(),
+            };
+            offset = ids_offset + n_ids * 8;
+            Ok(checkpoint)
+        })
+        .collect()
+}
+
+fn checkpoints_block(checkpoints: &[Checkpoint]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for checkpoint in checkpoints {
+        body.extend(checkpoint.uuid.as_bytes());
+        body.extend((checkpoint.ids.len() as u32).to_le_bytes());
+        body.extend(checkpoint.step.to_le_bytes());
+        body.extend(checkpoint.time.to_le_bytes());
+        for id in &checkpoint.ids {
+            body.extend(id.to_le_bytes());
+        }
+    }
+    list_block(CHECKPOINTS_MAGIC, checkpoints.len(), &body)
+}
+
+/// Returns the name of the file that lists the batches of `spine`.
+fn spine_file(spine: &str) -> String {
+    format!("pspine-batches-{spine}.dat")
+}
+
+/// Reads the batches of each spine as of the checkpoint `uuid` in `root`, as
+/// a map from each spine's name to its batches' file names and levels.
+pub fn read_checkpoint(
+    root: &Path,
+    uuid: &str,
+) -> Result<BTreeMap<String, Vec<(String, usize)>>, String> {
+    let dir = root.join(uuid);
+    let mut spines = BTreeMap::new();
+    for entry in fs::read_dir(&dir).map_err(io_error(&dir))? {
+        let path = entry.map_err(io_error(&dir))?.path();
+        let Some(spine) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("pspine-batches-"))
+            .and_then(|name| name.strip_suffix(".dat"))
+        else {
+            continue;
+        };
+        let block = fs::read(&path).map_err(io_error(&path))?;
+        let error = |e: String| format!("{}: {e}", path.display());
+        if block.len() < 32 {
+            return Err(error("truncated".into()));
+        }
+        check_block(&block, BATCHES_MAGIC, Algorithm::Crc32c).map_err(error)?;
+        let mut rest = &block[32..];
+        let mut batches = Vec::new();
+        for _ in 0..get(&block, 24, 4) {
+            let [level, len, ..] = *rest else {
+                return Err(error("truncated".into()));
+            };
+            let name = rest
+                .get(2..2 + len as usize)
+                .and_then(|name| std::str::from_utf8(name).ok())
+                .ok_or_else(|| error("bad batch name".into()))?;
+            batches.push((name.to_string(), level as usize));
+            rest = &rest[2 + len as usize..];
+        }
+        spines.insert(spine.to_string(), batches);
+    }
+    Ok(spines)
+}
+
+impl Storage {
+    /// Opens the storage location at `root`, creating it if it doesn't exist,
+    /// restores its newest checkpoint, and collects garbage.
+    pub fn open(root: &Path, options: &StorageOptions) -> Result<Self, String> {
+        fs::create_dir_all(root).map_err(io_error(root))?;
+        let checkpoints = read_checkpoints(root)?;
+        let mut spines = BTreeMap::new();
+        if let Some(checkpoint) = checkpoints.last() {
+            for (name, batches) in read_checkpoint(root, &checkpoint.uuid)? {
+                let mut spine = Spine::default();
+                for (name, level) in batches {
+                    let reader = Reader::open(&root.join(&name))?;
+                    if reader.steps().1 > checkpoint.step {
+                        return Err(format!(
+                            "{name}: holds step {} after checkpoint {} at step {}",
+                            reader.steps().1,
+                            checkpoint.uuid,
+                            checkpoint.step
+                        ));
+                    }
+                    spine.batches.push(Batch {
+                        name,
+                        level,
+                        reader,
+                    });
+                }
+                spines.insert(name, spine);
+            }
+        }
+        let storage = Self {
+            root: root.into(),
+            options: options.clone(),
+            spines,
+            checkpoints,
+            ids: BTreeSet::new(),
+            subscribers: Vec::new(),
+        };
+        storage.collect_garbage()?;
+        Ok(storage)
+    }
+
+    /// Calls `subscriber` with each [Event] from now on, synchronously, in
+    /// the operation that causes it.  Errors in opening the location come
+    /// before any subscriber, so they are only returned.
+    pub fn subscribe(&mut self, subscriber: impl Fn(&Event) + 'static) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    fn emit(&self, event: Event) {
+        for subscriber in &self.subscribers {
+            subscriber(&event);
+        }
+    }
+
+    /// Emits [Event::CorruptionDetected] for `error` in batch file `name`,
+    /// unless it is `budget` running out.
+    fn corruption(&self, name: &str, error: &str, budget: Option<&Budget>) {
+        if budget.is_some_and(Budget::exhausted) {
+            return;
+        }
+        self.emit(Event::CorruptionDetected {
+            name: name.into(),
+            error: error.into(),
+        });
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    pub fn spines(&self) -> &BTreeMap<String, Spine> {
+        &self.spines
+    }
+
+    /// Adds `rows`, which must be in order and consolidated, as a batch for
+    /// `step` at level 0 of `spine`.  An empty batch adds nothing.
+    pub fn insert(&mut self, spine: &str, step: u64, rows: &[Row]) -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.insert_rows(spine, (step, step), rows.iter().map(|&row| Ok(row)))
+            .map(|_| ())
+    }
+
+    /// Adds `rows`, in any order and not necessarily consolidated, as a batch
+    /// for `step` at level 0 of `spine`.  If they don't fit in the memory
+    /// budget, they are sorted externally, in runs in the storage location.
+    pub fn insert_batch(
+        &mut self,
+        spine: &str,
+        step: u64,
+        rows: impl IntoIterator<Item = Row>,
+    ) -> Result<SortStats, String> {
+        let mut sort = ExternalSort::new(
+            &self.root,
+            self.options.memory_budget,
+            self.options.sort_fanin,
+            &self.options.layer,
+        );
+        for row in rows {
+            sort.push(row)?;
+        }
+        let sorted = sort.finish()?;
+        self.insert_rows(spine, (step, step), sorted.rows()?)?;
+        Ok(sorted.stats())
+    }
+
+    /// Adds `rows`, like [Storage::insert_batch], as the batch with id `id`,
+    /// unless a retained checkpoint or an earlier call since the newest one
+    /// recorded `id`, and returns whether it added them.  A checkpoint forgets
+    /// its ids when it is deleted, so a caller may re-submit a batch only
+    /// while the checkpoint that would record it is retained.
+    pub fn ingest(
+        &mut self,
+        id: u64,
+        spine: &str,
+        step: u64,
+        rows: impl IntoIterator<Item = Row>,
+    ) -> Result<bool, String> {
+        if self.ids.contains(&id) || self.checkpoints.iter().any(|c| c.ids.contains(&id)) {
+            return Ok(false);
+        }
+        self.insert_batch(spine, step, rows)?;
+        self.ids.insert(id);
+        Ok(true)
+    }
+
+    /// Writes `rows` as a new batch file for `steps` and returns its name,
+    /// or `None` if there were no rows.
+    fn write_batch(
+        &self,
+        steps: (u64, u64),
+        rows: impl Iterator<Item = Result<Row, String>>,
+    ) -> Result<Option<(String, Reader)>, String> {
+        let name = format!("{}.{BATCH_EXTENSION}", new_uuid());
+        let path = self.root.join(&name);
+        let mut writer =
+            Writer::create(&path, steps, &self.options.layer).map_err(io_error(&path))?;
+        let mut n_rows = 0;
+        for row in rows {
+            writer.push(row?).map_err(io_error(&path))?;
+            n_rows += 1;
+        }
+        writer.finish().map_err(io_error(&path))?;
+        if n_rows == 0 {
+            fs::remove_file(&path).map_err(io_error(&path))?;
+            return Ok(None);
+        }
+        self.emit(Event::FileCreated { name: name.clone() });
+        Ok(Some((name, Reader::open(&path)?)))
+    }
+
+    /// Adds `rows`, in order and consolidated, to `spine` as a level-0
+    /// batch for `steps`, and returns whether there were any.
+    pub fn insert_rows(
+        &mut self,
+        spine: &str,
+        steps: (u64, u64),
+        rows: impl Iterator<Item = Result<Row, String>>,
+    ) -> Result<bool, String> {
+        let Some((name, reader)) = self.write_batch(steps, rows)? else {
+            return Ok(false);
+        };
+        self.spines
+            .entry(spine.into())
+            .or_default()
+            .batches
+            .push(Batch {
+                name,
+                level: 0,
+                reader,
+            });
+        Ok(true)
+    }
+
+    /// Merges batches in `spine` until no level has `fanout` of them, and
+    /// returns the number of merges.
+    pub fn compact(&mut self, spine: &str) -> Result<usize, String> {
+        let fanout = self.options.fanout.max(2);
+        let mut merges = 0;
+        loop {
+            let Some(batches) = self.spines.get(spine).map(|spine| &spine.batches) else {
+                return Ok(merges);
+            };
+            let mut counts = BTreeMap::new();
+            for batch in batches {
+                *counts.entry(batch.level).or_insert(0) += 1;
+            }
+            let Some(level) = counts
+                .iter()
+                .find(|&(_, &count)| count >= fanout)
+                .map(|(&level, _)| level)
+            else {
+                return Ok(merges);
+            };
+            let inputs: Vec<usize> = (0..batches.len())
+                .filter(|&i| batches[i].level == level)
+                .take(fanout)
+                .collect();
+            let steps = inputs.iter().fold((u64::MAX, 0), |(first, last), &i| {
+                let steps = batches[i].reader.steps();
+                (first.min(steps.0), last.max(steps.1))
+            });
+            self.emit(Event::MergeStarted {
+                spine: spine.into(),
+                inputs: inputs.iter().map(|&i| batches[i].name.clone()).collect(),
+            });
+            let cursors = inputs.iter().map(|&i| batches[i].reader.cursor()).collect();
+            let output = self.write_batch(steps, Merger::new(cursors)?)?;
+            fail_point!("merge::after-write");
+            self.emit(Event::MergeFinished {
+                spine: spine.into(),
+                output: output.as_ref().map(|(name, _)| name.clone()),
+            });
+
+            // The output takes the place of the newest input, and the inputs
+            // are deleted unless a checkpoint still refers to them.
+            let batches = &mut self.spines.get_mut(spine).unwrap().batches;
+            let position = *inputs.last().unwrap() + 1 - inputs.len();
+            let mut removed = Vec::new();
+            for &i in inputs.iter().rev() {
+                removed.push(batches.remove(i).name);
+            }
+            if let Some((name, reader)) = output {
+                batches.insert(
+                    position,
+                    Batch {
+                        name,
+                        level: level + 1,
+                        reader,
+                    },
+                );
+            }
+            let referenced = self.referenced()?;
+            for name in removed.iter().filter(|name| !referenced.contains(*name)) {
+                let path = self.root.join(name);
+                fs::remove_file(&path).map_err(io_error(&path))?;
+                self.emit(Event::FileDeleted { path });
+            }
+            merges += 1;
+        }
+    }
+
+    /// Returns the weights of the values of `key` in `spine`, consolidated
+    /// across its batches.  A batch with a filter that rules out `key` reads
+    /// nothing.
+    pub fn get(&self, spine: &str, key: u64) -> Result<Vec<(u64, i64)>, String> {
+        self.get_within(spine, key, None)
+    }
+
+    /// Like [Storage::get], but reads data blocks from storage only within
+    /// `budget`, if there is one, and fails with "budget exhausted" past it.
+    pub fn get_within(
+        &self,
+        spine: &str,
+        key: u64,
+        budget: Option<&Budget>,
+    ) -> Result<Vec<(u64, i64)>, String> {
+        let batches = self
+            .spines
+            .get(spine)
+            .map_or(&[][..], |spine| &spine.batches);
+        let inputs = batches
+            .iter()
+            .map(|batch| {
+                let rows = batch
+                    .reader
+                    .get_within(key, budget)
+                    .inspect_err(|e| self.corruption(&batch.name, e, budget))?;
+                Ok(rows.into_iter().map(Ok::<_, String>))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Merger::new(inputs)?
+            .map(|row| row.map(|(_, value, weight)| (value, weight)))
+            .collect()
+    }
+
+    /// Returns the rows of `spine` whose keys are between `from` and `to`,
+    /// inclusive, consolidated across its batches.
+    pub fn scan(
+        &self,
+        spine: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<impl Iterator<Item = Result<Row, String>> + '_, String> {
+        self.scan_within(spine, from, to, None)
+    }
+
+    /// Like [Storage::scan], but reads data blocks from storage only within
+    /// `budget`, if there is one, and fails with "budget exhausted" past it.
+    pub fn scan_within<'a>(
+        &'a self,
+        spine: &str,
+        from: u64,
+        to: u64,
+        budget: Option<&'a Budget>,
+    ) -> Result<impl Iterator<Item = Result<Row, String>> + 'a, String> {
+        let cursors = match self.spines.get(spine) {
+            Some(spine) => spine
+                .batches
+                .iter()
+                .map(|batch| {
+                    let cursor = batch
+                        .reader
+                        .seek_within(from, budget)
+                        .inspect_err(|e| self.corruption(&batch.name, e, budget))?;
+                    Ok(cursor.inspect(move |row| {
+                        if let Err(e) = row {
+                            self.corruption(&batch.name, e, budget);
+                        }
+                    }))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            None => Vec::new(),
+        };
+        let merger = Merger::new(cursors)?;
+        Ok(merger.take_while(move |row| row.as_ref().map_or(true, |&(key, _, _)| key <= to)))
+    }
+
+    /// Returns up to `limit` rows of the scan that `token` is a position in,
+    /// consolidated across the spine's batches as of its checkpoint, and a
+    /// token for the rest of the scan, or `None` if it is done.  It seeks each
+    /// batch to just past the token's last row, so that this costs about as
+    /// much as a seek however far the scan got.
+    pub fn resume(
+        &self,
+        token: &ScanToken,
+        limit: usize,
+    ) -> Result<(Vec<Row>, Option<ScanToken>), String> {
+        if !self.checkpoints.iter().any(|c| c.uuid == token.uuid) {
+            return Err(format!(
+                "scan token: checkpoint {} no longer exists",
+                token.uuid
+            ));
+        }
+        let checkpoint = read_checkpoint(&self.root, &token.uuid)?;
+        let readers = checkpoint
+            .get(&token.spine)
+            .map_or(&[][..], |batches| batches)
+            .iter()
+            .map(|(name, _)| Reader::open(&self.root.join(name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (key, value) = token.last.unwrap_or_default();
+        let cursors = readers
+            .iter()
+            .map(|reader| reader.seek(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let rows = Merger::new(cursors)?
+            .filter(|row| {
+                row.as_ref().map_or(true, |&(k, v, _)| {
+                    token.last.is_none() || (k, v) > (key, value)
+                })
+            })
+            .take(limit)
+            .collect::<Result<Vec<_>, _>>()?;
+        let next = match rows.last() {
+            Some(&(key, value, _)) if rows.len() == limit => Some(ScanToken {
+                last: Some((key, value)),
+                ..token.clone()
+            }),
+            _ => None,
+        };
+        Ok((rows, next))
+    }
+
+    /// Takes a checkpoint of every spine at `step`, commits it, and deletes
+    /// the checkpoints beyond the number to retain.
+    pub fn checkpoint(&mut self, step: u64) -> Result<Checkpoint, String> {
+        let checkpoint = Checkpoint {
+            uuid: new_uuid(),
+            step,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            ids: self.ids.iter().copied().collect(),
+        };
+        let dir = self.root.join(&checkpoint.uuid);
+        fs::create_dir(&dir).map_err(io_error(&dir))?;
+        for (name, spine) in &self.spines {
+            let mut body = Vec::new();
+            for batch in &spine.batches {
+                body.extend([batch.level as u8, batch.name.len() as u8]);
+                body.extend(batch.name.as_bytes());
+            }
+            let block = list_block(BATCHES_MAGIC, spine.batches.len(), &body);
+            write_file(&dir.join(spine_file(name)), &block)?;
+        }
+        sync_dir(&dir)?;
+
+        let mut checkpoints = self.checkpoints.clone();
+        checkpoints.push(checkpoint.clone());
+        let retained = checkpoints.len().saturating_sub(self.options.retain.max(1));
+        checkpoints.drain(..retained);
+        let path = self.root.join(CHECKPOINTS);
+        let temporary = mut_path(&path);
+        write_synced(&temporary, &checkpoints_block(&checkpoints))?;
+        fail_point!("checkpoint::before-commit");
+        fs::rename(&temporary, &path).map_err(io_error(&path))?;
+        sync_dir(&self.root)?;
+        fail_point!("checkpoint::after-commit");
+        self.checkpoints = checkpoints;
+        self.ids.clear();
+        self.emit(Event::CheckpointCommitted {
+            uuid: checkpoint.uuid.clone(),
+            step,
+        });
+        self.collect_garbage()?;
+        Ok(checkpoint)
+    }
+
+    /// Returns the names of the batch files that the spines or a checkpoint
+    /// refer to.
+    fn referenced(&self) -> Result<BTreeSet<String>, String> {
+        let mut referenced: BTreeSet<String> = self
+            .spines
+            .values()
+            .flat_map(|spine| spine.batches.iter().map(|batch| batch.name.clone()))
+            .collect();
+        for checkpoint in &self.checkpoints {
+            for batches in read_checkpoint(&self.root, &checkpoint.uuid)?.into_values() {
+                referenced.extend(batches.into_iter().map(|(name, _)| name));
+            }
+        }
+        Ok(referenced)
+    }
+
+    /// Returns the files and directories in the storage location that
+    /// nothing refers to: `*.mut` files, checkpoint directories that aren't
+    /// listed, and batch files that neither a spine nor a checkpoint refers
+    /// to.
+    pub fn garbage(&self) -> Result<Vec<PathBuf>, String> {
+        let referenced = self.referenced()?;
+        let uuids: BTreeSet<&str> = self.checkpoints.iter().map(|c| c.uuid.as_str()).collect();
+        let mut garbage = Vec::new();
+        for entry in fs::read_dir(&self.root).map_err(io_error(&self.root))? {
+            let path = entry.map_err(io_error(&self.root))?.path();
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            let is_garbage = if name.ends_with(".mut") {
+                true
+            } else if path.is_dir() {
+                !uuids.contains(name)
+            } else if name == CHECKPOINTS {
+                false
+            } else {
+                name.ends_with(&format!(".{BATCH_EXTENSION}")) && !referenced.contains(name)
+            };
+            if is_garbage {
+                garbage.push(path);
+            }
+        }
+        garbage.sort();
+        Ok(garbage)
+    }
+
+    fn collect_garbage(&self) -> Result<(), String> {
+        for path in self.garbage()? {
+            match path.is_dir() {
+                true => fs::remove_dir_all(&path),
+                false => fs::remove_file(&path),
+            }
+            .map_err(io_error(&path))?;
+            self.emit(Event::FileDeleted { path });
+        }
+        Ok(())
+    }
+
+    /// Checks that the storage location holds nothing but what the spines and
+    /// checkpoints refer to, and that every batch file they refer to exists.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(path) = self.garbage()?.first() {
+            return Err(format!("{}: not referenced", path.display()));
+        }
+        for name in self.referenced()? {
+            let path = self.root.join(&name);
+            if !path.is_file() {
+                return Err(format!("{}: referenced but missing", path.display()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failpoint;
+    use crate::superblock::{KeyFilter, BLOCK_SIZE};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn temp_root(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "storage-design-storage-{name}-{}-{}",
+            std::process::id(),
+            unique()
+        ))
+    }
+
+    fn scan(storage: &Storage, spine: &str) -> Vec<Row> {
+        storage
+            .scan(spine, 0, u64::MAX)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn compact_checkpoint_reopen() {
+        let root = temp_root("reopen");
+        let options = StorageOptions::default();
+        let mut storage = Storage::open(&root, &options).unwrap();
+        for step in 1..=10 {
+            let rows: Vec<Row> = (0..100).map(|key| (key, step, 1)).collect();
+            storage.insert("a", step, &rows).unwrap();
+            storage.compact("a").unwrap();
+        }
+        // Retract everything that step 1 inserted.
+        let rows: Vec<Row> = (0..100).map(|key| (key, 1, -1)).collect();
+        storage.insert("a", 11, &rows).unwrap();
+        storage.compact("a").unwrap();
+        assert!(storage.spines()["a"].batches.len() < 3 * options.fanout);
+        let before = scan(&storage, "a");
+        assert_eq!(before.len(), 900);
+        assert_eq!(storage.get("a", 7).unwrap().len(), 9);
+        storage.checkpoint(11).unwrap();
+        storage.check().unwrap();
+
+        // A batch after the checkpoint, and a stray `.mut` file, are garbage
+        // after reopening.
+        storage.insert("a", 12, &[(5, 5, 5)]).unwrap();
+        std::fs::write(root.join("stray.feldera.mut"), b"").unwrap();
+        drop(storage);
+        let storage = Storage::open(&root, &options).unwrap();
+        storage.check().unwrap();
+        assert_eq!(scan(&storage, "a"), before);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn filters_rule_out_batches() {
+        let root = temp_root("filters");
+        let mut options = StorageOptions::default();
+        options.layer.filter = KeyFilter::Xor(8);
+        let mut storage = Storage::open(&root, &options).unwrap();
+        for step in 1..=3 {
+            let rows: Vec<Row> = (0..1000).map(|key| (key * 3 + step, 0, 1)).collect();
+            storage.insert("a", step, &rows).unwrap();
+        }
+        let blocks = |storage: &Storage| -> u64 {
+            let batches = &storage.spines()["a"].batches;
+            batches
+                .iter()
+                .map(|batch| {
+                    let (hits, misses) = batch.reader.cache_stats();
+                    hits + misses
+                })
+                .sum()
+        };
+        for key in 1..3000 {
+            assert_eq!(storage.get("a", key).unwrap(), [(0, 1)], "{key}");
+        }
+        // Each present key is in one batch, and the filters of the other two
+        // rule it out almost every time.
+        assert!(blocks(&storage) < 3000 + 100, "{}", blocks(&storage));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn events() {
+        let root = temp_root("events");
+        let options = StorageOptions {
+            fanout: 2,
+            ..StorageOptions::default()
+        };
+        let mut storage = Storage::open(&root, &options).unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let subscriber = events.clone();
+        storage.subscribe(move |event| subscriber.borrow_mut().push(event.clone()));
+
+        let rows: Vec<Row> = (0..1000).map(|key| (key, 0, 1)).collect();
+        storage.insert("a", 1, &rows).unwrap();
+        storage.insert("a", 2, &rows).unwrap();
+        storage.compact("a").unwrap();
+        storage.checkpoint(2).unwrap();
+        let events = events.take();
+        let names: Vec<&str> = events
+            .iter()
+            .map(|event| match event {
+                Event::FileCreated { .. } => "created",
+                Event::FileDeleted { .. } => "deleted",
+                Event::MergeStarted { .. } => "started",
+                Event::MergeFinished { .. } => "finished",
+                Event::CheckpointCommitted { .. } => "committed",
+                Event::CorruptionDetected { .. } => "corruption",
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "created",
+                "created",
+                "started",
+                "created",
+                "finished",
+                "deleted",
+                "deleted",
+                "committed"
+            ]
+        );
+        let Event::MergeStarted { inputs, .. } = &events[2] else {
+            unreachable!()
+        };
+        assert_eq!(
+            events[0],
+            Event::FileCreated {
+                name: inputs[0].clone()
+            }
+        );
+        let Event::FileCreated { name } = &events[3] else {
+            unreachable!()
+        };
+        let output = Some(name.clone());
+        assert_eq!(
+            events[4],
+            Event::MergeFinished {
+                spine: "a".into(),
+                output
+            }
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checksum_failure_is_reported() {
+        let root = temp_root("corruption");
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let subscriber = events.clone();
+        storage.subscribe(move |event| subscriber.borrow_mut().push(event.clone()));
+        let rows: Vec<Row> = (0..1000).map(|key| (key, 0, 1)).collect();
+        storage.insert("a", 1, &rows).unwrap();
+        let name = storage.spines()["a"].batches[0].name.clone();
+        events.borrow_mut().clear();
+
+        // Flip a bit in the first data block, which nothing has read yet.
+        let path = root.join(&name);
+        let mut file = std::fs::read(&path).unwrap();
+        file[2 * BLOCK_SIZE + 40] ^= 1;
+        std::fs::write(&path, &file).unwrap();
+
+        let error = storage.get("a", 0).unwrap_err();
+        assert!(error.contains("checksum"), "{error}");
+        assert_eq!(
+            events.take(),
+            [Event::CorruptionDetected {
+                name: name.clone(),
+                error: error.clone()
+            }]
+        );
+        let error = storage.scan("a", 0, u64::MAX).err().unwrap();
+        assert!(error.contains("checksum"), "{error}");
+        assert_eq!(events.take(), [Event::CorruptionDetected { name, error }]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn budgets_limit_reads_from_storage() {
+        let root = temp_root("budget");
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        for step in 1..=3 {
+            let rows: Vec<Row> = (0..1000).map(|key| (key, step, 1)).collect();
+            storage.insert("a", step, &rows).unwrap();
+        }
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let subscriber = events.clone();
+        storage.subscribe(move |event| subscriber.borrow_mut().push(event.clone()));
+
+        // A lookup reads a block from each batch, and allowed only two, it
+        // stops before the third.
+        let budget = Budget::new(Some(2), None);
+        let error = storage.get_within("a", 500, Some(&budget)).unwrap_err();
+        assert!(error.contains("budget exhausted"), "{error}");
+        assert!(budget.exhausted());
+        assert_eq!(budget.used().0, 2);
+
+        // The blocks that it did read are cached, so they cost nothing now.
+        let budget = Budget::new(Some(1), None);
+        assert_eq!(
+            storage.get_within("a", 500, Some(&budget)).unwrap().len(),
+            3
+        );
+        assert_eq!(budget.used().0, 1);
+        let budget = Budget::new(Some(0), None);
+        assert_eq!(
+            storage.get_within("a", 500, Some(&budget)).unwrap().len(),
+            3
+        );
+
+        // A scan fails partway through once it runs out of bytes.  Its seeks read the first block of each batch, and there are more
+        // than four blocks left.
+        let block_size = storage.options.layer.block_size as u64;
+        let budget = Budget::new(None, Some(4 * block_size));
+        let error = storage
+            .scan_within("a", 0, u64::MAX, Some(&budget))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(error.contains("budget exhausted"), "{error}");
+        assert_eq!(budget.used(), (4, 4 * block_size));
+        assert_eq!(scan(&storage, "a").len(), 3000);
+        assert_eq!(events.take(), []);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn scans_resume_after_reopening() {
+        let root = temp_root("resume");
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        for step in 1..=3 {
+            let rows: Vec<Row> = (0..1000).map(|key| (key * 3 % 1000, step, 1)).collect();
+            storage.insert_batch("a", step, rows).unwrap();
+        }
+        let checkpoint = storage.checkpoint(3).unwrap();
+        let expected = scan(&storage, "a");
+
+        let token = ScanToken::start(&checkpoint.uuid, "a");
+        let (mut rows, token) = storage.resume(&token, 1234).unwrap();
+        let token = token.unwrap().encode();
+        assert_eq!(rows.len(), 1234);
+
+        // The scan continues from the checkpoint, not the spine as it is now,
+        // even in another process.
+        storage.insert("a", 4, &[(0, 0, 1)]).unwrap();
+        storage.checkpoint(4).unwrap();
+        drop(storage);
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        let mut token = Some(ScanToken::decode(&token).unwrap());
+        while let Some(position) = token {
+            let (more, next) = storage.resume(&position, 1000).unwrap();
+            rows.extend(more);
+            token = next;
+        }
+        assert_eq!(rows, expected);
+
+        let mut corrupt = ScanToken::start(&checkpoint.uuid, "a").encode();
+        corrupt[10] ^= 1;
+        let error = ScanToken::decode(&corrupt).unwrap_err();
+        assert!(error.contains("checksum"), "{error}");
+
+        // Two checkpoints later, the checkpoint is gone.
+        storage.checkpoint(5).unwrap();
+        let error = storage
+            .resume(&ScanToken::start(&checkpoint.uuid, "a"), 1)
+            .unwrap_err();
+        assert!(error.contains("no longer exists"), "{error}");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checkpoints_are_retained() {
+        let root = temp_root("retain");
+        let options = StorageOptions {
+            fanout: 2,
+            retain: 2,
+            ..StorageOptions::default()
+        };
+        let mut storage = Storage::open(&root, &options).unwrap();
+        for step in 1..=8 {
+            storage.insert("a", step, &[(step, 0, 1)]).unwrap();
+            storage.checkpoint(step).unwrap();
+            storage.compact("a").unwrap();
+            storage.check().unwrap();
+        }
+        let checkpoints = read_checkpoints(&root).unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints[1].step, 8);
+        assert_eq!(checkpoints, storage.checkpoints());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn batch_ids_deduplicate_replays() {
+        let root = temp_root("ids");
+        let options = StorageOptions {
+            retain: 2,
+            ..StorageOptions::default()
+        };
+        let mut storage = Storage::open(&root, &options).unwrap();
+        let batch = |key| vec![(key, 0, 1), (key, 1, 1)];
+        assert!(storage.ingest(7, "a", 1, batch(1)).unwrap());
+        assert!(!storage.ingest(7, "a", 1, batch(1)).unwrap());
+        storage.checkpoint(1).unwrap();
+        assert!(storage.ingest(8, "a", 2, batch(2)).unwrap());
+        assert_eq!(read_checkpoints(&root).unwrap()[0].ids, [7]);
+
+        // After a crash, batch 7 is in the restored checkpoint, but batch 8
+        // isn't, so only batch 8 is applied again.
+        drop(storage);
+        let mut storage = Storage::open(&root, &options).unwrap();
+        assert!(!storage.ingest(7, "a", 1, batch(1)).unwrap());
+        assert!(storage.ingest(8, "a", 2, batch(2)).unwrap());
+        assert_eq!(scan(&storage, "a"), [batch(1), batch(2)].concat());
+
+        // Batch 7's id lasts as long as its checkpoint.
+        storage.checkpoint(2).unwrap();
+        drop(storage);
+        let mut storage = Storage::open(&root, &options).unwrap();
+        assert_eq!(storage.checkpoints()[1].ids, [8]);
+        assert!(!storage.ingest(7, "a", 3, batch(1)).unwrap());
+        storage.checkpoint(3).unwrap();
+        assert!(storage.ingest(7, "a", 4, batch(1)).unwrap());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Returns a storage location with a checkpoint at step 1 of 1,000 rows,
+    /// more than a data block, in spine `a`, and a batch at step 2 that is not
+    /// yet checkpointed, along with the rows as of each step.
+    fn setup(name: &str) -> (PathBuf, Storage, Vec<Row>, Vec<Row>) {
+        let root = temp_root(name);
+        let options = StorageOptions {
+            fanout: 2,
+            ..StorageOptions::default()
+        };
+        let mut storage = Storage::open(&root, &options).unwrap();
+        let rows: Vec<Row> = (0..1000).map(|key| (key, 0, 1)).collect();
+        storage.insert("a", 1, &rows).unwrap();
+        storage.checkpoint(1).unwrap();
+        let step1 = scan(&storage, "a");
+        storage.insert("a", 2, &[(5, 0, -1), (5000, 0, 1)]).unwrap();
+        let step2 = scan(&storage, "a");
+        (root, storage, step1, step2)
+    }
+
+    /// Runs `f` on `storage` with fail point `name` set to panic, checks that
+    /// it fired, and reopens the storage location as recovery would.
+    fn crash(storage: Storage, name: &str, f: impl FnOnce(&mut Storage)) -> Storage {
+        let root = storage.root().to_path_buf();
+        let options = storage.options.clone();
+        let mut storage = storage;
+        failpoint::configure(name, 1, failpoint::Action::Panic);
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || f(&mut storage)));
+        failpoint::clear();
+        assert!(result.is_err(), "{name} didn't fire");
+        let storage = Storage::open(&root, &options).unwrap();
+        storage.check().unwrap();
+        storage
+    }
+
+    /// Checks recovery from a crash while writing a batch at fail point
+    /// `name`: the `*.mut` file goes away and the last checkpoint is restored.
+    fn crash_while_writing(name: &str) {
+        let (root, storage, step1, _) = setup(name);
+        let storage = crash(storage, name, |storage| {
+            let rows: Vec<Row> = (0..1000).map(|key| (key, 1, 1)).collect();
+            storage.insert("a", 3, &rows).unwrap();
+        });
+        assert_eq!(scan(&storage, "a"), step1);
+        assert_eq!(storage.checkpoints().len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn crash_at_writer_data_block() {
+        crash_while_writing("writer::data-block");
+    }
+
+    #[test]
+    fn crash_at_writer_before_footer() {
+        crash_while_writing("writer::before-footer");
+    }
+
+    #[test]
+    fn crash_at_writer_before_rename() {
+        crash_while_writing("writer::before-rename");
+    }
+
+    #[test]
+    fn crash_at_merge_after_write() {
+        let (root, mut storage, _, step2) = setup("merge");
+        storage.checkpoint(2).unwrap();
+        let storage = crash(storage, "merge::after-write", |storage| {
+            storage.compact("a").unwrap();
+        });
+        // The inputs are still there and the output has been collected.
+        assert_eq!(storage.spines()["a"].batches.len(), 2);
+        assert_eq!(scan(&storage, "a"), step2);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn crash_at_checkpoint_before_commit() {
+        let (root, storage, step1, _) = setup("before-commit");
+        let storage = crash(storage, "checkpoint::before-commit", |storage| {
+            storage.checkpoint(2).unwrap();
+        });
+        assert_eq!(storage.checkpoints().len(), 1);
+        assert_eq!(storage.checkpoints()[0].step, 1);
+        assert_eq!(scan(&storage, "a"), step1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn crash_at_checkpoint_after_commit() {
+        let (root, storage, _, step2) = setup("after-commit");
+        let storage = crash(storage, "checkpoint::after-commit", |storage| {
+            storage.checkpoint(2).unwrap();
+        });
+        assert_eq!(storage.checkpoints().len(), 2);
+        assert_eq!(storage.checkpoints()[1].step, 2);
+        assert_eq!(scan(&storage, "a"), step2);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn insert_batch_spills() {
+        let root = temp_root("sort");
+        let options = StorageOptions {
+            memory_budget: 1000 * crate::layer::ROW_SIZE,
+            sort_fanin: 4,
+            ..StorageOptions::default()
+        };
+        let mut storage = Storage::open(&root, &options).unwrap();
+        // Keys in descending order, each inserted twice and half of them
+        // retracted once.
+        let rows = (0..20_000u64)
+            .rev()
+            .flat_map(|key| [(key, 0, 1), (key, 0, if key % 2 == 0 { -1 } else { 1 })]);
+        let stats = storage.insert_batch("a", 1, rows).unwrap();
+        assert_eq!(stats.runs, 40);
+        assert!(stats.merges > 0);
+        let expected: Vec<Row> = (0..20_000)
+            .filter(|key| key % 2 == 1)
+            .map(|key| (key, 0, 2))
+            .collect();
+        assert_eq!(scan(&storage, "a"), expected);
+        assert_eq!(storage.spines()["a"].batches.len(), 1);
+        storage.check().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
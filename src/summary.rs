@@ -0,0 +1,377 @@
+//! Summary of the health of a storage location.
+//!
+//! This reads the list of checkpoints of a storage location (see
+//! `storage.rs`) and each checkpoint's lists of batches, and stats the files
+//! in the location, without opening it, so it neither collects garbage nor
+//! needs the pipeline to stop.  It prints:
+//!
+//! - The number of checkpoints and the oldest and newest of them by time taken,
+//!   with their step ids.
+//!
+//! - Files and bytes by category: batch files that the newest checkpoint refers
+//!   to, batch files that only older checkpoints refer to, which removing them
+//!   would free, batch files in no checkpoint, `*.mut` files, and checkpoint
+//!   directories.  A running pipeline's batches since its newest checkpoint
+//!   are in no checkpoint, as is garbage that collection hasn't removed yet.
+//!
+//! - For each spine in the newest checkpoint, its number of batches, their
+//!   size, and its compaction debt: the bytes that size-tiered compaction with
+//!   `--fanout` would still write to bring the spine to its target shape,
+//!   taking a merge's output to be as big as its inputs.
+//!
+//! - The spine with the most batches and the one with the most debt.
+//!
+//! It fails if a batch file that the newest checkpoint refers to is missing.
+
+use clap::Args as ClapArgs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::{read_checkpoint, read_checkpoints, Checkpoint, BATCH_EXTENSION, CHECKPOINTS};
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct SummaryArgs {
+    /// Storage location to summarize.
+    root: PathBuf,
+
+    /// Number of batches at a level that merge into one at the next level,
+    /// for computing compaction debt.
+    #[clap(long, default_value_t = 4)]
+    fanout: usize,
+}
+
+/// A number of files and their total size.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Files {
+    count: u64,
+    bytes: u64,
+}
+
+impl Files {
+    fn add(&mut self, bytes: u64) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// A spine in the newest checkpoint.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct SpineSummary {
+    name: String,
+    batches: usize,
+    bytes: u64,
+    debt: u64,
+}
+
+/// What [summarize] finds in a storage location.
+#[derive(Debug, Default)]
+struct Summary {
+    checkpoints: Vec<Checkpoint>,
+    newest: Files,
+    older: Files,
+    unreferenced: Files,
+    in_progress: Files,
+    checkpoint_dirs: Files,
+    spines: Vec<SpineSummary>,
+
+    /// Batch files that the newest checkpoint refers to but that don't exist.
+    missing: Vec<String>,
+}
+
+/// Returns the bytes that size-tiered compaction with `fanout` writes to
+/// merge `batches`, given as the level and size of each, oldest first, until
+/// no level has `fanout` of them.  It merges as `Storage::compact` does, and
+/// takes each merge's output to be as big as its inputs.
+fn compaction_debt(batches: &[(usize, u64)], fanout: usize) -> u64 {
+    let fanout = fanout.max(2);
+    let mut batches = batches.to_vec();
+    let mut debt = 0;
+    loop {
+        let mut counts = BTreeMap::new();
+        for &(level, _) in &batches {
+            *counts.entry(level).or_insert(0) += 1;
+        }
+        let Some(level) = counts
+            .iter()
+            .find(|&(_, &count)| count >= fanout)
+            .map(|(&level, _)| level)
+        else {
+            return debt;
+        };
+        let inputs: Vec<usize> = (0..batches.len())
+            .filter(|&i| batches[i].0 == level)
+            .take(fanout)
+            .collect();
+        let bytes: u64 = inputs.iter().map(|&i| batches[i].1).sum();
+        let position = *inputs.last().unwrap() + 1 - inputs.len();
+        for &i in inputs.iter().rev() {
+            batches.remove(i);
+        }
+        batches.insert(position, (level + 1, bytes));
+        debt += bytes;
+    }
+}
+
+/// Returns the total size of the files in `dir`.
+fn dir_bytes(dir: &Path) -> Result<u64, String> {
+    let error = |e: std::io::Error| format!("{}: {e}", dir.display());
+    let mut bytes = 0;
+    for entry in fs::read_dir(dir).map_err(error)? {
+        bytes += entry
+            .and_then(|entry| entry.metadata())
+            .map_err(error)?
+            .len();
+    }
+    Ok(bytes)
+}
+
+/// Summarizes the storage location at `root`, computing compaction debt with
+/// `fanout`.
+fn summarize(root: &Path, fanout: usize) -> Result<Summary, String> {
+    let checkpoints = read_checkpoints(root)?;
+    let mut spines = BTreeMap::new();
+    let mut newest = BTreeSet::new();
+    let mut older = BTreeSet::new();
+    for (i, checkpoint) in checkpoints.iter().enumerate().rev() {
+        let checkpoint_spines = read_checkpoint(root, &checkpoint.uuid)?;
+        let names = checkpoint_spines
+            .values()
+            .flatten()
+            .map(|(name, _)| name.clone());
+        if i == checkpoints.len() - 1 {
+            newest.extend(names);
+            spines = checkpoint_spines;
+        } else {
+            older.extend(names.filter(|name| !newest.contains(name)));
+        }
+    }
+
+    let mut summary = Summary::default();
+    let error = |e: std::io::Error| format!("{}: {e}", root.display());
+    let mut sizes = BTreeMap::new();
+    for entry in fs::read_dir(root).map_err(error)? {
+        let entry = entry.map_err(error)?;
+        let path = entry.path();
+        let metadata = entry
+            .metadata()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".mut") {
+            summary.in_progress.add(metadata.len());
+        } else if metadata.is_dir() {
+            summary.checkpoint_dirs.add(dir_bytes(&path)?);
+        } else if name != CHECKPOINTS && name.ends_with(&format!(".{BATCH_EXTENSION}")) {
+            let files = if newest.contains(&name) {
+                &mut summary.newest
+            } else if older.contains(&name) {
+                &mut summary.older
+            } else {
+                &mut summary.unreferenced
+            };
+            files.add(metadata.len());
+            sizes.insert(name, metadata.len());
+        }
+    }
+    summary.missing = newest
+        .into_iter()
+        .filter(|name| !sizes.contains_key(name))
+        .collect();
+
+    for (name, batches) in spines {
+        let batches: Vec<(usize, u64)> = batches
+            .iter()
+            .map(|(file, level)| (*level, sizes.get(file).copied().unwrap_or(0)))
+            .collect();
+        summary.spines.push(SpineSummary {
+            name,
+            batches: batches.len(),
+            bytes: batches.iter().map(|&(_, bytes)| bytes).sum(),
+            debt: compaction_debt(&batches, fanout),
+        });
+    }
+    summary.checkpoints = checkpoints;
+    Ok(summary)
+}
+
+/// Returns how long ago `time`, in seconds since the Unix epoch, was.
+fn age(time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    match now.saturating_sub(time) {
+        seconds @ 0..=119 => format!("{seconds} s"),
+        seconds @ 120..=7199 => format!("{} min", seconds / 60),
+        seconds @ 7200..=172_799 => format!("{} h", seconds / 3600),
+        seconds => format!("{} days", seconds / 86_400),
+    }
+}
+
+pub fn summary(args: &SummaryArgs) -> Result<(), String> {
+    let summary = summarize(&args.root, args.fanout)?;
+    println!("Storage location {}:", args.root.display());
+    println!();
+    let oldest = summary.checkpoints.iter().min_by_key(|c| c.time);
+    let newest = summary.checkpoints.iter().max_by_key(|c| c.time);
+    match (oldest, newest) {
+        (Some(oldest), Some(newest)) => println!(
+            "{} checkpoints, the oldest at step {} taken {} ago, the newest at step {} taken {} ago.",
+            summary.checkpoints.len(),
+            oldest.step,
+            age(oldest.time),
+            newest.step,
+            age(newest.time)
+        ),
+        _ => println!("No checkpoints."),
+    }
+
+    print!(
+        r#"
+Files                      Count     Bytes
+-------------------------  -----  --------
+"#
+    );
+    for (category, files) in [
+        ("In the newest checkpoint", summary.newest),
+        ("Only in older checkpoints", summary.older),
+        ("In no checkpoint", summary.unreferenced),
+        ("Being written (*.mut)", summary.in_progress),
+        ("Checkpoint directories", summary.checkpoint_dirs),
+    ] {
+        println!(
+            "{category:25}  {:>5}  {:>8}",
+            HumanCount(files.count),
+            HumanBytes(files.bytes)
+        );
+    }
+
+    if !summary.spines.is_empty() {
+        print!(
+            r#"
+Spine                 Batches     Bytes  Compaction Debt
+--------------------  -------  --------  ---------------
+"#
+        );
+        for spine in &summary.spines {
+            println!(
+                "{:20}  {:7}  {:>8}  {:>15}",
+                spine.name,
+                spine.batches,
+                HumanBytes(spine.bytes),
+                HumanBytes(spine.debt)
+            );
+        }
+        // The first of equal spines, in name order.
+        let most_batches = summary.spines.iter().rev().max_by_key(|s| s.batches);
+        let most_debt = summary.spines.iter().rev().max_by_key(|s| s.debt);
+        if let (Some(batches), Some(debt)) = (most_batches, most_debt) {
+            let debt = match debt.debt {
+                0 => "none".to_string(),
+                bytes => format!("{} ({})", debt.name, HumanBytes(bytes)),
+            };
+            println!();
+            println!(
+                "Most batches: {} ({}).  Most compaction debt: {debt}.",
+                batches.name, batches.batches
+            );
+        }
+    }
+
+    if summary.missing.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "{}: missing {} batch files of the newest checkpoint: {}",
+        args.root.display(),
+        summary.missing.len(),
+        summary.missing.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::unique;
+    use crate::storage::{Storage, StorageOptions};
+
+    #[test]
+    fn debt() {
+        assert_eq!(compaction_debt(&[], 4), 0);
+        assert_eq!(compaction_debt(&[(0, 10); 3], 4), 0);
+        assert_eq!(compaction_debt(&[(0, 10); 4], 4), 40);
+        // 16 batches merge into 4 at level 1, which merge into 1 at level 2.
+        assert_eq!(compaction_debt(&[(0, 1); 16], 4), 32);
+        // A merge's output can fill up the next level.
+        let batches = [(1, 100), (1, 100), (1, 100), (0, 1), (0, 1), (0, 1), (0, 1)];
+        assert_eq!(compaction_debt(&batches, 4), 4 + 304);
+    }
+
+    #[test]
+    fn storage_location() {
+        let root = std::env::temp_dir().join(format!(
+            "storage-design-summary-{}-{}",
+            std::process::id(),
+            unique()
+        ));
+        let mut storage = Storage::open(&root, &StorageOptions::default()).unwrap();
+        for step in 1..=4 {
+            storage.insert("a", step, &[(step, 0, 1)]).unwrap();
+        }
+        storage.checkpoint(4).unwrap();
+        // The merge's inputs stay for the older checkpoint.
+        storage.compact("a").unwrap();
+        storage.insert("a", 5, &[(5, 0, 1)]).unwrap();
+        for step in [5, 6, 6] {
+            storage.insert("b", step, &[(step, 0, 1)]).unwrap();
+        }
+        storage.checkpoint(6).unwrap();
+        storage.insert("a", 7, &[(7, 0, 1)]).unwrap();
+        fs::write(root.join("x.feldera.mut"), [0; 10]).unwrap();
+        drop(storage);
+
+        let summary = summarize(&root, 2).unwrap();
+        let steps: Vec<u64> = summary.checkpoints.iter().map(|c| c.step).collect();
+        assert_eq!(steps, [4, 6]);
+        let counts = [
+            summary.newest,
+            summary.older,
+            summary.unreferenced,
+            summary.in_progress,
+            summary.checkpoint_dirs,
+        ]
+        .map(|files| files.count);
+        assert_eq!(counts, [5, 4, 1, 1, 2]);
+        assert_eq!(summary.in_progress.bytes, 10);
+        assert!(summary.missing.is_empty());
+
+        let spines: Vec<_> = summary
+            .spines
+            .iter()
+            .map(|s| (s.name.as_str(), s.batches))
+            .collect();
+        assert_eq!(spines, [("a", 2), ("b", 3)]);
+        let (a, b) = (&summary.spines[0], &summary.spines[1]);
+        assert_eq!(summary.newest.bytes, a.bytes + b.bytes);
+        // With a fanout of 2, `b`'s first two batches would merge.
+        assert_eq!(a.debt, 0);
+        assert_eq!(b.debt, b.bytes / 3 * 2);
+        assert!(summarize(&root, 4)
+            .unwrap()
+            .spines
+            .iter()
+            .all(|s| s.debt == 0));
+
+        let args = SummaryArgs {
+            root: root.clone(),
+            fanout: 4,
+        };
+        super::summary(&args).unwrap();
+        let checkpoint = read_checkpoint(&root, &summary.checkpoints[1].uuid).unwrap();
+        fs::remove_file(root.join(&checkpoint["b"][0].0)).unwrap();
+        let error = super::summary(&args).unwrap_err();
+        assert!(error.contains("missing 1 batch files"), "{error}");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
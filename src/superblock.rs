@@ -0,0 +1,1118 @@
+//! Layout of the file header, schema, and trailer blocks.
+//!
+//! The header and trailer are 4-kB blocks that start with the usual block
+//! header (magic, size, checksum, epoch, count, reserved).  The rest of each
+//! is laid out as follows, with every integer little-endian:
+//!
+//! - The file header, at offset 0, holds the format version, the checksum
+//!   algorithm for the rest of the file, two sets of feature flags, the number
+//!   of columns, the range of step ids, and the location of the schema block.
+//!   The header block itself always uses CRC32C.
+//!
+//! - The schema block describes each column's fields, their types, which of
+//!   them form the key, and how the column's values are encoded, so that a
+//!   reader can open the file without any out-of-band knowledge.  It also
+//!   says which kind of filter, if any, covers column 1's keys.
+//!
+//! - The file trailer, in the file's last block, repeats the version, the
+//!   incompatible feature flags, and the number of columns, then holds its
+//!   generation, the offset of the previous generation's trailer, the
+//!   locations of a file-level xor filter and of the column-chunk directory,
+//!   and an entry per column with the location of its index roots.
+//!
+//! A file can hold several generations of roots.  An appender writes new data
+//! and index blocks after the current trailer and then a new trailer that
+//! points to the new roots and, for reclamation, to the old trailer.  A reader
+//! opens the file at the length that it was given, such as the one recorded
+//! in a checkpoint, and uses the trailer in the last block before it, so
+//! readers of an older generation keep their roots while an appender
+//! publishes new ones.
+//!
+//! A reader rejects a file whose version is newer than it knows, or that sets
+//! an incompatible feature flag that it doesn't know.  Compatible feature
+//! flags mark parts of the file that a reader may ignore.
+//!
+//! With `--write`, this writes a sample file with a header, schema, two
+//! generations of trailers, and dummy root blocks.  With `--inspect`, it
+//! validates and decodes the header, schema, and trailer of a file, or of an
+//! older generation with `--length`.  With neither, it checks that each
+//! generation of the sample file reads back and that the reader rejects every
+//! single-bit corruption of the latest one's header, schema, and trailer.
+
+use clap::Args as ClapArgs;
+use std::fmt::{Display, Formatter, Result as FmtResult, Write as _};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::batches::padded;
+use crate::checksum::Algorithm;
+use crate::HumanBytes;
+
+#[derive(ClapArgs, Debug)]
+pub struct SuperblockArgs {
+    /// Writes a sample file to this path.
+    #[clap(long, value_name = "PATH")]
+    write: Option<PathBuf>,
+
+    /// Validates the file at this path and prints its header, schema, and
+    /// trailer.
+    #[clap(long, value_name = "PATH")]
+    inspect: Option<PathBuf>,
+
+    /// Opens the file for `--inspect` as if it ended after this many bytes,
+    /// to read the generation of roots that was current at that length.
+    #[clap(long, value_name = "BYTES")]
+    length: Option<u64>,
+}
+
+/// Size of the header and trailer blocks.
+pub(crate) const BLOCK_SIZE: usize = 4096;
+
+/// Magic numbers of the header and trailer blocks.
+const HEADER_MAGIC: u32 = u32::from_le_bytes(*b"LFhd");
+const TRAILER_MAGIC: u32 = u32::from_le_bytes(*b"LFtr");
+const SCHEMA_MAGIC: u32 = u32::from_le_bytes(*b"LFsc");
+
+/// Newest format version that this code reads and the one that it writes.
+pub(crate) const VERSION: u32 = 1;
+
+/// Checksum field of the block header.
+const CHECKSUM_FIELD: std::ops::Range<usize> = 8..16;
+
+/// Incompatible feature flags, which a reader must understand to read the
+/// file at all.
+const INCOMPATIBLE_FEATURES: [(u64, &str); 6] = [
+    (1 << 0, "child-checksums"),
+    (NORMALIZED_KEYS, "normalized-keys"),
+    (1 << 2, "stripes"),
+    (DICTIONARIES, "dictionaries"),
+    (DELTA_KEYS, "delta-keys"),
+    (PACKED_INTEGERS, "packed-integers"),
+];
+
+const NORMALIZED_KEYS: u64 = 1 << 1;
+const DICTIONARIES: u64 = 1 << 3;
+const DELTA_KEYS: u64 = 1 << 4;
+const PACKED_INTEGERS: u64 = 1 << 5;
+
+/// Compatible feature flags, which a reader may ignore.
+const COMPATIBLE_FEATURES: [(u64, &str); 3] = [
+    (FILTERS, "filters"),
+    (1 << 1, "zone-maps"),
+    (1 << 2, "hash-index"),
+];
+
+pub(crate) const FILTERS: u64 = 1 << 0;
+
+/// Offset of the first column entry in the trailer, and the size of each.
+const COLUMNS_OFFSET: usize = 96;
+const COLUMN_SIZE: usize = 48;
+
+/// SQL type of a field, as recorded in the schema block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum FieldType {
+    Boolean,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Double,
+    Decimal { precision: u8, scale: u8 },
+    Varchar,
+    Binary,
+    Date,
+    Timestamp,
+}
+
+impl FieldType {
+    /// Returns this type's id and its precision and scale, which are 0 for
+    /// types other than `DECIMAL`.
+    fn id(&self) -> (u8, u8, u8) {
+        match *self {
+            FieldType::Boolean => (0, 0, 0),
+            FieldType::TinyInt => (1, 0, 0),
+            FieldType::SmallInt => (2, 0, 0),
+            FieldType::Int => (3, 0, 0),
+            FieldType::BigInt => (4, 0, 0),
+            FieldType::Real => (5, 0, 0),
+            FieldType::Double => (6, 0, 0),
+            FieldType::Decimal { precision, scale } => (7, precision, scale),
+            FieldType::Varchar => (8, 0, 0),
+            FieldType::Binary => (9, 0, 0),
+            FieldType::Date => (10, 0, 0),
+            FieldType::Timestamp => (11, 0, 0),
+        }
+    }
+
+    fn from_id(id: u8, precision: u8, scale: u8) -> Result<Self, String> {
+        let field_type = match id {
+            0 => FieldType::Boolean,
+            1 => FieldType::TinyInt,
+            2 => FieldType::SmallInt,
+            3 => FieldType::Int,
+            4 => FieldType::BigInt,
+            5 => FieldType::Real,
+            6 => FieldType::Double,
+            7 if (1..=38).contains(&precision) && scale <= precision => {
+                return Ok(FieldType::Decimal { precision, scale });
+            }
+            7 => return Err(format!("bad DECIMAL({precision}, {scale})")),
+            8 => FieldType::Varchar,
+            9 => FieldType::Binary,
+            10 => FieldType::Date,
+            11 => FieldType::Timestamp,
+            _ => return Err(format!("unknown type {id}")),
+        };
+        if precision != 0 || scale != 0 {
+            return Err(format!("type {id} with precision or scale"));
+        }
+        Ok(field_type)
+    }
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            FieldType::Boolean => write!(f, "BOOLEAN"),
+            FieldType::TinyInt => write!(f, "TINYINT"),
+            FieldType::SmallInt => write!(f, "SMALLINT"),
+            FieldType::Int => write!(f, "INT"),
+            FieldType::BigInt => write!(f, "BIGINT"),
+            FieldType::Real => write!(f, "REAL"),
+            FieldType::Double => write!(f, "DOUBLE"),
+            FieldType::Decimal { precision, scale } => write!(f, "DECIMAL({precision}, {scale})"),
+            FieldType::Varchar => write!(f, "VARCHAR"),
+            FieldType::Binary => write!(f, "BINARY"),
+            FieldType::Date => write!(f, "DATE"),
+            FieldType::Timestamp => write!(f, "TIMESTAMP"),
+        }
+    }
+}
+
+/// One field of a column's values.  The direction and the placement of NULLs
+/// only matter for key fields.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Field {
+    pub(crate) name: String,
+    pub(crate) field_type: FieldType,
+    pub(crate) nullable: bool,
+    pub(crate) descending: bool,
+    pub(crate) nulls_last: bool,
+}
+
+impl Field {
+    const NULLABLE: u8 = 1 << 0;
+    const DESCENDING: u8 = 1 << 1;
+    const NULLS_LAST: u8 = 1 << 2;
+}
+
+/// How a column's values are stored in its data blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Encoding {
+    /// Each value serialized with `rkyv`.
+    Rkyv,
+
+    /// Each value as a normalized key, which requires the
+    /// `normalized-keys` feature.
+    Normalized,
+
+    /// Each value as a varint delta from the one before, with restart
+    /// points, which requires the `delta-keys` feature.  Only for a column
+    /// whose single field is a non-nullable integer key.
+    Delta,
+
+    /// Each value minus the data block's minimum, bit-packed at the block's
+    /// bit width, which requires the `packed-integers` feature.  Only for a
+    /// column whose single field is a non-nullable integer.
+    Packed,
+}
+
+/// How a column's data blocks are compressed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Compression {
+    None,
+
+    /// zstd at the given level.
+    Zstd(i16),
+
+    /// LZ4 at the given level, where 0 is the fast compressor and 1 through
+    /// 12 are LZ4HC.
+    Lz4(i16),
+}
+
+/// Schema of one column: its fields, the indexes of the fields that make up
+/// its key, in order, its encoding, and its compression.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ColumnSchema {
+    pub(crate) encoding: Encoding,
+    pub(crate) compression: Compression,
+    pub(crate) fields: Vec<Field>,
+    pub(crate) key: Vec<u16>,
+}
+
+/// Reads little-endian integers and byte strings from the front of a block,
+/// failing instead of reading past its end.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if n > self.0.len() {
+            return Err("truncated".into());
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn int(&mut self, n: usize) -> Result<u64, String> {
+        Ok(get(self.bytes(n)?, 0, n))
+    }
+}
+
+/// Location of one index root, as the offset and size of its block.  A column
+/// without a value index has a value root of offset and size 0.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Root {
+    pub(crate) offset: u64,
+    pub(crate) size: u32,
+}
+
+/// Filter over column 1's keys, as the schema block records it with its kind
+/// (1 byte, 0 for none, 1 for Bloom, 2 for xor) and size (1 byte), which is
+/// the bits per value of a Bloom filter and the fingerprint size of an xor
+/// filter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum KeyFilter {
+    None,
+    Bloom(u8),
+    Xor(u8),
+}
+
+/// Contents of a column's trailer entry.  A column without a dictionary has
+/// a dictionary root of offset and size 0.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Column {
+    pub(crate) value_root: Root,
+    pub(crate) row_root: Root,
+    pub(crate) row_sample: u32,
+    pub(crate) rows: u64,
+    pub(crate) dictionary_root: Root,
+}
+
+/// Decoded header and trailer of a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Superblock {
+    pub(crate) version: u32,
+    pub(crate) epoch: u64,
+    pub(crate) algorithm: Algorithm,
+    pub(crate) compatible: u64,
+    pub(crate) incompatible: u64,
+    pub(crate) steps: (u64, u64),
+    pub(crate) schema_root: Root,
+    pub(crate) filter: KeyFilter,
+    pub(crate) schema: Vec<ColumnSchema>,
+
+    /// Generation of the trailer, which is 0 for the first one, and the
+    /// offset of the previous generation's trailer, which is 0 if there is
+    /// none.
+    pub(crate) generation: u64,
+    pub(crate) previous_trailer: u64,
+
+    /// Location of the xor filter, which is offset and size 0 for any other
+    /// kind of filter.
+    pub(crate) filter_root: Root,
+
+    /// Location of the column-chunk directory, which is offset and size 0
+    /// for a file whose columns' blocks are interleaved.
+    pub(crate) directory_root: Root,
+
+    pub(crate) columns: Vec<Column>,
+}
+
+fn algorithm_id(algorithm: Algorithm) -> u8 {
+    match algorithm {
+        Algorithm::Crc32c => 0,
+        Algorithm::XxHash64 => 1,
+        Algorithm::Xxh3 => 2,
+    }
+}
+
+pub(crate) fn put(block: &mut [u8], offset: usize, bytes: &[u8]) {
+    block[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+pub(crate) fn get(block: &[u8], offset: usize, len: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes[..len].copy_from_slice(&block[offset..offset + len]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Returns a block with the common block header filled in, except for the
+/// checksum.
+pub(crate) fn new_block(magic: u32, epoch: u64, count: u32) -> Vec<u8> {
+    let mut block = vec![0; BLOCK_SIZE];
+    put(&mut block, 0, &magic.to_le_bytes());
+    put(&mut block, 4, &(BLOCK_SIZE as u32).to_le_bytes());
+    put(&mut block, 16, &epoch.to_le_bytes());
+    put(&mut block, 24, &count.to_le_bytes());
+    block
+}
+
+/// Fills in the checksum of `block`, which covers everything but the
+/// checksum field.
+pub(crate) fn seal(block: &mut [u8], algorithm: Algorithm) {
+    block[CHECKSUM_FIELD].fill(0);
+    let checksum = algorithm.checksum(block);
+    put(block, CHECKSUM_FIELD.start, &checksum.to_le_bytes());
+}
+
+/// Checks the magic number and size in the common block header of `block`,
+/// but not its checksum.
+pub(crate) fn check_block_header(block: &[u8], magic: u32) -> Result<(), String> {
+    if get(block, 0, 4) != magic as u64 {
+        return Err(format!("bad magic {:#010x}", get(block, 0, 4)));
+    }
+    if get(block, 4, 4) != block.len() as u64 {
+        return Err(format!("bad size {}", get(block, 4, 4)));
+    }
+    Ok(())
+}
+
+/// Checks the common block header and checksum of `block`.
+pub(crate) fn check_block(block: &[u8], magic: u32, algorithm: Algorithm) -> Result<(), String> {
+    check_block_header(block, magic)?;
+    let mut covered = block.to_vec();
+    covered[CHECKSUM_FIELD].fill(0);
+    if algorithm.checksum(&covered) != get(block, CHECKSUM_FIELD.start, 8) {
+        return Err("bad checksum".into());
+    }
+    Ok(())
+}
+
+/// Validates a schema `block` for a file with `n_columns` columns and returns
+/// its filter and the column schemas.
+fn read_schema(
+    block: &[u8],
+    algorithm: Algorithm,
+    epoch: u64,
+    n_columns: usize,
+    compatible: u64,
+    incompatible: u64,
+) -> Result<(KeyFilter, Vec<ColumnSchema>), String> {
+    check_block(block, SCHEMA_MAGIC, algorithm)?;
+    if get(block, 16, 8) != epoch {
+        return Err("epoch differs from header".into());
+    }
+    if get(block, 24, 4) != n_columns as u64 {
+        return Err("count differs from number of columns".into());
+    }
+    let mut cursor = Cursor(&block[32..]);
+    if cursor.int(4)? != n_columns as u64 {
+        return Err("bad number of columns".into());
+    }
+    let filter = match (cursor.int(1)?, cursor.int(1)? as u8, cursor.int(2)?) {
+        (0, 0, 0) => KeyFilter::None,
+        (1, bits @ 1..=64, 0) => KeyFilter::Bloom(bits),
+        (2, bits @ 1..=16, 0) => KeyFilter::Xor(bits),
+        (kind, bits, _) => return Err(format!("unknown filter {kind} of {bits} bits")),
+    };
+    if (filter != KeyFilter::None) != (compatible & FILTERS != 0) {
+        return Err("filter disagrees with the feature flags".into());
+    }
+    let mut columns = Vec::with_capacity(n_columns);
+    for i in 0..n_columns {
+        let column = i + 1;
+        let encoding = match cursor.int(1)? {
+            0 => Encoding::Rkyv,
+            1 => Encoding::Normalized,
+            2 => Encoding::Delta,
+            3 => Encoding::Packed,
+            id => return Err(format!("column {column}: unknown encoding {id}")),
+        };
+        let compression = cursor.int(1)?;
+        let (n_fields, n_key) = (cursor.int(2)? as usize, cursor.int(2)? as usize);
+        let level = cursor.int(2)? as u16 as i16;
+        let compression = match (compression, level) {
+            (0, 0) => Compression::None,
+            (1, -7..=22) => Compression::Zstd(level),
+            (2, 0..=12) => Compression::Lz4(level),
+            _ => {
+                return Err(format!(
+                    "column {column}: unknown compression {compression} level {level}"
+                ))
+            }
+        };
+        if n_fields == 0 {
+            return Err(format!("column {column}: no fields"));
+        }
+        let mut key = Vec::with_capacity(n_key);
+        for _ in 0..n_key {
+            let index = cursor.int(2)? as u16;
+            if index as usize >= n_fields || key.contains(&index) {
+                return Err(format!("column {column}: bad key field {index}"));
+            }
+            key.push(index);
+        }
+        let mut fields = Vec::with_capacity(n_fields);
+        for _ in 0..n_fields {
+            let [id, flags, precision, scale] = cursor.bytes(4)?.try_into().unwrap();
+            let field_type = FieldType::from_id(id, precision, scale)
+                .map_err(|e| format!("column {column}: {e}"))?;
+            if flags & !(Field::NULLABLE | Field::DESCENDING | Field::NULLS_LAST) != 0 {
+                return Err(format!("column {column}: unknown field flags {flags:#x}"));
+            }
+            let len = cursor.int(2)? as usize;
+            let name = std::str::from_utf8(cursor.bytes(len)?)
+                .ok()
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| format!("column {column}: bad field name"))?;
+            fields.push(Field {
+                name: name.into(),
+                field_type,
+                nullable: flags & Field::NULLABLE != 0,
+                descending: flags & Field::DESCENDING != 0,
+                nulls_last: flags & Field::NULLS_LAST != 0,
+            });
+        }
+        if encoding == Encoding::Delta || encoding == Encoding::Packed {
+            let integer = matches!(
+                fields[0].field_type,
+                FieldType::TinyInt
+                    | FieldType::SmallInt
+                    | FieldType::Int
+                    | FieldType::BigInt
+                    | FieldType::Date
+                    | FieldType::Timestamp
+            );
+            if fields.len() != 1 || !integer || fields[0].nullable {
+                return Err(format!(
+                    "column {column}: integer encoding needs a single non-nullable integer field"
+                ));
+            }
+            if encoding == Encoding::Delta && key != [0] {
+                return Err(format!("column {column}: delta encoding needs a key"));
+            }
+        }
+        columns.push(ColumnSchema {
+            encoding,
+            compression,
+            fields,
+            key,
+        });
+    }
+    if cursor.0.iter().any(|&b| b != 0) {
+        return Err("nonzero bytes after the last column".into());
+    }
+
+    // A reader that doesn't know normalized or delta-encoded keys has to be
+    // stopped by the feature flag before it gets this far.
+    for (encoding, flag, name) in [
+        (Encoding::Normalized, NORMALIZED_KEYS, "normalized"),
+        (Encoding::Delta, DELTA_KEYS, "delta"),
+        (Encoding::Packed, PACKED_INTEGERS, "packed"),
+    ] {
+        let used = columns.iter().any(|column| column.encoding == encoding);
+        if used != (incompatible & flag != 0) {
+            return Err(format!("{name} encoding disagrees with the feature flags"));
+        }
+    }
+    Ok((filter, columns))
+}
+
+impl Superblock {
+    /// Returns the header block.
+    pub(crate) fn header(&self) -> Vec<u8> {
+        let mut block = new_block(HEADER_MAGIC, self.epoch, 0);
+        put(&mut block, 32, &self.version.to_le_bytes());
+        block[36] = algorithm_id(self.algorithm);
+        put(&mut block, 40, &self.compatible.to_le_bytes());
+        put(&mut block, 48, &self.incompatible.to_le_bytes());
+        put(&mut block, 56, &(self.columns.len() as u32).to_le_bytes());
+        put(&mut block, 64, &self.steps.0.to_le_bytes());
+        put(&mut block, 72, &self.steps.1.to_le_bytes());
+        put(&mut block, 80, &self.schema_root.offset.to_le_bytes());
+        put(&mut block, 88, &self.schema_root.size.to_le_bytes());
+        seal(&mut block, Algorithm::Crc32c);
+        block
+    }
+
+    /// Returns the schema block.  It starts with the number of columns, the
+    /// filter's kind and size, and 2 reserved bytes.  Then, for each column, there is its encoding and
+    /// compression (1 byte each), the number of fields and of key fields and
+    /// the compression level (2 bytes each), the index of each key field (2
+    /// bytes each), and, for each field, its type, flags, precision, and scale
+    /// (1 byte each), and its name's length (2 bytes) and UTF-8 bytes.
+    pub(crate) fn schema_block(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend((self.schema.len() as u32).to_le_bytes());
+        body.extend(match self.filter {
+            KeyFilter::None => [0, 0],
+            KeyFilter::Bloom(bits) => [1, bits],
+            KeyFilter::Xor(bits) => [2, bits],
+        });
+        body.extend([0; 2]);
+        for column in &self.schema {
+            body.push(match column.encoding {
+                Encoding::Rkyv => 0,
+                Encoding::Normalized => 1,
+                Encoding::Delta => 2,
+                Encoding::Packed => 3,
+            });
+            let (compression, level) = match column.compression {
+                Compression::None => (0, 0),
+                Compression::Zstd(level) => (1, level),
+                Compression::Lz4(level) => (2, level),
+            };
+            body.push(compression);
+            body.extend((column.fields.len() as u16).to_le_bytes());
+            body.extend((column.key.len() as u16).to_le_bytes());
+            body.extend(level.to_le_bytes());
+            for index in &column.key {
+                body.extend(index.to_le_bytes());
+            }
+            for field in &column.fields {
+                let (id, precision, scale) = field.field_type.id();
+                let flags = [
+                    (field.nullable, Field::NULLABLE),
+                    (field.descending, Field::DESCENDING),
+                    (field.nulls_last, Field::NULLS_LAST),
+                ]
+                .into_iter()
+                .filter(|&(set, _)| set)
+                .fold(0, |flags, (_, flag)| flags | flag);
+                body.extend([id, flags, precision, scale]);
+                body.extend((field.name.len() as u16).to_le_bytes());
+                body.extend(field.name.as_bytes());
+            }
+        }
+        let size = padded(32 + body.len() as u64) as usize;
+        let mut block = new_block(SCHEMA_MAGIC, self.epoch, self.schema.len() as u32);
+        put(&mut block, 4, &(size as u32).to_le_bytes());
+        block.resize(size, 0);
+        put(&mut block, 32, &body);
+        seal(&mut block, self.algorithm);
+        block
+    }
+
+    /// Returns the trailer block.
+    pub(crate) fn trailer(&self) -> Vec<u8> {
+        let mut block = new_block(TRAILER_MAGIC, self.epoch, self.columns.len() as u32);
+        put(&mut block, 32, &self.version.to_le_bytes());
+        put(&mut block, 36, &(self.columns.len() as u32).to_le_bytes());
+        put(&mut block, 40, &self.incompatible.to_le_bytes());
+        put(&mut block, 48, &self.generation.to_le_bytes());
+        put(&mut block, 56, &self.previous_trailer.to_le_bytes());
+        put(&mut block, 64, &self.filter_root.offset.to_le_bytes());
+        put(&mut block, 72, &self.filter_root.size.to_le_bytes());
+        put(&mut block, 80, &self.directory_root.offset.to_le_bytes());
+        put(&mut block, 88, &self.directory_root.size.to_le_bytes());
+        for (i, column) in self.columns.iter().enumerate() {
+            let offset = COLUMNS_OFFSET + i * COLUMN_SIZE;
+            put(&mut block, offset, &column.value_root.offset.to_le_bytes());
+            put(
+                &mut block,
+                offset + 8,
+                &column.value_root.size.to_le_bytes(),
+            );
+            put(&mut block, offset + 12, &column.row_root.size.to_le_bytes());
+            put(
+                &mut block,
+                offset + 16,
+                &column.row_root.offset.to_le_bytes(),
+            );
+            put(&mut block, offset + 24, &column.row_sample.to_le_bytes());
+            put(
+                &mut block,
+                offset + 28,
+                &column.dictionary_root.size.to_le_bytes(),
+            );
+            put(&mut block, offset + 32, &column.rows.to_le_bytes());
+            put(
+                &mut block,
+                offset + 40,
+                &column.dictionary_root.offset.to_le_bytes(),
+            );
+        }
+        seal(&mut block, self.algorithm);
+        block
+    }
+
+    /// Validates the header, schema, and trailer blocks of `file` and returns
+    /// their contents.
+    pub(crate) fn read(file: &[u8]) -> Result<Self, String> {
+        Self::read_from(file.len() as u64, &mut |offset, size| {
+            Ok(file[offset as usize..offset as usize + size].to_vec())
+        })
+    }
+
+    /// Validates the header, schema, and trailer blocks of a file of
+    /// `file_size` bytes, reading each of them with `read_at(offset, size)`,
+    /// and returns their contents.
+    pub(crate) fn read_from(
+        file_size: u64,
+        read_at: &mut dyn FnMut(u64, usize) -> Result<Vec<u8>, String>,
+    ) -> Result<Self, String> {
+        if file_size < 2 * BLOCK_SIZE as u64 {
+            return Err("too short for a header and trailer".into());
+        }
+        let header = &read_at(0, BLOCK_SIZE)?;
+        let trailer = &read_at(file_size - BLOCK_SIZE as u64, BLOCK_SIZE)?;
+        check_block(header, HEADER_MAGIC, Algorithm::Crc32c).map_err(|e| format!("header: {e}"))?;
+        let version = get(header, 32, 4) as u32;
+        if version == 0 || version > VERSION {
+            return Err(format!("header: unsupported version {version}"));
+        }
+        let algorithm = match header[36] {
+            0 => Algorithm::Crc32c,
+            1 => Algorithm::XxHash64,
+            2 => Algorithm::Xxh3,
+            id => return Err(format!("header: unsupported checksum algorithm {id}")),
+        };
+        let incompatible = get(header, 48, 8);
+        let known = INCOMPATIBLE_FEATURES
+            .iter()
+            .fold(0, |known, (bit, _)| known | bit);
+        if incompatible & !known != 0 {
+            return Err(format!(
+                "header: unknown incompatible features {:#x}",
+                incompatible & !known
+            ));
+        }
+        let epoch = get(header, 16, 8);
+        let n_columns = get(header, 56, 4) as usize;
+        if n_columns == 0 || COLUMNS_OFFSET + n_columns * COLUMN_SIZE > BLOCK_SIZE {
+            return Err(format!("header: bad number of columns {n_columns}"));
+        }
+
+        check_block(trailer, TRAILER_MAGIC, algorithm).map_err(|e| format!("trailer: {e}"))?;
+        if get(trailer, 16, 8) != epoch {
+            return Err("trailer: epoch differs from header".into());
+        }
+        let repeated = [
+            ("version", get(trailer, 32, 4), version as u64),
+            ("number of columns", get(trailer, 36, 4), n_columns as u64),
+            ("count", get(trailer, 24, 4), n_columns as u64),
+            ("incompatible features", get(trailer, 40, 8), incompatible),
+        ];
+        for (name, actual, expected) in repeated {
+            if actual != expected {
+                return Err(format!("trailer: {name} {actual} differs from header"));
+            }
+        }
+
+        // An earlier generation's trailer is somewhere between the header
+        // and this one.
+        let (generation, previous_trailer) = (get(trailer, 48, 8), get(trailer, 56, 8));
+        let trailer_offset = file_size - BLOCK_SIZE as u64;
+        let previous_ok = match generation {
+            0 => previous_trailer == 0,
+            _ => {
+                previous_trailer >= BLOCK_SIZE as u64
+                    && previous_trailer < trailer_offset
+                    && previous_trailer.is_multiple_of(BLOCK_SIZE as u64)
+            }
+        };
+        if !previous_ok {
+            return Err(format!(
+                "trailer: generation {generation} has bad previous trailer offset {previous_trailer}"
+            ));
+        }
+
+        // Every root has to be a block between the header and the trailer.
+        let check_root = |root: Root, i: usize, name: &str| {
+            let size = root.size as u64;
+            let in_bounds = root.offset >= BLOCK_SIZE as u64
+                && root.offset.saturating_add(size) <= file_size - BLOCK_SIZE as u64;
+            if !size.is_multiple_of(4096) || !(size / 4096).is_power_of_two() || !in_bounds {
+                return Err(format!(
+                    "trailer: column {} {name} root of {size} bytes at {} is not a valid block",
+                    i + 1,
+                    root.offset
+                ));
+            }
+            Ok(())
+        };
+        let block_root = |offset_at, size_at, block: &[u8]| Root {
+            offset: get(block, offset_at, 8),
+            size: get(block, size_at, 4) as u32,
+        };
+        let schema_root = block_root(80, 88, header);
+        check_root(schema_root, 0, "schema").map_err(|_| "header: bad schema block location")?;
+        let schema_block = &read_at(schema_root.offset, schema_root.size as usize)?;
+        let compatible = get(header, 40, 8);
+        let (filter, schema) = read_schema(
+            schema_block,
+            algorithm,
+            epoch,
+            n_columns,
+            compatible,
+            incompatible,
+        )
+        .map_err(|e| format!("schema: {e}"))?;
+
+        // Only an xor filter is found through the trailer, since the others
+        // are found through the index.
+        let filter_root = block_root(64, 72, trailer);
+        match filter {
+            KeyFilter::Xor(_) => check_root(filter_root, 0, "filter")
+                .map_err(|_| "trailer: bad xor filter location")?,
+            _ if filter_root != Root::default() => {
+                return Err("trailer: filter root without an xor filter".into())
+            }
+            _ => (),
+        }
+        let directory_root = block_root(80, 88, trailer);
+        if directory_root != Root::default() {
+            check_root(directory_root, 0, "directory")
+                .map_err(|_| "trailer: bad directory location")?;
+        }
+        if get(trailer, 76, 4) != 0 || get(trailer, 92, 4) != 0 {
+            return Err("trailer: nonzero reserved bytes".into());
+        }
+
+        let mut columns = Vec::with_capacity(n_columns);
+        for i in 0..n_columns {
+            let offset = COLUMNS_OFFSET + i * COLUMN_SIZE;
+            let root = |offset_at, size_at| block_root(offset_at, size_at, trailer);
+            let column = Column {
+                value_root: root(offset, offset + 8),
+                row_root: root(offset + 16, offset + 12),
+                row_sample: get(trailer, offset + 24, 4) as u32,
+                rows: get(trailer, offset + 32, 8),
+                dictionary_root: root(offset + 40, offset + 28),
+            };
+            if column.value_root != Root::default() {
+                check_root(column.value_root, i, "value")?;
+            }
+            check_root(column.row_root, i, "row")?;
+            if column.dictionary_root != Root::default() {
+                if incompatible & DICTIONARIES == 0 {
+                    return Err(format!(
+                        "trailer: column {} has a dictionary without the dictionaries feature",
+                        i + 1
+                    ));
+                }
+                check_root(column.dictionary_root, i, "dictionary")?;
+            }
+            if column.row_sample == 0 {
+                return Err(format!("trailer: column {} has row sample 0", i + 1));
+            }
+            columns.push(column);
+        }
+        if trailer[COLUMNS_OFFSET + n_columns * COLUMN_SIZE..]
+            .iter()
+            .any(|&b| b != 0)
+        {
+            return Err("trailer: nonzero bytes after the last column".into());
+        }
+
+        Ok(Self {
+            version,
+            epoch,
+            algorithm,
+            compatible,
+            incompatible,
+            steps: (get(header, 64, 8), get(header, 72, 8)),
+            schema_root,
+            filter,
+            schema,
+            generation,
+            previous_trailer,
+            filter_root,
+            directory_root,
+            columns,
+        })
+    }
+
+    /// Returns a description of this header, schema, and trailer, as
+    /// `inspect` prints it.
+    fn describe(&self, file_size: u64) -> String {
+        let features = |flags: u64, names: &[(u64, &str)]| {
+            let mut list: Vec<String> = names
+                .iter()
+                .filter(|(bit, _)| flags & bit != 0)
+                .map(|(_, name)| name.to_string())
+                .collect();
+            let known = names.iter().fold(0, |known, (bit, _)| known | bit);
+            if flags & !known != 0 {
+                list.push(format!("{:#x}", flags & !known));
+            }
+            match list.is_empty() {
+                true => "none".into(),
+                false => list.join(", "),
+            }
+        };
+        let root = |root: Root| match root.size {
+            0 => "none".to_string(),
+            _ => format!("{} at {}", HumanBytes(root.size as u64), root.offset),
+        };
+        let mut s = String::new();
+        writeln!(s, "Header (4 kB at 0):").unwrap();
+        writeln!(s, "  version                {}", self.version).unwrap();
+        writeln!(s, "  epoch                  {:#018x}", self.epoch).unwrap();
+        writeln!(s, "  checksum               {}", self.algorithm).unwrap();
+        let compatible = features(self.compatible, &COMPATIBLE_FEATURES);
+        writeln!(s, "  compatible features    {compatible}").unwrap();
+        let incompatible = features(self.incompatible, &INCOMPATIBLE_FEATURES);
+        writeln!(s, "  incompatible features  {incompatible}").unwrap();
+        writeln!(s, "  columns                {}", self.columns.len()).unwrap();
+        writeln!(
+            s,
+            "  steps                  {}..{}",
+            self.steps.0, self.steps.1
+        )
+        .unwrap();
+        writeln!(s, "Schema ({}):", root(self.schema_root)).unwrap();
+        let filter = match self.filter {
+            KeyFilter::None => "none".into(),
+            KeyFilter::Bloom(bits) => format!("bloom, {bits} bits per value"),
+            KeyFilter::Xor(bits) => format!("xor, {bits}-bit fingerprints"),
+        };
+        writeln!(s, "  filter                 {filter}").unwrap();
+        for (i, column) in self.schema.iter().enumerate() {
+            let encoding = match column.encoding {
+                Encoding::Rkyv => "rkyv",
+                Encoding::Normalized => "normalized",
+                Encoding::Delta => "delta",
+                Encoding::Packed => "packed",
+            };
+            let key: Vec<&str> = column
+                .key
+                .iter()
+                .map(|&index| column.fields[index as usize].name.as_str())
+                .collect();
+            let key = match key.is_empty() {
+                true => "no key".into(),
+                false => format!("key {}", key.join(", ")),
+            };
+            let compression = match column.compression {
+                Compression::None => "uncompressed".into(),
+                Compression::Zstd(level) => format!("zstd level {level}"),
+                Compression::Lz4(level) => format!("lz4 level {level}"),
+            };
+            writeln!(s, "  column {} ({encoding}, {compression}, {key}):", i + 1).unwrap();
+            for field in &column.fields {
+                write!(s, "    {} {}", field.name, field.field_type).unwrap();
+                if field.nullable {
+                    write!(s, " NULL").unwrap();
+                }
+                if field.descending {
+                    write!(s, " DESC").unwrap();
+                }
+                if field.nulls_last {
+                    write!(s, " NULLS LAST").unwrap();
+                }
+                writeln!(s).unwrap();
+            }
+        }
+        let trailer_offset = file_size - BLOCK_SIZE as u64;
+        writeln!(
+            s,
+            "Trailer (4 kB at {trailer_offset}, generation {}, previous trailer {}):",
+            self.generation,
+            match self.previous_trailer {
+                0 => "none".into(),
+                offset => format!("at {offset}"),
+            }
+        )
+        .unwrap();
+        if self.filter_root != Root::default() {
+            writeln!(s, "  xor filter: {}", root(self.filter_root)).unwrap();
+        }
+        if self.directory_root != Root::default() {
+            writeln!(s, "  directory: {}", root(self.directory_root)).unwrap();
+        }
+        for (i, column) in self.columns.iter().enumerate() {
+            writeln!(
+                s,
+                "  column {}: {} rows, value root {}, row root {}, row sample {}, dictionary {}",
+                i + 1,
+                column.rows,
+                root(column.value_root),
+                root(column.row_root),
+                column.row_sample,
+                root(column.dictionary_root)
+            )
+            .unwrap();
+        }
+        s
+    }
+}
+
+/// Returns a sample 2-column file with two generations of roots, as the
+/// header, schema, and trailer of each generation with the length of the file
+/// that ends with its trailer, and the whole file.
+fn sample() -> (Vec<(Superblock, usize)>, Vec<u8>) {
+    let field = |name: &str, field_type, nullable| Field {
+        name: name.into(),
+        field_type,
+        nullable,
+        descending: false,
+        nulls_last: false,
+    };
+    let superblock = Superblock {
+        version: VERSION,
+        epoch: 0x0123_4567_89ab_cdef,
+        algorithm: Algorithm::XxHash64,
+        compatible: FILTERS,
+        incompatible: INCOMPATIBLE_FEATURES[0].0 | NORMALIZED_KEYS | DICTIONARIES,
+        steps: (17, 42),
+        schema_root: Root {
+            offset: 4096,
+            size: 4096,
+        },
+        filter: KeyFilter::Bloom(10),
+        schema: vec![
+            ColumnSchema {
+                encoding: Encoding::Normalized,
+                compression: Compression::None,
+                fields: vec![
+                    field("account", FieldType::BigInt, false),
+                    Field {
+                        descending: true,
+                        nulls_last: true,
+                        ..field("region", FieldType::Varchar, true)
+                    },
+                ],
+                key: vec![0, 1],
+            },
+            ColumnSchema {
+                encoding: Encoding::Rkyv,
+                compression: Compression::Zstd(3),
+                fields: vec![
+                    field(
+                        "balance",
+                        FieldType::Decimal {
+                            precision: 18,
+                            scale: 2,
+                        },
+                        false,
+                    ),
+                    field("updated", FieldType::Timestamp, true),
+                ],
+                key: vec![],
+            },
+        ],
+        generation: 0,
+        previous_trailer: 0,
+        filter_root: Root::default(),
+        directory_root: Root::default(),
+        columns: vec![
+            Column {
+                value_root: Root {
+                    offset: 8192,
+                    size: 8192,
+                },
+                row_root: Root {
+                    offset: 16384,
+                    size: 4096,
+                },
+                row_sample: 1,
+                rows: 1000,
+                dictionary_root: Root::default(),
+            },
+            Column {
+                value_root: Root::default(),
+                row_root: Root {
+                    offset: 20480,
+                    size: 4096,
+                },
+                row_sample: 4,
+                rows: 250_000,
+                dictionary_root: Root {
+                    offset: 24576,
+                    size: 4096,
+                },
+            },
+        ],
+    };
+    let mut file = superblock.header();
+    file.extend(superblock.schema_block());
+    file.resize(28672, 0);
+    file.extend(superblock.trailer());
+
+    // An appender adds 250,000 rows to column 2 in a new data block and a
+    // new row root, then publishes a trailer that points to them.
+    let mut appended = Superblock {
+        generation: 1,
+        previous_trailer: 28672,
+        ..superblock.clone()
+    };
+    appended.columns[1].rows += 250_000;
+    appended.columns[1].row_root = Root {
+        offset: 36864,
+        size: 4096,
+    };
+    let first = file.len();
+    file.resize(40960, 0);
+    file.extend(appended.trailer());
+    let second = file.len();
+    (vec![(superblock, first), (appended, second)], file)
+}
+
+/// Writes, inspects, or checks header, schema, and trailer blocks as `args`
+/// says.
+pub fn superblock(args: &SuperblockArgs) -> Result<(), String> {
+    let (generations, file) = sample();
+    if let Some(path) = &args.write {
+        fs::write(path, &file).map_err(|e| format!("{}: {e}", path.display()))?;
+        println!(
+            "Wrote {} sample file to {}.",
+            HumanBytes(file.len() as u64),
+            path.display()
+        );
+    }
+    if let Some(path) = &args.inspect {
+        let mut file = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        if let Some(length) = args.length {
+            if length > file.len() as u64 {
+                return Err(format!("{}: shorter than {length} bytes", path.display()));
+            }
+            file.truncate(length as usize);
+        }
+        let superblock = Superblock::read(&file).map_err(|e| format!("{}: {e}", path.display()))?;
+        print!("{}", superblock.describe(file.len() as u64));
+    }
+    if args.write.is_some() || args.inspect.is_some() {
+        return Ok(());
+    }
+
+    // Each generation reads back from the file cut off after its trailer.
+    for (superblock, length) in &generations {
+        assert_eq!(Superblock::read(&file[..*length]), Ok(superblock.clone()));
+    }
+
+    // Flip every bit of each block of the latest generation on its own.
+    let (superblock, _) = generations.last().unwrap();
+    println!("Single-bit corruptions of the header, schema, and trailer of a sample file:");
+    print!(
+        r#"
+   Block  Flips  Rejected  Accepted
+--------  -----  --------  --------
+"#
+    );
+    let schema_start = superblock.schema_root.offset as usize;
+    let blocks = [
+        ("header", 0..BLOCK_SIZE),
+        (
+            "schema",
+            schema_start..schema_start + superblock.schema_root.size as usize,
+        ),
+        ("trailer", file.len() - BLOCK_SIZE..file.len()),
+    ];
+    for (name, range) in blocks {
+        let (mut rejected, mut accepted) = (0, 0);
+        let bits = range.start * 8..range.end * 8;
+        for bit in bits.clone() {
+            let mut file = file.clone();
+            file[bit / 8] ^= 1 << (bit % 8);
+            match Superblock::read(&file) {
+                Ok(_) => accepted += 1,
+                Err(_) => rejected += 1,
+            }
+        }
+        println!("{name:>8}  {:5}  {rejected:8}  {accepted:8}", bits.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,360 @@
+//! Time columns for DBSP traces and "as of" pruning.
+//!
+//! A trace is an indexed Z-set whose weights vary over time: each (key, value)
+//! pair carries a run of (time, diff) pairs, and its weight as of time `T` is
+//! the sum of the diffs at times up to `T`.  It is a 3-column file: column 1
+//! holds the keys, column 2 each key's run of values, and column 3 each
+//! value's run of (time, diff) pairs, in time order, with each row of columns
+//! 1 and 2 holding the end of its run in the next column.
+//!
+//! Column 3 holds each time's running weight, the sum of the diffs up to and
+//! including it, rather than the diff itself, so the weight as of `T` is the
+//! running weight at the last time at or before `T`, wherever in the run that
+//! is.  Merging two traces recomputes the running weights of the runs that
+//! they share as it goes, which costs nothing extra.
+//!
+//! Each column 2 row also holds a summary of its time run, as its first and
+//! last times and its final weight (8 bytes each).  A reader that wants
+//! weights as of `T` skips a run whose first time is after `T`, since none of
+//! it counts, and takes the final weight of a run whose last time is at or
+//! before `T`, so it only reads column 3 for the runs that straddle `T`.  For
+//! those, the entries in column 3's row index also hold the first time in
+//! each child (8 bytes more), which serve as the runs' sub-index: the reader
+//! binary searches the entries within the run for the last block that starts
+//! at or before `T` and reads only that block, instead of reading the run
+//! from the start up to the first time after `T`.  A value whose weight as
+//! of `T` is 0 is left out.
+//!
+//! This generates keys with runs of values and values with runs of times of
+//! random lengths, computes the weights as of several times, by scanning the
+//! whole trace and by looking up random keys, by reading every run up to the
+//! first time after `T`, by pruning runs with their summaries, and by pruning
+//! and then searching the runs that straddle `T`, checks that each gives the
+//! weights that summing the diffs does, and compares the column 3 data blocks
+//! that each reads.
+
+use clap::Args as ClapArgs;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct TracesArgs {
+    /// Average number of (time, diff) pairs per value.  Runs have from 1 to
+    /// twice this many pairs, less one.
+    #[clap(long = "times-per-value", default_values_t = vec![1, 16, 256, 4096])]
+    times_per_value: Vec<u64>,
+
+    /// Time to compute weights as of, as a fraction of the trace's time
+    /// range.
+    #[clap(long = "as-of", default_values_t = vec![0.1, 0.5, 0.9, 1.0])]
+    as_of: Vec<f64>,
+
+    /// Average number of values per key.
+    #[clap(long, default_value_t = 4)]
+    values_per_key: u64,
+
+    /// Number of rows in column 3.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Size of a column 2 row without its time run summary, in bytes.
+    #[clap(long, default_value_t = 24)]
+    value_size: u64,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of keys to look up for each time.
+    #[clap(long, default_value_t = 10_000)]
+    lookups: u64,
+}
+
+/// Size of a column 3 row: a time and a diff.
+const TIME_SIZE: u64 = 16;
+
+/// Size of a time run's summary: its first and last times and its final
+/// weight.
+const SUMMARY_SIZE: u64 = 24;
+
+/// Number of distinct times in a trace.
+const HORIZON: u64 = 1_000_000;
+
+/// How a reader finds a value's weight as of a time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    /// Reads the run up to the first time after `T`.
+    Plain,
+
+    /// Uses the run's summary if it's enough, and reads like `Plain` if not.
+    Pruned,
+
+    /// Uses the run's summary if it's enough, and reads a single block found
+    /// through column 3's row index if not.
+    Searched,
+}
+
+/// A value in column 2, with the end of its time run in column 3 and the
+/// run's summary.
+struct Value {
+    value: u64,
+    end: usize,
+    first: u64,
+    last: u64,
+    weight: i64,
+}
+
+/// A 3-column trace.
+struct Trace {
+    /// Each key and the end of its run in `values`.
+    keys: Vec<(u64, usize)>,
+    values: Vec<Value>,
+
+    /// Each time and its running weight.
+    times: Vec<(u64, i64)>,
+    times_per_block: usize,
+
+    /// Each time's diff, to check the weights against.
+    diffs: Vec<i64>,
+}
+
+impl Trace {
+    /// Returns the weights of the values of key `i` as of `as_of`, leaving
+    /// out those that are 0, and adds the column 3 data blocks that it reads
+    /// to `reads`, given that `held` is the block that the reader holds.
+    fn weights(
+        &self,
+        i: usize,
+        as_of: u64,
+        mode: Mode,
+        held: &mut Option<usize>,
+        reads: &mut u64,
+    ) -> Vec<(u64, i64)> {
+        let mut read = |block: usize| {
+            if *held != Some(block) {
+                *held = Some(block);
+                *reads += 1;
+            }
+        };
+        let start = match i {
+            0 => 0,
+            _ => self.keys[i - 1].1,
+        };
+        let mut weights = Vec::new();
+        for j in start..self.keys[i].1 {
+            let value = &self.values[j];
+            let start = match j {
+                0 => 0,
+                _ => self.values[j - 1].end,
+            };
+            let prune = mode != Mode::Plain;
+            let weight = match (prune, value.first > as_of, value.last <= as_of) {
+                (true, true, _) => 0,
+                (true, _, true) => value.weight,
+                _ if mode == Mode::Searched => {
+                    // The last block that starts inside the run at or before
+                    // `as_of`, or else the run's first block, has the last
+                    // time at or before it.
+                    let tpb = self.times_per_block;
+                    let blocks = start / tpb + 1..(value.end - 1) / tpb + 1;
+                    let block = blocks
+                        .rev()
+                        .find(|&block| self.times[block * tpb].0 <= as_of)
+                        .unwrap_or(start / tpb);
+                    read(block);
+                    let rows = (block * tpb).max(start)..((block + 1) * tpb).min(value.end);
+                    let last = rows
+                        .clone()
+                        .take_while(|&row| self.times[row].0 <= as_of)
+                        .last();
+                    last.map_or(0, |row| self.times[row].1)
+                }
+                _ => {
+                    let mut weight = 0;
+                    for row in start..value.end {
+                        read(row / self.times_per_block);
+                        let (time, running) = self.times[row];
+                        if time > as_of {
+                            break;
+                        }
+                        weight = running;
+                    }
+                    weight
+                }
+            };
+            if weight != 0 {
+                weights.push((value.value, weight));
+            }
+        }
+        weights
+    }
+
+    /// Returns the weights of the values of key `i` as of `as_of` by summing
+    /// all of their diffs.
+    fn expected(&self, i: usize, as_of: u64) -> Vec<(u64, i64)> {
+        let start = match i {
+            0 => 0,
+            _ => self.keys[i - 1].1,
+        };
+        (start..self.keys[i].1)
+            .filter_map(|j| {
+                let begin = match j {
+                    0 => 0,
+                    _ => self.values[j - 1].end,
+                };
+                let weight: i64 = (begin..self.values[j].end)
+                    .filter(|&row| self.times[row].0 <= as_of)
+                    .map(|row| self.diffs[row])
+                    .sum();
+                (weight != 0).then_some((self.values[j].value, weight))
+            })
+            .collect()
+    }
+}
+
+/// Prints the column 3 blocks that computing weights as of each time reads,
+/// with and without pruning, for each run length in `args`.
+/// Returns the number of failed checks.
+pub fn traces(args: &TracesArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = args.block_size.max(4096);
+    let rows = args.rows.max(1);
+    let values_per_key = args.values_per_key.max(1);
+    let lookups = args.lookups.max(1);
+    let value_size = args.value_size.max(1);
+    println!(
+        "Traces of {} times in {} blocks, {values_per_key} values per key, column 2 rows {} to {}:",
+        HumanCount(rows),
+        HumanBytes(block_size),
+        HumanBytes(value_size),
+        HumanBytes(value_size + SUMMARY_SIZE),
+    );
+    print!(
+        r#"
+    Times                    ------Scan Reads------  ----Reads/Lookup------
+Per Value  As Of  Straddling   Plain  Pruned  Search   Plain  Pruned  Search  Failures
+---------  -----  -----------  ------  ------  ------  ------  ------  ------  --------
+"#
+    );
+    for &per_value in &args.times_per_value {
+        let per_value = per_value.clamp(1, rows);
+        let mut random = rng::random(rows ^ per_value);
+
+        // Times are spread over the whole range, and diffs are small and
+        // never 0.
+        let mut trace = Trace {
+            keys: Vec::new(),
+            values: Vec::new(),
+            times: Vec::new(),
+            times_per_block: (block_size / TIME_SIZE) as usize,
+            diffs: Vec::new(),
+        };
+        let mut key = 0;
+        while (trace.times.len() as u64) < rows {
+            key += 1 + random() % 4;
+            let mut value = 0;
+            for _ in 0..1 + random() % (2 * values_per_key - 1) {
+                value += 1 + random() % 4;
+                let mut times: Vec<u64> = (0..1 + random() % (2 * per_value - 1))
+                    .map(|_| random() % HORIZON)
+                    .collect();
+                times.sort_unstable();
+                times.dedup();
+                let diffs: Vec<i64> = times
+                    .iter()
+                    .map(|_| ((random() % 7) as i64 - 3) | 1)
+                    .collect();
+                let mut weight = 0;
+                for (&time, &diff) in times.iter().zip(&diffs) {
+                    weight += diff;
+                    trace.times.push((time, weight));
+                }
+                trace.diffs.extend(diffs);
+                trace.values.push(Value {
+                    value,
+                    end: trace.times.len(),
+                    first: times[0],
+                    last: *times.last().unwrap(),
+                    weight,
+                });
+            }
+            trace.keys.push((key, trace.values.len()));
+        }
+        let lookup_keys: Vec<usize> = (0..lookups)
+            .map(|_| random() as usize % trace.keys.len())
+            .collect();
+
+        for &fraction in &args.as_of {
+            let as_of = (HORIZON as f64 * fraction.clamp(0.0, 1.0)) as u64;
+            let mut failures = 0;
+            let straddling = trace
+                .values
+                .iter()
+                .filter(|value| value.first <= as_of && value.last > as_of)
+                .count();
+
+            // A scan holds its block from one run to the next, and a lookup
+            // starts with no block.
+            let expected: Vec<Vec<(u64, i64)>> = (0..trace.keys.len())
+                .map(|key| trace.expected(key, as_of))
+                .collect();
+            let modes = [Mode::Plain, Mode::Pruned, Mode::Searched];
+            let (mut scan_reads, mut lookup_reads) = ([0; 3], [0; 3]);
+            for (i, &mode) in modes.iter().enumerate() {
+                let mut held = None;
+                for (key, expected) in expected.iter().enumerate() {
+                    let weights = trace.weights(key, as_of, mode, &mut held, &mut scan_reads[i]);
+                    failures += (weights != *expected) as u64;
+                }
+                for &key in &lookup_keys {
+                    let weights = trace.weights(key, as_of, mode, &mut None, &mut lookup_reads[i]);
+                    failures += (weights != expected[key]) as u64;
+                }
+            }
+            println!(
+                "{per_value:>9}  {:4.0}%  {:10.1}%  {:6}  {:6}  {:6}  {:6.3}  {:6.3}  {:6.3}  {failures:8}",
+                fraction * 100.0,
+                straddling as f64 / trace.values.len() as f64 * 100.0,
+                scan_reads[0],
+                scan_reads[1],
+                scan_reads[2],
+                lookup_reads[0] as f64 / lookups as f64,
+                lookup_reads[1] as f64 / lookups as f64,
+                lookup_reads[2] as f64 / lookups as f64,
+            );
+            total_failures += failures;
+        }
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: TracesArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "traces",
+            "--rows",
+            "50000",
+            "--lookups",
+            "1000",
+            "--times-per-value",
+            "1",
+            "--times-per-value",
+            "16",
+            "--times-per-value",
+            "256",
+        ]);
+        assert_eq!(traces(&args), 0);
+    }
+}
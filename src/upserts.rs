@@ -0,0 +1,268 @@
+//! Dead data in a spine under a steady stream of upserts.
+//!
+//! The spine model grows its key population with every batch.  Many tables
+//! instead hold a roughly fixed set of keys that are overwritten over and over,
+//! such as the latest state per account.  This starts from the whole key
+//! population in one fully merged file, then ingests batches that each
+//! overwrite a fixed fraction of the population, with the keys chosen
+//! uniformly and independently from batch to batch.  Every overwritten
+//! version stays on disk as dead data until a merge brings it together with
+//! its newer version, so the amount of it depends on the merge policy:
+//!
+//! - `tiered` merges `fanout` files of about the same size into one, as in the
+//!   spine model.  A file's size is its size after consolidation, so once
+//!   files approach the size of the whole population they stop growing and
+//!   pile up, `fanout` of them, before they merge.
+//!
+//! - `leveled` keeps a single file per level, with each level `fanout` times
+//!   the capacity of the one above it.  Level 0 collects `fanout` batches,
+//!   which are then merged into level 1, and whenever a level reaches its
+//!   capacity it is merged into the next.  The population's file is the last
+//!   level, which has no capacity limit.
+//!
+//! Merges happen as soon as they are due.  The figures are averages at steady
+//! state, measured after each batch over the second half of the batches.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::{HumanBytes, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct UpsertsArgs {
+    /// Fraction of the key population that each batch overwrites.  The batch
+    /// size is this fraction of the total data size, which is the size of the
+    /// population.
+    #[clap(long = "overwrite-ratio", default_values_t = vec![0.001, 0.01, 0.1])]
+    overwrite_ratios: Vec<f64>,
+
+    /// Merge policy to model.
+    #[clap(long = "policy", default_values_t = vec![Policy::Tiered, Policy::Leveled])]
+    policies: Vec<Policy>,
+
+    /// Number of files merged at once by `tiered`, and the ratio of the
+    /// capacities of successive levels for `leveled`.
+    #[clap(long = "fanout", default_values_t = vec![4, 8, 16])]
+    fanouts: Vec<u64>,
+
+    /// Number of batches to ingest.
+    #[clap(long, default_value_t = 100_000)]
+    batches: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Policy {
+    /// Merges files of about the same size.
+    Tiered,
+
+    /// Keeps one file per level and merges each level into the next.
+    Leveled,
+}
+
+impl Display for Policy {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Policy::Tiered => "tiered",
+            Policy::Leveled => "leveled",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// Spine made of files that each hold the consolidated contents of some
+/// number of batches.  A file is represented by that number, which is
+/// infinite for the file holding the initial population.
+struct Spine {
+    policy: Policy,
+    fanout: u64,
+
+    /// Fraction of the population that each batch overwrites.
+    ratio: f64,
+
+    /// For `tiered`, all of the files.  For `leveled`, the files in level 0.
+    files: Vec<f64>,
+
+    /// For `leveled`, the file at each level starting from level 1, or 0 if
+    /// the level is empty.
+    levels: Vec<f64>,
+
+    /// Data written by merges, as a multiple of the population.
+    merged: f64,
+}
+
+impl Spine {
+    fn new(policy: Policy, fanout: u64, ratio: f64) -> Self {
+        let (files, levels) = match policy {
+            Policy::Tiered => (vec![f64::INFINITY], Vec::new()),
+            Policy::Leveled => {
+                // Level `i` holds `fanout^i` batches, so the last level is
+                // the first one big enough for the population.
+                let n_levels = ((1.0 / ratio).ln() / (fanout as f64).ln()).ceil().max(1.0);
+                let mut levels = vec![0.0; n_levels as usize];
+                *levels.last_mut().unwrap() = f64::INFINITY;
+                (Vec::new(), levels)
+            }
+        };
+        Self {
+            policy,
+            fanout,
+            ratio,
+            files,
+            levels,
+            merged: 0.0,
+        }
+    }
+
+    /// Returns the size of a file holding `batches` batches, as a fraction of
+    /// the population.  Each batch overwrites a uniformly chosen
+    /// `self.ratio` of the keys, so a key is missing from all of them with
+    /// probability `(1 - ratio)^batches`.
+    fn size(&self, batches: f64) -> f64 {
+        1.0 - (1.0 - self.ratio).powf(batches)
+    }
+
+    /// Returns the size of a file holding `batches` batches in units of one
+    /// batch.
+    fn size_in_batches(&self, batches: f64) -> f64 {
+        self.size(batches) / self.ratio
+    }
+
+    /// Merges the files with the given numbers of batches into one, and
+    /// returns the number of batches in the result.
+    fn merge(&mut self, inputs: impl IntoIterator<Item = f64>) -> f64 {
+        let batches = inputs.into_iter().sum();
+        self.merged += self.size(batches);
+        batches
+    }
+
+    /// Adds a batch and performs the merges that it makes due.
+    fn push(&mut self) {
+        self.files.push(1.0);
+        match self.policy {
+            Policy::Tiered => self.merge_tiered(),
+            Policy::Leveled => self.merge_leveled(),
+        }
+    }
+
+    fn merge_tiered(&mut self) {
+        let fanout = self.fanout as f64;
+        let level = |spine: &Self, batches: f64| {
+            (spine.size_in_batches(batches).ln() / fanout.ln()).floor() as i64
+        };
+        loop {
+            let mut levels: Vec<_> = self.files.iter().map(|&b| level(self, b)).collect();
+            levels.sort_unstable();
+            let full = levels
+                .windows(self.fanout as usize)
+                .find(|window| window[0] == window[window.len() - 1])
+                .map(|window| window[0]);
+            let Some(full) = full else {
+                break;
+            };
+            let (inputs, rest): (Vec<_>, Vec<_>) = self
+                .files
+                .iter()
+                .partition(|&&batches| level(self, batches) == full);
+            let mut inputs: Vec<f64> = inputs.into_iter().copied().collect();
+            let mut files: Vec<f64> = rest.into_iter().copied().collect();
+            files.extend(inputs.drain(self.fanout as usize..));
+            files.push(self.merge(inputs));
+            self.files = files;
+        }
+    }
+
+    fn merge_leveled(&mut self) {
+        if (self.files.len() as u64) < self.fanout {
+            return;
+        }
+        let files = std::mem::take(&mut self.files);
+        self.levels[0] = self.merge(files.into_iter().chain([self.levels[0]]));
+        let mut capacity = self.fanout as f64;
+        for i in 0..self.levels.len() - 1 {
+            if self.size_in_batches(self.levels[i]) < capacity {
+                break;
+            }
+            let inputs = [self.levels[i], self.levels[i + 1]];
+            self.levels[i] = 0.0;
+            self.levels[i + 1] = self.merge(inputs);
+            capacity *= self.fanout as f64;
+        }
+    }
+
+    /// Returns the number of files in the spine.
+    fn n_files(&self) -> usize {
+        self.files.len() + self.levels.iter().filter(|&&batches| batches > 0.0).count()
+    }
+
+    /// Returns the data in the spine's files as a multiple of the population.
+    fn space_amp(&self) -> f64 {
+        self.files
+            .iter()
+            .chain(&self.levels)
+            .map(|&batches| self.size(batches))
+            .sum()
+    }
+}
+
+/// Prints the steady-state dead data and write amplification of each merge
+/// policy and fanout in `args`, for a key population of
+/// `params.total_data_size` bytes.
+pub fn upserts(params: &Params, args: &UpsertsArgs) {
+    let population = params.total_data_size;
+    let n_batches = args.batches.max(2);
+    println!(
+        "Steady-state dead data for upserts into {} of live data over {n_batches} batches:",
+        HumanBytes(population),
+    );
+    print!(
+        r#"
+Overwrite                             Write   ---Files---   -Space Amp.-      Dead
+    Ratio    Batch   Policy  Fanout    Amp.    Avg    Max    Avg  Worst      Data
+---------  -------  -------  ------   -----   ----   ----   ----  -----   -------
+"#
+    );
+    for &ratio in &args.overwrite_ratios {
+        let ratio = ratio.clamp(1e-6, 1.0);
+        let mut first = true;
+        for &policy in &args.policies {
+            for &fanout in &args.fanouts {
+                let mut spine = Spine::new(policy, fanout.max(2), ratio);
+                let (mut files, mut max_files) = (0, 0);
+                let (mut space_amp, mut worst_space_amp) = (0.0, 0.0f64);
+                let warmup = n_batches / 2;
+                for i in 0..n_batches {
+                    if i == warmup {
+                        spine.merged = 0.0;
+                    }
+                    spine.push();
+                    if i >= warmup {
+                        let n_files = spine.n_files();
+                        files += n_files;
+                        max_files = max_files.max(n_files);
+                        let amp = spine.space_amp();
+                        space_amp += amp;
+                        worst_space_amp = worst_space_amp.max(amp);
+                    }
+                }
+                let measured = (n_batches - warmup) as f64;
+                let space_amp = space_amp / measured;
+                let write_amp = 1.0 + spine.merged / (measured * ratio);
+                if first {
+                    print!(
+                        "{:9}  {:>7}",
+                        format!("{:.1}%", ratio * 100.0),
+                        HumanBytes((population as f64 * ratio) as u64)
+                    );
+                    first = false;
+                } else {
+                    print!("{:9}  {:7}", "", "");
+                }
+                println!(
+                    "  {policy:>7}  {fanout:6}   {write_amp:5.1}   {:4.1}   {max_files:4}   {space_amp:4.2}  {worst_space_amp:5.2}   {:>7}",
+                    files as f64 / measured,
+                    HumanBytes(((space_amp - 1.0) * population as f64) as u64),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Comparison of the model against statistics from a real layer file.
+//!
+//! There is no writer for the format yet, so this can't read layer files
+//! directly.  Instead, it reads a summary of a file's statistics, which the
+//! writer (or a tool that walks a file's blocks) can print, and compares them
+//! against the model's predictions for a file with the same number of values
+//! of the same size.  Each line of the summary is `name = value`:
+//!
+//! - `value_size`: average size of a value, in bytes (required).
+//! - `rows`: number of values (required).
+//! - `data_blocks`: number of data blocks.
+//! - `data_size`: total bytes in data blocks.
+//! - `INDEX.height` and `INDEX.size`: height of an index and total bytes in
+//!   its blocks, where `INDEX` is an index type such as `data` or `row`.
+//!
+//! Blank lines and lines that start with `#` are ignored.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{HumanBytes, IndexType, LayerFile, Params};
+
+#[derive(ClapArgs, Debug)]
+pub struct ValidateArgs {
+    /// Summary of the file's statistics.
+    file: PathBuf,
+}
+
+/// Statistics read from a summary file.
+#[derive(Default)]
+struct Stats {
+    value_size: Option<u64>,
+    rows: Option<u64>,
+    data_blocks: Option<u64>,
+    data_size: Option<u64>,
+    heights: BTreeMap<String, (IndexType, u64)>,
+    sizes: BTreeMap<String, (IndexType, u64)>,
+}
+
+fn read_stats(path: &PathBuf) -> Result<Stats, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut stats = Stats::default();
+    for (i, line) in text.lines().enumerate() {
+        let error = |msg: String| format!("{}:{}: {msg}", path.display(), i + 1);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected name = value".into()))?;
+        let (name, value) = (name.trim(), value.trim());
+        let value: u64 = value.parse().map_err(|e| error(format!("{value}: {e}")))?;
+        match name {
+            "value_size" => stats.value_size = Some(value),
+            "rows" => stats.rows = Some(value),
+            "data_blocks" => stats.data_blocks = Some(value),
+            "data_size" => stats.data_size = Some(value),
+            _ => {
+                let (index, stat) = name
+                    .split_once('.')
+                    .ok_or_else(|| error(format!("{name}: unknown statistic")))?;
+                let index_type = IndexType::from_str(index, true).map_err(&error)?;
+                let map = match stat {
+                    "height" => &mut stats.heights,
+                    "size" => &mut stats.sizes,
+                    _ => return Err(error(format!("{name}: unknown statistic"))),
+                };
+                map.insert(index.into(), (index_type, value));
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Prints the statistics in `args.file` next to the model's predictions for
+/// the same file under `params`, with the model's error for each.
+pub fn validate(params: &Params, args: &ValidateArgs) -> Result<(), String> {
+    let stats = read_stats(&args.file)?;
+    let file = args.file.display();
+    let value_size = stats
+        .value_size
+        .ok_or_else(|| format!("{file}: missing value_size"))?
+        .max(1);
+    let rows = stats.rows.ok_or_else(|| format!("{file}: missing rows"))?;
+    let layer_file = LayerFile::new(&Params {
+        value_size,
+        total_data_size: rows * value_size,
+        ..params.clone()
+    });
+
+    println!(
+        "Model versus {file}, {rows} values of {value_size} bytes, min_branch={}, min_data_block={}, min_index_block={}{}:",
+        params.min_branch,
+        params.min_data_block,
+        params.min_index_block,
+        params.describe_non_defaults()
+    );
+    print!(
+        r#"
+Statistic            Actual       Model    Error
+------------------  ---------  ---------  -------
+"#
+    );
+    let row = |name: &str, actual: u64, model: u64, bytes: bool| {
+        let show = |n: u64| {
+            if bytes {
+                HumanBytes(n).to_string()
+            } else {
+                n.to_string()
+            }
+        };
+        let error = if actual == 0 {
+            "-".to_string()
+        } else {
+            format!("{:+.1}%", (model as f64 / actual as f64 - 1.0) * 100.0)
+        };
+        println!(
+            "{name:18}  {:>9}  {:>9}  {error:>7}",
+            show(actual),
+            show(model)
+        );
+    };
+    if let Some(data_blocks) = stats.data_blocks {
+        row(
+            "data_blocks",
+            data_blocks,
+            rows.div_ceil(layer_file.values_per_data_block),
+            false,
+        );
+    }
+    if let Some(data_size) = stats.data_size {
+        row("data_size", data_size, layer_file.data_size(), true);
+    }
+    let model_index = |index_type: IndexType| {
+        layer_file
+            .indexes
+            .iter()
+            .find(|index| index.index_type == index_type)
+            .unwrap()
+    };
+    for (name, &(index_type, height)) in &stats.heights {
+        let model = model_index(index_type).height as u64;
+        row(&format!("{name}.height"), height, model, false);
+    }
+    for (name, &(index_type, size)) in &stats.sizes {
+        let model = model_index(index_type).total_size();
+        row(&format!("{name}.size"), size, model, true);
+    }
+    Ok(())
+}
@@ -0,0 +1,457 @@
+//! Variable-length values in data blocks.
+//!
+//! The rest of the model assumes that every value in a column has the same
+//! size, but strings and serialized tuples don't.  A data block of
+//! variable-length values ends with an array that holds the offset of each
+//! value within the block and of the end of the last one, followed by the
+//! number of values, so that a reader can binary search the values without
+//! decoding them in order.  Offsets take
+//! 2 bytes in a block of up to 64 kB and 4 bytes in a bigger one.
+//!
+//! The writer can't know how many values fit until it has them, so it adds
+//! values to a block until the next one, with its offset, would overflow it.
+//! A value too big for a block by itself gets a block of its own, which is the
+//! smallest power-of-2 multiple of 4 kB that holds it, so one big string
+//! doesn't make the rest of the column's blocks big.
+//!
+//! Better still, a value bigger than an overflow threshold goes in a blob
+//! block, which is a block header followed by the value, padded to a multiple
+//! of 4 kB, and the data block holds an overflow pointer in its place: the
+//! blob block's offset (8 bytes), the value's length (4 bytes), and the
+//! value's first [PREFIX_SIZE] bytes.  A data block with any overflow
+//! pointers sets the top bit of its count and has a bitmap of them after its
+//! offsets.  A search compares against the prefix and reads the blob block
+//! only if the prefix is equal, so a huge value costs its neighbors only the
+//! size of a pointer.
+//!
+//! This generates sorted byte strings whose lengths follow a distribution,
+//! splits them into data blocks, checks that a binary search in the right
+//! block finds each value in a sample, and reports how full the blocks are,
+//! what the offsets cost, how many values needed a bigger block or a blob
+//! block, and how many blob blocks a search reads.
+
+use clap::Args as ClapArgs;
+use std::time::Instant;
+
+use crate::batches::{padded, Distribution};
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct VarlenArgs {
+    /// Average size of each value, in bytes.
+    #[clap(long = "value-size", default_values_t = vec![8, 64, 512, 4096])]
+    value_sizes: Vec<u64>,
+
+    /// Distribution of the value sizes around the average.
+    #[clap(long, value_enum, default_value_t = Distribution::Exponential)]
+    distribution: Distribution,
+
+    /// Size of each data block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of values to generate for each average size.
+    #[clap(long, default_value_t = 200_000)]
+    rows: u64,
+
+    /// Number of values to seek for.
+    #[clap(long, default_value_t = 100_000)]
+    seeks: u64,
+
+    /// Largest value, in bytes, that is stored in a data block rather than
+    /// in a blob block of its own, or 0 to store every value in data blocks.
+    #[clap(long, default_value_t = 2048)]
+    overflow_threshold: u64,
+}
+
+/// Size of the header that begins every block, with its magic, size, and
+/// checksum.
+const BLOCK_HEADER_SIZE: usize = 32;
+
+/// Size of the count of values at the end of a data block.
+const COUNT_SIZE: usize = 4;
+
+/// Bit of the count that says that the block has overflow pointers.
+const HAS_OVERFLOW: u32 = 1 << 31;
+
+/// Number of bytes of an overflowed value that its pointer holds, for
+/// comparisons.
+const PREFIX_SIZE: usize = 16;
+
+/// Size of an overflow pointer: the blob block's offset, the value's length,
+/// and its prefix.
+const POINTER_SIZE: usize = 12 + PREFIX_SIZE;
+
+/// Returns the size of each offset in a block of `block_size` bytes.
+fn offset_size(block_size: usize) -> usize {
+    match block_size <= 1 << 16 {
+        true => 2,
+        false => 4,
+    }
+}
+
+/// Returns the size of a block that holds `data` bytes of `n` values, with a
+/// bitmap of overflow pointers if `overflow`.
+fn block_bytes(data: usize, n: usize, overflow: bool, block_size: usize) -> usize {
+    let bitmap = match overflow {
+        true => n.div_ceil(8),
+        false => 0,
+    };
+    BLOCK_HEADER_SIZE + data + (n + 1) * offset_size(block_size) + bitmap + COUNT_SIZE
+}
+
+/// A value as a data block stores it, which is either the value itself or an
+/// overflow pointer to a blob block.
+struct Slot {
+    bytes: Vec<u8>,
+    overflow: bool,
+}
+
+impl Slot {
+    /// Returns the slot for `value`, appending it to `blobs` as a blob block
+    /// if it's bigger than `threshold`, unless `threshold` is 0.
+    fn new(value: &[u8], threshold: usize, blobs: &mut Vec<u8>) -> Self {
+        if threshold == 0 || value.len() <= threshold {
+            return Self {
+                bytes: value.into(),
+                overflow: false,
+            };
+        }
+        let offset = blobs.len() as u64;
+        blobs.resize(blobs.len() + BLOCK_HEADER_SIZE, 0);
+        blobs.extend_from_slice(value);
+        blobs.resize(blobs.len().next_multiple_of(4096), 0);
+        let mut bytes = Vec::with_capacity(POINTER_SIZE);
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&value[..PREFIX_SIZE.min(value.len())]);
+        bytes.resize(POINTER_SIZE, 0);
+        Self {
+            bytes,
+            overflow: true,
+        }
+    }
+}
+
+/// A data block of variable-length values.
+struct Block {
+    bytes: Vec<u8>,
+    offset_size: usize,
+    len: usize,
+    has_overflow: bool,
+}
+
+impl Block {
+    /// Writes `slots` into a block of `block_size` bytes, after the block
+    /// header, with the offsets, the bitmap of overflow pointers if there
+    /// are any, and the count at the end.
+    fn new(slots: &[Slot], block_size: usize) -> Self {
+        let offset_size = offset_size(block_size);
+        let has_overflow = slots.iter().any(|slot| slot.overflow);
+        let mut bytes = vec![0; BLOCK_HEADER_SIZE];
+        let mut offsets = Vec::with_capacity(slots.len() + 1);
+        let mut bitmap = vec![0u8; slots.len().div_ceil(8)];
+        for (i, slot) in slots.iter().enumerate() {
+            offsets.push(bytes.len());
+            bytes.extend_from_slice(&slot.bytes);
+            bitmap[i / 8] |= (slot.overflow as u8) << (i % 8);
+        }
+        offsets.push(bytes.len());
+        if !has_overflow {
+            bitmap.clear();
+        }
+        bytes.resize(
+            block_size - offsets.len() * offset_size - bitmap.len() - COUNT_SIZE,
+            0,
+        );
+        for offset in offsets {
+            bytes.extend_from_slice(&offset.to_le_bytes()[..offset_size]);
+        }
+        bytes.extend_from_slice(&bitmap);
+        let flag = if has_overflow { HAS_OVERFLOW } else { 0 };
+        bytes.extend_from_slice(&(slots.len() as u32 | flag).to_le_bytes());
+        assert_eq!(bytes.len(), block_size);
+        Self {
+            bytes,
+            offset_size,
+            len: slots.len(),
+            has_overflow,
+        }
+    }
+
+    /// Returns the size of the bitmap of overflow pointers.
+    fn bitmap_size(&self) -> usize {
+        match self.has_overflow {
+            true => self.len.div_ceil(8),
+            false => 0,
+        }
+    }
+
+    /// Returns whether the `i`th slot in the block is an overflow pointer.
+    fn is_overflow(&self, i: usize) -> bool {
+        let bitmap = self.bytes.len() - COUNT_SIZE - self.bitmap_size();
+        self.has_overflow && self.bytes[bitmap + i / 8] & (1 << (i % 8)) != 0
+    }
+
+    /// Returns the `i`th value in the block, reading it from `blobs` if the
+    /// block holds an overflow pointer to it, and counting that in `reads`.
+    fn value<'a>(&'a self, i: usize, blobs: &'a [u8], reads: &mut u64) -> &'a [u8] {
+        let slot = self.slot(i);
+        if !self.is_overflow(i) {
+            return slot;
+        }
+        *reads += 1;
+        let offset = u64::from_le_bytes(slot[..8].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(slot[8..12].try_into().unwrap()) as usize;
+        &blobs[offset + BLOCK_HEADER_SIZE..offset + BLOCK_HEADER_SIZE + len]
+    }
+
+    /// Returns the `i`th slot in the block.
+    fn slot(&self, i: usize) -> &[u8] {
+        let offsets =
+            self.bytes.len() - COUNT_SIZE - self.bitmap_size() - (self.len + 1) * self.offset_size;
+        let offset = |i: usize| {
+            let at = offsets + i * self.offset_size;
+            let mut bytes = [0; 4];
+            bytes[..self.offset_size].copy_from_slice(&self.bytes[at..at + self.offset_size]);
+            u32::from_le_bytes(bytes) as usize
+        };
+        &self.bytes[offset(i)..offset(i + 1)]
+    }
+
+    /// Returns whether the `i`th value in the block is less than `target`,
+    /// comparing against the prefix of an overflowed value first and only
+    /// reading it from `blobs` if the prefix is equal.
+    fn is_less(&self, i: usize, target: &[u8], blobs: &[u8], reads: &mut u64) -> bool {
+        if self.is_overflow(i) {
+            let prefix = &self.slot(i)[12..];
+            let n = PREFIX_SIZE.min(target.len());
+            if prefix[..n] != target[..n] || target.len() < PREFIX_SIZE {
+                // An overflowed value is longer than its prefix, so it's
+                // greater than a target that equals or is a prefix of it.
+                return prefix[..n] < target[..n];
+            }
+        }
+        self.value(i, blobs, reads) < target
+    }
+
+    /// Returns the index of the first value in the block that is at least
+    /// `target`, counting blob blocks read in `reads`.
+    fn seek(&self, target: &[u8], blobs: &[u8], reads: &mut u64) -> usize {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.is_less(mid, target, blobs, reads) {
+                true => lo = mid + 1,
+                false => hi = mid,
+            }
+        }
+        lo
+    }
+}
+
+/// Splits `slots` into data blocks of `block_size` bytes, except that a
+/// slot too big for one gets a bigger block of its own.  Returns each block
+/// with the number of values in it.
+fn split(slots: &[Slot], block_size: usize) -> Vec<(Block, usize)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    while start < slots.len() {
+        let mut n = 0;
+        let mut data = 0;
+        let mut overflow = false;
+        while let Some(slot) = slots.get(start + n) {
+            let size = block_bytes(
+                data + slot.bytes.len(),
+                n + 1,
+                overflow || slot.overflow,
+                block_size,
+            );
+            if size > block_size {
+                break;
+            }
+            data += slot.bytes.len();
+            overflow |= slot.overflow;
+            n += 1;
+        }
+        let size = match n {
+            0 => {
+                n = 1;
+                let slot = &slots[start];
+                let bytes = |block_size| {
+                    let size = block_bytes(slot.bytes.len(), 1, slot.overflow, block_size);
+                    padded(size as u64) as usize
+                };
+                match offset_size(bytes(block_size)) == offset_size(block_size) {
+                    true => bytes(block_size),
+                    false => bytes(bytes(block_size)),
+                }
+            }
+            _ => block_size,
+        };
+        let block = Block::new(&slots[start..start + n], size);
+        blocks.push((block, n));
+        start += n;
+    }
+    blocks
+}
+
+/// Prints how variable-length values of each average size in `args` fill
+/// data blocks, and checks that seeks find them.
+/// Returns the number of failed checks.
+pub fn varlen(args: &VarlenArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = padded(args.block_size.max(4096)) as usize;
+    let threshold = match args.overflow_threshold {
+        0 => 0,
+        threshold => threshold.max(POINTER_SIZE as u64) as usize,
+    };
+    let rows = args.rows.max(1);
+    let seeks = args.seeks.max(1);
+    println!(
+        "Variable-length values, {} sizes, {} blocks, {} values, {}:",
+        format!("{:?}", args.distribution).to_lowercase(),
+        HumanBytes(block_size as u64),
+        HumanCount(rows),
+        match threshold {
+            0 => "no overflow".into(),
+            _ => format!("overflow above {}", HumanBytes(threshold as u64)),
+        }
+    );
+    print!(
+        r#"
+ Average   Values      Block  Offsets  Oversized  Overflow     Blob  Blob Reads             Seek
+   Value   /Block       Fill    /Data     Values    Values  Padding       /Seek  Failures   (ns)
+--------  -------  ---------  -------  ---------  --------  -------  ----------  --------  -----
+"#
+    );
+    for &value_size in &args.value_sizes {
+        let value_size = value_size.max(1);
+        let sizes = args.distribution.samples(value_size);
+        let mut random = rng::random(value_size);
+        let mut values: Vec<Vec<u8>> = (0..rows)
+            .map(|_| {
+                let len = sizes[random() as usize % sizes.len()] as usize;
+                (0..len).map(|_| random() as u8).collect()
+            })
+            .collect();
+        values.sort();
+        values.dedup();
+
+        let mut blobs = Vec::new();
+        let slots: Vec<Slot> = values
+            .iter()
+            .map(|value| Slot::new(value, threshold, &mut blobs))
+            .collect();
+        let blocks = split(&slots, block_size);
+        let mut first_values = Vec::with_capacity(blocks.len());
+        let mut start = 0;
+        for (_, n) in &blocks {
+            first_values.push(&values[start]);
+            start += n;
+        }
+
+        // Find each target's block first, as the index would, so that the
+        // time is only the search within the block.  Reading the value that
+        // the search finds takes one more blob block read if it overflowed,
+        // which isn't counted.
+        let targets: Vec<(&[u8], usize)> = (0..seeks)
+            .map(|_| {
+                let target = &values[random() as usize % values.len()];
+                let block = first_values.partition_point(|&value| value <= target) - 1;
+                (target.as_slice(), block)
+            })
+            .collect();
+        let mut failures = 0;
+        let mut reads = 0;
+        let start = Instant::now();
+        for &(target, block) in &targets {
+            let block = &blocks[block].0;
+            let j = block.seek(target, &blobs, &mut reads);
+            failures += (j == block.len || block.value(j, &blobs, &mut 0) != target) as u64;
+        }
+        let seek_time = start.elapsed().as_secs_f64();
+
+        let data: usize = values.iter().map(Vec::len).sum();
+        let slot_data: usize = slots.iter().map(|slot| slot.bytes.len()).sum();
+        let overflowed = slots.iter().filter(|slot| slot.overflow).count();
+        let blob_data: usize = slots
+            .iter()
+            .zip(&values)
+            .filter(|(slot, _)| slot.overflow)
+            .map(|(_, value)| value.len())
+            .sum();
+        let disk: usize = blocks.iter().map(|(block, _)| block.bytes.len()).sum();
+        let offsets: usize = blocks
+            .iter()
+            .map(|(block, n)| block.offset_size * (n + 1) + block.bitmap_size() + COUNT_SIZE)
+            .sum();
+        let oversized = blocks
+            .iter()
+            .filter(|(block, _)| block.bytes.len() > block_size)
+            .count();
+        println!(
+            "{:>8}  {:7.1}  {:8.1}%  {:6.1}%  {:8.3}%  {:7.3}%  {:6.1}%  {:10.3}  {failures:8}  {:5.0}",
+            HumanBytes(value_size),
+            values.len() as f64 / blocks.len() as f64,
+            slot_data as f64 / disk as f64 * 100.0,
+            offsets as f64 / data as f64 * 100.0,
+            oversized as f64 / values.len() as f64 * 100.0,
+            overflowed as f64 / values.len() as f64 * 100.0,
+            (blobs.len() - blob_data) as f64 / blob_data.max(1) as f64 * 100.0,
+            reads as f64 / seeks as f64,
+            seek_time / seeks as f64 * 1e9,
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: VarlenArgs,
+    }
+
+    /// Parses `args`, with fewer rows and seeks than the defaults.
+    fn parse(args: &[&str]) -> VarlenArgs {
+        let fixed = ["varlen", "--rows", "5000", "--seeks", "1000"];
+        Wrapper::parse_from(fixed.iter().chain(args)).args
+    }
+
+    #[test]
+    fn checks_pass() {
+        let args = parse(&[
+            "--value-size",
+            "8",
+            "--value-size",
+            "512",
+            "--distribution",
+            "uniform",
+            "--overflow-threshold",
+            "0",
+        ]);
+        assert_eq!(varlen(&args), 0);
+    }
+
+    #[test]
+    fn overflow_checks_pass() {
+        // Most values of the 4-kB size, and some of the 512-byte size, go to
+        // blob blocks.
+        let args = parse(&[
+            "--value-size",
+            "512",
+            "--value-size",
+            "4096",
+            "--overflow-threshold",
+            "1024",
+        ]);
+        assert_eq!(varlen(&args), 0);
+    }
+}
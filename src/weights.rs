@@ -0,0 +1,340 @@
+//! Run-length encoding of the weight column.
+//!
+//! In the last column, each row has a weight, and in an append-mostly Z-set
+//! nearly all of them are +1.  Instead of one integer per row, a data block
+//! can store its weights as a sequence of runs, each of which starts with a
+//! 1-byte tag:
+//!
+//! - [UNIT_RUN] is followed by a 2-byte count of consecutive +1 weights.
+//!
+//! - [WEIGHT] is followed by a single weight other than +1.
+//!
+//! The data block header says whether the block's weights are run-length
+//! encoded or raw, and the writer picks whichever is smaller, so a block of
+//! mostly other weights costs no more than before.  A reader doesn't see the
+//! difference: [WeightCursor] yields one weight per row either way, and skips
+//! ahead over whole runs when a seek moves it forward.
+//!
+//! This generates weights with a given fraction of +1 weights and average run
+//! length, encodes them block by block, checks that the cursor gives back
+//! every row's weight, and compares the space against raw weights and against
+//! the model's estimate, along with the time to read every weight.
+
+use clap::Args as ClapArgs;
+use std::time::Instant;
+
+use crate::rng;
+use crate::{HumanCount, Weights};
+
+#[derive(ClapArgs, Debug)]
+pub struct WeightsArgs {
+    /// Fraction of rows whose weight is +1.
+    #[clap(long = "unit-weights", default_values_t = vec![0.5, 0.9, 0.95, 0.99, 1.0])]
+    unit_fractions: Vec<f64>,
+
+    /// Average length of a run of consecutive +1 weights.  By default,
+    /// weights are independent, so that the average run length is
+    /// `1 / (1 - FRACTION)`.
+    #[clap(long, value_name = "N")]
+    unit_run: Option<f64>,
+
+    /// Size of each weight, in bytes, from 1 to 8.
+    #[clap(long, default_value_t = 8)]
+    weight_size: u64,
+
+    /// Number of rows in each data block.
+    #[clap(long, default_value_t = 341)]
+    block_rows: u64,
+
+    /// Number of rows to generate for each fraction.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+}
+
+/// Tag of a run of +1 weights, followed by a 2-byte count.
+const UNIT_RUN: u8 = 0;
+
+/// Tag of a single weight other than +1, followed by the weight.
+const WEIGHT: u8 = 1;
+
+/// Values of the byte that says how a data block stores its weights.
+const RAW: u8 = 0;
+const RLE: u8 = 1;
+
+/// Appends `weight` to `out` as a little-endian integer of `size` bytes.
+fn put_weight(out: &mut Vec<u8>, weight: i64, size: usize) {
+    out.extend_from_slice(&weight.to_le_bytes()[..size]);
+}
+
+/// Reads a little-endian, sign-extended integer of `size` bytes at `pos`.
+fn get_weight(block: &[u8], pos: usize, size: usize) -> i64 {
+    let mut bytes = [0; 8];
+    bytes[..size].copy_from_slice(&block[pos..pos + size]);
+    let shift = 64 - 8 * size as u32;
+    (i64::from_le_bytes(bytes) << shift) >> shift
+}
+
+/// Returns `weights` encoded for a data block, as a byte that says how they
+/// are stored followed by either the run-length encoding or one `size`-byte
+/// integer per row, whichever is smaller.
+fn encode(weights: &[i64], size: usize) -> Vec<u8> {
+    let mut rle = vec![RLE];
+    let mut i = 0;
+    while i < weights.len() {
+        if weights[i] == 1 {
+            let run = weights[i..]
+                .iter()
+                .take(u16::MAX as usize)
+                .take_while(|&&weight| weight == 1)
+                .count();
+            rle.push(UNIT_RUN);
+            rle.extend_from_slice(&(run as u16).to_le_bytes());
+            i += run;
+        } else {
+            rle.push(WEIGHT);
+            put_weight(&mut rle, weights[i], size);
+            i += 1;
+        }
+    }
+    if rle.len() < 1 + weights.len() * size {
+        return rle;
+    }
+    let mut raw = vec![RAW];
+    for &weight in weights {
+        put_weight(&mut raw, weight, size);
+    }
+    raw
+}
+
+/// A cursor over the weights of one data block, which reads them the same
+/// way whether the block stores them raw or run-length encoded.
+struct WeightCursor<'a> {
+    block: &'a [u8],
+    size: usize,
+    rle: bool,
+
+    /// Offset of the next run, or of the next raw weight.
+    pos: usize,
+
+    /// Number of +1 weights left in the current run, including the current
+    /// row's, or 0 if the current row's weight came from a [WEIGHT] run.
+    units: u16,
+
+    /// The current row's weight, or `None` past the last row.
+    weight: Option<i64>,
+}
+
+impl<'a> WeightCursor<'a> {
+    /// Returns a cursor on the first row of `block`, which holds weights of
+    /// `size` bytes.
+    fn new(block: &'a [u8], size: usize) -> Self {
+        let mut cursor = Self {
+            block,
+            size,
+            rle: block.first() == Some(&RLE),
+            pos: 1,
+            units: 0,
+            weight: None,
+        };
+        cursor.load();
+        cursor
+    }
+
+    /// Returns the current row's weight, or `None` past the last row.
+    fn weight(&self) -> Option<i64> {
+        self.weight
+    }
+
+    /// Moves to the next row.
+    fn step(&mut self) {
+        self.advance(1);
+    }
+
+    /// Moves forward `rows` rows, skipping whole runs of +1 weights without
+    /// visiting each row.
+    fn advance(&mut self, mut rows: usize) {
+        while rows > 0 && self.weight.is_some() {
+            if self.units > 1 {
+                let skip = rows.min(self.units as usize - 1);
+                self.units -= skip as u16;
+                rows -= skip;
+                if rows == 0 {
+                    break;
+                }
+            }
+            self.units = 0;
+            self.load();
+            rows -= 1;
+        }
+    }
+
+    /// Reads the weight at `pos` into `weight`, starting a new run if the
+    /// block is run-length encoded.
+    fn load(&mut self) {
+        if self.pos >= self.block.len() {
+            self.weight = None;
+        } else if !self.rle {
+            self.weight = Some(get_weight(self.block, self.pos, self.size));
+            self.pos += self.size;
+        } else if self.block[self.pos] == UNIT_RUN {
+            let count = &self.block[self.pos + 1..self.pos + 3];
+            self.units = u16::from_le_bytes([count[0], count[1]]);
+            self.weight = Some(1);
+            self.pos += 3;
+        } else {
+            self.weight = Some(get_weight(self.block, self.pos + 1, self.size));
+            self.pos += 1 + self.size;
+        }
+    }
+}
+
+/// Returns `rows` weights of which a fraction `unit_fraction` are +1, in runs
+/// of `unit_run` on average.  The other weights are small integers other than
+/// 0 and +1, mostly -1, as from deletions.
+fn generate(rows: u64, unit_fraction: f64, unit_run: f64, seed: u64) -> Vec<i64> {
+    // Leaving a run of +1 weights with probability `1 / unit_run` and
+    // starting one with probability `start` gives the requested fraction.
+    let leave = 1.0 / unit_run;
+    let start = match unit_fraction < 1.0 {
+        true => (unit_fraction * leave / (1.0 - unit_fraction)).min(1.0),
+        false => 1.0,
+    };
+    let mut random = rng::random(seed);
+    let uniform = |r: u64| (r >> 11) as f64 / (1u64 << 53) as f64;
+    let mut unit = uniform(random()) < unit_fraction;
+    (0..rows)
+        .map(|_| {
+            let r = random();
+            let weight = match unit {
+                true => 1,
+                false => [-1, -1, -1, -2, 2, 3][(r >> 33) as usize % 6],
+            };
+            unit = match unit {
+                true => uniform(r) >= leave || unit_fraction >= 1.0,
+                false => uniform(r) < start,
+            };
+            weight
+        })
+        .collect()
+}
+
+/// Prints the space and read time of raw and run-length encoded weights for
+/// each fraction of +1 weights in `args`.  Returns the number of failed checks.
+pub fn weights(args: &WeightsArgs) -> u64 {
+    let mut total_failures = 0;
+    let size = args.weight_size.clamp(1, 8) as usize;
+    let block_rows = args.block_rows.max(1) as usize;
+    let rows = args.rows.max(1);
+    println!(
+        "Run-length encoded weights, {size}-byte weights, {block_rows} rows per block, {} rows{}:",
+        HumanCount(rows),
+        match args.unit_run {
+            Some(run) => format!(", runs of {run} +1 weights"),
+            None => String::new(),
+        }
+    );
+    print!(
+        r#"
+  Unit   Unit   ---Bytes per Row---     RLE  Round-trip   -Read (ns/row)-
+  Rows    Run    Raw    RLE   Model  Blocks    Failures     Raw      RLE
+------  -----  -----  -----  ------  ------  ----------  -------  -------
+"#
+    );
+    for &unit_fraction in &args.unit_fractions {
+        let model = Weights::new(unit_fraction, size as u64, args.unit_run);
+        let weights = generate(rows, model.unit_fraction, model.unit_run, rows);
+
+        let blocks: Vec<Vec<u8>> = weights
+            .chunks(block_rows)
+            .map(|chunk| encode(chunk, size))
+            .collect();
+        let raw_blocks: Vec<Vec<u8>> = weights
+            .chunks(block_rows)
+            .map(|chunk| {
+                let mut block = vec![RAW];
+                for &weight in chunk {
+                    put_weight(&mut block, weight, size);
+                }
+                block
+            })
+            .collect();
+        let encoded: usize = blocks.iter().map(|block| block.len() - 1).sum();
+        let rle_blocks = blocks.iter().filter(|block| block[0] == RLE).count();
+
+        // Read back every weight through the cursor, and also seek to the
+        // middle of each block, which can land inside a run.
+        let mut failures = 0;
+        for (chunk, block) in weights.chunks(block_rows).zip(&blocks) {
+            let mut cursor = WeightCursor::new(block, size);
+            for &weight in chunk {
+                failures += (cursor.weight() != Some(weight)) as u64;
+                cursor.step();
+            }
+            failures += cursor.weight().is_some() as u64;
+            let mut cursor = WeightCursor::new(block, size);
+            cursor.advance(chunk.len() / 2);
+            failures += (cursor.weight() != Some(chunk[chunk.len() / 2])) as u64;
+        }
+
+        let read = |blocks: &[Vec<u8>]| {
+            let start = Instant::now();
+            let mut sum = 0i64;
+            for block in blocks {
+                let mut cursor = WeightCursor::new(block, size);
+                while let Some(weight) = cursor.weight() {
+                    sum = sum.wrapping_add(weight);
+                    cursor.step();
+                }
+            }
+            (start.elapsed().as_secs_f64(), sum)
+        };
+        let (raw_time, raw_sum) = read(&raw_blocks);
+        let (rle_time, rle_sum) = read(&blocks);
+        failures += (raw_sum != rle_sum) as u64;
+
+        println!(
+            "{:5.1}%  {:5.1}  {:5.2}  {:5.2}  {:6.2}  {:5.0}%  {failures:10}  {:7.2}  {:7.2}",
+            model.unit_fraction * 100.0,
+            model.unit_run,
+            size as f64,
+            encoded as f64 / rows as f64,
+            model.rle_bytes_per_row(),
+            rle_blocks as f64 / blocks.len() as f64 * 100.0,
+            raw_time / rows as f64 * 1e9,
+            rle_time / rows as f64 * 1e9,
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: WeightsArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "weights",
+            "--unit-weights",
+            "0.5",
+            "--unit-weights",
+            "0.99",
+            "--unit-run",
+            "20",
+            "--weight-size",
+            "2",
+            "--rows",
+            "20000",
+        ]);
+        assert_eq!(args.unit_fractions, [0.5, 0.99]);
+        assert_eq!(weights(&args), 0);
+    }
+}
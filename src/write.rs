@@ -0,0 +1,328 @@
+//! Benchmark of ways for a writer to get blocks into a file.
+//!
+//! A writer produces a file as a stream of blocks, appending each one as it
+//! fills.  This writes a file of a given size in blocks of a given size through
+//! each backend and reports the throughput:
+//!
+//! - `buffered` writes each block through a `BufWriter`, so that the kernel
+//!   sees large writes through the page cache.
+//!
+//! - `direct` opens the file with `O_DIRECT` and writes each block from an
+//!   aligned buffer, bypassing the page cache.
+//!
+//! - `mmap` sizes the file ahead of time, maps it, and copies each block into
+//!   the mapping, with no system call per write.  When the mapping fills up,
+//!   it doubles the file and maps it again, and when the file is complete, it
+//!   truncates the file to its final size.
+//!
+//! The `direct` and `mmap` backends are only implemented on Linux.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::HumanBytes;
+
+#[derive(ClapArgs, Debug)]
+pub struct WriteArgs {
+    /// Block size to benchmark, in bytes.  For `direct`, this must be a
+    /// multiple of the device's logical block size.
+    #[clap(long = "block-size", default_values_t = vec![4096, 8192, 65536, 1 << 20])]
+    block_sizes: Vec<u64>,
+
+    /// Backend to benchmark.
+    #[clap(long = "backend", default_values_t = vec![Backend::Buffered, Backend::Direct, Backend::Mmap])]
+    backends: Vec<Backend>,
+
+    /// Number of bytes to write for each measurement.
+    #[clap(long, default_value_t = 1 << 30)]
+    bytes: u64,
+
+    /// Size that the `mmap` backend initially gives the file, in bytes.  A
+    /// writer that can estimate the final size of the file can use it here
+    /// to avoid remapping.
+    #[clap(long, default_value_t = 64 << 20)]
+    initial_map: u64,
+
+    /// Directory in which to write the file, which is deleted afterward.
+    /// Defaults to the system's temporary directory.
+    #[clap(long)]
+    dir: Option<PathBuf>,
+
+    /// Skips syncing the file to storage at the end of each measurement.
+    /// Without syncing, the `buffered` and `mmap` backends measure only how
+    /// fast they can fill the page cache.
+    #[clap(long)]
+    no_sync: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Backend {
+    /// Writes through a `BufWriter`.
+    Buffered,
+
+    /// Writes with `O_DIRECT`.
+    Direct,
+
+    /// Copies into a memory-mapped file.
+    Mmap,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Backend::Buffered => "buffered",
+            Backend::Direct => "direct",
+            Backend::Mmap => "mmap",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// Prints the throughput of each backend in `args` at each block size.
+pub fn write(args: &WriteArgs) {
+    let dir = args.dir.clone().unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("storage-design-write-{}", std::process::id()));
+    println!(
+        "Write throughput for {} files in {}, {}:",
+        HumanBytes(args.bytes),
+        dir.display(),
+        if args.no_sync {
+            "without syncing"
+        } else {
+            "including the final sync"
+        }
+    );
+    print!(
+        r#"
+ Block
+  Size  Backend     MB/s  us/Block
+------  --------  ------  --------
+"#
+    );
+    for &block_size in &args.block_sizes {
+        let block_size = block_size.max(1);
+        let n = (args.bytes / block_size).max(1);
+        for (i, backend) in args.backends.iter().enumerate() {
+            if i == 0 {
+                print!("{:>6}", HumanBytes(block_size));
+            } else {
+                print!("{:6}", "");
+            }
+            let start = Instant::now();
+            let result = match backend {
+                Backend::Buffered => write_buffered(&path, block_size, n, !args.no_sync),
+                Backend::Direct => write_direct(&path, block_size, n, !args.no_sync),
+                Backend::Mmap => write_mmap(&path, block_size, n, args.initial_map, !args.no_sync),
+            };
+            let seconds = start.elapsed().as_secs_f64();
+            let _ = fs::remove_file(&path);
+            match result {
+                Ok(()) => println!(
+                    "  {backend:8}  {:6.0}  {:8.2}",
+                    (n * block_size) as f64 / seconds / 1e6,
+                    seconds / n as f64 * 1e6
+                ),
+                Err(error) => println!("  {backend:8}  ({error})"),
+            }
+        }
+    }
+}
+
+/// Fills `block` with the contents of a block numbered `i`, standing in for
+/// the work that a writer does to build a block.
+pub fn fill_block(block: &mut [u8], i: u64) {
+    for (j, word) in block.chunks_mut(8).enumerate() {
+        let value = (i ^ j as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        word.copy_from_slice(&value.to_le_bytes()[..word.len()]);
+    }
+}
+
+fn write_buffered(path: &Path, block_size: u64, n: u64, sync: bool) -> IoResult<()> {
+    let mut block = vec![0; block_size as usize];
+    let mut writer = BufWriter::with_capacity(1 << 20, File::create(path)?);
+    for i in 0..n {
+        fill_block(&mut block, i);
+        writer.write_all(&block)?;
+    }
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    if sync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn write_direct(path: &Path, block_size: u64, n: u64, sync: bool) -> IoResult<()> {
+    use std::alloc::{alloc_zeroed, dealloc, Layout};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    // `O_DIRECT` has a different value on some architectures.  The rest,
+    // including x86, x86-64, RISC-V, s390x, and LoongArch, use the generic
+    // value.
+    #[cfg(any(target_arch = "aarch64", target_arch = "arm", target_arch = "m68k"))]
+    const O_DIRECT: i32 = 0o200000;
+    #[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+    const O_DIRECT: i32 = 0o400000;
+    #[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+    const O_DIRECT: i32 = 0o100000;
+    #[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+    const O_DIRECT: i32 = 0x100000;
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "arm",
+        target_arch = "m68k",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    )))]
+    const O_DIRECT: i32 = 0o40000;
+
+    /// A buffer aligned for `O_DIRECT`.
+    struct AlignedBuffer(*mut u8, Layout);
+    impl Drop for AlignedBuffer {
+        fn drop(&mut self) {
+            // SAFETY: The buffer was allocated with this layout.
+            unsafe { dealloc(self.0, self.1) };
+        }
+    }
+
+    let layout = Layout::from_size_align(block_size as usize, 4096).unwrap();
+    // SAFETY: The layout has a nonzero size.
+    let buffer = AlignedBuffer(unsafe { alloc_zeroed(layout) }, layout);
+    assert!(!buffer.0.is_null());
+    // SAFETY: The buffer is allocated and has `block_size` bytes.
+    let block = unsafe { std::slice::from_raw_parts_mut(buffer.0, block_size as usize) };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(O_DIRECT)
+        .open(path)?;
+    for i in 0..n {
+        fill_block(block, i);
+        file.write_all(block)?;
+    }
+    if sync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_direct(_path: &Path, _block_size: u64, _n: u64, _sync: bool) -> IoResult<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(target_os = "linux")]
+fn write_mmap(path: &Path, block_size: u64, n: u64, initial_map: u64, sync: bool) -> IoResult<()> {
+    use std::ffi::c_void;
+    use std::io::Error;
+    use std::os::fd::AsRawFd;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            off: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+        fn msync(addr: *mut c_void, len: usize, flags: i32) -> i32;
+    }
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const MAP_SHARED: i32 = 1;
+    const MS_SYNC: i32 = 4;
+
+    /// A shared, writable mapping of a whole file.
+    struct Mapping(*mut u8, usize);
+    impl Mapping {
+        fn new(file: &File, len: u64) -> IoResult<Self> {
+            file.set_len(len)?;
+            // SAFETY: Maps `len` bytes of a file that has that length.
+            let addr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len as usize,
+                    PROT_READ | PROT_WRITE,
+                    MAP_SHARED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if addr as isize == -1 {
+                return Err(Error::last_os_error());
+            }
+            Ok(Self(addr as *mut u8, len as usize))
+        }
+        fn as_mut_slice(&mut self) -> &mut [u8] {
+            // SAFETY: The mapping has `self.1` writable bytes.
+            unsafe { std::slice::from_raw_parts_mut(self.0, self.1) }
+        }
+        fn sync(&self) -> IoResult<()> {
+            // SAFETY: Syncs the whole mapping.
+            if unsafe { msync(self.0 as *mut c_void, self.1, MS_SYNC) } != 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: Unmaps the whole mapping, which nothing else refers to.
+            unsafe { munmap(self.0 as *mut c_void, self.1) };
+        }
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    let mut len = initial_map.max(block_size);
+    let mut mapping = Mapping::new(&file, len)?;
+    let mut block = vec![0; block_size as usize];
+    let mut offset = 0;
+    for i in 0..n {
+        if offset + block_size > len {
+            drop(mapping);
+            len = (len * 2).max(offset + block_size);
+            mapping = Mapping::new(&file, len)?;
+        }
+        fill_block(&mut block, i);
+        let start = offset as usize;
+        mapping.as_mut_slice()[start..start + block.len()].copy_from_slice(&block);
+        offset += block_size;
+    }
+    if sync {
+        mapping.sync()?;
+    }
+    drop(mapping);
+    file.set_len(offset)?;
+    if sync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_mmap(
+    _path: &Path,
+    _block_size: u64,
+    _n: u64,
+    _initial_map: u64,
+    _sync: bool,
+) -> IoResult<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,243 @@
+//! Zone maps and predicate pushdown for range scans.
+//!
+//! A zone map keeps the minimum and maximum value of a column for each data
+//! block, in a statistics region that the trailer points to rather than in the
+//! index entries.  To let a scan skip whole subtrees, and not just single data
+//! blocks, the region has a level per index level, parallel to the index: its
+//! level 1 holds a (minimum, maximum) pair per data block, indexed by block
+//! number, and each level above holds one pair per index block at the matching
+//! index level, covering that block's whole subtree.  Level `k + 1`'s `i`th
+//! pair covers level `k`'s pairs `i * fanout` through `(i + 1) * fanout - 1`,
+//! so the region needs no pointers, and it is only `1 / (fanout - 1)` bigger
+//! than with level 1 alone.
+//!
+//! A scan with a range predicate `lo <= column < hi` starts at the top level
+//! and descends only into pairs that overlap the range, then reads just the
+//! data blocks whose pairs overlap it, and filters their rows as before.  Rows
+//! with a null in the column don't count toward the minimum and maximum, and
+//! a block of nothing but nulls has an empty pair that no range overlaps.
+//!
+//! This generates a column whose values follow the row order closely, loosely,
+//! or not at all, builds its zone map, runs range predicates of a given
+//! selectivity, checks that each scan returns exactly the rows that match,
+//! and compares the data blocks it reads and the pairs it reads against the
+//! blocks that hold a match and against reading level 1 from end to end.
+
+use clap::{Args as ClapArgs, ValueEnum};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::rng;
+use crate::HumanCount;
+
+#[derive(ClapArgs, Debug)]
+pub struct ZoneMapsArgs {
+    /// How the column's values relate to the order of the rows.
+    #[clap(long = "order", default_values_t = vec![Order::Sorted, Order::Clustered, Order::Random])]
+    orders: Vec<Order>,
+
+    /// Fraction of the column's range that each predicate selects.
+    #[clap(long = "selectivity", default_values_t = vec![0.001, 0.01, 0.1])]
+    selectivities: Vec<f64>,
+
+    /// Number of rows in each data block.
+    #[clap(long, default_value_t = 256)]
+    block_rows: u64,
+
+    /// Number of pairs at one level of the region that are covered by a
+    /// single pair at the level above, like an index block's entries.
+    #[clap(long, default_value_t = 64)]
+    fanout: u64,
+
+    /// Number of rows to generate.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Number of predicates to run for each order and selectivity.
+    #[clap(long, default_value_t = 20)]
+    scans: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Order {
+    /// Values increase with the row order, like an ingest timestamp.
+    Sorted,
+
+    /// Values follow the row order, give or take 1% of their range, like an
+    /// event time that arrives a little out of order.
+    Clustered,
+
+    /// Values are independent of the row order.
+    Random,
+}
+
+impl Display for Order {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let s = match self {
+            Order::Sorted => "sorted",
+            Order::Clustered => "clustered",
+            Order::Random => "random",
+        };
+        write!(f, "{s:>width$}", width = f.width().unwrap_or_default())
+    }
+}
+
+/// A pair that no range overlaps, for a block that only has nulls.
+const EMPTY: (u64, u64) = (u64::MAX, 0);
+
+/// The statistics region for a column, as its levels from the pairs for the
+/// data blocks upward, ending with a level of a single pair.
+struct ZoneMap {
+    levels: Vec<Vec<(u64, u64)>>,
+    fanout: usize,
+}
+
+impl ZoneMap {
+    /// Returns the zone map for `values`, in blocks of `block_rows` rows.
+    fn new(values: &[Option<u64>], block_rows: usize, fanout: usize) -> Self {
+        let cover = |pairs: &mut dyn Iterator<Item = (u64, u64)>| {
+            pairs.fold(EMPTY, |(min, max), (lo, hi)| (min.min(lo), max.max(hi)))
+        };
+        let mut levels = vec![values
+            .chunks(block_rows)
+            .map(|block| cover(&mut block.iter().flatten().map(|&value| (value, value))))
+            .collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let above = levels
+                .last()
+                .unwrap()
+                .chunks(fanout)
+                .map(|pairs| cover(&mut pairs.iter().copied()))
+                .collect();
+            levels.push(above);
+        }
+        Self { levels, fanout }
+    }
+
+    /// Returns the data blocks whose pairs overlap `lo..hi`, in order, and
+    /// the number of pairs that it read to find them.
+    fn blocks(&self, lo: u64, hi: u64) -> (Vec<usize>, usize) {
+        let overlaps = |&(min, max): &(u64, u64)| min < hi && max >= lo;
+        let top = self.levels.last().unwrap();
+        let mut read = top.len();
+        let mut candidates: Vec<usize> = (0..top.len()).filter(|&i| overlaps(&top[i])).collect();
+        for level in self.levels.iter().rev().skip(1) {
+            let mut below = Vec::new();
+            for &i in &candidates {
+                let children = i * self.fanout..((i + 1) * self.fanout).min(level.len());
+                read += children.len();
+                below.extend(children.filter(|&j| overlaps(&level[j])));
+            }
+            candidates = below;
+        }
+        (candidates, read)
+    }
+}
+
+/// Prints the data blocks and zone map pairs that range scans read, for each
+/// order and selectivity in `args`.  Returns the number of failed checks.
+pub fn zone_maps(args: &ZoneMapsArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_rows = args.block_rows.max(1) as usize;
+    let fanout = args.fanout.max(2) as usize;
+    let rows = args.rows.max(1);
+    let scans = args.scans.max(1);
+    println!(
+        "Range scans with zone maps, {} rows, {block_rows} rows per block, fanout {fanout}:",
+        HumanCount(rows),
+    );
+    print!(
+        r#"
+                        -Data Blocks-   -Pairs Read/Scan-
+    Order  Selectivity    Read  Needed     Tree      Flat  Failures
+---------  -----------  ------  ------  -------  --------  --------
+"#
+    );
+    for &order in &args.orders {
+        let mut random = rng::random(rows ^ order as u64);
+
+        // Values span `0..range`, and 1 row in 100 is null.
+        let range = rows * 1000;
+        let values: Vec<Option<u64>> = (0..rows)
+            .map(|i| {
+                let value = match order {
+                    Order::Sorted => i * 1000 + random() % 1000,
+                    Order::Clustered => {
+                        (i * 1000 + random() % (range / 50)).saturating_sub(range / 100)
+                    }
+                    Order::Random => random() % range,
+                };
+                (!random().is_multiple_of(100)).then_some(value.min(range - 1))
+            })
+            .collect();
+        let zone_map = ZoneMap::new(&values, block_rows, fanout);
+        let n_blocks = zone_map.levels[0].len();
+
+        for &selectivity in &args.selectivities {
+            let width = ((range as f64 * selectivity.clamp(0.0, 1.0)) as u64).max(1);
+            let (mut read_blocks, mut needed_blocks, mut read_pairs) = (0, 0, 0);
+            let mut failures = 0;
+            for _ in 0..scans {
+                let lo = random() % (range - width.min(range - 1));
+                let hi = lo + width;
+                let matches = |value: &Option<u64>| value.is_some_and(|v| (lo..hi).contains(&v));
+
+                let (blocks, pairs) = zone_map.blocks(lo, hi);
+                let found: Vec<usize> = blocks
+                    .iter()
+                    .flat_map(|&block| {
+                        let start = block * block_rows;
+                        let end = (start + block_rows).min(values.len());
+                        (start..end).filter(|&row| matches(&values[row]))
+                    })
+                    .collect();
+                let expected: Vec<usize> = (0..values.len())
+                    .filter(|&row| matches(&values[row]))
+                    .collect();
+                failures += (found != expected) as u64;
+
+                read_blocks += blocks.len();
+                needed_blocks += values
+                    .chunks(block_rows)
+                    .filter(|block| block.iter().any(matches))
+                    .count();
+                read_pairs += pairs;
+            }
+            println!(
+                "{order:9}  {:10.1}%  {:5.1}%  {:5.1}%  {:>7}  {:>8}  {failures:8}",
+                selectivity * 100.0,
+                read_blocks as f64 / (n_blocks as u64 * scans) as f64 * 100.0,
+                needed_blocks as f64 / (n_blocks as u64 * scans) as f64 * 100.0,
+                HumanCount(read_pairs as u64 / scans),
+                HumanCount(n_blocks as u64),
+            );
+            total_failures += failures;
+        }
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: ZoneMapsArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "zone-maps",
+            "--rows",
+            "50000",
+            "--block-rows",
+            "64",
+            "--fanout",
+            "8",
+        ]);
+        assert_eq!(zone_maps(&args), 0);
+    }
+}
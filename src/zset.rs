@@ -0,0 +1,368 @@
+//! Indexed Z-sets: a key column over runs of values.
+//!
+//! An indexed Z-set maps each key to one or more (value, weight) pairs.  It is
+//! a 2-column file: column 1 holds the distinct keys, in order, and column 2
+//! holds each key's values, in order, as one run per key.  Each column 1 row
+//! holds the end of its run in column 2, which is the start of the next key's
+//! run, so a key's values are the column 2 rows from the end of the previous
+//! key's run to the end of its own.
+//!
+//! The first key in a data block would need the end of the last key in the
+//! previous block, which costs a read of that block for a reader that didn't
+//! come from it.  So each entry of column 1's row index also holds the start
+//! of the run of its child's first key, a 6-byte row number that takes entries
+//! from 12 to 18 bytes, and a reader that descends the row index or has it
+//! cached gets the whole row range of every key in the block from the block
+//! itself.
+//!
+//! A cursor over the file is positioned at a key, and, within the key, at a
+//! value.  Seeking moves it to the first key at or after a given one, stepping
+//! moves it to the next key, and either one puts it at the first value of the
+//! key's run.  Stepping the value moves it along the run until the run ends,
+//! and rewinding the values goes back to the start of the run.  A cursor keeps
+//! the data block that it is in for each column, and the L1 row index block
+//! for column 2, so stepping within a block and from one block to the next in
+//! the same L1 block reads nothing.
+//!
+//! This generates keys with runs of random lengths, scans the whole file
+//! through a cursor, seeks to random keys that are present and missing and
+//! iterates and rewinds their values, checks that each step returns exactly
+//! the expected keys and values, and compares the blocks read per seek with
+//! and without run starts in column 1's row index.
+
+use clap::Args as ClapArgs;
+
+use crate::rng;
+use crate::{HumanBytes, HumanCount};
+
+#[derive(ClapArgs, Debug)]
+pub struct ZSetArgs {
+    /// Average number of values per key.  Runs have from 1 to twice this
+    /// many values, less one.
+    #[clap(long = "values-per-key", default_values_t = vec![1, 4, 16, 256, 1024])]
+    values_per_key: Vec<u64>,
+
+    /// Number of rows in column 2.
+    #[clap(long, default_value_t = 1_000_000)]
+    rows: u64,
+
+    /// Size of a key, in bytes, including its run end.
+    #[clap(long, default_value_t = 32)]
+    key_size: u64,
+
+    /// Size of a value and its weight, in bytes.
+    #[clap(long, default_value_t = 24)]
+    value_size: u64,
+
+    /// Size of each data and index block, in bytes.
+    #[clap(long, default_value_t = 8192)]
+    block_size: u64,
+
+    /// Number of seeks for each average run length.
+    #[clap(long, default_value_t = 10_000)]
+    seeks: u64,
+}
+
+/// Size of a row index entry, and of the run start that column 1's entries
+/// add.
+const ENTRY_SIZE: u64 = 12;
+const START_SIZE: u64 = 6;
+
+/// A 2-column file of keys and their runs of values.
+struct File {
+    /// Each key and the end of its run in `values`.
+    keys: Vec<(u64, u64)>,
+    values: Vec<(u64, i64)>,
+    keys_per_block: usize,
+    values_per_block: usize,
+    entries_per_block: usize,
+
+    /// The start of the run of each column 1 data block's first key, as
+    /// column 1's row index records it, if it does.
+    starts: Option<Vec<u64>>,
+}
+
+/// A cursor over a `File`, at a key and a value in its run.
+struct Cursor<'a> {
+    file: &'a File,
+    key: usize,
+    run: (u64, u64),
+    value: u64,
+
+    /// The blocks that the cursor holds, as the column 1 data block, the
+    /// column 2 data block, and column 2's L1 row index block, and the number
+    /// of blocks that it has read.
+    blocks: (Option<usize>, Option<usize>, Option<usize>),
+    reads: u64,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(file: &'a File) -> Self {
+        Self {
+            file,
+            key: file.keys.len(),
+            run: (0, 0),
+            value: 0,
+            blocks: (None, None, None),
+            reads: 0,
+        }
+    }
+
+    fn load(held: &mut Option<usize>, block: usize, reads: &mut u64) {
+        if *held != Some(block) {
+            *held = Some(block);
+            *reads += 1;
+        }
+    }
+
+    /// Moves to key `i`, whose run starts at `start` if the caller already
+    /// knows, and to the first value of its run.
+    fn enter(&mut self, i: usize, start: Option<u64>) {
+        let file = self.file;
+        self.key = i;
+        if i >= file.keys.len() {
+            return;
+        }
+        let block = i / file.keys_per_block;
+        Self::load(&mut self.blocks.0, block, &mut self.reads);
+        let start = match (start, i % file.keys_per_block, &file.starts) {
+            (Some(start), _, _) => start,
+            (None, 0, Some(starts)) => starts[block],
+            (None, 0, None) if i > 0 => {
+                // Read the previous block first, for its last key's run end.
+                self.reads += 1;
+                file.keys[i - 1].1
+            }
+            (None, _, _) if i > 0 => file.keys[i - 1].1,
+            (None, _, _) => 0,
+        };
+        self.run = (start, file.keys[i].1);
+        self.rewind_values();
+    }
+
+    /// Seeks to the first key at or after `key`, with column 1's value index
+    /// cached, and returns whether there is one.
+    fn seek(&mut self, key: u64) -> bool {
+        let i = self.file.keys.partition_point(|&(k, _)| k < key);
+        self.enter(i, None);
+        self.key().is_some()
+    }
+
+    fn key(&self) -> Option<u64> {
+        self.file.keys.get(self.key).map(|&(key, _)| key)
+    }
+
+    fn step_key(&mut self) {
+        let end = self.run.1;
+        self.enter(self.key + 1, Some(end));
+    }
+
+    fn value(&self) -> Option<(u64, i64)> {
+        (self.key().is_some() && self.value < self.run.1)
+            .then(|| self.file.values[self.value as usize])
+    }
+
+    fn step_value(&mut self) {
+        self.value += 1;
+        self.load_value();
+    }
+
+    fn rewind_values(&mut self) {
+        self.value = self.run.0;
+        self.load_value();
+    }
+
+    fn load_value(&mut self) {
+        if self.value < self.run.1 {
+            let block = self.value as usize / self.file.values_per_block;
+            Self::load(
+                &mut self.blocks.2,
+                block / self.file.entries_per_block,
+                &mut self.reads,
+            );
+            Self::load(&mut self.blocks.1, block, &mut self.reads);
+        }
+    }
+
+    /// Returns the rest of the current key's values.
+    fn values(&mut self) -> Vec<(u64, i64)> {
+        let mut values = Vec::new();
+        while let Some(value) = self.value() {
+            values.push(value);
+            self.step_value();
+        }
+        values
+    }
+}
+
+/// Prints the blocks that a cursor reads to scan and seek, for each average
+/// run length in `args`.  Returns the number of failed checks.
+pub fn zset(args: &ZSetArgs) -> u64 {
+    let mut total_failures = 0;
+    let block_size = args.block_size.max(4096);
+    let rows = args.rows.max(1);
+    let seeks = args.seeks.max(1);
+    let keys_per_block = (block_size / args.key_size.max(1)).max(1) as usize;
+    println!(
+        "Indexed Z-sets with {} values of {} and keys of {} in {} blocks:",
+        HumanCount(rows),
+        HumanBytes(args.value_size),
+        HumanBytes(args.key_size),
+        HumanBytes(block_size),
+    );
+    print!(
+        r#"
+                    --Seek Reads--  First in Block   Index    Data    Scan
+  Values  Column 1   Plain  Starts   Plain  Starts  Growth  Blocks   Reads  Failures
+--------  --------  ------  ------  ------  ------  ------  ------  ------  --------
+"#
+    );
+    for &per_key in &args.values_per_key {
+        let per_key = per_key.clamp(1, rows);
+        let mut random = rng::random(rows ^ per_key);
+
+        // Keys and each key's values go up by 1 to 4, so that there are
+        // missing keys and values between them.
+        let (mut keys, mut values) = (Vec::new(), Vec::new());
+        let mut key = 0;
+        while (values.len() as u64) < rows {
+            key += 1 + random() % 4;
+            let mut value = 0;
+            for _ in 0..1 + random() % (2 * per_key - 1) {
+                value += 1 + random() % 4;
+                values.push((value, ((random() % 7) as i64 - 3) | 1));
+            }
+            keys.push((key, values.len() as u64));
+        }
+        let mut file = File {
+            keys,
+            values,
+            keys_per_block,
+            values_per_block: (block_size / args.value_size.max(1)).max(1) as usize,
+            entries_per_block: (block_size / ENTRY_SIZE) as usize,
+            starts: None,
+        };
+        let key_blocks = file.keys.len().div_ceil(keys_per_block);
+        let value_blocks = file.values.len().div_ceil(file.values_per_block);
+        let mut failures = 0;
+        let expected = |file: &File, i: usize| {
+            let start = match i {
+                0 => 0,
+                _ => file.keys[i - 1].1,
+            };
+            file.values[start as usize..file.keys[i].1 as usize].to_vec()
+        };
+
+        // A scan steps through every key and value, reading each data block
+        // and each of column 2's L1 row index blocks once.
+        let mut cursor = Cursor::new(&file);
+        cursor.seek(0);
+        let mut i = 0;
+        while let Some(key) = cursor.key() {
+            failures += (key != file.keys[i].0 || cursor.values() != expected(&file, i)) as u64;
+            cursor.step_key();
+            i += 1;
+        }
+        failures += (i != file.keys.len()) as u64;
+        let scan_reads = cursor.reads;
+        let l1_blocks = value_blocks.div_ceil(file.entries_per_block);
+        failures += (scan_reads != (key_blocks + value_blocks + l1_blocks) as u64) as u64;
+
+        // Seeks to present and missing keys, some of them past the last, find
+        // the first key at or after them, and iterate its values twice.
+        // Seeks to the first key of a data block other than the first are
+        // where run starts in the index matter.
+        let targets: Vec<u64> = (0..seeks)
+            .map(|_| {
+                let (key, _) = file.keys[random() as usize % file.keys.len()];
+                key - random() % 2
+            })
+            .chain([file.keys.last().unwrap().0 + 1])
+            .collect();
+        let firsts: Vec<u64> = (0..seeks)
+            .filter_map(|_| {
+                let block = 1 + random() as usize % key_blocks.saturating_sub(1).max(1);
+                file.keys.get(block * keys_per_block).map(|&(key, _)| key)
+            })
+            .collect();
+        let mut reads = [[0; 2]; 2];
+        for (with_starts, reads) in [false, true].into_iter().zip(&mut reads) {
+            file.starts = with_starts.then(|| {
+                (0..key_blocks)
+                    .map(|block| match block {
+                        0 => 0,
+                        _ => file.keys[block * keys_per_block - 1].1,
+                    })
+                    .collect()
+            });
+            for (targets, reads) in [&targets, &firsts].into_iter().zip(reads) {
+                for &target in targets {
+                    let mut cursor = Cursor::new(&file);
+                    let i = file.keys.partition_point(|&(k, _)| k < target);
+                    match cursor.seek(target) {
+                        true => {
+                            *reads += cursor.reads;
+                            failures += (cursor.key() != Some(file.keys[i].0)) as u64;
+                            let values = expected(&file, i);
+                            failures += (cursor.values() != values) as u64;
+                            cursor.rewind_values();
+                            failures += (cursor.values() != values) as u64;
+                        }
+                        false => failures += (i != file.keys.len()) as u64,
+                    }
+                }
+            }
+        }
+        let per_seek = |reads: u64, targets: &[u64]| reads as f64 / targets.len().max(1) as f64;
+
+        // Each run start takes 6 bytes in each entry of column 1's row index.
+        let index_growth = key_blocks
+            .div_ceil((block_size / (ENTRY_SIZE + START_SIZE)) as usize)
+            .saturating_sub(key_blocks.div_ceil(file.entries_per_block))
+            as u64
+            * block_size;
+        println!(
+            "{per_key:>8}  {:>8}  {:6.2}  {:6.2}  {:6.2}  {:6.2}  {:>6}  {:>6}  {:>6}  {failures:8}",
+            HumanCount(file.keys.len() as u64),
+            per_seek(reads[0][0], &targets),
+            per_seek(reads[1][0], &targets),
+            per_seek(reads[0][1], &firsts),
+            per_seek(reads[1][1], &firsts),
+            HumanBytes(index_growth),
+            HumanCount((key_blocks + value_blocks) as u64),
+            HumanCount(scan_reads),
+        );
+        total_failures += failures;
+    }
+    total_failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[clap(flatten)]
+        args: ZSetArgs,
+    }
+
+    #[test]
+    fn checks_pass() {
+        let Wrapper { args } = Wrapper::parse_from([
+            "zset",
+            "--rows",
+            "50000",
+            "--seeks",
+            "1000",
+            "--values-per-key",
+            "1",
+            "--values-per-key",
+            "16",
+            "--values-per-key",
+            "256",
+        ]);
+        assert_eq!(zset(&args), 0);
+    }
+}